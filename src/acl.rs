@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::sync::RwLock;
+
+use sha2::{Digest, Sha256};
+
+/// Name of the user connections act as until they AUTH.
+pub const DEFAULT_USER: &str = "default";
+
+/// One `ACL SETUSER` modifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    On,
+    Off,
+    NoPass,
+    ResetPass,
+    AddPassword(String),
+    RemovePassword(String),
+    AllKeys,
+    ResetKeys,
+    KeyPattern(String),
+    AllCommands,
+    NoCommands,
+    /// `+name`/`-name`, or `+@category`/`-@category` with the `@` kept.
+    Command {
+        allow: bool,
+        target: String,
+    },
+    Reset,
+}
+
+impl Rule {
+    /// Parse a modifier, without checking that command and category names
+    /// exist.
+    pub fn parse(rule: &str) -> Option<Self> {
+        let lower = rule.to_ascii_lowercase();
+        Some(match lower.as_str() {
+            "on" => Self::On,
+            "off" => Self::Off,
+            "nopass" => Self::NoPass,
+            "resetpass" => Self::ResetPass,
+            "allkeys" => Self::AllKeys,
+            "resetkeys" => Self::ResetKeys,
+            "allcommands" => Self::AllCommands,
+            "nocommands" => Self::NoCommands,
+            "reset" => Self::Reset,
+            _ => match rule.split_at_checked(1)? {
+                (">", pass) => Self::AddPassword(pass.to_string()),
+                ("<", pass) => Self::RemovePassword(pass.to_string()),
+                ("~", pattern) if !pattern.is_empty() => Self::KeyPattern(pattern.to_string()),
+                ("+" | "-", target) if !target.is_empty() && !target.contains('|') => {
+                    Self::Command {
+                        allow: rule.starts_with('+'),
+                        target: target.to_ascii_lowercase(),
+                    }
+                }
+                _ => return None,
+            },
+        })
+    }
+}
+
+/// What a user may do. Command rules are kept in the order given and the
+/// last one matching a command decides, which is how Redis applies them.
+#[derive(Debug, Clone, Default)]
+pub struct User {
+    pub enabled: bool,
+    pub nopass: bool,
+    /// Hex SHA-256 digests; passwords themselves are never stored.
+    pub passwords: BTreeSet<String>,
+    pub key_patterns: Vec<String>,
+    pub command_rules: Vec<(bool, String)>,
+}
+
+impl User {
+    /// The user every connection starts as: allowed everything, no password.
+    fn unrestricted() -> Self {
+        let mut user = Self::default();
+        for rule in [Rule::On, Rule::NoPass, Rule::AllKeys, Rule::AllCommands] {
+            user.apply(rule);
+        }
+        user
+    }
+
+    fn apply(&mut self, rule: Rule) {
+        match rule {
+            Rule::On => self.enabled = true,
+            Rule::Off => self.enabled = false,
+            Rule::NoPass => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            Rule::ResetPass => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            Rule::AddPassword(pass) => {
+                self.nopass = false;
+                self.passwords.insert(password_hash(&pass));
+            }
+            Rule::RemovePassword(pass) => {
+                self.passwords.remove(&password_hash(&pass));
+            }
+            Rule::AllKeys => self.key_patterns = vec!["*".into()],
+            Rule::ResetKeys => self.key_patterns.clear(),
+            Rule::KeyPattern(pattern) => self.key_patterns.push(pattern),
+            // These override every earlier rule, so those can go.
+            Rule::AllCommands => self.command_rules = vec![(true, "@all".into())],
+            Rule::NoCommands => self.command_rules.clear(),
+            Rule::Command { allow, target } => {
+                if target == "@all" {
+                    self.command_rules.clear();
+                }
+                self.command_rules.push((allow, target));
+            }
+            Rule::Reset => *self = Self::default(),
+        }
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        self.nopass || self.passwords.contains(&password_hash(password))
+    }
+
+    /// Whether the last command rule matching per `matches` allows it.
+    pub fn allows(&self, matches: impl Fn(&str) -> bool) -> bool {
+        self.command_rules
+            .iter()
+            .rev()
+            .find(|(_, target)| matches(target))
+            .is_some_and(|(allow, _)| *allow)
+    }
+
+    /// The command rules as one string, e.g. `+@all -debug`.
+    pub fn commands_description(&self) -> String {
+        if self.command_rules.is_empty() {
+            return "-@all".into();
+        }
+        let rules: Vec<String> = self
+            .command_rules
+            .iter()
+            .map(|(allow, target)| format!("{}{target}", if *allow { '+' } else { '-' }))
+            .collect();
+        rules.join(" ")
+    }
+
+    /// The key patterns as one string, e.g. `~cache:* ~session:*`.
+    pub fn keys_description(&self) -> String {
+        let patterns: Vec<String> = self.key_patterns.iter().map(|p| format!("~{p}")).collect();
+        patterns.join(" ")
+    }
+
+    /// The user as an `ACL LIST` line.
+    pub fn describe(&self, name: &str) -> String {
+        let mut line = format!("user {name} {}", if self.enabled { "on" } else { "off" });
+        if self.nopass {
+            line.push_str(" nopass");
+        }
+        for hash in &self.passwords {
+            let _ = write!(line, " #{hash}");
+        }
+        let keys = self.keys_description();
+        if keys.is_empty() {
+            line.push_str(" resetkeys");
+        } else {
+            let _ = write!(line, " {keys}");
+        }
+        let _ = write!(line, " {}", self.commands_description());
+        line
+    }
+}
+
+fn password_hash(password: &str) -> String {
+    Sha256::digest(password.as_bytes())
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, b| {
+            let _ = write!(hex, "{b:02x}");
+            hex
+        })
+}
+
+/// The users known to the server.
+#[derive(Debug)]
+pub struct Acl {
+    users: RwLock<BTreeMap<String, User>>,
+}
+
+impl Acl {
+    pub fn new() -> Self {
+        let users = BTreeMap::from([(DEFAULT_USER.to_string(), User::unrestricted())]);
+        Self {
+            users: RwLock::new(users),
+        }
+    }
+
+    /// Apply `rules` in order to the named user, creating it (disabled and
+    /// allowed nothing) if it doesn't exist yet.
+    pub fn set_user(&self, name: &str, rules: Vec<Rule>) {
+        let mut users = self.users.write().unwrap();
+        let user = users.entry(name.to_string()).or_default();
+        for rule in rules {
+            user.apply(rule);
+        }
+    }
+
+    /// Run `f` on the named user, if it exists.
+    pub fn with_user<R>(&self, name: &str, f: impl FnOnce(&User) -> R) -> Option<R> {
+        self.users.read().unwrap().get(name).map(f)
+    }
+
+    /// `ACL LIST` lines for every user, ordered by name.
+    pub fn list(&self) -> Vec<String> {
+        let users = self.users.read().unwrap();
+        users
+            .iter()
+            .map(|(name, user)| user.describe(name))
+            .collect()
+    }
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(rules: &[&str]) -> User {
+        let mut user = User::default();
+        for rule in rules {
+            user.apply(Rule::parse(rule).unwrap());
+        }
+        user
+    }
+
+    #[test]
+    fn last_matching_command_rule_wins() {
+        let user = user(&["+@all", "-debug", "+debug", "-set"]);
+        assert!(user.allows(|t| t == "@all" || t == "debug"));
+        assert!(!user.allows(|t| t == "@all" || t == "set"));
+        assert!(!User::default().allows(|_| true));
+
+        // @all discards everything before it.
+        let user = self::user(&["-get", "+set", "-@all", "+get"]);
+        assert_eq!(user.commands_description(), "-@all +get");
+    }
+
+    #[test]
+    fn passwords_are_checked_by_hash() {
+        let user = user(&[">secret", ">other", "<other"]);
+        assert!(user.check_password("secret"));
+        assert!(!user.check_password("other"));
+        assert!(!user.passwords.iter().any(|h| h.contains("secret")));
+        assert!(self::user(&[">secret", "nopass"]).check_password("anything"));
+    }
+}
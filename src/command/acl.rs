@@ -0,0 +1,233 @@
+//! AUTH and the ACL command, plus the permission check `dispatch` runs
+//! before every command.
+
+use bytes::Bytes;
+
+use crate::acl::{DEFAULT_USER, Rule};
+use crate::protocol::{RespFrame, reply};
+use crate::server::client::ClientState;
+use crate::server::state::ServerState;
+use crate::store::glob::glob_match;
+
+use super::bulk_to_string;
+use super::table::{self, CATEGORIES, COMMANDS, CommandSpec};
+
+/// Refuse `argv` (command name included) unless the client's user may run
+/// the command on its keys. AUTH is always allowed, since it's how a client
+/// changes user.
+pub(super) fn authorize(
+    spec: &CommandSpec,
+    argv: &[RespFrame],
+    server: &ServerState,
+    client: &mut ClientState,
+) -> Result<(), RespFrame> {
+    if spec.name == "AUTH" {
+        return Ok(());
+    }
+    let anonymous = client.user.is_none();
+    let name = client.user.as_deref().unwrap_or(DEFAULT_USER);
+    let verdict = server.acl.with_user(name, |user| {
+        // A connection that hasn't AUTHed is logged in as the default user
+        // only while that needs no password.
+        if anonymous && !(user.enabled && user.nopass) {
+            return Err(RespFrame::Error("NOAUTH Authentication required.".into()));
+        }
+        let allowed = user.allows(|target| match target.strip_prefix('@') {
+            Some(category) => spec.in_category(category),
+            None => target.eq_ignore_ascii_case(spec.name),
+        });
+        if !allowed {
+            return Err(no_permission(name, spec));
+        }
+        let keys = spec.keys.extract(argv).unwrap_or_default();
+        let permitted = |key: &&RespFrame| match key {
+            RespFrame::BulkString(Some(key)) => user
+                .key_patterns
+                .iter()
+                .any(|p| glob_match(p.as_bytes(), key)),
+            _ => true,
+        };
+        if !keys.iter().all(permitted) {
+            return Err(RespFrame::Error(
+                "NOPERM No permissions to access a key".into(),
+            ));
+        }
+        Ok(())
+    });
+    // A user deleted out from under its connection can do nothing.
+    let verdict = verdict.unwrap_or_else(|| Err(no_permission(name, spec)));
+    // It stays logged in even if the default user later gets a password,
+    // as in Redis.
+    if anonymous && verdict.is_ok() {
+        client.user = Some(DEFAULT_USER.to_string());
+    }
+    verdict
+}
+
+fn no_permission(user: &str, spec: &CommandSpec) -> RespFrame {
+    RespFrame::Error(format!(
+        "NOPERM User {user} has no permissions to run the '{}' command",
+        spec.name.to_ascii_lowercase()
+    ))
+}
+
+/// AUTH [username] password
+pub(super) fn handle_auth(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    client: &mut ClientState,
+) -> RespFrame {
+    let strings: Option<Vec<String>> = args.iter().map(bulk_to_string).collect();
+    let (name, password) = match strings.as_deref() {
+        Some([password]) => {
+            let open = server
+                .acl
+                .with_user(DEFAULT_USER, |u| u.nopass)
+                .unwrap_or(false);
+            if open {
+                return RespFrame::Error(
+                    "ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".into(),
+                );
+            }
+            (DEFAULT_USER.to_string(), password.clone())
+        }
+        Some([name, password]) => (name.clone(), password.clone()),
+        _ => return RespFrame::Error("ERR syntax error".into()),
+    };
+
+    let valid = server
+        .acl
+        .with_user(&name, |u| u.enabled && u.check_password(&password))
+        .unwrap_or(false);
+    if !valid {
+        return RespFrame::Error(
+            "WRONGPASS invalid username-password pair or user is disabled.".into(),
+        );
+    }
+    client.user = Some(name);
+    reply::ok()
+}
+
+const ACL_HELP: &[&str] = &[
+    "CAT [<category>]",
+    "    List all commands that belong to <category>, or all command categories",
+    "    when no category is specified.",
+    "GETUSER <username>",
+    "    Get the user's details.",
+    "LIST",
+    "    Show users details in config file format.",
+    "SETUSER <username> <attribute> [<attribute> ...]",
+    "    Create or modify a user with the specified attributes.",
+    "WHOAMI",
+    "    Return the current connection username.",
+];
+
+pub(super) fn handle_acl(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    client: &ClientState,
+) -> RespFrame {
+    let Some(sub) = bulk_to_string(&args[0]) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+    match (sub.to_ascii_uppercase().as_str(), &args[1..]) {
+        ("SETUSER", [name, rules @ ..]) => acl_setuser(name, rules, server),
+        ("GETUSER", [name]) => acl_getuser(name, server),
+        ("LIST", []) => RespFrame::Array(Some(
+            server
+                .acl
+                .list()
+                .into_iter()
+                .map(|line| RespFrame::BulkString(Some(line.into())))
+                .collect(),
+        )),
+        ("CAT", []) => RespFrame::Array(Some(
+            CATEGORIES
+                .iter()
+                .map(|c| RespFrame::BulkString(Some(Bytes::from_static(c.as_bytes()))))
+                .collect(),
+        )),
+        ("CAT", [category]) => acl_cat(category),
+        ("WHOAMI", []) => {
+            let name = client.user.as_deref().unwrap_or(DEFAULT_USER);
+            RespFrame::BulkString(Some(name.to_string().into()))
+        }
+        ("HELP", []) => reply::help("ACL", ACL_HELP),
+        _ => reply::unknown_subcommand("ACL", &sub),
+    }
+}
+
+/// Parse every rule before applying any, so a bad one leaves the user as
+/// it was.
+fn acl_setuser(name: &RespFrame, rules: &[RespFrame], server: &ServerState) -> RespFrame {
+    let Some(name) = bulk_to_string(name) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+    let mut parsed = Vec::with_capacity(rules.len());
+    for rule in rules {
+        let text = bulk_to_string(rule).unwrap_or_default();
+        let Some(rule) = Rule::parse(&text) else {
+            return RespFrame::Error(format!(
+                "ERR Error in ACL SETUSER modifier '{text}': Syntax error"
+            ));
+        };
+        if let Rule::Command { target, .. } = &rule {
+            let known = match target.strip_prefix('@') {
+                Some(category) => category == "all" || CATEGORIES.contains(&category),
+                None => table::lookup(&target.to_ascii_uppercase()).is_some(),
+            };
+            if !known {
+                return RespFrame::Error(format!(
+                    "ERR Error in ACL SETUSER modifier '{text}': Unknown command or category name in ACL"
+                ));
+            }
+        }
+        parsed.push(rule);
+    }
+    server.acl.set_user(&name, parsed);
+    reply::ok()
+}
+
+/// The commands in `category`, lowercased as Redis lists them.
+fn acl_cat(category: &RespFrame) -> RespFrame {
+    let category = bulk_to_string(category)
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if !CATEGORIES.contains(&category.as_str()) {
+        return RespFrame::Error(format!("ERR Unknown category '{category}'"));
+    }
+    RespFrame::Array(Some(
+        COMMANDS
+            .iter()
+            .filter(|spec| spec.in_category(&category))
+            .map(|spec| RespFrame::BulkString(Some(spec.name.to_ascii_lowercase().into())))
+            .collect(),
+    ))
+}
+
+fn acl_getuser(name: &RespFrame, server: &ServerState) -> RespFrame {
+    let Some(name) = bulk_to_string(name) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+    let bulk = |s: String| RespFrame::BulkString(Some(s.into()));
+    server
+        .acl
+        .with_user(&name, |user| {
+            let mut flags = vec![bulk(if user.enabled { "on" } else { "off" }.into())];
+            if user.nopass {
+                flags.push(bulk("nopass".into()));
+            }
+            let passwords = user.passwords.iter().cloned().map(bulk).collect();
+            RespFrame::Array(Some(vec![
+                bulk("flags".into()),
+                RespFrame::Array(Some(flags)),
+                bulk("passwords".into()),
+                RespFrame::Array(Some(passwords)),
+                bulk("commands".into()),
+                bulk(user.commands_description()),
+                bulk("keys".into()),
+                bulk(user.keys_description()),
+            ]))
+        })
+        .unwrap_or_else(reply::nil)
+}
@@ -1,24 +1,55 @@
-use crate::protocol::RespFrame;
+use bytes::Bytes;
 
-pub(super) fn handle_ping(args: Vec<RespFrame>) -> RespFrame {
-    if args.is_empty() {
-        RespFrame::SimpleString("PONG".into())
-    } else if args.len() == 1 {
-        match &args[0] {
-            RespFrame::BulkString(Some(data)) => RespFrame::BulkString(Some(data.clone())),
-            _ => RespFrame::Error("ERR PING expects bulk string".into()),
-        }
-    } else {
-        RespFrame::Error("ERR too many arguments for PING".into())
+use crate::protocol::{RespFrame, reply};
+use crate::server::client::ClientState;
+use crate::server::state::ServerState;
+
+/// PING [message]. A subscribed connection gets `["pong", message]` (with an
+/// empty message when none is given), so a client reading pushes can tell
+/// the reply apart from a published message.
+pub(super) fn handle_ping(args: Vec<RespFrame>, client: &ClientState) -> RespFrame {
+    if client.is_subscriber() {
+        let message = match args.into_iter().next() {
+            None => Bytes::new(),
+            Some(RespFrame::BulkString(Some(data))) => data,
+            Some(_) => return RespFrame::Error("ERR PING expects bulk string".into()),
+        };
+        return RespFrame::Array(Some(vec![
+            RespFrame::BulkString(Some(Bytes::from_static(b"pong"))),
+            RespFrame::BulkString(Some(message)),
+        ]));
+    }
+    match args.first() {
+        None => RespFrame::SimpleString("PONG".into()),
+        Some(RespFrame::BulkString(Some(data))) => RespFrame::BulkString(Some(data.clone())),
+        Some(_) => RespFrame::Error("ERR PING expects bulk string".into()),
     }
 }
 
-pub(super) fn handle_echo(args: Vec<RespFrame>) -> RespFrame {
-    if args.len() != 1 {
-        return RespFrame::Error("ERR wrong number of arguments for 'echo'".into());
+/// MONITOR: from now on the connection receives every command the server
+/// runs, and its own commands other than RESET are ignored.
+pub(super) fn handle_monitor(server: &ServerState, client: &mut ClientState) -> RespFrame {
+    if !client.monitor {
+        client.monitor = true;
+        server.monitors.add(client.id, client.push_tx.clone());
     }
+    reply::ok()
+}
+
+pub(super) fn handle_echo(args: Vec<RespFrame>) -> RespFrame {
     match &args[0] {
         RespFrame::BulkString(Some(data)) => RespFrame::BulkString(Some(data.clone())),
         _ => RespFrame::Error("ERR ECHO expects bulk string".into()),
     }
 }
+
+/// RESET: abort MULTI, drop subscriptions and leave MONITOR mode, returning
+/// the connection to its freshly-connected state.
+pub(super) fn handle_reset(server: &ServerState, client: &mut ClientState) -> RespFrame {
+    client.reset(&server.pubsub);
+    if client.monitor {
+        client.monitor = false;
+        server.monitors.remove(client.id);
+    }
+    RespFrame::SimpleString("RESET".into())
+}
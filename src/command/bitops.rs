@@ -0,0 +1,207 @@
+use crate::propagate::Effects;
+use crate::protocol::{RespFrame, reply};
+use crate::store::{BitOp, StoreAccess, TypeError};
+
+use super::bulk_to_string;
+
+/// Largest bit offset accepted, keeping strings within Redis' 512MB cap.
+const MAX_BIT_OFFSET: u64 = 512 * 1024 * 1024 * 8 - 1;
+
+fn parse_offset(frame: &RespFrame) -> Option<usize> {
+    bulk_to_string(frame)
+        .and_then(|s| s.parse::<u64>().ok())
+        .filter(|&o| o <= MAX_BIT_OFFSET)
+        .map(|o| o as usize)
+}
+
+pub(super) fn handle_setbit(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    let Some(offset) = parse_offset(&args[1]) else {
+        return RespFrame::Error("ERR bit offset is not an integer or out of range".into());
+    };
+
+    let on = match bulk_to_string(&args[2]).as_deref() {
+        Some("0") => false,
+        Some("1") => true,
+        _ => return RespFrame::Error("ERR bit is not an integer or out of range".into()),
+    };
+
+    match store.write() {
+        Ok(mut guard) => match guard.setbit(&key, offset, on) {
+            Ok(old) => {
+                let bit = if on { "1" } else { "0" };
+                effects.push(&["SETBIT", &key, &offset.to_string(), bit]);
+                reply::int(old as i64)
+            }
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+pub(super) fn handle_getbit(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    let Some(offset) = parse_offset(&args[1]) else {
+        return RespFrame::Error("ERR bit offset is not an integer or out of range".into());
+    };
+
+    match store.write() {
+        Ok(mut guard) => {
+            if !guard.is_type(&key, "string") {
+                return reply::wrongtype();
+            }
+            reply::int(guard.getbit(&key, offset) as i64)
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+pub(super) fn handle_bitcount(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    // START without END is the one length the table can't rule out.
+    if args.len() == 2 {
+        return reply::wrong_args("bitcount");
+    }
+
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    let mut range = None;
+    let mut bit_unit = false;
+    if args.len() >= 3 {
+        let start = bulk_to_string(&args[1]).and_then(|s| s.parse::<i64>().ok());
+        let end = bulk_to_string(&args[2]).and_then(|s| s.parse::<i64>().ok());
+        match (start, end) {
+            (Some(s), Some(e)) => range = Some((s, e)),
+            _ => return RespFrame::Error("ERR value is not an integer or out of range".into()),
+        }
+        if let Some(unit) = args.get(3) {
+            match bulk_to_string(unit)
+                .map(|u| u.to_ascii_uppercase())
+                .as_deref()
+            {
+                Some("BYTE") => {}
+                Some("BIT") => bit_unit = true,
+                _ => return RespFrame::Error("ERR syntax error".into()),
+            }
+        }
+    }
+
+    match store.write() {
+        Ok(mut guard) => {
+            if !guard.is_type(&key, "string") {
+                return reply::wrongtype();
+            }
+            reply::int(guard.bitcount(&key, range, bit_unit) as i64)
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+pub(super) fn handle_bitpos(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    let bit = match bulk_to_string(&args[1]).as_deref() {
+        Some("0") => false,
+        Some("1") => true,
+        _ => return RespFrame::Error("ERR The bit argument must be 1 or 0.".into()),
+    };
+
+    let parse = |frame: &RespFrame| bulk_to_string(frame).and_then(|s| s.parse::<i64>().ok());
+    let mut range = None;
+    let mut bit_unit = false;
+    if let Some(start) = args.get(2) {
+        let end = match args.get(3) {
+            Some(end) => match parse(end) {
+                Some(e) => Some(e),
+                None => {
+                    return RespFrame::Error("ERR value is not an integer or out of range".into());
+                }
+            },
+            None => None,
+        };
+        match parse(start) {
+            Some(s) => range = Some((s, end)),
+            None => return RespFrame::Error("ERR value is not an integer or out of range".into()),
+        }
+        if let Some(unit) = args.get(4) {
+            match bulk_to_string(unit)
+                .map(|u| u.to_ascii_uppercase())
+                .as_deref()
+            {
+                Some("BYTE") => {}
+                Some("BIT") => bit_unit = true,
+                _ => return RespFrame::Error("ERR syntax error".into()),
+            }
+        }
+    }
+
+    match store.write() {
+        Ok(mut guard) => {
+            if !guard.is_type(&key, "string") {
+                return reply::wrongtype();
+            }
+            reply::int(guard.bitpos(&key, bit, range, bit_unit))
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// BITOP AND|OR|XOR|NOT destkey key [key ...]
+pub(super) fn handle_bitop(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let op = match bulk_to_string(&args[0])
+        .map(|op| op.to_ascii_uppercase())
+        .as_deref()
+    {
+        Some("AND") => BitOp::And,
+        Some("OR") => BitOp::Or,
+        Some("XOR") => BitOp::Xor,
+        Some("NOT") => BitOp::Not,
+        _ => return RespFrame::Error("ERR syntax error".into()),
+    };
+
+    let keys: Option<Vec<String>> = args[1..].iter().map(bulk_to_string).collect();
+    let Some(keys) = keys else {
+        return RespFrame::Error("ERR key must be bulk string".into());
+    };
+    let (dest, sources) = keys.split_first().expect("arity checked by the table");
+    if op == BitOp::Not && sources.len() != 1 {
+        return RespFrame::Error("ERR BITOP NOT must be called with a single source key.".into());
+    }
+
+    match store.write() {
+        Ok(mut guard) => {
+            if !sources.iter().all(|key| guard.is_type(key, "string")) {
+                return reply::wrongtype();
+            }
+            let result = guard.bitop(op, dest, sources);
+            if result.is_empty() {
+                effects.push(&["DEL", dest]);
+            } else {
+                effects.push_bytes(&[b"SET", dest.as_bytes(), &result]);
+            }
+            reply::int(result.len() as i64)
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
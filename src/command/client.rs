@@ -0,0 +1,82 @@
+use crate::protocol::{RespFrame, reply};
+use crate::server::client::{ClientState, KillFilter};
+use crate::server::state::ServerState;
+
+use super::bulk_to_string;
+
+const CLIENT_HELP: &[&str] = &[
+    "ID",
+    "    Return the ID of the current connection.",
+    "KILL <ip:port>",
+    "    Kill connection made from <ip:port>.",
+    "KILL <option> <value> [<option> <value> [...]]",
+    "    Kill connections. Options are:",
+    "    * ID <client-id>",
+    "      Kill connection by client id.",
+    "    * ADDR <ip:port>",
+    "      Kill connection made from <ip:port>.",
+    "    * SKIPME (YES|NO)",
+    "      Skip killing current connection (default: yes).",
+];
+
+/// CLIENT ID | KILL ...
+pub(super) fn handle_client(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    client: &ClientState,
+) -> RespFrame {
+    let Some(sub) = bulk_to_string(&args[0]) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+    match (sub.to_ascii_uppercase().as_str(), &args[1..]) {
+        ("ID", []) => reply::int(client.id as i64),
+        ("KILL", [addr]) => client_kill_addr(addr, server),
+        ("KILL", filters) if !filters.is_empty() => client_kill(filters, server, client),
+        ("HELP", []) => reply::help("CLIENT", CLIENT_HELP),
+        _ => reply::unknown_subcommand("CLIENT", &sub),
+    }
+}
+
+/// The old form, CLIENT KILL ip:port: one connection, answered with +OK.
+fn client_kill_addr(addr: &RespFrame, server: &ServerState) -> RespFrame {
+    let Some(addr) = bulk_to_string(addr) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+    let filter = KillFilter {
+        addr: Some(addr),
+        ..KillFilter::default()
+    };
+    match server.clients.kill(&filter) {
+        0 => RespFrame::Error("ERR No such client".into()),
+        _ => reply::ok(),
+    }
+}
+
+/// CLIENT KILL with filter/value pairs, replying with the number killed.
+fn client_kill(filters: &[RespFrame], server: &ServerState, client: &ClientState) -> RespFrame {
+    if !filters.len().is_multiple_of(2) {
+        return RespFrame::Error("ERR syntax error".into());
+    }
+    let mut filter = KillFilter {
+        skip: Some(client.id),
+        ..KillFilter::default()
+    };
+    for pair in filters.chunks_exact(2) {
+        let (Some(name), Some(value)) = (bulk_to_string(&pair[0]), bulk_to_string(&pair[1])) else {
+            return RespFrame::Error("ERR syntax error".into());
+        };
+        match name.to_ascii_uppercase().as_str() {
+            "ID" => match value.parse::<u64>() {
+                Ok(id) if id > 0 => filter.id = Some(id),
+                _ => {
+                    return RespFrame::Error("ERR client-id should be greater than 0".into());
+                }
+            },
+            "ADDR" => filter.addr = Some(value),
+            "SKIPME" if value.eq_ignore_ascii_case("YES") => filter.skip = Some(client.id),
+            "SKIPME" if value.eq_ignore_ascii_case("NO") => filter.skip = None,
+            _ => return RespFrame::Error("ERR syntax error".into()),
+        }
+    }
+    reply::int(server.clients.kill(&filter) as i64)
+}
@@ -0,0 +1,58 @@
+//! CLUSTER, answered as a standalone instance.
+//!
+//! Cluster-aware clients probe with CLUSTER INFO or CLUSTER SLOTS to decide
+//! whether to route by slot. These replies say cluster mode is off and own
+//! no slots, so such clients fall back to talking to this one node.
+
+use bytes::Bytes;
+
+use crate::protocol::{RespFrame, reply};
+use crate::server::state::ServerState;
+
+use super::bulk_to_string;
+
+const CLUSTER_HELP: &[&str] = &[
+    "INFO",
+    "    Return information about the cluster.",
+    "MYID",
+    "    Return the node id.",
+    "SHARDS",
+    "    Return information about slot range mappings and the nodes associated",
+    "    with them.",
+    "SLOTS",
+    "    Return information about slots range mappings. Each range is made of:",
+    "    start, end, master and replicas IP addresses, ports and ids",
+];
+
+/// CLUSTER INFO | MYID | SLOTS | SHARDS
+pub(super) fn handle_cluster(args: Vec<RespFrame>, server: &ServerState) -> RespFrame {
+    let Some(sub) = bulk_to_string(&args[0]) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+
+    match (sub.to_ascii_uppercase().as_str(), &args[1..]) {
+        ("INFO", []) => RespFrame::BulkString(Some(cluster_info().into())),
+        ("MYID", []) => RespFrame::BulkString(Some(Bytes::from(server.run_id.clone()))),
+        ("SLOTS" | "SHARDS", []) => RespFrame::Array(Some(Vec::new())),
+        ("HELP", []) => reply::help("CLUSTER", CLUSTER_HELP),
+        _ => reply::unknown_subcommand("CLUSTER", &sub),
+    }
+}
+
+/// CLUSTER INFO's fields for a node outside any cluster.
+fn cluster_info() -> String {
+    [
+        ("cluster_enabled", 0),
+        ("cluster_slots_assigned", 0),
+        ("cluster_slots_ok", 0),
+        ("cluster_slots_pfail", 0),
+        ("cluster_slots_fail", 0),
+        ("cluster_known_nodes", 1),
+        ("cluster_size", 0),
+        ("cluster_current_epoch", 0),
+        ("cluster_my_epoch", 0),
+    ]
+    .iter()
+    .map(|(field, value)| format!("{field}:{value}\r\n"))
+    .collect()
+}
@@ -0,0 +1,223 @@
+//! CONFIG GET / SET over the parameters that can change at runtime, and
+//! REWRITE to save them to the config file.
+
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+
+use bytes::Bytes;
+
+use crate::config::{self, InvalidMemory};
+use crate::protocol::{RespFrame, reply};
+use crate::server::state::ServerState;
+use crate::store::glob::glob_match;
+
+use super::bulk_to_string;
+
+/// Locates a parameter's value within the server state.
+#[derive(Clone, Copy)]
+enum Field {
+    Size(fn(&ServerState) -> &AtomicUsize),
+    Signed(fn(&ServerState) -> &AtomicI64),
+    /// A size that must lie within `min..=max`.
+    Bounded(fn(&ServerState) -> &AtomicUsize, usize, usize),
+    /// A byte count, also accepted with a unit such as `100mb`.
+    Memory(fn(&ServerState) -> &AtomicUsize),
+}
+
+impl Field {
+    fn get(self, server: &ServerState) -> String {
+        match self {
+            Field::Size(field) => field(server).load(Ordering::Relaxed).to_string(),
+            Field::Signed(field) => field(server).load(Ordering::Relaxed).to_string(),
+            Field::Bounded(field, ..) => field(server).load(Ordering::Relaxed).to_string(),
+            Field::Memory(field) => field(server).load(Ordering::Relaxed).to_string(),
+        }
+    }
+
+    /// Parse `value` for this parameter, to be applied once the whole
+    /// batch has been validated. Fails with the reason to report.
+    fn parse(self, value: &str) -> Result<Update, String> {
+        const NOT_INTEGER: &str = "argument couldn't be parsed into an integer";
+        Ok(match self {
+            Field::Size(field) => Update::Size(field, value.parse().map_err(|_| NOT_INTEGER)?),
+            Field::Signed(field) => Update::Signed(field, value.parse().map_err(|_| NOT_INTEGER)?),
+            Field::Bounded(field, min, max) => {
+                let n: usize = value.parse().map_err(|_| NOT_INTEGER)?;
+                if !(min..=max).contains(&n) {
+                    return Err(format!(
+                        "argument must be between {min} and {max} inclusive"
+                    ));
+                }
+                Update::Size(field, n)
+            }
+            Field::Memory(field) => {
+                let bytes = config::parse_memory(value).map_err(|e| e.to_string())?;
+                let bytes = usize::try_from(bytes).map_err(|_| InvalidMemory.to_string())?;
+                Update::Size(field, bytes)
+            }
+        })
+    }
+}
+
+/// A parsed value and the field it goes to.
+enum Update {
+    Size(fn(&ServerState) -> &AtomicUsize, usize),
+    Signed(fn(&ServerState) -> &AtomicI64, i64),
+}
+
+impl Update {
+    fn apply(self, server: &ServerState) {
+        match self {
+            Update::Size(field, value) => field(server).store(value, Ordering::Relaxed),
+            Update::Signed(field, value) => field(server).store(value, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Runtime-settable parameters, by their Redis names.
+const PARAMS: &[(&str, Field)] = &[
+    (
+        "hash-max-listpack-entries",
+        Field::Size(|s| &s.encoding.hash_max_listpack_entries),
+    ),
+    (
+        "hash-max-listpack-value",
+        Field::Size(|s| &s.encoding.hash_max_listpack_value),
+    ),
+    (
+        "set-max-intset-entries",
+        Field::Size(|s| &s.encoding.set_max_intset_entries),
+    ),
+    (
+        "set-max-listpack-entries",
+        Field::Size(|s| &s.encoding.set_max_listpack_entries),
+    ),
+    (
+        "set-max-listpack-value",
+        Field::Size(|s| &s.encoding.set_max_listpack_value),
+    ),
+    (
+        "zset-max-listpack-entries",
+        Field::Size(|s| &s.encoding.zset_max_listpack_entries),
+    ),
+    (
+        "zset-max-listpack-value",
+        Field::Size(|s| &s.encoding.zset_max_listpack_value),
+    ),
+    (
+        "list-max-listpack-size",
+        Field::Signed(|s| &s.encoding.list_max_listpack_size),
+    ),
+    ("maxmemory", Field::Memory(|s| &s.maxmemory.limit)),
+    ("hz", Field::Bounded(|s| &s.hz, 1, 500)),
+    (
+        "active-expire-effort",
+        Field::Bounded(|s| &s.active_expire_effort, 1, 10),
+    ),
+];
+
+fn param(name: &str) -> Option<Field> {
+    PARAMS
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, field)| *field)
+}
+
+const CONFIG_HELP: &[&str] = &[
+    "GET <pattern> [<pattern> ...]",
+    "    Return parameters matching the glob-like <pattern>s and their values.",
+    "SET <directive> <value> [<directive> <value> ...]",
+    "    Set the configuration <directive>s to <value>s.",
+    "REWRITE",
+    "    Rewrite the configuration file.",
+];
+
+/// CONFIG GET pattern [pattern ...] | SET parameter value [parameter value ...]
+/// | REWRITE
+pub(super) fn handle_config(args: Vec<RespFrame>, server: &ServerState) -> RespFrame {
+    let Some(sub) = bulk_to_string(&args[0]) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+
+    match (sub.to_ascii_uppercase().as_str(), &args[1..]) {
+        ("GET", patterns @ [_, ..]) => config_get(patterns, server),
+        ("SET", pairs @ [_, _, ..]) if pairs.len().is_multiple_of(2) => config_set(pairs, server),
+        ("REWRITE", []) => config_rewrite(server),
+        ("HELP", []) => reply::help("CONFIG", CONFIG_HELP),
+        _ => reply::unknown_subcommand("CONFIG", &sub),
+    }
+}
+
+/// Name/value pairs of every parameter matching any of `patterns`.
+fn config_get(patterns: &[RespFrame], server: &ServerState) -> RespFrame {
+    let mut patterns_lower = Vec::with_capacity(patterns.len());
+    for p in patterns {
+        match bulk_to_string(p) {
+            Some(p) => patterns_lower.push(p.to_ascii_lowercase()),
+            None => return RespFrame::Error("ERR syntax error".into()),
+        }
+    }
+
+    let mut frames = Vec::new();
+    for (name, field) in PARAMS {
+        if patterns_lower
+            .iter()
+            .any(|p| glob_match(p.as_bytes(), name.as_bytes()))
+        {
+            let value = field.get(server);
+            frames.push(RespFrame::BulkString(Some(Bytes::from_static(
+                name.as_bytes(),
+            ))));
+            frames.push(RespFrame::BulkString(Some(Bytes::from(value))));
+        }
+    }
+    RespFrame::Array(Some(frames))
+}
+
+/// Apply every pair, or none of them if any is invalid.
+fn config_set(pairs: &[RespFrame], server: &ServerState) -> RespFrame {
+    let mut updates = Vec::with_capacity(pairs.len() / 2);
+    for pair in pairs.chunks(2) {
+        let name = bulk_to_string(&pair[0]).unwrap_or_default();
+        let Some(field) = param(&name) else {
+            return RespFrame::Error(format!(
+                "ERR Unknown option or number of arguments for CONFIG SET - '{name}'"
+            ));
+        };
+        let value = bulk_to_string(&pair[1]).unwrap_or_default();
+        match field.parse(&value) {
+            Ok(update) => updates.push(update),
+            Err(reason) => {
+                return RespFrame::Error(format!(
+                    "ERR CONFIG SET failed (possibly related to argument '{name}') - {reason}"
+                ));
+            }
+        }
+    }
+
+    for update in updates {
+        update.apply(server);
+    }
+    reply::ok()
+}
+
+/// Save every runtime parameter's current value to the config file the
+/// server started with.
+fn config_rewrite(server: &ServerState) -> RespFrame {
+    let Some(path) = &server.config_file else {
+        return RespFrame::Error("ERR The server is running without a config file".into());
+    };
+    let settings: Vec<(&str, String)> = PARAMS
+        .iter()
+        .map(|(name, field)| (*name, field.get(server)))
+        .collect();
+    match config::rewrite_config_file(path, &settings) {
+        Ok(()) => {
+            tracing::info!(path = %path.display(), "CONFIG REWRITE executed with success");
+            reply::ok()
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, path = %path.display(), "CONFIG REWRITE failed");
+            RespFrame::Error(format!("ERR Rewriting config file: {e}"))
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! DEBUG subcommands.
+//!
+//! Compatibility test suites and some clients issue DEBUG subcommands this
+//! server has no meaningful implementation for. Rather than failing those
+//! runs early, the subcommands in [`NOOP_SUBCOMMANDS`] are accepted and
+//! answered with `+OK` without doing anything:
+//!
+//! - `QUICKLIST-PACKED-THRESHOLD` — lists have no packed node encoding.
+//!
+//! `STRINGMATCH-LEN pattern string` runs the glob matcher used by MATCH
+//! options and reports `:1` or `:0`, so it can be tested over the protocol.
+//! `RELOAD` rewrites the AOF from the current dataset and loads it back,
+//! which exercises persistence round-trips. `OBJECT` describes how a key is
+//! stored. `SET-ACTIVE-EXPIRE 0|1` pauses or resumes background eviction of
+//! expired keys. `CHANGE-REPL-ID` gives the server a new replication id.
+//! `POPULATE count [prefix] [size]` fills the keyspace for benchmarking;
+//! like in Redis, the keys it creates are neither written to the AOF nor
+//! sent to replicas. `LISTPACK-ENTRIES key` counts a list's elements, which
+//! should always agree with LLEN, and the unlisted `SELF-TEST` checks the
+//! store's internal invariants. Any other subcommand is still an error.
+//!
+//! `SLEEP seconds` stands in for Redis stalling its single thread. Here
+//! connections run in parallel, so instead it holds the store's write lock
+//! for the duration: every other command that reads or writes keys waits it
+//! out, while commands that never touch the store (PING, CLIENT, ...) still
+//! answer.
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use crate::persistence::aof;
+use crate::protocol::{RespFrame, reply};
+use crate::server::state::ServerState;
+use crate::store::glob::glob_match;
+use crate::store::value::Value;
+use crate::store::{Database, StoreAccess};
+
+use super::bulk_to_string;
+
+/// Subcommands accepted as no-ops for compatibility.
+const NOOP_SUBCOMMANDS: &[&str] = &["QUICKLIST-PACKED-THRESHOLD"];
+
+const DEBUG_HELP: &[&str] = &[
+    "OBJECT <key>",
+    "    Show low level info about the <key> and associated value.",
+    "RELOAD",
+    "    Rewrite the AOF from the current dataset and load it back.",
+    "SET-ACTIVE-EXPIRE <0|1>",
+    "    Setting it to 0 disables expiring keys in background when they are not",
+    "    accessed (otherwise the Redis behavior). Setting it to 1 reenables back the",
+    "    default.",
+    "POPULATE <count> [<prefix>] [<size>]",
+    "    Create <count> string keys named key:<num>. If <prefix> is specified it is",
+    "    used instead of the 'key' prefix. The value is 'value:<num>', padded or",
+    "    truncated to <size> bytes if given. Existing keys are left alone, and the",
+    "    new keys are not persisted or replicated.",
+    "CHANGE-REPL-ID",
+    "    Change the replication id reported by INFO replication.",
+    "LISTPACK-ENTRIES <key>",
+    "    Return the number of elements stored in the list at <key>.",
+    "STRINGMATCH-LEN <pattern> <string>",
+    "    Return 1 if the glob-style <pattern> matches <string>, 0 otherwise.",
+    "QUICKLIST-PACKED-THRESHOLD",
+    "    Accepted for compatibility; does nothing.",
+    "SLEEP <seconds>",
+    "    Hold the keyspace for <seconds>, blocking other clients' commands on it.",
+    "    Decimals allowed.",
+];
+
+pub(super) fn handle_debug(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    store: &StoreAccess,
+) -> RespFrame {
+    let Some(sub) = bulk_to_string(&args[0]) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+
+    let upper = sub.to_ascii_uppercase();
+    if NOOP_SUBCOMMANDS.contains(&upper.as_str()) {
+        return reply::ok();
+    }
+    match (upper.as_str(), &args[1..]) {
+        ("RELOAD", []) => return debug_reload(server, store),
+        ("OBJECT", [key]) => return debug_object(key, server, store),
+        ("LISTPACK-ENTRIES", [key]) => return debug_listpack_entries(key, store),
+        ("SELF-TEST", []) => return debug_self_test(store),
+        ("SET-ACTIVE-EXPIRE", [flag]) => return debug_set_active_expire(flag, server),
+        ("SLEEP", [seconds]) => return debug_sleep(seconds, store),
+        ("STRINGMATCH-LEN", [pattern, s]) => return debug_stringmatch_len(pattern, s),
+        ("POPULATE", [count, rest @ ..]) if rest.len() <= 2 => {
+            return debug_populate(count, rest, store);
+        }
+        ("HELP", []) => return reply::help("DEBUG", DEBUG_HELP),
+        ("CHANGE-REPL-ID", _) => {
+            server.replication.change_replid();
+            return reply::ok();
+        }
+        _ => {}
+    }
+
+    RespFrame::Error(format!("ERR unknown subcommand '{sub}'. Try DEBUG HELP."))
+}
+
+fn debug_stringmatch_len(pattern: &RespFrame, s: &RespFrame) -> RespFrame {
+    match (pattern, s) {
+        (RespFrame::BulkString(Some(pattern)), RespFrame::BulkString(Some(s))) => {
+            reply::int(glob_match(pattern, s) as i64)
+        }
+        _ => RespFrame::Error("ERR syntax error".into()),
+    }
+}
+
+/// Hold the store's write lock for `seconds`. The guard borrows the lock, so
+/// it can't move to a `spawn_blocking` task; `block_in_place` instead hands
+/// this worker's other tasks to another thread while it sleeps.
+fn debug_sleep(seconds: &RespFrame, store: &StoreAccess) -> RespFrame {
+    let Some(duration) = bulk_to_string(seconds)
+        .and_then(|s| s.parse::<f64>().ok())
+        .and_then(|s| Duration::try_from_secs_f64(s).ok())
+    else {
+        return RespFrame::Error("ERR value is not a valid float".into());
+    };
+    let guard = match store.write() {
+        Ok(g) => g,
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    tokio::task::block_in_place(|| std::thread::sleep(duration));
+    drop(guard);
+    reply::ok()
+}
+
+/// Insert `count` string keys under one write lock. They bypass `Effects`,
+/// so a restart or a replica never sees them.
+fn debug_populate(count: &RespFrame, rest: &[RespFrame], store: &StoreAccess) -> RespFrame {
+    const OUT_OF_RANGE: &str = "ERR value is out of range, must be positive";
+    let Some(count) = bulk_to_string(count).and_then(|s| s.parse::<u64>().ok()) else {
+        return RespFrame::Error(OUT_OF_RANGE.into());
+    };
+    let Some(prefix) = rest.first().map_or(Some("key".into()), bulk_to_string) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+    let size = match rest.get(1) {
+        Some(size) => match bulk_to_string(size).and_then(|s| s.parse::<usize>().ok()) {
+            Some(size) => Some(size),
+            None => return RespFrame::Error(OUT_OF_RANGE.into()),
+        },
+        None => None,
+    };
+
+    let mut guard = match store.write() {
+        Ok(g) => g,
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    for n in 0..count {
+        let key = format!("{prefix}:{n}");
+        if guard.exists(std::slice::from_ref(&key)) > 0 {
+            continue;
+        }
+        guard.set(key, Value::String(populate_value(n, size)));
+    }
+    reply::ok()
+}
+
+/// `value:<n>`, zero-padded or truncated to `size` bytes when one is given.
+fn populate_value(n: u64, size: Option<usize>) -> Bytes {
+    let text = format!("value:{n}");
+    let Some(size) = size else {
+        return Bytes::from(text);
+    };
+    let mut value = BytesMut::with_capacity(size);
+    value.put_slice(&text.as_bytes()[..text.len().min(size)]);
+    value.resize(size, 0);
+    value.freeze()
+}
+
+/// Rewrite the AOF and replace the dataset with what loads back from it.
+/// The write lock is held throughout so no command observes a half-loaded
+/// store, and so is the order lock (unless EXEC already holds it), so a
+/// write that has changed the store but not yet reached the AOF can't be
+/// both in the rewrite and appended after it.
+fn debug_reload(server: &ServerState, store: &StoreAccess) -> RespFrame {
+    let Some(writer) = server.aof.as_ref() else {
+        return RespFrame::Error("ERR DEBUG RELOAD requires AOF persistence".into());
+    };
+    let _order = (!store.is_held()).then(|| server.replication.lock_order());
+    let mut guard = match store.write() {
+        Ok(g) => g,
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+
+    if let Err(e) = writer.rewrite(&guard) {
+        return RespFrame::Error(format!("ERR error rewriting AOF: {e}"));
+    }
+    let mut reloaded = Database::new();
+    if let Err(e) = aof::load_aof(&writer.path(), &mut reloaded) {
+        return RespFrame::Error(format!("ERR error loading AOF: {e}"));
+    }
+    guard.replace_contents(reloaded);
+    reply::ok()
+}
+
+fn debug_listpack_entries(key: &RespFrame, store: &StoreAccess) -> RespFrame {
+    let Some(key) = bulk_to_string(key) else {
+        return RespFrame::Error("ERR key must be bulk string".into());
+    };
+    let mut guard = match store.write() {
+        Ok(g) => g,
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    match guard.inspect(&key) {
+        Some(Value::List(items)) => reply::int(items.len() as i64),
+        Some(_) => reply::wrongtype(),
+        None => RespFrame::Error("ERR no such key".into()),
+    }
+}
+
+fn debug_self_test(store: &StoreAccess) -> RespFrame {
+    let guard = match store.read() {
+        Ok(g) => g,
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    match guard.verify_invariants() {
+        Ok(()) => reply::ok(),
+        Err(problem) => RespFrame::Error(format!("ERR self-test failed: {problem}")),
+    }
+}
+
+fn debug_set_active_expire(flag: &RespFrame, server: &ServerState) -> RespFrame {
+    let enabled = match bulk_to_string(flag).as_deref() {
+        Some("0") => false,
+        Some("1") => true,
+        _ => return RespFrame::Error("ERR syntax error".into()),
+    };
+    server.active_expire.store(enabled, Ordering::Relaxed);
+    reply::ok()
+}
+
+/// Redis-style `key:value` description of how `key` is stored.
+fn debug_object(key: &RespFrame, server: &ServerState, store: &StoreAccess) -> RespFrame {
+    let Some(key) = bulk_to_string(key) else {
+        return RespFrame::Error("ERR key must be bulk string".into());
+    };
+    let guard = match store.read() {
+        Ok(g) => g,
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    match guard.debug_object(&key, &server.encoding) {
+        Some(info) => RespFrame::SimpleString(info),
+        None => RespFrame::Error("ERR no such key".into()),
+    }
+}
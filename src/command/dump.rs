@@ -0,0 +1,115 @@
+//! DUMP / RESTORE: a key's value as an opaque, checksummed payload; see
+//! [`serial`] for the format.
+
+use std::time::Instant;
+
+use bytes::Bytes;
+
+use crate::persistence::serial::{self, DumpError};
+use crate::propagate::Effects;
+use crate::protocol::{RespFrame, reply};
+use crate::store::StoreAccess;
+use crate::store::expire::unix_millis_from_instant;
+
+use super::string::expire_deadline;
+use super::{bulk_to_bytes, bulk_to_string};
+
+/// DUMP key
+pub(super) fn handle_dump(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    match store.write() {
+        Ok(mut guard) => match guard.inspect(&key) {
+            Some(value) => RespFrame::BulkString(Some(Bytes::from(serial::dump(value)))),
+            None => reply::nil(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// RESTORE key ttl serialized-value [REPLACE] [ABSTTL]
+///
+/// Replicated with an absolute TTL and REPLACE, so a replay lands on the
+/// same deadline whatever the key held by then.
+pub(super) fn handle_restore(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let Some(payload) = bulk_to_bytes(&args[2]) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+    let (mut replace, mut absttl) = (false, false);
+    for opt in &args[3..] {
+        match bulk_to_string(opt)
+            .map(|o| o.to_ascii_uppercase())
+            .as_deref()
+        {
+            Some("REPLACE") => replace = true,
+            Some("ABSTTL") => absttl = true,
+            _ => return RespFrame::Error("ERR syntax error".into()),
+        }
+    }
+    let Some(ttl) = bulk_to_string(&args[1]).and_then(|s| s.parse::<i64>().ok()) else {
+        return RespFrame::Error("ERR value is not an integer or out of range".into());
+    };
+
+    let mut guard = match store.write() {
+        Ok(g) => g,
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    if !replace && guard.exists(std::slice::from_ref(&key)) > 0 {
+        return RespFrame::Error("BUSYKEY Target key name already exists.".into());
+    }
+    if ttl < 0 {
+        return RespFrame::Error("ERR Invalid TTL value, must be >= 0".into());
+    }
+    let value = match serial::restore(&payload) {
+        Ok(value) => value,
+        Err(DumpError::Footer) => {
+            return RespFrame::Error("ERR DUMP payload version or checksum are wrong".into());
+        }
+        Err(DumpError::Format) => return RespFrame::Error("ERR Bad data format".into()),
+    };
+
+    let now = Instant::now();
+    let deadline = match ttl {
+        0 => None,
+        ms => match expire_deadline(ms, true, absttl, now) {
+            Some(deadline) => Some(deadline),
+            None => return RespFrame::Error("ERR invalid expire time in 'restore'".into()),
+        },
+    };
+    match deadline {
+        // Already past: the key is deleted rather than restored, as in Redis.
+        Some(deadline) if deadline <= now => {
+            if guard.del(std::slice::from_ref(&key)) > 0 {
+                effects.push(&["DEL", &key]);
+            }
+        }
+        Some(deadline) => {
+            guard.set_with_deadline(key.clone(), value, deadline);
+            let at = unix_millis_from_instant(deadline).to_string();
+            effects.push_bytes(&[
+                b"RESTORE",
+                key.as_bytes(),
+                at.as_bytes(),
+                &payload,
+                b"REPLACE",
+                b"ABSTTL",
+            ]);
+        }
+        None => {
+            guard.set(key.clone(), value);
+            effects.push_bytes(&[b"RESTORE", key.as_bytes(), b"0", &payload, b"REPLACE"]);
+        }
+    }
+    reply::ok()
+}
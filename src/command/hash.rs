@@ -1,16 +1,23 @@
-use crate::persistence::aof::AofWriter;
-use crate::protocol::RespFrame;
-use crate::store::SharedStore;
+use std::time::Instant;
 
-use super::{bulk_to_bytes, bulk_to_string};
+use bytes::Bytes;
+
+use crate::propagate::Effects;
+use crate::protocol::{RespFrame, Sample, reply};
+use crate::store::expire::unix_millis_from_instant;
+use crate::store::number::{IncrError, parse_i64};
+use crate::store::{FieldTtl, StoreAccess, TypeError, random};
+
+use super::string::{expire_deadline, parse_expire_condition};
+use super::{bulk_to_bytes, bulk_to_string, parse_randfield_args};
 
 pub(super) fn handle_hset(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.len() < 3 || !(args.len() - 1).is_multiple_of(2) {
-        return RespFrame::Error("ERR wrong number of arguments for 'hset'".into());
+    if !(args.len() - 1).is_multiple_of(2) {
+        return reply::wrong_args("hset");
     }
 
     let key = match bulk_to_string(&args[0]) {
@@ -19,7 +26,7 @@ pub(super) fn handle_hset(
     };
 
     let mut fields = Vec::with_capacity((args.len() - 1) / 2);
-    let mut field_strs: Vec<String> = Vec::new();
+    let mut propagated: Vec<Bytes> = Vec::with_capacity(args.len() - 1);
     let mut i = 1;
     while i < args.len() {
         let field = match bulk_to_bytes(&args[i]) {
@@ -30,37 +37,63 @@ pub(super) fn handle_hset(
             Some(b) => b,
             None => return RespFrame::Error("ERR value must be bulk string".into()),
         };
-        field_strs.push(String::from_utf8_lossy(&field).into_owned());
-        field_strs.push(String::from_utf8_lossy(&value).into_owned());
+        propagated.push(field.clone());
+        propagated.push(value.clone());
         fields.push((field, value));
         i += 2;
     }
 
     match store.write() {
         Ok(mut guard) => {
-            if !guard.is_type(&key, "hash") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            let added = guard.hset(key.clone(), fields);
-            if let Some(w) = aof {
-                let mut a: Vec<String> = vec!["HSET".into(), key];
-                a.extend(field_strs);
-                let refs: Vec<&str> = a.iter().map(|s| s.as_str()).collect();
-                w.append(&refs);
-            }
-            RespFrame::Integer(added as i64)
+            let Ok(added) = guard.hset(key.clone(), fields) else {
+                return reply::wrongtype();
+            };
+            let mut a: Vec<&[u8]> = vec![b"HSET", key.as_bytes()];
+            a.extend(propagated.iter().map(|b| b.as_ref()));
+            effects.push_bytes(&a);
+            reply::int(added as i64)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-pub(super) fn handle_hget(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() != 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'hget'".into());
+/// HINCRBY key field increment
+pub(super) fn handle_hincrby(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let field = match bulk_to_bytes(&args[1]) {
+        Some(b) => b,
+        None => return RespFrame::Error("ERR field must be bulk string".into()),
+    };
+    let Some(delta) = bulk_to_bytes(&args[2]).and_then(|b| parse_i64(&b)) else {
+        return RespFrame::Error("ERR value is not an integer or out of range".into());
+    };
+
+    let result = match store.write() {
+        Ok(mut guard) => guard.hincrby(key.clone(), field.clone(), delta),
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    match result {
+        Ok(n) => {
+            let delta = delta.to_string();
+            effects.push_bytes(&[b"HINCRBY", key.as_bytes(), &field, delta.as_bytes()]);
+            reply::int(n)
+        }
+        Err(IncrError::WrongType) => reply::wrongtype(),
+        Err(IncrError::NotInteger) => RespFrame::Error("ERR hash value is not an integer".into()),
+        Err(IncrError::Overflow) => {
+            RespFrame::Error("ERR increment or decrement would overflow".into())
+        }
     }
+}
 
+pub(super) fn handle_hget(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -71,46 +104,397 @@ pub(super) fn handle_hget(args: Vec<RespFrame>, store: &SharedStore) -> RespFram
         None => return RespFrame::Error("ERR field must be bulk string".into()),
     };
 
+    match store.read() {
+        Ok(guard) => match guard.hget(&key, &field) {
+            Ok(Some(b)) => RespFrame::BulkString(Some(b)),
+            Ok(None) => reply::nil(),
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+pub(super) fn handle_hgetall(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    match store.read() {
+        Ok(guard) => {
+            let Ok(pairs) = guard.hgetall(&key) else {
+                return reply::wrongtype();
+            };
+            let mut items = Vec::new();
+            for (k, v) in pairs {
+                items.push(RespFrame::BulkString(Some(k.clone())));
+                items.push(RespFrame::BulkString(Some(v.clone())));
+            }
+            RespFrame::Array(Some(items))
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// HRANDFIELD key [count [WITHVALUES]]
+pub(super) fn handle_hrandfield(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let (count, with_values) = match parse_randfield_args(&args[1..], "WITHVALUES") {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+
     match store.read() {
         Ok(guard) => {
-            if !guard.is_type(&key, "hash") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
+            // A negative count draws with repeats, from every field.
+            let distinct = match count {
+                Some(n) => usize::try_from(n).unwrap_or(usize::MAX),
+                None => 1,
+            };
+            let Ok(pairs) = guard.hrandfield(&key, distinct) else {
+                return reply::wrongtype();
+            };
+            let Some(count) = count else {
+                return RespFrame::BulkString(pairs.into_iter().next().map(|(field, _)| field));
+            };
+            let mut frames = Vec::new();
+            for (field, value) in pairs {
+                frames.push(RespFrame::BulkString(Some(field)));
+                if with_values {
+                    frames.push(RespFrame::BulkString(Some(value)));
+                }
             }
-            match guard.hget(&key, &field) {
-                Some(b) => RespFrame::BulkString(Some(b)),
-                None => RespFrame::BulkString(None),
+            if count < 0 {
+                let group = if with_values { 2 } else { 1 };
+                let picks = count.unsigned_abs() as usize;
+                return RespFrame::Sample(Sample::new(frames, group, picks, random::next_u64()));
+            }
+            RespFrame::Array(Some(frames))
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// HDEL key field [field ...]
+pub(super) fn handle_hdel(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let mut fields = Vec::with_capacity(args.len() - 1);
+    for arg in &args[1..] {
+        match bulk_to_bytes(arg) {
+            Some(b) => fields.push(b),
+            None => return RespFrame::Error("ERR field must be bulk string".into()),
+        }
+    }
+
+    match store.write() {
+        Ok(mut guard) => {
+            let Ok(removed) = guard.hdel(&key, &fields) else {
+                return reply::wrongtype();
+            };
+            if removed > 0 {
+                let mut a: Vec<&[u8]> = vec![b"HDEL", key.as_bytes()];
+                a.extend(fields.iter().map(|f| f.as_ref()));
+                effects.push_bytes(&a);
             }
+            reply::int(removed as i64)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-pub(super) fn handle_hgetall(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() != 1 {
-        return RespFrame::Error("ERR wrong number of arguments for 'hgetall'".into());
+/// The fields named by a trailing `FIELDS numfields field [field ...]`.
+fn parse_fields(args: &[RespFrame]) -> Result<Vec<Bytes>, RespFrame> {
+    let [keyword, numfields, fields @ ..] = args else {
+        return Err(missing_fields());
+    };
+    if !bulk_to_string(keyword).is_some_and(|k| k.eq_ignore_ascii_case("FIELDS")) {
+        return Err(missing_fields());
+    }
+    let Some(n) = bulk_to_string(numfields).and_then(|s| s.parse::<i64>().ok()) else {
+        return Err(RespFrame::Error(
+            "ERR value is not an integer or out of range".into(),
+        ));
+    };
+    if n <= 0 {
+        return Err(RespFrame::Error(
+            "ERR Parameter `numFields` should be greater than 0".into(),
+        ));
     }
+    if n as usize != fields.len() {
+        return Err(RespFrame::Error(
+            "ERR The `numfields` parameter must match the number of arguments".into(),
+        ));
+    }
+    fields
+        .iter()
+        .map(|f| {
+            bulk_to_bytes(f).ok_or_else(|| RespFrame::Error("ERR field must be bulk string".into()))
+        })
+        .collect()
+}
 
+fn missing_fields() -> RespFrame {
+    RespFrame::Error("ERR Mandatory argument FIELDS is missing or not at the right position".into())
+}
+
+fn int_array(codes: Vec<i64>) -> RespFrame {
+    RespFrame::Array(Some(codes.into_iter().map(reply::int).collect()))
+}
+
+fn bulk_array(values: Vec<Option<Bytes>>) -> RespFrame {
+    RespFrame::Array(Some(
+        values.into_iter().map(RespFrame::BulkString).collect(),
+    ))
+}
+
+/// The fields that were present, judging by the values read for them.
+fn present<'a>(fields: &'a [Bytes], values: &[Option<Bytes>]) -> Vec<&'a Bytes> {
+    fields
+        .iter()
+        .zip(values)
+        .filter(|(_, v)| v.is_some())
+        .map(|(f, _)| f)
+        .collect()
+}
+
+/// HGETDEL key FIELDS numfields field [field ...]. The removed fields
+/// propagate as an HDEL.
+pub(super) fn handle_hgetdel(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
     };
+    let fields = match parse_fields(&args[1..]) {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
 
-    match store.read() {
-        Ok(guard) => {
-            if !guard.is_type(&key, "hash") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
+    match store.write() {
+        Ok(mut guard) => {
+            let Ok(values) = guard.hgetdel(&key, &fields) else {
+                return reply::wrongtype();
+            };
+            let removed = present(&fields, &values);
+            if !removed.is_empty() {
+                let mut a: Vec<&[u8]> = vec![b"HDEL", key.as_bytes()];
+                a.extend(removed.iter().map(|f| f.as_ref()));
+                effects.push_bytes(&a);
             }
-            let pairs = guard.hgetall(&key);
-            let mut items = Vec::with_capacity(pairs.len() * 2);
-            for (k, v) in pairs {
-                items.push(RespFrame::BulkString(Some(k)));
-                items.push(RespFrame::BulkString(Some(v)));
+            bulk_array(values)
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// HGETEX key [EX seconds | PX ms | EXAT ts | PXAT ts-ms | PERSIST]
+/// FIELDS numfields field [field ...]. A new deadline propagates as an
+/// HPEXPIREAT and PERSIST as an HPERSIST, both naming the fields that
+/// exist.
+pub(super) fn handle_hgetex(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let opt = bulk_to_string(&args[1]).map(|o| o.to_ascii_uppercase());
+    let (ttl, rest) = match opt.as_deref() {
+        Some("PERSIST") => (Some(FieldTtl::Persist), &args[2..]),
+        Some(unit @ ("EX" | "PX" | "EXAT" | "PXAT")) => {
+            let n = args.get(2).and_then(bulk_to_string);
+            let deadline = n
+                .and_then(|s| s.parse::<i64>().ok())
+                .filter(|n| *n > 0)
+                .and_then(|n| {
+                    let millis = unit.starts_with('P');
+                    expire_deadline(n, millis, unit.ends_with("AT"), Instant::now())
+                });
+            let Some(deadline) = deadline else {
+                return RespFrame::Error("ERR invalid expire time in 'hgetex'".into());
+            };
+            (Some(FieldTtl::At(deadline)), &args[3..])
+        }
+        _ => (None, &args[1..]),
+    };
+    let fields = match parse_fields(rest) {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
+
+    match store.write() {
+        Ok(mut guard) => {
+            let Ok(values) = guard.hgetex(&key, &fields, ttl) else {
+                return reply::wrongtype();
+            };
+            let changed = present(&fields, &values);
+            if let Some(ttl) = ttl
+                && !changed.is_empty()
+            {
+                let n = changed.len().to_string();
+                let at;
+                let mut a: Vec<&[u8]> = match ttl {
+                    FieldTtl::At(deadline) => {
+                        at = unix_millis_from_instant(deadline).to_string();
+                        let at = at.as_bytes();
+                        vec![b"HPEXPIREAT", key.as_bytes(), at, b"FIELDS", n.as_bytes()]
+                    }
+                    FieldTtl::Persist => {
+                        vec![b"HPERSIST", key.as_bytes(), b"FIELDS", n.as_bytes()]
+                    }
+                };
+                a.extend(changed.iter().map(|f| f.as_ref()));
+                effects.push_bytes(&a);
             }
-            RespFrame::Array(Some(items))
+            bulk_array(values)
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// HEXPIRE key seconds [NX | XX | GT | LT] FIELDS numfields field [field ...],
+/// and its PEXPIRE/EXPIREAT/PEXPIREAT variants. Fields whose deadline was
+/// set or that were deleted propagate as an HPEXPIREAT.
+pub(super) fn handle_hexpire(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+    millis: bool,
+    absolute: bool,
+) -> RespFrame {
+    let cmd = match (millis, absolute) {
+        (false, false) => "hexpire",
+        (true, false) => "hpexpire",
+        (false, true) => "hexpireat",
+        (true, true) => "hpexpireat",
+    };
+
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let Some(n) = bulk_to_string(&args[1]).and_then(|s| s.parse::<i64>().ok()) else {
+        return RespFrame::Error("ERR value is not an integer or out of range".into());
+    };
+    let fields_at = args[2..]
+        .iter()
+        .position(|a| bulk_to_string(a).is_some_and(|s| s.eq_ignore_ascii_case("FIELDS")))
+        .map_or(args.len(), |i| i + 2);
+    let cond = match parse_expire_condition(&args[2..fields_at]) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+    let fields = match parse_fields(&args[fields_at..]) {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
+    let Some(deadline) = expire_deadline(n, millis, absolute, Instant::now()) else {
+        return RespFrame::Error(format!("ERR invalid expire time in '{cmd}'"));
+    };
+
+    match store.write() {
+        Ok(mut guard) => {
+            let Ok(codes) = guard.hexpire(&key, &fields, deadline, cond) else {
+                return reply::wrongtype();
+            };
+            let changed: Vec<&Bytes> = fields
+                .iter()
+                .zip(&codes)
+                .filter(|(_, code)| matches!(code, 1 | 2))
+                .map(|(f, _)| f)
+                .collect();
+            if !changed.is_empty() {
+                let at = unix_millis_from_instant(deadline).to_string();
+                let n = changed.len().to_string();
+                let mut a: Vec<&[u8]> = vec![
+                    b"HPEXPIREAT",
+                    key.as_bytes(),
+                    at.as_bytes(),
+                    b"FIELDS",
+                    n.as_bytes(),
+                ];
+                a.extend(changed.iter().map(|f| f.as_ref()));
+                effects.push_bytes(&a);
+            }
+            int_array(codes)
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// HTTL / HPTTL key FIELDS numfields field [field ...]
+pub(super) fn handle_httl(args: Vec<RespFrame>, store: &StoreAccess, millis: bool) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let fields = match parse_fields(&args[1..]) {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
+
+    match store.read() {
+        Ok(guard) => match guard.httl_millis(&key, &fields) {
+            Ok(ttls) if millis => int_array(ttls),
+            Ok(ttls) => int_array(
+                ttls.into_iter()
+                    .map(|ms| if ms < 0 { ms } else { ms / 1000 })
+                    .collect(),
+            ),
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// HPERSIST key FIELDS numfields field [field ...]
+pub(super) fn handle_hpersist(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let fields = match parse_fields(&args[1..]) {
+        Ok(f) => f,
+        Err(e) => return e,
+    };
+
+    match store.write() {
+        Ok(mut guard) => {
+            let Ok(codes) = guard.hpersist(&key, &fields) else {
+                return reply::wrongtype();
+            };
+            let persisted: Vec<&Bytes> = fields
+                .iter()
+                .zip(&codes)
+                .filter(|(_, code)| **code == 1)
+                .map(|(f, _)| f)
+                .collect();
+            if !persisted.is_empty() {
+                let n = persisted.len().to_string();
+                let mut a: Vec<&[u8]> = vec![b"HPERSIST", key.as_bytes(), b"FIELDS", n.as_bytes()];
+                a.extend(persisted.iter().map(|f| f.as_ref()));
+                effects.push_bytes(&a);
+            }
+            int_array(codes)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
@@ -0,0 +1,122 @@
+//! INFO: server status as `field:value` lines grouped into sections.
+
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+
+use bytes::Bytes;
+
+use crate::protocol::RespFrame;
+use crate::server::state::ServerState;
+use crate::store::StoreAccess;
+
+use super::bulk_to_string;
+
+type Section = fn(&ServerState, &StoreAccess, &mut String);
+
+/// Sections in the order INFO prints them.
+const SECTIONS: &[(&str, Section)] = &[
+    ("Server", server_info),
+    ("Persistence", persistence),
+    ("Stats", stats),
+    ("Replication", replication),
+    ("Keyspace", keyspace),
+];
+
+/// INFO [section ...]
+pub(super) fn handle_info(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    store: &StoreAccess,
+) -> RespFrame {
+    let mut wanted = Vec::with_capacity(args.len());
+    for arg in &args {
+        match bulk_to_string(arg) {
+            Some(s) => wanted.push(s.to_ascii_lowercase()),
+            None => return RespFrame::Error("ERR syntax error".into()),
+        }
+    }
+    let all = wanted.is_empty()
+        || wanted
+            .iter()
+            .any(|w| matches!(w.as_str(), "all" | "default" | "everything"));
+
+    let mut out = String::new();
+    for (name, section) in SECTIONS {
+        if !all && !wanted.iter().any(|w| w.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str("\r\n");
+        }
+        let _ = write!(out, "# {name}\r\n");
+        section(server, store, &mut out);
+    }
+    RespFrame::BulkString(Some(Bytes::from(out)))
+}
+
+fn server_info(server: &ServerState, _store: &StoreAccess, out: &mut String) {
+    let _ = write!(out, "process_id:{}\r\n", std::process::id());
+    let _ = write!(out, "run_id:{}\r\n", server.run_id);
+}
+
+fn persistence(server: &ServerState, _store: &StoreAccess, out: &mut String) {
+    let aof = server.aof.as_ref();
+    let status = match aof {
+        Some(w) if !w.last_write_ok() => "err",
+        _ => "ok",
+    };
+    let _ = write!(out, "aof_enabled:{}\r\n", aof.is_some() as u8);
+    let _ = write!(out, "aof_last_write_status:{status}\r\n");
+}
+
+fn stats(server: &ServerState, store: &StoreAccess, out: &mut String) {
+    let stats = &server.stats;
+    let stale = stats.expired_stale_bp.load(Ordering::Relaxed);
+    if let Ok(guard) = store.read() {
+        let _ = write!(out, "expired_keys:{}\r\n", guard.expired_keys());
+    }
+    let _ = write!(
+        out,
+        "expired_stale_perc:{}.{:02}\r\n",
+        stale / 100,
+        stale % 100
+    );
+    let _ = write!(
+        out,
+        "evicted_keys:{}\r\n",
+        stats.evicted_keys.load(Ordering::Relaxed)
+    );
+}
+
+fn replication(server: &ServerState, _store: &StoreAccess, out: &mut String) {
+    let repl = &server.replication;
+    match repl.master() {
+        Some(master) => {
+            let (host, port) = master.rsplit_once(':').unwrap_or((&master, ""));
+            let _ = write!(
+                out,
+                "role:slave\r\nmaster_host:{host}\r\nmaster_port:{port}\r\n"
+            );
+        }
+        None => out.push_str("role:master\r\n"),
+    }
+    let _ = write!(out, "connected_slaves:{}\r\n", repl.replica_count());
+    out.push_str("master_failover_state:no-failover\r\n");
+    let _ = write!(out, "master_replid:{}\r\n", repl.replid());
+    // No partial resync, so there's never a previous history or a backlog.
+    let _ = write!(out, "master_replid2:{}\r\n", "0".repeat(40));
+    let _ = write!(out, "master_repl_offset:{}\r\n", repl.offset());
+    out.push_str("second_repl_offset:-1\r\nrepl_backlog_active:0\r\n");
+}
+
+/// One line for the single database, left out while it's empty as in Redis.
+fn keyspace(_server: &ServerState, store: &StoreAccess, out: &mut String) {
+    let Ok(guard) = store.read() else {
+        return;
+    };
+    let keys = guard.dbsize();
+    if keys > 0 {
+        let expires = guard.volatile_count();
+        let _ = write!(out, "db0:keys={keys},expires={expires},avg_ttl=0\r\n");
+    }
+}
@@ -0,0 +1,149 @@
+use bytes::Bytes;
+
+use crate::protocol::{RespFrame, reply};
+
+use super::bulk_to_string;
+use super::table::{self, Arity, CommandSpec, KeySpec};
+
+const COMMAND_HELP: &[&str] = &[
+    "(no subcommand)",
+    "    Return details about all commands.",
+    "COUNT",
+    "    Return the total number of commands in this server.",
+    "GETKEYS <full-command>",
+    "    Return the keys from a full command.",
+    "DOCS [<command-name> ...]",
+    "    Return documentation details about multiple commands.",
+    "    If no command names are given, documentation details for all",
+    "    commands are returned.",
+];
+
+/// COMMAND [COUNT | DOCS [command ...] | GETKEYS command [arg ...]]
+pub(super) fn handle_command(args: Vec<RespFrame>) -> RespFrame {
+    let Some(first) = args.first() else {
+        return RespFrame::Array(Some(table::COMMANDS.iter().map(command_info).collect()));
+    };
+    let Some(sub) = bulk_to_string(first) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+
+    match (sub.to_ascii_uppercase().as_str(), &args[1..]) {
+        ("COUNT", []) => reply::int(table::COMMANDS.len() as i64),
+        ("DOCS", []) => command_docs(table::COMMANDS.iter()),
+        ("DOCS", names) => command_docs(
+            names
+                .iter()
+                .filter_map(|name| table::lookup(&bulk_to_string(name)?.to_ascii_uppercase())),
+        ),
+        ("GETKEYS", argv @ [_, ..]) => command_getkeys(argv),
+        ("HELP", []) => reply::help("COMMAND", COMMAND_HELP),
+        _ => reply::unknown_subcommand("COMMAND", &sub),
+    }
+}
+
+/// The keys `argv` would access, found from its command's key spec.
+fn command_getkeys(argv: &[RespFrame]) -> RespFrame {
+    let Some(spec) = bulk_to_string(&argv[0]).and_then(|c| table::lookup(&c.to_ascii_uppercase()))
+    else {
+        return RespFrame::Error("ERR Invalid command specified".into());
+    };
+    let keys = if spec.arity.accepts(argv.len() - 1) {
+        spec.keys.extract(argv)
+    } else {
+        None
+    };
+    match keys {
+        Some(keys) => RespFrame::Array(Some(keys.into_iter().cloned().collect())),
+        None => RespFrame::Error("ERR Invalid arguments specified for command".into()),
+    }
+}
+
+fn bulk(s: impl Into<Bytes>) -> RespFrame {
+    RespFrame::BulkString(Some(s.into()))
+}
+
+/// One entry of the bare COMMAND reply, in the Redis 7 layout: name, arity,
+/// flags, first/last/step key positions, ACL categories, then the (here
+/// always empty) tips, key specs and subcommands.
+fn command_info(spec: &CommandSpec) -> RespFrame {
+    // Redis counts the command name in the arity and marks a minimum with
+    // a negative number.
+    let arity = match spec.arity {
+        Arity::Exact(n) => n as i64 + 1,
+        Arity::AtLeast(n) | Arity::Range(n, _) => -(n as i64 + 1),
+    };
+    let mut flags = Vec::new();
+    if spec.has(table::WRITE) {
+        flags.push("write");
+    } else if spec.in_category("read") {
+        flags.push("readonly");
+    }
+    if spec.has(table::DENYOOM) {
+        flags.push("denyoom");
+    }
+    if spec.in_category("fast") {
+        flags.push("fast");
+    }
+    let (first, last, step) = match spec.keys {
+        KeySpec::Range { first, last, step } => (first as i64, last as i64, step as i64),
+        KeySpec::NumKeys => {
+            flags.push("movablekeys");
+            (0, 0, 0)
+        }
+        KeySpec::None => (0, 0, 0),
+    };
+    let status = |s: &str| RespFrame::SimpleString(s.into());
+    RespFrame::Array(Some(vec![
+        bulk(spec.name.to_ascii_lowercase()),
+        reply::int(arity),
+        RespFrame::Array(Some(flags.into_iter().map(status).collect())),
+        reply::int(first),
+        reply::int(last),
+        reply::int(step),
+        RespFrame::Array(Some(
+            spec.categories
+                .iter()
+                .map(|c| status(&format!("@{c}")))
+                .collect(),
+        )),
+        RespFrame::Array(Some(Vec::new())),
+        RespFrame::Array(Some(Vec::new())),
+        RespFrame::Array(Some(Vec::new())),
+    ]))
+}
+
+/// COMMAND DOCS as RESP2 sends it: command names alternating with their
+/// `summary`/`group` field lists.
+fn command_docs<'a>(specs: impl Iterator<Item = &'a CommandSpec>) -> RespFrame {
+    let mut items = Vec::new();
+    for spec in specs {
+        items.push(bulk(spec.name.to_ascii_lowercase()));
+        items.push(RespFrame::Array(Some(vec![
+            bulk("summary"),
+            bulk(spec.summary),
+            bulk("group"),
+            bulk(doc_group(spec)),
+        ])));
+    }
+    RespFrame::Array(Some(items))
+}
+
+/// The Redis documentation group for `spec`, read off its ACL categories.
+fn doc_group(spec: &CommandSpec) -> &'static str {
+    const GROUPS: &[(&str, &str)] = &[
+        ("string", "string"),
+        ("bitmap", "bitmap"),
+        ("list", "list"),
+        ("set", "set"),
+        ("sortedset", "sorted-set"),
+        ("hash", "hash"),
+        ("pubsub", "pubsub"),
+        ("transaction", "transactions"),
+        ("connection", "connection"),
+        ("keyspace", "generic"),
+    ];
+    GROUPS
+        .iter()
+        .find(|(category, _)| spec.in_category(category))
+        .map_or("server", |(_, group)| group)
+}
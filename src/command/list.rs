@@ -1,50 +1,36 @@
-use crate::persistence::aof::AofWriter;
-use crate::protocol::RespFrame;
-use crate::store::SharedStore;
+use crate::propagate::Effects;
+use crate::protocol::{RespFrame, reply};
+use crate::store::{StoreAccess, TypeError};
 
 use super::{bulk_to_bytes, bulk_to_string};
 
 pub(super) fn handle_lpush(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.len() < 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'lpush'".into());
-    }
-
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
     };
 
     let mut values = Vec::with_capacity(args.len() - 1);
-    let mut val_strs = Vec::with_capacity(args.len() - 1);
     for arg in &args[1..] {
         match bulk_to_bytes(arg) {
-            Some(b) => {
-                val_strs.push(String::from_utf8_lossy(&b).into_owned());
-                values.push(b);
-            }
+            Some(b) => values.push(b),
             None => return RespFrame::Error("ERR value must be bulk string".into()),
         }
     }
 
     match store.write() {
         Ok(mut guard) => {
-            if !guard.is_type(&key, "list") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            let len = guard.lpush(key.clone(), values);
-            if let Some(w) = aof {
-                let mut a: Vec<String> = vec!["LPUSH".into(), key];
-                a.extend(val_strs);
-                let refs: Vec<&str> = a.iter().map(|s| s.as_str()).collect();
-                w.append(&refs);
-            }
-            RespFrame::Integer(len as i64)
+            let Ok(len) = guard.lpush(key.clone(), values.clone()) else {
+                return reply::wrongtype();
+            };
+            let mut a: Vec<&[u8]> = vec![b"LPUSH", key.as_bytes()];
+            a.extend(values.iter().map(|v| v.as_ref()));
+            effects.push_bytes(&a);
+            reply::int(len as i64)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
@@ -52,45 +38,31 @@ pub(super) fn handle_lpush(
 
 pub(super) fn handle_rpush(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.len() < 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'rpush'".into());
-    }
-
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
     };
 
     let mut values = Vec::with_capacity(args.len() - 1);
-    let mut val_strs = Vec::with_capacity(args.len() - 1);
     for arg in &args[1..] {
         match bulk_to_bytes(arg) {
-            Some(b) => {
-                val_strs.push(String::from_utf8_lossy(&b).into_owned());
-                values.push(b);
-            }
+            Some(b) => values.push(b),
             None => return RespFrame::Error("ERR value must be bulk string".into()),
         }
     }
 
     match store.write() {
         Ok(mut guard) => {
-            if !guard.is_type(&key, "list") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            let len = guard.rpush(key.clone(), values);
-            if let Some(w) = aof {
-                let mut a: Vec<String> = vec!["RPUSH".into(), key];
-                a.extend(val_strs);
-                let refs: Vec<&str> = a.iter().map(|s| s.as_str()).collect();
-                w.append(&refs);
-            }
-            RespFrame::Integer(len as i64)
+            let Ok(len) = guard.rpush(key.clone(), values.clone()) else {
+                return reply::wrongtype();
+            };
+            let mut a: Vec<&[u8]> = vec![b"RPUSH", key.as_bytes()];
+            a.extend(values.iter().map(|v| v.as_ref()));
+            effects.push_bytes(&a);
+            reply::int(len as i64)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
@@ -98,13 +70,9 @@ pub(super) fn handle_rpush(
 
 pub(super) fn handle_lpop(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.is_empty() || args.len() > 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'lpop'".into());
-    }
-
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -121,38 +89,20 @@ pub(super) fn handle_lpop(
 
     match store.write() {
         Ok(mut guard) => {
-            if !guard.is_type(&key, "list") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
+            let Ok(items) = guard.lpop(&key, count.unwrap_or(1)) else {
+                return reply::wrongtype();
+            };
+            for _ in 0..items.len() {
+                effects.push(&["LPOP", &key]);
             }
             match count {
-                Some(n) => {
-                    let mut items = Vec::with_capacity(n);
-                    for _ in 0..n {
-                        match guard.lpop(&key) {
-                            Some(b) => items.push(RespFrame::BulkString(Some(b))),
-                            None => break,
-                        }
-                    }
-                    if !items.is_empty()
-                        && let Some(w) = aof
-                    {
-                        for _ in 0..items.len() {
-                            w.append(&["LPOP", &key]);
-                        }
-                    }
-                    RespFrame::Array(Some(items))
-                }
-                None => match guard.lpop(&key) {
-                    Some(b) => {
-                        if let Some(w) = aof {
-                            w.append(&["LPOP", &key]);
-                        }
-                        RespFrame::BulkString(Some(b))
-                    }
-                    None => RespFrame::BulkString(None),
-                },
+                Some(_) => RespFrame::Array(Some(
+                    items
+                        .into_iter()
+                        .map(|b| RespFrame::BulkString(Some(b)))
+                        .collect(),
+                )),
+                None => RespFrame::BulkString(items.into_iter().next()),
             }
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
@@ -161,13 +111,9 @@ pub(super) fn handle_lpop(
 
 pub(super) fn handle_rpop(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.is_empty() || args.len() > 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'rpop'".into());
-    }
-
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -184,49 +130,27 @@ pub(super) fn handle_rpop(
 
     match store.write() {
         Ok(mut guard) => {
-            if !guard.is_type(&key, "list") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
+            let Ok(items) = guard.rpop(&key, count.unwrap_or(1)) else {
+                return reply::wrongtype();
+            };
+            for _ in 0..items.len() {
+                effects.push(&["RPOP", &key]);
             }
             match count {
-                Some(n) => {
-                    let mut items = Vec::with_capacity(n);
-                    for _ in 0..n {
-                        match guard.rpop(&key) {
-                            Some(b) => items.push(RespFrame::BulkString(Some(b))),
-                            None => break,
-                        }
-                    }
-                    if !items.is_empty()
-                        && let Some(w) = aof
-                    {
-                        for _ in 0..items.len() {
-                            w.append(&["RPOP", &key]);
-                        }
-                    }
-                    RespFrame::Array(Some(items))
-                }
-                None => match guard.rpop(&key) {
-                    Some(b) => {
-                        if let Some(w) = aof {
-                            w.append(&["RPOP", &key]);
-                        }
-                        RespFrame::BulkString(Some(b))
-                    }
-                    None => RespFrame::BulkString(None),
-                },
+                Some(_) => RespFrame::Array(Some(
+                    items
+                        .into_iter()
+                        .map(|b| RespFrame::BulkString(Some(b)))
+                        .collect(),
+                )),
+                None => RespFrame::BulkString(items.into_iter().next()),
             }
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-pub(super) fn handle_lrange(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() != 3 {
-        return RespFrame::Error("ERR wrong number of arguments for 'lrange'".into());
-    }
-
+pub(super) fn handle_lrange(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -242,43 +166,108 @@ pub(super) fn handle_lrange(args: Vec<RespFrame>, store: &SharedStore) -> RespFr
         None => return RespFrame::Error("ERR value is not an integer or out of range".into()),
     };
 
-    match store.write() {
-        Ok(mut guard) => {
-            if !guard.is_type(&key, "list") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            let items = guard.lrange(&key, start, stop);
-            RespFrame::Array(Some(
+    match store.read() {
+        Ok(guard) => match guard.lrange(&key, start, stop) {
+            Ok(items) => RespFrame::Array(Some(
                 items
-                    .into_iter()
-                    .map(|b| RespFrame::BulkString(Some(b)))
+                    .map(|b| RespFrame::BulkString(Some(b.clone())))
                     .collect(),
-            ))
-        }
+            )),
+            Err(TypeError) => reply::wrongtype(),
+        },
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-pub(super) fn handle_llen(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() != 1 {
-        return RespFrame::Error("ERR wrong number of arguments for 'llen'".into());
+pub(super) fn handle_llen(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    match store.read() {
+        Ok(guard) => match guard.llen(&key) {
+            Ok(len) => reply::int(len as i64),
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
+}
 
+pub(super) fn handle_lpos(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
     };
 
+    let element = match bulk_to_bytes(&args[1]) {
+        Some(b) => b,
+        None => return RespFrame::Error("ERR value must be bulk string".into()),
+    };
+
+    let mut rank: i64 = 1;
+    let mut count: Option<usize> = None;
+    let mut maxlen: usize = 0;
+
+    let mut i = 2;
+    while i < args.len() {
+        let opt = match bulk_to_string(&args[i]) {
+            Some(s) => s.to_ascii_uppercase(),
+            None => return RespFrame::Error("ERR syntax error".into()),
+        };
+        let Some(value) = args
+            .get(i + 1)
+            .and_then(bulk_to_string)
+            .and_then(|s| s.parse::<i64>().ok())
+        else {
+            return match args.get(i + 1) {
+                Some(_) => RespFrame::Error("ERR value is not an integer or out of range".into()),
+                None => RespFrame::Error("ERR syntax error".into()),
+            };
+        };
+        match opt.as_str() {
+            "RANK" => {
+                if value == 0 {
+                    return RespFrame::Error(
+                        "ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list".into(),
+                    );
+                }
+                if value == i64::MIN {
+                    return RespFrame::Error("ERR value is out of range".into());
+                }
+                rank = value;
+            }
+            "COUNT" => {
+                if value < 0 {
+                    return RespFrame::Error("ERR COUNT can't be negative".into());
+                }
+                count = Some(value as usize);
+            }
+            "MAXLEN" => {
+                if value < 0 {
+                    return RespFrame::Error("ERR MAXLEN can't be negative".into());
+                }
+                maxlen = value as usize;
+            }
+            _ => return RespFrame::Error("ERR syntax error".into()),
+        }
+        i += 2;
+    }
+
     match store.read() {
         Ok(guard) => {
-            if !guard.is_type(&key, "list") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
+            let Ok(found) = guard.lpos(&key, &element, rank, count.unwrap_or(1), maxlen) else {
+                return reply::wrongtype();
+            };
+            match count {
+                Some(_) => RespFrame::Array(Some(
+                    found.into_iter().map(|i| reply::int(i as i64)).collect(),
+                )),
+                None => match found.first() {
+                    Some(&i) => reply::int(i as i64),
+                    None => reply::nil(),
+                },
             }
-            RespFrame::Integer(guard.llen(&key) as i64)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
@@ -0,0 +1,80 @@
+use std::sync::atomic::Ordering;
+
+use crate::protocol::{RespFrame, reply};
+use crate::server::state::ServerState;
+use crate::store::StoreAccess;
+
+use super::bulk_to_string;
+
+/// Used memory, as a share of `maxmemory`, past which MEMORY DOCTOR warns.
+const DOCTOR_WARN_PERCENT: usize = 90;
+
+const MEMORY_HELP: &[&str] = &[
+    "DOCTOR",
+    "    Return memory problems reports.",
+    "USAGE <key> [SAMPLES <count>]",
+    "    Return memory in bytes used by <key> and its value. Nested values are",
+    "    sampled up to <count> times (default: 0, which means sample all).",
+];
+
+/// MEMORY USAGE key [SAMPLES count] | DOCTOR
+pub(super) fn handle_memory(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    store: &StoreAccess,
+) -> RespFrame {
+    let Some(sub) = bulk_to_string(&args[0]) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+
+    match (sub.to_ascii_uppercase().as_str(), &args[1..]) {
+        ("USAGE", [key]) => memory_usage(key, 0, server, store),
+        ("USAGE", [key, opt, count]) => {
+            if !bulk_to_string(opt).is_some_and(|o| o.eq_ignore_ascii_case("SAMPLES")) {
+                return RespFrame::Error("ERR syntax error".into());
+            }
+            match bulk_to_string(count).and_then(|s| s.parse::<usize>().ok()) {
+                Some(samples) => memory_usage(key, samples, server, store),
+                None => RespFrame::Error("ERR value is not an integer or out of range".into()),
+            }
+        }
+        ("DOCTOR", []) => memory_doctor(server, store),
+        ("HELP", []) => reply::help("MEMORY", MEMORY_HELP),
+        _ => reply::unknown_subcommand("MEMORY", &sub),
+    }
+}
+
+fn memory_usage(
+    key: &RespFrame,
+    samples: usize,
+    server: &ServerState,
+    store: &StoreAccess,
+) -> RespFrame {
+    let Some(key) = bulk_to_string(key) else {
+        return RespFrame::Error("ERR key must be bulk string".into());
+    };
+    match store.write() {
+        Ok(mut guard) => match guard.memory_usage(&key, samples, &server.encoding) {
+            Some(bytes) => reply::int(bytes as i64),
+            None => reply::nil(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+fn memory_doctor(server: &ServerState, store: &StoreAccess) -> RespFrame {
+    let used = match store.write() {
+        Ok(mut guard) => guard.used_memory(&server.encoding),
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    let limit = server.maxmemory.limit.load(Ordering::Relaxed);
+    let report = if limit > 0 && used * 100 >= limit * DOCTOR_WARN_PERCENT {
+        format!(
+            "The dataset uses about {used} bytes, close to maxmemory ({limit} bytes). \
+             Expect evictions or OOM errors for writes."
+        )
+    } else {
+        format!("No memory issues detected. The dataset uses about {used} bytes.")
+    };
+    RespFrame::BulkString(Some(report.into()))
+}
@@ -1,22 +1,79 @@
-use crate::persistence::aof::AofWriter;
-use crate::protocol::RespFrame;
-use crate::store::SharedStore;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
 
+use bytes::Bytes;
+
+use crate::persistence::aof::AofErrorPolicy;
+use crate::propagate::{Effects, propagate};
+use crate::protocol::encoder::encoded_len;
+use crate::protocol::{RespFrame, reply};
+use crate::server::client::ClientState;
+use crate::server::state::ServerState;
+use crate::store::StoreAccess;
+
+mod acl;
 mod basic;
+mod bitops;
+mod client;
+mod cluster;
+mod config;
+mod debug;
+mod dump;
 mod hash;
+mod info;
+mod introspection;
 mod list;
+mod memory;
+mod object;
+mod pubsub;
+mod replication;
+mod scan;
 mod set;
+mod shutdown;
+mod slowlog;
 mod string;
+mod table;
+mod transaction;
 mod zset;
 
-use basic::{handle_echo, handle_ping};
-use hash::{handle_hget, handle_hgetall, handle_hset};
-use list::{handle_llen, handle_lpop, handle_lpush, handle_lrange, handle_rpop, handle_rpush};
-use set::{handle_sadd, handle_smembers, handle_srem};
-use string::{handle_del, handle_exists, handle_get, handle_set, handle_ttl};
+use acl::{authorize, handle_acl, handle_auth};
+use basic::{handle_echo, handle_monitor, handle_ping, handle_reset};
+use bitops::{handle_bitcount, handle_bitop, handle_bitpos, handle_getbit, handle_setbit};
+use client::handle_client;
+use cluster::handle_cluster;
+use config::handle_config;
+use debug::handle_debug;
+use dump::{handle_dump, handle_restore};
+use hash::{
+    handle_hdel, handle_hexpire, handle_hget, handle_hgetall, handle_hgetdel, handle_hgetex,
+    handle_hincrby, handle_hpersist, handle_hrandfield, handle_hset, handle_httl,
+};
+use info::handle_info;
+use introspection::handle_command;
+use list::{
+    handle_llen, handle_lpop, handle_lpos, handle_lpush, handle_lrange, handle_rpop, handle_rpush,
+};
+use memory::handle_memory;
+use object::handle_object;
+use pubsub::{
+    handle_publish, handle_pubsub, handle_spublish, handle_ssubscribe, handle_subscribe,
+    handle_sunsubscribe, handle_unsubscribe,
+};
+use replication::{handle_failover, handle_replicaof, handle_sync, handle_waitaof};
+use scan::{handle_hscan, handle_sscan, handle_zscan};
+use set::{handle_sadd, handle_sintercard, handle_smembers, handle_srandmember, handle_srem};
+use shutdown::handle_shutdown;
+use slowlog::handle_slowlog;
+use string::{
+    handle_append, handle_cas, handle_copy, handle_dbsize, handle_del, handle_exists,
+    handle_expire, handle_expiretime, handle_flush, handle_get, handle_getdel, handle_getex,
+    handle_getrange, handle_getset, handle_incr, handle_lcs, handle_persist, handle_set,
+    handle_setrange, handle_strlen, handle_touch, handle_ttl, handle_unlink,
+};
+use transaction::{handle_discard, handle_exec, handle_multi};
 use zset::{
-    handle_zadd, handle_zcard, handle_zcount, handle_zrange, handle_zrank, handle_zrem,
-    handle_zrevrange, handle_zscore,
+    handle_zadd, handle_zcard, handle_zcount, handle_zintercard, handle_zrandmember, handle_zrange,
+    handle_zrank, handle_zrem, handle_zrevrange, handle_zscore,
 };
 
 // ── Helpers (private here; accessible to all child modules via `super::`) ─
@@ -28,65 +85,507 @@ fn bulk_to_string(frame: &RespFrame) -> Option<String> {
     }
 }
 
-fn bulk_to_bytes(frame: &RespFrame) -> Option<bytes::Bytes> {
+fn bulk_to_bytes(frame: &RespFrame) -> Option<Bytes> {
     match frame {
         RespFrame::BulkString(Some(bytes)) => Some(bytes.clone()),
         _ => None,
     }
 }
 
+/// Parse `numkeys key [key ...] [LIMIT limit]` as taken by SINTERCARD and
+/// ZINTERCARD.
+fn parse_intercard_args(args: &[RespFrame]) -> Result<(Vec<String>, usize), RespFrame> {
+    let numkeys = match bulk_to_string(&args[0]).and_then(|s| s.parse::<i64>().ok()) {
+        Some(n) if n > 0 => n as usize,
+        Some(_) => {
+            return Err(RespFrame::Error(
+                "ERR numkeys should be greater than 0".into(),
+            ));
+        }
+        None => {
+            return Err(RespFrame::Error(
+                "ERR value is not an integer or out of range".into(),
+            ));
+        }
+    };
+    if numkeys > args.len() - 1 {
+        return Err(RespFrame::Error(
+            "ERR Number of keys can't be greater than number of args".into(),
+        ));
+    }
+
+    let mut keys = Vec::with_capacity(numkeys);
+    for arg in &args[1..=numkeys] {
+        match bulk_to_string(arg) {
+            Some(k) => keys.push(k),
+            None => return Err(RespFrame::Error("ERR key must be bulk string".into())),
+        }
+    }
+
+    let mut limit = 0;
+    let rest = &args[numkeys + 1..];
+    match rest {
+        [] => {}
+        [opt, value] if bulk_to_string(opt).is_some_and(|o| o.eq_ignore_ascii_case("LIMIT")) => {
+            limit = match bulk_to_string(value).and_then(|s| s.parse::<i64>().ok()) {
+                Some(n) if n >= 0 => n as usize,
+                Some(_) => return Err(RespFrame::Error("ERR LIMIT can't be negative".into())),
+                None => {
+                    return Err(RespFrame::Error(
+                        "ERR value is not an integer or out of range".into(),
+                    ));
+                }
+            };
+        }
+        _ => return Err(RespFrame::Error("ERR syntax error".into())),
+    }
+
+    Ok((keys, limit))
+}
+
+/// Parse the `[count [flag]]` tail of HRANDFIELD and ZRANDMEMBER, where
+/// `flag` is WITHVALUES or WITHSCORES.
+fn parse_randfield_args(args: &[RespFrame], flag: &str) -> Result<(Option<i64>, bool), RespFrame> {
+    let Some(count) = args.first() else {
+        return Ok((None, false));
+    };
+    let with_flag = match args.get(1) {
+        None => false,
+        Some(opt) if bulk_to_string(opt).is_some_and(|o| o.eq_ignore_ascii_case(flag)) => true,
+        Some(_) => return Err(RespFrame::Error("ERR syntax error".into())),
+    };
+    Ok((Some(parse_rand_count(count, with_flag)?), with_flag))
+}
+
+/// Parse the count of SRANDMEMBER, HRANDFIELD or ZRANDMEMBER. As in Redis,
+/// its magnitude must fit an i64, and twice over when each pick comes
+/// `paired` with a value or score.
+fn parse_rand_count(arg: &RespFrame, paired: bool) -> Result<i64, RespFrame> {
+    let Some(count) = bulk_to_string(arg).and_then(|s| s.parse::<i64>().ok()) else {
+        return Err(RespFrame::Error(
+            "ERR value is not an integer or out of range".into(),
+        ));
+    };
+    let limit = if paired { i64::MAX / 2 } else { i64::MAX };
+    if !(-limit..=limit).contains(&count) {
+        return Err(RespFrame::Error("ERR value is out of range".into()));
+    }
+    Ok(count)
+}
+
+/// The arguments of a command as SLOWLOG keeps them, with the passwords
+/// given to ACL SETUSER masked.
+fn slowlog_args(command: &RespFrame, args: &[RespFrame], name: &str) -> Vec<Bytes> {
+    let mut argv: Vec<Bytes> = std::iter::once(command)
+        .chain(args)
+        .filter_map(bulk_to_bytes)
+        .collect();
+    if name == "ACL"
+        && argv
+            .get(1)
+            .is_some_and(|sub| sub.eq_ignore_ascii_case(b"SETUSER"))
+    {
+        for rule in argv.iter_mut().skip(3) {
+            if rule.starts_with(b">") || rule.starts_with(b"<") {
+                *rule = Bytes::from_static(b"(redacted)");
+            }
+        }
+    }
+    argv
+}
+
 // ── Public entry point ────────────────────────────────────────────────────
 
-pub fn dispatch(frame: RespFrame, store: &SharedStore, aof: Option<&AofWriter>) -> RespFrame {
+/// Execute one request. Returns `None` when the command answers only through
+/// the client's push queue (e.g. SUBSCRIBE confirmations).
+pub fn dispatch(
+    frame: RespFrame,
+    server: &ServerState,
+    client: &mut ClientState,
+) -> Option<RespFrame> {
     match frame {
-        RespFrame::Array(Some(items)) => handle_array(items, store, aof),
-        _ => RespFrame::Error("ERR expected array".into()),
+        RespFrame::Array(Some(items)) => {
+            handle_array(items, server, client, &StoreAccess::Shared(&server.store))
+        }
+        _ => Some(RespFrame::Error("ERR expected array".into())),
     }
 }
 
+/// Commands a connection may still issue while it has subscriptions.
+const SUBSCRIBER_COMMANDS: &[&str] = &[
+    "SUBSCRIBE",
+    "UNSUBSCRIBE",
+    "SSUBSCRIBE",
+    "SUNSUBSCRIBE",
+    "PING",
+    "RESET",
+];
+
+/// Commands executed immediately rather than queued inside MULTI.
+const TRANSACTION_COMMANDS: &[&str] = &["MULTI", "EXEC", "DISCARD", "RESET"];
+
+/// Run one command against `store`, which inside EXEC is the transaction's
+/// already-held write lock.
 fn handle_array(
     mut items: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
-) -> RespFrame {
+    server: &ServerState,
+    client: &mut ClientState,
+    store: &StoreAccess,
+) -> Option<RespFrame> {
     if items.is_empty() {
-        return RespFrame::Error("ERR empty command".into());
+        return Some(RespFrame::Error("ERR empty command".into()));
     }
 
-    let command_frame = items.remove(0);
-    let Some(cmd) = bulk_to_string(&command_frame) else {
-        return RespFrame::Error("ERR command must be bulk string".into());
+    let Some(cmd) = bulk_to_string(&items[0]) else {
+        return Some(RespFrame::Error("ERR command must be bulk string".into()));
+    };
+    let upper = cmd.to_ascii_uppercase();
+    if client.monitor && upper != "RESET" {
+        return None;
+    }
+
+    let Some(spec) = table::lookup(&upper) else {
+        return Some(RespFrame::Error(format!("ERR unknown command '{cmd}'")));
     };
+    if !spec.arity.accepts(items.len() - 1) {
+        return Some(reply::wrong_args(&cmd));
+    }
+
+    // The replication link applies whatever its master sends.
+    if !client.is_master
+        && let Err(err) = authorize(spec, &items, server, client)
+    {
+        return Some(err);
+    }
+    let command_frame = items.remove(0);
+
+    if client.is_subscriber() && !SUBSCRIBER_COMMANDS.contains(&upper.as_str()) {
+        return Some(RespFrame::Error(format!(
+            "ERR Can't execute '{}': only (S)SUBSCRIBE / (S)UNSUBSCRIBE / PING / RESET are allowed in this context",
+            cmd.to_ascii_lowercase()
+        )));
+    }
+
+    if let Some(queued) = client.multi.as_mut()
+        && !TRANSACTION_COMMANDS.contains(&upper.as_str())
+    {
+        // SYNC and SHUTDOWN need the replication order lock, which EXEC
+        // already holds.
+        if upper == "SYNC" || upper == "SHUTDOWN" {
+            return Some(RespFrame::Error(
+                "ERR Command not allowed inside a transaction".into(),
+            ));
+        }
+        items.insert(0, command_frame);
+        queued.push(items);
+        return Some(RespFrame::SimpleString("QUEUED".into()));
+    }
 
-    match cmd.to_ascii_uppercase().as_str() {
-        "PING" => handle_ping(items),
+    if spec.has(table::WRITE) && !client.is_master && server.is_read_only() {
+        return Some(RespFrame::Error(
+            "READONLY You can't write against a read only replica.".into(),
+        ));
+    }
+
+    // Writes hold the replication order lock until they've been propagated;
+    // see `Replication::order`. EXEC already holds it for the whole batch.
+    let _order =
+        (spec.has(table::WRITE) && !store.is_held()).then(|| server.replication.lock_order());
+
+    if spec.has(table::WRITE)
+        && server.aof_error_policy == AofErrorPolicy::Stop
+        && server.aof.as_ref().is_some_and(|w| !w.last_write_ok())
+    {
+        return Some(RespFrame::Error(
+            "MISCONF Errors writing to the AOF file, write commands are disabled. Check the server log for details.".into(),
+        ));
+    }
+
+    if spec.has(table::DENYOOM)
+        && let Some(err) = enforce_maxmemory(server, store)
+    {
+        return Some(err);
+    }
+
+    // Only pay for copying the arguments when they might be logged. AUTH's
+    // are all credentials, so it stays out of the log altogether.
+    let logged_args = (server.slowlog.enabled() && spec.name != "AUTH")
+        .then(|| slowlog_args(&command_frame, &items, spec.name));
+    let started = Instant::now();
+
+    // Admin commands stay out of the feed, as in Redis; AUTH is shown
+    // without its password. A monitor's own RESET isn't echoed back to it.
+    if server.monitors.is_active() && !client.monitor && !spec.in_category("admin") {
+        let argv: Vec<Bytes> = std::iter::once(&command_frame)
+            .chain(&items)
+            .filter_map(bulk_to_bytes)
+            .collect();
+        server
+            .monitors
+            .feed(&client.addr, &argv, spec.name == "AUTH");
+    }
+
+    let mut effects = Effects::new();
+
+    let reply = match upper.as_str() {
+        "SUBSCRIBE" => return handle_subscribe(items, server, client),
+        "SYNC" => return handle_sync(server, client),
+        "UNSUBSCRIBE" => return handle_unsubscribe(items, server, client),
+        "PUBLISH" => handle_publish(items, server),
+        "SSUBSCRIBE" => return handle_ssubscribe(items, server, client),
+        "SUNSUBSCRIBE" => return handle_sunsubscribe(items, server, client),
+        "SPUBLISH" => handle_spublish(items, server),
+        "PUBSUB" => handle_pubsub(items, server),
+        "MULTI" => handle_multi(client),
+        "EXEC" => handle_exec(server, client),
+        "DISCARD" => handle_discard(client),
+        "RESET" => handle_reset(server, client),
+        "MONITOR" => handle_monitor(server, client),
+        "PING" => handle_ping(items, client),
         "ECHO" => handle_echo(items),
-        "SET" => handle_set(items, store, aof),
-        "GET" => handle_get(items, store),
-        "DEL" => handle_del(items, store, aof),
-        "EXISTS" => handle_exists(items, store),
-        "TTL" => handle_ttl(items, store, false),
-        "PTTL" => handle_ttl(items, store, true),
-        "LPUSH" => handle_lpush(items, store, aof),
-        "RPUSH" => handle_rpush(items, store, aof),
-        "LPOP" => handle_lpop(items, store, aof),
-        "RPOP" => handle_rpop(items, store, aof),
-        "LRANGE" => handle_lrange(items, store),
-        "LLEN" => handle_llen(items, store),
-        "SADD" => handle_sadd(items, store, aof),
-        "SREM" => handle_srem(items, store, aof),
-        "SMEMBERS" => handle_smembers(items, store),
-        "HSET" => handle_hset(items, store, aof),
-        "HGET" => handle_hget(items, store),
-        "HGETALL" => handle_hgetall(items, store),
-        "ZADD" => handle_zadd(items, store, aof),
-        "ZRANGE" => handle_zrange(items, store),
-        "ZSCORE" => handle_zscore(items, store),
-        "ZRANK" => handle_zrank(items, store),
-        "ZCARD" => handle_zcard(items, store),
-        "ZREM" => handle_zrem(items, store, aof),
-        "ZCOUNT" => handle_zcount(items, store),
-        "ZREVRANGE" => handle_zrevrange(items, store),
-        _ => RespFrame::Error(format!("ERR unknown command '{cmd}'")),
+        "CLIENT" => handle_client(items, server, client),
+        "DEBUG" => handle_debug(items, server, store),
+        "OBJECT" => handle_object(items, server, store),
+        "MEMORY" => handle_memory(items, server, store),
+        "SLOWLOG" => handle_slowlog(items, server),
+        "INFO" => handle_info(items, server, store),
+        "CONFIG" => handle_config(items, server),
+        "CLUSTER" => handle_cluster(items, server),
+        "COMMAND" => handle_command(items),
+        "AUTH" => handle_auth(items, server, client),
+        "ACL" => handle_acl(items, server, client),
+        "REPLICAOF" => handle_replicaof(items, server),
+        "FAILOVER" => handle_failover(items, server),
+        "SHUTDOWN" => handle_shutdown(items, server),
+        "WAITAOF" => handle_waitaof(items, server),
+        _ => execute(spec.name, items, store, &mut effects),
+    };
+
+    let elapsed = started.elapsed();
+    metrics::histogram!("rfs_command_duration_seconds", "command" => spec.name)
+        .record(elapsed.as_secs_f64());
+    let kind = if spec.has(table::WRITE) {
+        "write"
+    } else {
+        "read"
+    };
+    metrics::counter!("rfs_commands_total", "kind" => kind).increment(1);
+    let reply_bytes = encoded_len(&reply);
+    metrics::histogram!("rfs_command_reply_bytes", "command" => spec.name)
+        .record(reply_bytes as f64);
+    if server.warn_reply_bytes > 0 && reply_bytes > server.warn_reply_bytes {
+        tracing::warn!(
+            command = spec.name,
+            client = %client.addr,
+            bytes = reply_bytes,
+            limit = server.warn_reply_bytes,
+            "large reply"
+        );
+    }
+    if let Some(args) = logged_args {
+        server.slowlog.record(&args, elapsed, &client.addr);
+    }
+    debug_assert!(
+        spec.has(table::WRITE) || effects.is_empty(),
+        "{} is not flagged WRITE but produced effects",
+        spec.name
+    );
+    for args in effects {
+        propagate(server, args);
+    }
+
+    Some(reply)
+}
+
+/// The core of every keyspace command: parse `args`, check types, change
+/// the data and record in `effects` what should be propagated. It knows
+/// nothing of clients, ACLs or sinks; `handle_array` decides how `store` is
+/// locked and propagates the effects afterwards. `name` is the command's
+/// table name and `args` must already satisfy its arity.
+fn execute(
+    name: &str,
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    match name {
+        "SET" => handle_set(args, store, effects),
+        "GET" => handle_get(args, store),
+        "GETEX" => handle_getex(args, store, effects),
+        "GETDEL" => handle_getdel(args, store, effects),
+        "GETSET" => handle_getset(args, store, effects),
+        "GETRANGE" => handle_getrange(args, store),
+        "SETRANGE" => handle_setrange(args, store, effects),
+        "STRLEN" => handle_strlen(args, store),
+        "LCS" => handle_lcs(args, store),
+        "INCR" | "INCRBY" => handle_incr(args, store, effects, 1),
+        "DECR" | "DECRBY" => handle_incr(args, store, effects, -1),
+        "APPEND" => handle_append(args, store, effects),
+        "DEL" => handle_del(args, store, effects),
+        "UNLINK" => handle_unlink(args, store, effects),
+        "COPY" => handle_copy(args, store, effects),
+        "DUMP" => handle_dump(args, store),
+        "RESTORE" => handle_restore(args, store, effects),
+        "CAS" => handle_cas(args, store, effects),
+        "EXISTS" => handle_exists(args, store),
+        "TOUCH" => handle_touch(args, store),
+        "DBSIZE" => handle_dbsize(store),
+        "FLUSHDB" | "FLUSHALL" => handle_flush(name, args, store, effects),
+        "TTL" => handle_ttl(args, store, false),
+        "PTTL" => handle_ttl(args, store, true),
+        "EXPIRE" => handle_expire(args, store, effects, false, false),
+        "PEXPIRE" => handle_expire(args, store, effects, true, false),
+        "EXPIREAT" => handle_expire(args, store, effects, false, true),
+        "PEXPIREAT" => handle_expire(args, store, effects, true, true),
+        "PERSIST" => handle_persist(args, store, effects),
+        "EXPIRETIME" => handle_expiretime(args, store, false),
+        "PEXPIRETIME" => handle_expiretime(args, store, true),
+        "SETBIT" => handle_setbit(args, store, effects),
+        "GETBIT" => handle_getbit(args, store),
+        "BITCOUNT" => handle_bitcount(args, store),
+        "BITPOS" => handle_bitpos(args, store),
+        "BITOP" => handle_bitop(args, store, effects),
+        "LPUSH" => handle_lpush(args, store, effects),
+        "RPUSH" => handle_rpush(args, store, effects),
+        "LPOP" => handle_lpop(args, store, effects),
+        "RPOP" => handle_rpop(args, store, effects),
+        "LRANGE" => handle_lrange(args, store),
+        "LLEN" => handle_llen(args, store),
+        "LPOS" => handle_lpos(args, store),
+        "SADD" => handle_sadd(args, store, effects),
+        "SREM" => handle_srem(args, store, effects),
+        "SMEMBERS" => handle_smembers(args, store),
+        "SINTERCARD" => handle_sintercard(args, store),
+        "SRANDMEMBER" => handle_srandmember(args, store),
+        "SSCAN" => handle_sscan(args, store),
+        "HSET" => handle_hset(args, store, effects),
+        "HGET" => handle_hget(args, store),
+        "HINCRBY" => handle_hincrby(args, store, effects),
+        "HGETALL" => handle_hgetall(args, store),
+        "HDEL" => handle_hdel(args, store, effects),
+        "HGETDEL" => handle_hgetdel(args, store, effects),
+        "HGETEX" => handle_hgetex(args, store, effects),
+        "HRANDFIELD" => handle_hrandfield(args, store),
+        "HEXPIRE" => handle_hexpire(args, store, effects, false, false),
+        "HPEXPIRE" => handle_hexpire(args, store, effects, true, false),
+        "HEXPIREAT" => handle_hexpire(args, store, effects, false, true),
+        "HPEXPIREAT" => handle_hexpire(args, store, effects, true, true),
+        "HTTL" => handle_httl(args, store, false),
+        "HPTTL" => handle_httl(args, store, true),
+        "HPERSIST" => handle_hpersist(args, store, effects),
+        "HSCAN" => handle_hscan(args, store),
+        "ZADD" => handle_zadd(args, store, effects),
+        "ZRANGE" => handle_zrange(args, store),
+        "ZSCORE" => handle_zscore(args, store),
+        "ZRANK" => handle_zrank(args, store),
+        "ZCARD" => handle_zcard(args, store),
+        "ZREM" => handle_zrem(args, store, effects),
+        "ZCOUNT" => handle_zcount(args, store),
+        "ZINTERCARD" => handle_zintercard(args, store),
+        "ZREVRANGE" => handle_zrevrange(args, store),
+        "ZRANDMEMBER" => handle_zrandmember(args, store),
+        "ZSCAN" => handle_zscan(args, store),
+        _ => RespFrame::Error(format!("ERR unknown command '{name}'")),
+    }
+}
+
+/// Evict keys if the dataset is over `maxmemory`, propagating each eviction
+/// to the AOF and replicas as a DEL. Returns an OOM error if usage still doesn't fit.
+fn enforce_maxmemory(server: &ServerState, store: &StoreAccess) -> Option<RespFrame> {
+    if server.maxmemory.limit.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    let Ok(mut guard) = store.write() else {
+        return Some(RespFrame::Error("ERR store lock poisoned".into()));
+    };
+
+    let (evicted, fits) = guard.evict_to_fit(&server.maxmemory, &server.encoding);
+    for key in &evicted {
+        let del = vec![
+            Bytes::from_static(b"DEL"),
+            Bytes::copy_from_slice(key.as_bytes()),
+        ];
+        propagate(server, del);
+    }
+    if !evicted.is_empty() {
+        server
+            .stats
+            .evicted_keys
+            .fetch_add(evicted.len() as u64, Ordering::Relaxed);
+        tracing::debug!(count = evicted.len(), "evicted keys for maxmemory");
+    }
+    (!fits)
+        .then(|| RespFrame::Error("OOM command not allowed when used memory > 'maxmemory'.".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::Database;
+
+    /// Run `argv` through the core against a bare database, returning the
+    /// reply and the commands it would propagate.
+    fn run(db: &mut Database, argv: &[&str]) -> (RespFrame, Vec<Vec<Bytes>>) {
+        let args = argv[1..]
+            .iter()
+            .map(|a| RespFrame::BulkString(Some(Bytes::copy_from_slice(a.as_bytes()))))
+            .collect();
+        let mut effects = Effects::new();
+        let reply = execute(argv[0], args, &StoreAccess::held(db), &mut effects);
+        (reply, effects.into_iter().collect())
+    }
+
+    fn command(argv: &[&str]) -> Vec<Bytes> {
+        argv.iter()
+            .map(|a| Bytes::copy_from_slice(a.as_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn writes_record_their_replayed_form() {
+        let mut db = Database::new();
+        assert_eq!(
+            run(&mut db, &["SET", "n", "10"]),
+            (reply::ok(), vec![command(&["SET", "n", "10"])])
+        );
+        assert_eq!(
+            run(&mut db, &["DECR", "n"]),
+            (reply::int(9), vec![command(&["INCRBY", "n", "-1"])])
+        );
+        // A condition that fails changes nothing, so there is nothing to
+        // propagate.
+        assert_eq!(
+            run(&mut db, &["SET", "n", "1", "NX"]),
+            (reply::nil(), vec![])
+        );
+        assert_eq!(db.get_string("n"), Ok(Some(Bytes::from_static(b"9"))));
+
+        run(&mut db, &["HSET", "h", "f", "v"]);
+        let (resp, effects) = run(&mut db, &["HGETDEL", "h", "FIELDS", "1", "f"]);
+        assert_eq!(
+            resp,
+            RespFrame::Array(Some(vec![RespFrame::BulkString(Some("v".into()))]))
+        );
+        assert_eq!(effects, vec![command(&["HDEL", "h", "f"])]);
+        assert_eq!(db.dbsize(), 1);
+    }
+
+    #[test]
+    fn reads_and_type_errors_record_nothing() {
+        let mut db = Database::new();
+        run(&mut db, &["RPUSH", "l", "a", "b"]);
+
+        for argv in [&["GET", "l"][..], &["INCR", "l"], &["APPEND", "l", "x"]] {
+            assert_eq!(run(&mut db, argv), (reply::wrongtype(), vec![]), "{argv:?}");
+        }
+        let (resp, effects) = run(&mut db, &["LRANGE", "l", "0", "-1"]);
+        assert_eq!(
+            resp,
+            RespFrame::Array(Some(vec![
+                RespFrame::BulkString(Some("a".into())),
+                RespFrame::BulkString(Some("b".into())),
+            ]))
+        );
+        assert!(effects.is_empty());
     }
 }
@@ -0,0 +1,67 @@
+use bytes::Bytes;
+
+use crate::protocol::{RespFrame, reply};
+use crate::server::state::ServerState;
+use crate::store::StoreAccess;
+
+use super::bulk_to_string;
+
+const OBJECT_HELP: &[&str] = &[
+    "ENCODING <key>",
+    "    Return the kind of internal representation used to store the value at <key>.",
+    "FREQ <key>",
+    "    Return the access frequency index of <key>. Requires an LFU maxmemory-policy.",
+];
+
+/// OBJECT ENCODING key | FREQ key
+pub(super) fn handle_object(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    store: &StoreAccess,
+) -> RespFrame {
+    let Some(sub) = bulk_to_string(&args[0]) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+
+    match (sub.to_ascii_uppercase().as_str(), &args[1..]) {
+        ("FREQ", [key]) => object_freq(key, server, store),
+        ("ENCODING", [key]) => object_encoding(key, server, store),
+        ("HELP", []) => reply::help("OBJECT", OBJECT_HELP),
+        _ => reply::unknown_subcommand("OBJECT", &sub),
+    }
+}
+
+fn object_freq(key: &RespFrame, server: &ServerState, store: &StoreAccess) -> RespFrame {
+    if !server.maxmemory.policy.is_lfu() {
+        return RespFrame::Error(
+            "ERR An LFU maxmemory policy is not selected, access frequency not tracked.".into(),
+        );
+    }
+    let Some(key) = bulk_to_string(key) else {
+        return RespFrame::Error("ERR key must be bulk string".into());
+    };
+
+    match store.write() {
+        Ok(mut guard) => match guard.object_freq(&key) {
+            Some(freq) => reply::int(freq as i64),
+            None => reply::nil(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+fn object_encoding(key: &RespFrame, server: &ServerState, store: &StoreAccess) -> RespFrame {
+    let Some(key) = bulk_to_string(key) else {
+        return RespFrame::Error("ERR key must be bulk string".into());
+    };
+
+    match store.write() {
+        Ok(mut guard) => match guard.inspect(&key) {
+            Some(value) => RespFrame::BulkString(Some(Bytes::from_static(
+                server.encoding.encoding(value).as_bytes(),
+            ))),
+            None => reply::nil(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
@@ -0,0 +1,215 @@
+use bytes::Bytes;
+
+use crate::protocol::{RespFrame, reply};
+use crate::server::client::ClientState;
+use crate::server::state::ServerState;
+
+use super::{bulk_to_bytes, bulk_to_string};
+
+fn confirmation(kind: &'static str, channel: Option<Bytes>, count: usize) -> RespFrame {
+    RespFrame::Array(Some(vec![
+        RespFrame::BulkString(Some(Bytes::from_static(kind.as_bytes()))),
+        RespFrame::BulkString(channel),
+        reply::int(count as i64),
+    ]))
+}
+
+fn channel_args(args: &[RespFrame]) -> Result<Vec<Bytes>, RespFrame> {
+    args.iter()
+        .map(|arg| {
+            bulk_to_bytes(arg)
+                .ok_or_else(|| RespFrame::Error("ERR channel must be bulk string".into()))
+        })
+        .collect()
+}
+
+/// SUBSCRIBE channel [channel ...]
+///
+/// Confirmations go through the client's push queue rather than the direct
+/// reply, one per channel. Each is queued before registering with the broker
+/// so it always precedes the first message on that channel.
+pub(super) fn handle_subscribe(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    client: &mut ClientState,
+) -> Option<RespFrame> {
+    let channels = match channel_args(&args) {
+        Ok(channels) => channels,
+        Err(err) => return Some(err),
+    };
+
+    for channel in channels {
+        let fresh = client.channels.insert(channel.clone());
+        let _ = client.push_tx.send(confirmation(
+            "subscribe",
+            Some(channel.clone()),
+            client.channels.len(),
+        ));
+        if fresh {
+            server
+                .pubsub
+                .subscribe(channel, client.id, client.push_tx.clone());
+        }
+    }
+    None
+}
+
+/// UNSUBSCRIBE [channel ...]
+///
+/// Each channel is confirmed with the number still subscribed, like
+/// SUBSCRIBE. With no arguments, unsubscribes from every channel; a client
+/// with none gets a single confirmation with a nil channel.
+pub(super) fn handle_unsubscribe(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    client: &mut ClientState,
+) -> Option<RespFrame> {
+    let mut channels = match channel_args(&args) {
+        Ok(channels) => channels,
+        Err(err) => return Some(err),
+    };
+    if channels.is_empty() {
+        channels = client.channels.iter().cloned().collect();
+        if channels.is_empty() {
+            return Some(confirmation("unsubscribe", None, 0));
+        }
+    }
+
+    for channel in channels {
+        if client.channels.remove(&channel) {
+            server.pubsub.unsubscribe(&channel, client.id);
+        }
+        let _ = client.push_tx.send(confirmation(
+            "unsubscribe",
+            Some(channel),
+            client.channels.len(),
+        ));
+    }
+    None
+}
+
+/// PUBLISH channel message
+pub(super) fn handle_publish(args: Vec<RespFrame>, server: &ServerState) -> RespFrame {
+    let channel = match bulk_to_bytes(&args[0]) {
+        Some(c) => c,
+        None => return RespFrame::Error("ERR channel must be bulk string".into()),
+    };
+    let message = match bulk_to_bytes(&args[1]) {
+        Some(m) => m,
+        None => return RespFrame::Error("ERR message must be bulk string".into()),
+    };
+
+    reply::int(server.pubsub.publish(&channel, message) as i64)
+}
+
+/// SSUBSCRIBE shardchannel [shardchannel ...]
+///
+/// Like SUBSCRIBE, but for shard channels, whose count is kept separately.
+pub(super) fn handle_ssubscribe(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    client: &mut ClientState,
+) -> Option<RespFrame> {
+    let channels = match channel_args(&args) {
+        Ok(channels) => channels,
+        Err(err) => return Some(err),
+    };
+
+    for channel in channels {
+        let fresh = client.shard_channels.insert(channel.clone());
+        let _ = client.push_tx.send(confirmation(
+            "ssubscribe",
+            Some(channel.clone()),
+            client.shard_channels.len(),
+        ));
+        if fresh {
+            server
+                .pubsub
+                .ssubscribe(channel, client.id, client.push_tx.clone());
+        }
+    }
+    None
+}
+
+/// SUNSUBSCRIBE [shardchannel ...]
+///
+/// With no arguments, unsubscribes from every shard channel.
+pub(super) fn handle_sunsubscribe(
+    args: Vec<RespFrame>,
+    server: &ServerState,
+    client: &mut ClientState,
+) -> Option<RespFrame> {
+    let mut channels = match channel_args(&args) {
+        Ok(channels) => channels,
+        Err(err) => return Some(err),
+    };
+    if channels.is_empty() {
+        channels = client.shard_channels.iter().cloned().collect();
+        if channels.is_empty() {
+            return Some(confirmation("sunsubscribe", None, 0));
+        }
+    }
+
+    for channel in channels {
+        if client.shard_channels.remove(&channel) {
+            server.pubsub.sunsubscribe(&channel, client.id);
+        }
+        let _ = client.push_tx.send(confirmation(
+            "sunsubscribe",
+            Some(channel),
+            client.shard_channels.len(),
+        ));
+    }
+    None
+}
+
+/// SPUBLISH shardchannel message
+pub(super) fn handle_spublish(args: Vec<RespFrame>, server: &ServerState) -> RespFrame {
+    let channel = match bulk_to_bytes(&args[0]) {
+        Some(c) => c,
+        None => return RespFrame::Error("ERR channel must be bulk string".into()),
+    };
+    let message = match bulk_to_bytes(&args[1]) {
+        Some(m) => m,
+        None => return RespFrame::Error("ERR message must be bulk string".into()),
+    };
+
+    reply::int(server.pubsub.spublish(&channel, message) as i64)
+}
+
+const PUBSUB_HELP: &[&str] = &[
+    "SHARDCHANNELS [<pattern>]",
+    "    Return the currently active shard level channels matching a <pattern> (default: '*').",
+    "SHARDNUMSUB [<shard-channel> ...]",
+    "    Return the number of subscribers for the specified shard level channel(s).",
+];
+
+/// PUBSUB SHARDCHANNELS [pattern] | SHARDNUMSUB [shardchannel ...]
+pub(super) fn handle_pubsub(args: Vec<RespFrame>, server: &ServerState) -> RespFrame {
+    let Some(sub) = bulk_to_string(&args[0]) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+    let bulk = |b: Bytes| RespFrame::BulkString(Some(b));
+
+    match (sub.to_ascii_uppercase().as_str(), &args[1..]) {
+        ("SHARDCHANNELS", [] | [_]) => {
+            let pattern = args.get(1).and_then(bulk_to_bytes);
+            let channels = server.pubsub.shard_channels(pattern.as_deref());
+            RespFrame::Array(Some(channels.into_iter().map(bulk).collect()))
+        }
+        ("SHARDNUMSUB", channels) => match channel_args(channels) {
+            Ok(channels) => RespFrame::Array(Some(
+                channels
+                    .into_iter()
+                    .flat_map(|channel| {
+                        let count = server.pubsub.shard_numsub(&channel);
+                        [bulk(channel), reply::int(count as i64)]
+                    })
+                    .collect(),
+            )),
+            Err(err) => err,
+        },
+        ("HELP", []) => reply::help("PUBSUB", PUBSUB_HELP),
+        _ => reply::unknown_subcommand("PUBSUB", &sub),
+    }
+}
@@ -0,0 +1,105 @@
+use crate::persistence::aof;
+use crate::protocol::{RespFrame, reply};
+use crate::server::client::ClientState;
+use crate::server::state::ServerState;
+
+use super::bulk_to_string;
+
+/// SYNC: full resync for a replica. The snapshot and all later writes go
+/// through the client's push queue, so there is no direct reply.
+pub(super) fn handle_sync(server: &ServerState, client: &mut ClientState) -> Option<RespFrame> {
+    let _order = server.replication.lock_order();
+    let guard = match server.store.read() {
+        Ok(g) => g,
+        Err(_) => return Some(RespFrame::Error("ERR store lock poisoned".into())),
+    };
+
+    for cmd in aof::snapshot_commands(&guard) {
+        let _ = client.push_tx.send(RespFrame::command(cmd));
+    }
+    server
+        .replication
+        .add_replica(client.id, client.push_tx.clone());
+    tracing::info!(addr = %client.addr, "replica attached");
+    None
+}
+
+/// REPLICAOF host port | REPLICAOF NO ONE
+pub(super) fn handle_replicaof(args: Vec<RespFrame>, server: &ServerState) -> RespFrame {
+    let (Some(host), Some(port)) = (bulk_to_string(&args[0]), bulk_to_string(&args[1])) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+
+    if host.eq_ignore_ascii_case("NO") && port.eq_ignore_ascii_case("ONE") {
+        if server.replication.master().is_some() {
+            tracing::info!("replication stopped, now a master");
+        }
+        server.replication.set_master(None);
+        return reply::ok();
+    }
+
+    let Ok(port) = port.parse::<u16>() else {
+        return RespFrame::Error("ERR Invalid master port".into());
+    };
+    let master = format!("{host}:{port}");
+    if server.replication.master().as_deref() == Some(master.as_str()) {
+        return RespFrame::SimpleString("OK Already connected to specified master".into());
+    }
+    tracing::info!(%master, "replicating from new master");
+    server.replication.set_master(Some(master));
+    reply::ok()
+}
+
+/// FAILOVER [TO host port [FORCE]] [ABORT] [TIMEOUT ms]
+///
+/// Coordinated failover isn't implemented, so there is never one to abort.
+/// The errors are Redis' own, so Sentinel and admin tools probing a
+/// standalone server get the answers they expect.
+pub(super) fn handle_failover(args: Vec<RespFrame>, server: &ServerState) -> RespFrame {
+    if let [flag] = args.as_slice()
+        && bulk_to_string(flag).is_some_and(|f| f.eq_ignore_ascii_case("ABORT"))
+    {
+        return RespFrame::Error("ERR No failover in progress.".into());
+    }
+    if server.replication.master().is_some() {
+        return RespFrame::Error("ERR FAILOVER is not valid when server is a replica.".into());
+    }
+    if server.replication.replica_count() == 0 {
+        return RespFrame::Error("ERR FAILOVER requires connected replicas.".into());
+    }
+    RespFrame::Error("ERR FAILOVER is not supported".into())
+}
+
+/// WAITAOF numlocal numreplicas timeout
+///
+/// Replicas never acknowledge writes here, so only the local half does
+/// anything: the AOF is fsynced on the spot and the reply is immediate,
+/// with no need to wait out `timeout`.
+pub(super) fn handle_waitaof(args: Vec<RespFrame>, server: &ServerState) -> RespFrame {
+    let int = |frame| bulk_to_string(frame).and_then(|s| s.parse::<i64>().ok());
+    let (Some(numlocal), Some(numreplicas), Some(timeout)) =
+        (int(&args[0]), int(&args[1]), int(&args[2]))
+    else {
+        return RespFrame::Error("ERR value is not an integer or out of range".into());
+    };
+    if numlocal < 0 || numreplicas < 0 {
+        return RespFrame::Error("ERR value is out of range, must be positive".into());
+    }
+    if timeout < 0 {
+        return RespFrame::Error("ERR timeout is negative".into());
+    }
+
+    let local = match server.aof.as_ref() {
+        Some(writer) => match writer.sync() {
+            Ok(()) => 1,
+            Err(e) => return RespFrame::Error(format!("ERR error syncing the AOF: {e}")),
+        },
+        None if numlocal > 0 => {
+            return RespFrame::Error(
+                "ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled".into(),
+            );
+        }
+        None => 0,
+    };
+    RespFrame::Array(Some(vec![reply::int(local), reply::int(0)]))
+}
@@ -0,0 +1,135 @@
+use bytes::Bytes;
+
+use crate::protocol::{RespFrame, reply};
+use crate::store::StoreAccess;
+use crate::store::scan::ScanPage;
+
+use super::{bulk_to_bytes, bulk_to_string};
+
+/// Elements examined per call when no COUNT is given.
+const DEFAULT_COUNT: usize = 10;
+
+struct ScanArgs {
+    key: String,
+    cursor: usize,
+    count: usize,
+    pattern: Option<Bytes>,
+}
+
+/// Parse `key cursor [MATCH pattern] [COUNT count]`.
+fn parse_scan_args(args: &[RespFrame]) -> Result<ScanArgs, RespFrame> {
+    let Some(key) = bulk_to_string(&args[0]) else {
+        return Err(RespFrame::Error("ERR key must be bulk string".into()));
+    };
+    let Some(cursor) = bulk_to_string(&args[1]).and_then(|s| s.parse::<usize>().ok()) else {
+        return Err(RespFrame::Error("ERR invalid cursor".into()));
+    };
+
+    let mut parsed = ScanArgs {
+        key,
+        cursor,
+        count: DEFAULT_COUNT,
+        pattern: None,
+    };
+    for opt in args[2..].chunks(2) {
+        let [name, value] = opt else {
+            return Err(RespFrame::Error("ERR syntax error".into()));
+        };
+        match bulk_to_string(name)
+            .map(|n| n.to_ascii_uppercase())
+            .as_deref()
+        {
+            Some("MATCH") => parsed.pattern = bulk_to_bytes(value),
+            Some("COUNT") => {
+                parsed.count = match bulk_to_string(value).and_then(|s| s.parse::<i64>().ok()) {
+                    Some(n) if n >= 1 => n as usize,
+                    Some(_) => return Err(RespFrame::Error("ERR syntax error".into())),
+                    None => {
+                        return Err(RespFrame::Error(
+                            "ERR value is not an integer or out of range".into(),
+                        ));
+                    }
+                };
+            }
+            _ => return Err(RespFrame::Error("ERR syntax error".into())),
+        }
+    }
+    Ok(parsed)
+}
+
+/// The `[cursor, [elements...]]` reply shared by every scan command.
+fn scan_reply<T>(page: ScanPage<T>, mut flatten: impl FnMut(T, &mut Vec<RespFrame>)) -> RespFrame {
+    let mut items = Vec::with_capacity(page.items.len());
+    for item in page.items {
+        flatten(item, &mut items);
+    }
+    RespFrame::Array(Some(vec![
+        RespFrame::BulkString(Some(Bytes::from(page.cursor.to_string()))),
+        RespFrame::Array(Some(items)),
+    ]))
+}
+
+/// HSCAN key cursor [MATCH pattern] [COUNT count]
+pub(super) fn handle_hscan(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let scan = match parse_scan_args(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+
+    match store.read() {
+        Ok(guard) => {
+            let Ok(page) = guard.hscan(&scan.key, scan.cursor, scan.count, scan.pattern.as_deref())
+            else {
+                return reply::wrongtype();
+            };
+            scan_reply(page, |(field, value), out| {
+                out.push(RespFrame::BulkString(Some(field)));
+                out.push(RespFrame::BulkString(Some(value)));
+            })
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// SSCAN key cursor [MATCH pattern] [COUNT count]
+pub(super) fn handle_sscan(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let scan = match parse_scan_args(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+
+    match store.read() {
+        Ok(guard) => {
+            let Ok(page) = guard.sscan(&scan.key, scan.cursor, scan.count, scan.pattern.as_deref())
+            else {
+                return reply::wrongtype();
+            };
+            scan_reply(page, |member, out| {
+                out.push(RespFrame::BulkString(Some(member)));
+            })
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// ZSCAN key cursor [MATCH pattern] [COUNT count]
+pub(super) fn handle_zscan(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let scan = match parse_scan_args(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+
+    match store.read() {
+        Ok(guard) => {
+            let Ok(page) = guard.zscan(&scan.key, scan.cursor, scan.count, scan.pattern.as_deref())
+            else {
+                return reply::wrongtype();
+            };
+            scan_reply(page, |(member, score), out| {
+                out.push(RespFrame::BulkString(Some(member)));
+                out.push(RespFrame::BulkString(Some(Bytes::from(score.to_string()))));
+            })
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
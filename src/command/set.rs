@@ -1,52 +1,38 @@
-use crate::persistence::aof::AofWriter;
-use crate::protocol::RespFrame;
-use crate::store::SharedStore;
+use crate::propagate::Effects;
+use crate::protocol::{RespFrame, Sample, reply};
+use crate::store::{StoreAccess, TypeError, random};
 
-use super::{bulk_to_bytes, bulk_to_string};
+use super::{bulk_to_bytes, bulk_to_string, parse_intercard_args, parse_rand_count};
 
 pub(super) fn handle_sadd(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.len() < 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'sadd'".into());
-    }
-
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
     };
 
     let mut members = Vec::with_capacity(args.len() - 1);
-    let mut mem_strs = Vec::with_capacity(args.len() - 1);
     for arg in &args[1..] {
         match bulk_to_bytes(arg) {
-            Some(b) => {
-                mem_strs.push(String::from_utf8_lossy(&b).into_owned());
-                members.push(b);
-            }
+            Some(b) => members.push(b),
             None => return RespFrame::Error("ERR member must be bulk string".into()),
         }
     }
 
     match store.write() {
         Ok(mut guard) => {
-            if !guard.is_type(&key, "set") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            let added = guard.sadd(key.clone(), members);
-            if added > 0
-                && let Some(w) = aof
-            {
-                let mut a: Vec<String> = vec!["SADD".into(), key];
-                a.extend(mem_strs);
-                let refs: Vec<&str> = a.iter().map(|s| s.as_str()).collect();
-                w.append(&refs);
+            let Ok(added) = guard.sadd(key.clone(), members.clone()) else {
+                return reply::wrongtype();
+            };
+            if added > 0 {
+                let mut a: Vec<&[u8]> = vec![b"SADD", key.as_bytes()];
+                a.extend(members.iter().map(|m| m.as_ref()));
+                effects.push_bytes(&a);
             }
-            RespFrame::Integer(added as i64)
+            reply::int(added as i64)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
@@ -54,77 +40,110 @@ pub(super) fn handle_sadd(
 
 pub(super) fn handle_srem(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.len() < 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'srem'".into());
-    }
-
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
     };
 
     let mut members = Vec::with_capacity(args.len() - 1);
-    let mut mem_strs = Vec::with_capacity(args.len() - 1);
     for arg in &args[1..] {
         match bulk_to_bytes(arg) {
-            Some(b) => {
-                mem_strs.push(String::from_utf8_lossy(&b).into_owned());
-                members.push(b);
-            }
+            Some(b) => members.push(b),
             None => return RespFrame::Error("ERR member must be bulk string".into()),
         }
     }
 
     match store.write() {
         Ok(mut guard) => {
-            if !guard.is_type(&key, "set") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
+            let Ok(removed) = guard.srem(&key, members.clone()) else {
+                return reply::wrongtype();
+            };
+            if removed > 0 {
+                let mut a: Vec<&[u8]> = vec![b"SREM", key.as_bytes()];
+                a.extend(members.iter().map(|m| m.as_ref()));
+                effects.push_bytes(&a);
             }
-            let removed = guard.srem(&key, members);
-            if removed > 0
-                && let Some(w) = aof
-            {
-                let mut a: Vec<String> = vec!["SREM".into(), key];
-                a.extend(mem_strs);
-                let refs: Vec<&str> = a.iter().map(|s| s.as_str()).collect();
-                w.append(&refs);
-            }
-            RespFrame::Integer(removed as i64)
+            reply::int(removed as i64)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-pub(super) fn handle_smembers(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() != 1 {
-        return RespFrame::Error("ERR wrong number of arguments for 'smembers'".into());
+pub(super) fn handle_smembers(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    match store.read() {
+        Ok(guard) => match guard.smembers(&key) {
+            Ok(members) => RespFrame::Array(Some(
+                members
+                    .map(|b| RespFrame::BulkString(Some(b.clone())))
+                    .collect(),
+            )),
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
+}
 
+/// SRANDMEMBER key [count]
+pub(super) fn handle_srandmember(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
     };
 
+    let count = match args
+        .get(1)
+        .map(|arg| parse_rand_count(arg, false))
+        .transpose()
+    {
+        Ok(count) => count,
+        Err(err) => return err,
+    };
+
     match store.read() {
         Ok(guard) => {
-            if !guard.is_type(&key, "set") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
+            // A negative count draws with repeats, from every member.
+            let distinct = match count {
+                Some(n) => usize::try_from(n).unwrap_or(usize::MAX),
+                None => 1,
+            };
+            let Ok(members) = guard.srandmember(&key, distinct) else {
+                return reply::wrongtype();
+            };
+            let mut frames = members.into_iter().map(|b| RespFrame::BulkString(Some(b)));
+            match count {
+                Some(n) if n < 0 => RespFrame::Sample(Sample::new(
+                    frames.collect(),
+                    1,
+                    n.unsigned_abs() as usize,
+                    random::next_u64(),
+                )),
+                Some(_) => RespFrame::Array(Some(frames.collect())),
+                None => frames.next().unwrap_or(RespFrame::BulkString(None)),
             }
-            let items = guard.smembers(&key);
-            RespFrame::Array(Some(
-                items
-                    .into_iter()
-                    .map(|b| RespFrame::BulkString(Some(b)))
-                    .collect(),
-            ))
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
+
+pub(super) fn handle_sintercard(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let (keys, limit) = match parse_intercard_args(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+
+    match store.read() {
+        Ok(guard) => match guard.sintercard(&keys, limit) {
+            Ok(count) => reply::int(count as i64),
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
@@ -0,0 +1,51 @@
+use crate::observability;
+use crate::protocol::RespFrame;
+use crate::server::state::ServerState;
+
+use super::bulk_to_string;
+
+/// SHUTDOWN [NOSAVE|SAVE]: rewrite the AOF from the dataset unless NOSAVE
+/// is given, flush and fsync it, remove the unix socket and flush the log,
+/// then exit. There is no reply on success; the client just sees the
+/// connection close. A failed save refuses to exit, as Redis does, while
+/// under NOSAVE errors are only logged.
+pub(super) fn handle_shutdown(args: Vec<RespFrame>, server: &ServerState) -> RespFrame {
+    let save = match args.first().map(bulk_to_string) {
+        None => true,
+        Some(Some(flag)) if flag.eq_ignore_ascii_case("SAVE") => true,
+        Some(Some(flag)) if flag.eq_ignore_ascii_case("NOSAVE") => false,
+        Some(_) => return RespFrame::Error("ERR syntax error".into()),
+    };
+
+    // Holding both locks until exit means no write lands after the save, or
+    // reaches the AOF after its final fsync.
+    let _order = server.replication.lock_order();
+    let guard = match server.store.write() {
+        Ok(g) => g,
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    tracing::warn!(save, "user requested shutdown");
+
+    if let Some(writer) = server.aof.as_ref() {
+        let result = if save {
+            writer.rewrite(&guard).and_then(|()| writer.sync())
+        } else {
+            writer.sync()
+        };
+        if let Err(e) = result {
+            tracing::error!(error = %e, "error saving the AOF on shutdown");
+            if save {
+                return RespFrame::Error("ERR Errors trying to SHUTDOWN. Check logs.".into());
+            }
+        }
+    }
+
+    if let Some(path) = &server.unixsocket
+        && let Err(e) = std::fs::remove_file(path)
+    {
+        tracing::warn!(error = %e, path = %path.display(), "error removing the unix socket");
+    }
+    tracing::warn!("rfs-rs is now ready to exit, bye bye");
+    observability::flush_logs();
+    std::process::exit(0)
+}
@@ -0,0 +1,66 @@
+use bytes::Bytes;
+
+use crate::protocol::{RespFrame, reply};
+use crate::server::state::ServerState;
+
+use super::bulk_to_string;
+
+/// Entries returned by SLOWLOG GET without a count.
+const SLOWLOG_DEFAULT_COUNT: usize = 10;
+
+const SLOWLOG_HELP: &[&str] = &[
+    "GET [<count>]",
+    "    Return top <count> entries from the slowlog (default: 10, -1 means all).",
+    "LEN",
+    "    Return the length of the slowlog.",
+    "RESET",
+    "    Reset the slowlog.",
+];
+
+/// SLOWLOG GET [count] | LEN | RESET
+pub(super) fn handle_slowlog(args: Vec<RespFrame>, server: &ServerState) -> RespFrame {
+    let Some(sub) = bulk_to_string(&args[0]) else {
+        return RespFrame::Error("ERR syntax error".into());
+    };
+
+    match (sub.to_ascii_uppercase().as_str(), &args[1..]) {
+        ("GET", [count]) => match bulk_to_string(count).and_then(|s| s.parse::<i64>().ok()) {
+            Some(-1) => slowlog_get(server, None),
+            Some(n) if n >= 0 => slowlog_get(server, Some(n as usize)),
+            _ => RespFrame::Error("ERR count should be greater than or equal to -1".into()),
+        },
+        ("GET", []) => slowlog_get(server, Some(SLOWLOG_DEFAULT_COUNT)),
+        ("LEN", []) => reply::int(server.slowlog.len() as i64),
+        ("RESET", []) => {
+            server.slowlog.reset();
+            reply::ok()
+        }
+        ("HELP", []) => reply::help("SLOWLOG", SLOWLOG_HELP),
+        _ => reply::unknown_subcommand("SLOWLOG", &sub),
+    }
+}
+
+fn slowlog_get(server: &ServerState, count: Option<usize>) -> RespFrame {
+    let entries = server
+        .slowlog
+        .get(count)
+        .into_iter()
+        .map(|e| {
+            RespFrame::Array(Some(vec![
+                reply::int(e.id as i64),
+                reply::int(e.timestamp as i64),
+                reply::int(e.duration_micros as i64),
+                RespFrame::Array(Some(
+                    e.args
+                        .into_iter()
+                        .map(|a| RespFrame::BulkString(Some(a)))
+                        .collect(),
+                )),
+                RespFrame::BulkString(Some(Bytes::from(e.client_addr))),
+                // Client names aren't supported.
+                RespFrame::BulkString(Some(Bytes::new())),
+            ]))
+        })
+        .collect();
+    RespFrame::Array(Some(entries))
+}
@@ -1,23 +1,24 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::persistence::aof::AofWriter;
-use crate::protocol::RespFrame;
-use crate::store::SharedStore;
+use bytes::Bytes;
+
+use crate::propagate::Effects;
+use crate::protocol::{RespFrame, reply};
+use crate::store::expire::{ExpireCondition, instant_from_unix_millis, unix_millis_from_instant};
+use crate::store::number::{IncrError, parse_i64};
 use crate::store::value::Value;
+use crate::store::{StoreAccess, TypeError};
 
 use super::bulk_to_string;
 
-// ── SET with EX/PX ────────────────────────────────────────────────────────
+// ── SET / GETSET ──────────────────────────────────────────────────────────
 
+/// SET key value [NX | XX] [GET] [EX s | PX ms | EXAT ts | PXAT ts-ms | KEEPTTL]
 pub(super) fn handle_set(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.len() < 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'set'".into());
-    }
-
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -29,10 +30,8 @@ pub(super) fn handle_set(
     };
     let value = Value::String(val_bytes.clone());
 
-    // Parse optional flags: EX seconds | PX milliseconds
-    let mut ttl: Option<Duration> = None;
-    let mut aof_args: Vec<String> = vec!["SET".into(), key.clone()];
-    aof_args.push(String::from_utf8_lossy(&val_bytes).into_owned());
+    let (mut nx, mut xx, mut get, mut keep_ttl) = (false, false, false, false);
+    let mut ttl: Option<Instant> = None;
 
     let mut i = 2;
     while i < args.len() {
@@ -41,88 +40,587 @@ pub(super) fn handle_set(
             None => return RespFrame::Error("ERR syntax error".into()),
         };
         match flag.as_str() {
-            "EX" => {
-                i += 1;
-                let secs = match args.get(i).and_then(bulk_to_string) {
-                    Some(s) => match s.parse::<u64>() {
-                        Ok(v) if v > 0 => v,
-                        _ => return RespFrame::Error("ERR invalid expire time in 'set'".into()),
-                    },
-                    None => return RespFrame::Error("ERR syntax error".into()),
-                };
-                ttl = Some(Duration::from_secs(secs));
-                aof_args.push("PX".into());
-                aof_args.push((secs * 1000).to_string());
-            }
-            "PX" => {
+            "NX" if !xx => nx = true,
+            "XX" if !nx => xx = true,
+            "GET" => get = true,
+            "KEEPTTL" if ttl.is_none() => keep_ttl = true,
+            "EX" | "PX" | "EXAT" | "PXAT" if ttl.is_none() && !keep_ttl => {
                 i += 1;
-                let ms = match args.get(i).and_then(bulk_to_string) {
-                    Some(s) => match s.parse::<u64>() {
+                let n = match args.get(i).and_then(bulk_to_string) {
+                    Some(s) => match s.parse::<i64>() {
                         Ok(v) if v > 0 => v,
                         _ => return RespFrame::Error("ERR invalid expire time in 'set'".into()),
                     },
                     None => return RespFrame::Error("ERR syntax error".into()),
                 };
-                ttl = Some(Duration::from_millis(ms));
-                aof_args.push("PX".into());
-                aof_args.push(ms.to_string());
+                let millis = matches!(flag.as_str(), "PX" | "PXAT");
+                let absolute = matches!(flag.as_str(), "EXAT" | "PXAT");
+                match expire_deadline(n, millis, absolute, Instant::now()) {
+                    Some(deadline) => ttl = Some(deadline),
+                    None => return RespFrame::Error("ERR invalid expire time in 'set'".into()),
+                }
             }
             _ => return RespFrame::Error("ERR syntax error".into()),
         }
         i += 1;
     }
 
+    let mut guard = match store.write() {
+        Ok(g) => g,
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    let old = if get {
+        match guard.get_string(&key) {
+            Ok(old) => old,
+            Err(TypeError) => return reply::wrongtype(),
+        }
+    } else {
+        None
+    };
+
+    let exists = guard.exists(std::slice::from_ref(&key)) > 0;
+    if (nx && exists) || (xx && !exists) {
+        return if get {
+            RespFrame::BulkString(old)
+        } else {
+            reply::nil()
+        };
+    }
+    if keep_ttl {
+        ttl = guard.deadline(&key);
+    }
+
+    // Only the outcome is propagated: NX/XX replayed against a different
+    // dataset could decide the other way, and relative or kept TTLs would
+    // drift. The AOF records the absolute deadline so a replay after a
+    // restart doesn't restart the countdown.
+    match ttl {
+        Some(deadline) => {
+            guard.set_with_deadline(key.clone(), value, deadline);
+            let at = unix_millis_from_instant(deadline).to_string();
+            effects.push_bytes(&[b"SET", key.as_bytes(), &val_bytes, b"PXAT", at.as_bytes()]);
+        }
+        None => {
+            guard.set(key.clone(), value);
+            effects.push_bytes(&[b"SET", key.as_bytes(), &val_bytes]);
+        }
+    }
+    if get {
+        RespFrame::BulkString(old)
+    } else {
+        reply::ok()
+    }
+}
+
+/// GETSET key value, the deprecated spelling of SET key value GET.
+pub(super) fn handle_getset(
+    mut args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    args.push(RespFrame::BulkString(Some(Bytes::from_static(b"GET"))));
+    handle_set(args, store, effects)
+}
+
+// ── GET ───────────────────────────────────────────────────────────────────
+
+pub(super) fn handle_get(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
     match store.write() {
-        Ok(mut guard) => {
-            match ttl {
-                Some(dur) => guard.set_with_expiry(key, value, dur),
-                None => guard.set(key, value),
-            }
-            if let Some(w) = aof {
-                let refs: Vec<&str> = aof_args.iter().map(|s| s.as_str()).collect();
-                w.append(&refs);
+        Ok(mut guard) => match guard.get_string(&key) {
+            Ok(Some(bytes)) => RespFrame::BulkString(Some(bytes)),
+            Ok(None) => reply::nil(),
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+// ── INCR / DECR / APPEND ──────────────────────────────────────────────────
+
+/// INCR key | INCRBY key increment, or with `sign` -1, DECR and DECRBY.
+///
+/// All four are replicated as INCRBY with the signed step.
+pub(super) fn handle_incr(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+    sign: i64,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let step = match args.get(1) {
+        Some(RespFrame::BulkString(Some(b))) => match parse_i64(b) {
+            Some(n) => n,
+            None => {
+                return RespFrame::Error("ERR value is not an integer or out of range".into());
             }
-            RespFrame::SimpleString("OK".into())
+        },
+        Some(_) => return RespFrame::Error("ERR value is not an integer or out of range".into()),
+        None => 1,
+    };
+    let Some(delta) = step.checked_mul(sign) else {
+        return RespFrame::Error("ERR decrement would overflow".into());
+    };
+
+    let result = match store.write() {
+        Ok(mut guard) => guard.incr_by(&key, delta),
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    match result {
+        Ok(n) => {
+            effects.push(&["INCRBY", &key, &delta.to_string()]);
+            reply::int(n)
         }
+        Err(IncrError::WrongType) => reply::wrongtype(),
+        Err(IncrError::NotInteger) => {
+            RespFrame::Error("ERR value is not an integer or out of range".into())
+        }
+        Err(IncrError::Overflow) => {
+            RespFrame::Error("ERR increment or decrement would overflow".into())
+        }
+    }
+}
+
+/// APPEND key value
+pub(super) fn handle_append(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let RespFrame::BulkString(Some(value)) = &args[1] else {
+        return RespFrame::Error("ERR value must be bulk string".into());
+    };
+
+    match store.write() {
+        Ok(mut guard) => match guard.append(&key, value) {
+            Ok(len) => {
+                effects.push_bytes(&[b"APPEND", key.as_bytes(), value]);
+                reply::int(len as i64)
+            }
+            Err(TypeError) => reply::wrongtype(),
+        },
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-// ── GET ───────────────────────────────────────────────────────────────────
+// ── GETRANGE / SETRANGE ───────────────────────────────────────────────────
 
-pub(super) fn handle_get(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() != 1 {
-        return RespFrame::Error("ERR wrong number of arguments for 'get'".into());
+/// Longest string SETRANGE may produce, Redis' 512MB cap.
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+/// GETRANGE key start end
+pub(super) fn handle_getrange(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let int = |frame| bulk_to_string(frame).and_then(|s| s.parse::<i64>().ok());
+    let (Some(start), Some(end)) = (int(&args[1]), int(&args[2])) else {
+        return RespFrame::Error("ERR value is not an integer or out of range".into());
+    };
+
+    match store.write() {
+        Ok(mut guard) => match guard.getrange(&key, start, end) {
+            Ok(bytes) => RespFrame::BulkString(Some(bytes)),
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
+}
 
+/// STRLEN key: an empty string and a missing key both have length 0.
+pub(super) fn handle_strlen(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
     };
 
     match store.write() {
-        Ok(mut guard) => match guard.get(&key) {
-            Some(Value::String(bytes)) => RespFrame::BulkString(Some(bytes)),
-            Some(_) => RespFrame::Error(
-                "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-            ),
-            None => RespFrame::BulkString(None),
+        Ok(mut guard) => match guard.get_string(&key) {
+            Ok(bytes) => reply::int(bytes.map_or(0, |b| b.len()) as i64),
+            Err(TypeError) => reply::wrongtype(),
         },
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-// ── DEL ───────────────────────────────────────────────────────────────────
+/// SETRANGE key offset value
+///
+/// Replicated as itself: the overwrite is deterministic and, unlike a SET
+/// of the result, leaves the TTL alone.
+pub(super) fn handle_setrange(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let Some(offset) = bulk_to_string(&args[1]).and_then(|s| s.parse::<i64>().ok()) else {
+        return RespFrame::Error("ERR value is not an integer or out of range".into());
+    };
+    let Ok(offset) = usize::try_from(offset) else {
+        return RespFrame::Error("ERR offset is out of range".into());
+    };
+    let RespFrame::BulkString(Some(value)) = &args[2] else {
+        return RespFrame::Error("ERR value must be bulk string".into());
+    };
+    if !value.is_empty() && offset.saturating_add(value.len()) > MAX_STRING_LEN {
+        return RespFrame::Error(
+            "ERR string exceeds maximum allowed size (proto-max-bulk-len)".into(),
+        );
+    }
 
-pub(super) fn handle_del(
+    match store.write() {
+        Ok(mut guard) => match guard.setrange(&key, offset, value) {
+            Ok(len) => {
+                if !value.is_empty() {
+                    let offset = offset.to_string();
+                    effects.push_bytes(&[b"SETRANGE", key.as_bytes(), offset.as_bytes(), value]);
+                }
+                reply::int(len as i64)
+            }
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+// ── GETEX ─────────────────────────────────────────────────────────────────
+
+enum GetExTtl {
+    Keep,
+    At(Instant),
+    Persist,
+}
+
+pub(super) fn handle_getex(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    let now = Instant::now();
+    let ttl = match &args[1..] {
+        [] => GetExTtl::Keep,
+        [opt] if bulk_to_string(opt).is_some_and(|o| o.eq_ignore_ascii_case("PERSIST")) => {
+            GetExTtl::Persist
+        }
+        [opt, value] => {
+            let Some(opt) = bulk_to_string(opt).map(|o| o.to_ascii_uppercase()) else {
+                return RespFrame::Error("ERR syntax error".into());
+            };
+            let n = match bulk_to_string(value).and_then(|s| s.parse::<i64>().ok()) {
+                Some(v) if v > 0 => v,
+                _ => return RespFrame::Error("ERR invalid expire time in 'getex'".into()),
+            };
+            let (millis, absolute) = match opt.as_str() {
+                "EX" => (false, false),
+                "PX" => (true, false),
+                "EXAT" => (false, true),
+                "PXAT" => (true, true),
+                _ => return RespFrame::Error("ERR syntax error".into()),
+            };
+            match expire_deadline(n, millis, absolute, now) {
+                Some(deadline) => GetExTtl::At(deadline),
+                None => return RespFrame::Error("ERR invalid expire time in 'getex'".into()),
+            }
+        }
+        _ => return RespFrame::Error("ERR syntax error".into()),
+    };
+
+    match store.write() {
+        Ok(mut guard) => {
+            let bytes = match guard.get_string(&key) {
+                Ok(Some(bytes)) => bytes,
+                Ok(None) => return reply::nil(),
+                Err(TypeError) => return reply::wrongtype(),
+            };
+            match ttl {
+                GetExTtl::Keep => {}
+                // Already past, as EXAT/PXAT can be: the value is still
+                // returned, but the key goes now, as with EXPIREAT.
+                GetExTtl::At(deadline) if deadline <= now => {
+                    guard.del(std::slice::from_ref(&key));
+                    effects.push(&["DEL", &key]);
+                }
+                GetExTtl::At(deadline) => {
+                    guard.set_expiry(&key, deadline, ExpireCondition::default());
+                    let at = unix_millis_from_instant(deadline).to_string();
+                    effects.push(&["PEXPIREAT", &key, &at]);
+                }
+                GetExTtl::Persist => {
+                    if guard.persist(&key) {
+                        effects.push(&["PERSIST", &key]);
+                    }
+                }
+            }
+            RespFrame::BulkString(Some(bytes))
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+// ── GETDEL ────────────────────────────────────────────────────────────────
+
+/// Unlike DEL, which removes a key of any type, GETDEL only takes strings:
+/// any other type is a WRONGTYPE error and the key is left in place.
+pub(super) fn handle_getdel(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.is_empty() {
-        return RespFrame::Error("ERR wrong number of arguments for 'del'".into());
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    match store.write() {
+        Ok(mut guard) => match guard.get_string(&key) {
+            Ok(Some(bytes)) => {
+                guard.del(std::slice::from_ref(&key));
+                effects.push(&["DEL", &key]);
+                RespFrame::BulkString(Some(bytes))
+            }
+            Ok(None) => reply::nil(),
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+// ── LCS ───────────────────────────────────────────────────────────────────
+
+/// LCS key1 key2 [LEN] [IDX] [MINMATCHLEN len] [WITHMATCHLEN]
+///
+/// Missing keys count as empty strings.
+pub(super) fn handle_lcs(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let (Some(key_a), Some(key_b)) = (bulk_to_string(&args[0]), bulk_to_string(&args[1])) else {
+        return RespFrame::Error("ERR key must be bulk string".into());
+    };
+
+    let mut len_only = false;
+    let mut idx = false;
+    let mut min_match_len = 0;
+    let mut with_match_len = false;
+    let mut i = 2;
+    while i < args.len() {
+        let opt = bulk_to_string(&args[i]).unwrap_or_default();
+        match opt.to_ascii_uppercase().as_str() {
+            "LEN" => len_only = true,
+            "IDX" => idx = true,
+            "WITHMATCHLEN" => with_match_len = true,
+            "MINMATCHLEN" => {
+                i += 1;
+                let Some(n) = args.get(i).and_then(bulk_to_string) else {
+                    return RespFrame::Error("ERR syntax error".into());
+                };
+                match n.parse::<i64>() {
+                    Ok(n) => min_match_len = n.max(0) as usize,
+                    Err(_) => {
+                        return RespFrame::Error(
+                            "ERR value is not an integer or out of range".into(),
+                        );
+                    }
+                }
+            }
+            _ => return RespFrame::Error("ERR syntax error".into()),
+        }
+        i += 1;
+    }
+    if len_only && idx {
+        return RespFrame::Error(
+            "ERR If you want both the length and indexes, please just use IDX.".into(),
+        );
+    }
+
+    let (a, b) = match store.write() {
+        Ok(mut guard) => {
+            let (Ok(a), Ok(b)) = (guard.get_string(&key_a), guard.get_string(&key_b)) else {
+                return reply::wrongtype();
+            };
+            (a.unwrap_or_default(), b.unwrap_or_default())
+        }
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+
+    // The length alone needs only two rows of the table.
+    if len_only {
+        return reply::int(lcs_len(&a, &b) as i64);
+    }
+    let Some(table) = LcsTable::new(&a, &b) else {
+        return RespFrame::Error(
+            "ERR Insufficient memory, transient memory for LCS exceeds the limit".into(),
+        );
+    };
+    let (subsequence, matches) = table.backtrack(&a, &b, min_match_len);
+    if !idx {
+        return RespFrame::BulkString(Some(subsequence.into()));
+    }
+
+    let range = |start: usize, end: usize| {
+        RespFrame::Array(Some(vec![reply::int(start as i64), reply::int(end as i64)]))
+    };
+    let matches = matches
+        .into_iter()
+        .map(|m| {
+            let mut entry = vec![range(m.a.0, m.a.1), range(m.b.0, m.b.1)];
+            if with_match_len {
+                entry.push(reply::int(m.len() as i64));
+            }
+            RespFrame::Array(Some(entry))
+        })
+        .collect();
+    RespFrame::Array(Some(vec![
+        RespFrame::BulkString(Some(Bytes::from_static(b"matches"))),
+        RespFrame::Array(Some(matches)),
+        RespFrame::BulkString(Some(Bytes::from_static(b"len"))),
+        reply::int(subsequence.len() as i64),
+    ]))
+}
+
+/// Entries allowed in an LCS table, keeping it within 512MB.
+const LCS_MAX_CELLS: usize = 128 * 1024 * 1024;
+
+/// Length of the longest common subsequence of `a` and `b`, computed a row
+/// at a time.
+fn lcs_len(a: &[u8], b: &[u8]) -> usize {
+    let mut prev = vec![0u32; b.len() + 1];
+    let mut row = vec![0u32; b.len() + 1];
+    for &x in a {
+        for (j, &y) in b.iter().enumerate() {
+            row[j + 1] = if x == y {
+                prev[j] + 1
+            } else {
+                prev[j + 1].max(row[j])
+            };
+        }
+        std::mem::swap(&mut prev, &mut row);
+    }
+    prev[b.len()] as usize
+}
+
+/// One run of consecutive matching bytes: inclusive byte ranges into each
+/// string.
+struct LcsMatch {
+    a: (usize, usize),
+    b: (usize, usize),
+}
+
+impl LcsMatch {
+    fn len(&self) -> usize {
+        self.a.1 - self.a.0 + 1
+    }
+}
+
+/// The full dynamic-programming table, where cell `(i, j)` holds the LCS
+/// length of `a[..i]` and `b[..j]`.
+struct LcsTable {
+    cells: Vec<u32>,
+    width: usize,
+}
+
+impl LcsTable {
+    /// Fill the table, or `None` if it would exceed [`LCS_MAX_CELLS`].
+    fn new(a: &[u8], b: &[u8]) -> Option<Self> {
+        let width = b.len() + 1;
+        let size = (a.len() + 1)
+            .checked_mul(width)
+            .filter(|&n| n <= LCS_MAX_CELLS)?;
+        let mut table = Self {
+            cells: vec![0; size],
+            width,
+        };
+        for (i, &x) in a.iter().enumerate() {
+            for (j, &y) in b.iter().enumerate() {
+                let value = if x == y {
+                    table.get(i, j) + 1
+                } else {
+                    table.get(i, j + 1).max(table.get(i + 1, j))
+                };
+                table.cells[(i + 1) * width + j + 1] = value;
+            }
+        }
+        Some(table)
+    }
+
+    fn get(&self, i: usize, j: usize) -> u32 {
+        self.cells[i * self.width + j]
     }
 
+    /// Walk back from the end, collecting the subsequence and its runs of
+    /// at least `min_match_len` bytes, last run first as Redis reports
+    /// them. Ties step back through `b`, which picks the same subsequence
+    /// as Redis.
+    fn backtrack(&self, a: &[u8], b: &[u8], min_match_len: usize) -> (Vec<u8>, Vec<LcsMatch>) {
+        let mut subsequence = Vec::with_capacity(self.get(a.len(), b.len()) as usize);
+        let mut matches = Vec::new();
+        let mut run: Option<LcsMatch> = None;
+        let mut flush = |run: &mut Option<LcsMatch>| {
+            if let Some(m) = run.take()
+                && m.len() >= min_match_len
+            {
+                matches.push(m);
+            }
+        };
+
+        let (mut i, mut j) = (a.len(), b.len());
+        while i > 0 && j > 0 {
+            if a[i - 1] == b[j - 1] {
+                subsequence.push(a[i - 1]);
+                match &mut run {
+                    Some(m) if m.a.0 == i && m.b.0 == j => {
+                        m.a.0 -= 1;
+                        m.b.0 -= 1;
+                    }
+                    _ => {
+                        flush(&mut run);
+                        run = Some(LcsMatch {
+                            a: (i - 1, i - 1),
+                            b: (j - 1, j - 1),
+                        });
+                    }
+                }
+                i -= 1;
+                j -= 1;
+            } else {
+                flush(&mut run);
+                if self.get(i - 1, j) > self.get(i, j - 1) {
+                    i -= 1;
+                } else {
+                    j -= 1;
+                }
+            }
+        }
+        flush(&mut run);
+        subsequence.reverse();
+        (subsequence, matches)
+    }
+}
+
+// ── DEL ───────────────────────────────────────────────────────────────────
+
+pub(super) fn handle_del(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
     let mut keys = Vec::with_capacity(args.len());
     for arg in &args {
         match bulk_to_string(arg) {
@@ -134,28 +632,39 @@ pub(super) fn handle_del(
     match store.write() {
         Ok(mut guard) => {
             let removed = guard.del(&keys);
-            if removed > 0
-                && let Some(w) = aof
-            {
+            if removed > 0 {
                 let mut a = vec!["DEL"];
                 for k in &keys {
                     a.push(k);
                 }
-                w.append(&a);
+                effects.push(&a);
             }
-            RespFrame::Integer(removed as i64)
+            reply::int(removed as i64)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-// ── EXISTS ────────────────────────────────────────────────────────────────
+// ── UNLINK ────────────────────────────────────────────────────────────────
 
-pub(super) fn handle_exists(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.is_empty() {
-        return RespFrame::Error("ERR wrong number of arguments for 'exists'".into());
+/// Collections with more elements than this are freed on a background task.
+const LAZYFREE_THRESHOLD: usize = 64;
+
+fn is_large(value: &Value) -> bool {
+    match value {
+        Value::String(_) => false,
+        Value::List(deque) => deque.len() > LAZYFREE_THRESHOLD,
+        Value::Set(hs) => hs.len() > LAZYFREE_THRESHOLD,
+        Value::Hash(hm) => hm.len() > LAZYFREE_THRESHOLD,
+        Value::ZSet(vec) => vec.len() > LAZYFREE_THRESHOLD,
     }
+}
 
+pub(super) fn handle_unlink(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
     let mut keys = Vec::with_capacity(args.len());
     for arg in &args {
         match bulk_to_string(arg) {
@@ -164,20 +673,191 @@ pub(super) fn handle_exists(args: Vec<RespFrame>, store: &SharedStore) -> RespFr
         }
     }
 
+    let removed = match store.write() {
+        Ok(mut guard) => guard.unlink(&keys),
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+
+    let count = removed.len();
+    if count > 0 {
+        // Replay is identical to DEL, so persist it as one.
+        let mut a = vec!["DEL"];
+        for k in &keys {
+            a.push(k);
+        }
+        effects.push(&a);
+    }
+
+    // Freeing a huge collection can take a while; do it off the connection.
+    let (large, small): (Vec<Value>, Vec<Value>) = removed.into_iter().partition(is_large);
+    drop(small);
+    if !large.is_empty() {
+        tokio::task::spawn_blocking(move || drop(large));
+    }
+
+    reply::int(count as i64)
+}
+
+// ── FLUSHDB / FLUSHALL ────────────────────────────────────────────────────
+
+/// FLUSHDB | FLUSHALL [ASYNC | SYNC]. There is only one database, so the two
+/// are the same command.
+pub(super) fn handle_flush(
+    name: &str,
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let lazy = match args.as_slice() {
+        [] => false,
+        [mode] => match bulk_to_string(mode)
+            .map(|m| m.to_ascii_uppercase())
+            .as_deref()
+        {
+            Some("SYNC") => false,
+            Some("ASYNC") => true,
+            _ => return RespFrame::Error("ERR syntax error".into()),
+        },
+        _ => return RespFrame::Error("ERR syntax error".into()),
+    };
+
+    let old = match store.write() {
+        Ok(mut guard) => guard.flush(),
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    effects.push(&[name]);
+
+    // As with UNLINK, freeing the old dataset can take a while.
+    if lazy {
+        tokio::task::spawn_blocking(move || drop(old));
+    } else {
+        drop(old);
+    }
+
+    reply::ok()
+}
+
+// ── COPY ──────────────────────────────────────────────────────────────────
+
+/// COPY source destination [REPLACE]
+pub(super) fn handle_copy(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let (Some(src), Some(dst)) = (bulk_to_string(&args[0]), bulk_to_string(&args[1])) else {
+        return RespFrame::Error("ERR key must be bulk string".into());
+    };
+    let replace = match args.get(2).map(bulk_to_string) {
+        None => false,
+        Some(Some(opt)) if opt.eq_ignore_ascii_case("REPLACE") => true,
+        Some(_) => return RespFrame::Error("ERR syntax error".into()),
+    };
+    if src == dst {
+        return RespFrame::Error("ERR source and destination objects are the same".into());
+    }
+
     match store.write() {
-        Ok(mut guard) => RespFrame::Integer(guard.exists(&keys) as i64),
+        Ok(mut guard) => {
+            if !guard.copy(&src, &dst, replace) {
+                return reply::int(0);
+            }
+            if replace {
+                effects.push(&["COPY", &src, &dst, "REPLACE"]);
+            } else {
+                effects.push(&["COPY", &src, &dst]);
+            }
+            reply::int(1)
+        }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-// ── TTL / PTTL ────────────────────────────────────────────────────────────
+// ── CAS ───────────────────────────────────────────────────────────────────
+
+/// CAS key expected new
+///
+/// Atomic under the write lock, so it's safe to use for optimistic
+/// concurrency without WATCH/MULTI. Replicated as a plain SET, and only
+/// when the swap happens.
+pub(super) fn handle_cas(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let (RespFrame::BulkString(Some(expected)), RespFrame::BulkString(Some(new))) =
+        (&args[1], &args[2])
+    else {
+        return RespFrame::Error("ERR value must be bulk string".into());
+    };
+
+    match store.write() {
+        Ok(mut guard) => {
+            if !guard.is_type(&key, "string") {
+                return reply::wrongtype();
+            }
+            if !guard.compare_and_swap(&key, expected, new.clone()) {
+                return reply::int(0);
+            }
+            effects.push_bytes(&[b"SET", key.as_bytes(), new]);
+            reply::int(1)
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+// ── EXISTS ────────────────────────────────────────────────────────────────
+
+pub(super) fn handle_exists(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let mut keys = Vec::with_capacity(args.len());
+    for arg in &args {
+        match bulk_to_string(arg) {
+            Some(k) => keys.push(k),
+            None => return RespFrame::Error("ERR key must be bulk string".into()),
+        }
+    }
+
+    match store.write() {
+        Ok(mut guard) => reply::int(guard.exists(&keys) as i64),
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
 
-pub(super) fn handle_ttl(args: Vec<RespFrame>, store: &SharedStore, millis: bool) -> RespFrame {
-    if args.len() != 1 {
-        let cmd = if millis { "pttl" } else { "ttl" };
-        return RespFrame::Error(format!("ERR wrong number of arguments for '{cmd}'"));
+// ── DBSIZE ────────────────────────────────────────────────────────────────
+
+pub(super) fn handle_dbsize(store: &StoreAccess) -> RespFrame {
+    match store.read() {
+        Ok(guard) => reply::int(guard.dbsize() as i64),
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
+}
+
+// ── TOUCH ─────────────────────────────────────────────────────────────────
+
+pub(super) fn handle_touch(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let mut keys = Vec::with_capacity(args.len());
+    for arg in &args {
+        match bulk_to_string(arg) {
+            Some(k) => keys.push(k),
+            None => return RespFrame::Error("ERR key must be bulk string".into()),
+        }
+    }
+
+    // Write lock so stale keys can be expired; values are untouched, so
+    // nothing goes to the AOF.
+    match store.write() {
+        Ok(mut guard) => reply::int(guard.touch(&keys) as i64),
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
 
+// ── TTL / PTTL ────────────────────────────────────────────────────────────
+
+pub(super) fn handle_ttl(args: Vec<RespFrame>, store: &StoreAccess, millis: bool) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -187,14 +867,160 @@ pub(super) fn handle_ttl(args: Vec<RespFrame>, store: &SharedStore, millis: bool
         Ok(mut guard) => {
             let ms = guard.ttl_millis(&key);
             if millis {
-                RespFrame::Integer(ms)
+                reply::int(ms)
             } else {
                 match ms {
-                    -2 | -1 => RespFrame::Integer(ms),
-                    _ => RespFrame::Integer(ms / 1000),
+                    -2 | -1 => reply::int(ms),
+                    _ => reply::int(ms / 1000),
                 }
             }
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
+
+// ── EXPIRETIME / PEXPIRETIME ──────────────────────────────────────────────
+
+pub(super) fn handle_expiretime(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    millis: bool,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    match store.write() {
+        Ok(mut guard) => match guard.expire_time_millis(&key) {
+            ms if ms < 0 || millis => reply::int(ms),
+            ms => reply::int(ms / 1000),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+// ── EXPIRE / PEXPIRE / EXPIREAT / PEXPIREAT ───────────────────────────────
+
+pub(super) fn parse_expire_condition(args: &[RespFrame]) -> Result<ExpireCondition, RespFrame> {
+    let mut cond = ExpireCondition::default();
+    for arg in args {
+        let Some(opt) = bulk_to_string(arg) else {
+            return Err(RespFrame::Error("ERR syntax error".into()));
+        };
+        match opt.to_ascii_uppercase().as_str() {
+            "NX" => cond.nx = true,
+            "XX" => cond.xx = true,
+            "GT" => cond.gt = true,
+            "LT" => cond.lt = true,
+            _ => return Err(RespFrame::Error(format!("ERR Unsupported option {opt}"))),
+        }
+    }
+
+    if cond.nx && (cond.xx || cond.gt || cond.lt) {
+        return Err(RespFrame::Error(
+            "ERR NX and XX, GT or LT options at the same time are not compatible".into(),
+        ));
+    }
+    if cond.gt && cond.lt {
+        return Err(RespFrame::Error(
+            "ERR GT and LT options at the same time are not compatible".into(),
+        ));
+    }
+    Ok(cond)
+}
+
+/// The deadline an EXPIRE-style argument `n` names: seconds or
+/// milliseconds, relative to `now` or a Unix time. Negative or zero times
+/// are `now` or earlier, so they expire at once. `None` if it overflows.
+pub(super) fn expire_deadline(
+    n: i64,
+    millis: bool,
+    absolute: bool,
+    now: Instant,
+) -> Option<Instant> {
+    let ms = if millis { n } else { n.checked_mul(1000)? };
+    match (absolute, u64::try_from(ms)) {
+        (_, Err(_)) | (false, Ok(0)) => Some(now),
+        (true, Ok(at)) => Some(instant_from_unix_millis(at)),
+        (false, Ok(ms)) => now.checked_add(Duration::from_millis(ms)),
+    }
+}
+
+pub(super) fn handle_expire(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+    millis: bool,
+    absolute: bool,
+) -> RespFrame {
+    let cmd = match (millis, absolute) {
+        (false, false) => "expire",
+        (true, false) => "pexpire",
+        (false, true) => "expireat",
+        (true, true) => "pexpireat",
+    };
+
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let n = match bulk_to_string(&args[1]).and_then(|s| s.parse::<i64>().ok()) {
+        Some(n) => n,
+        None => {
+            return RespFrame::Error("ERR value is not an integer or out of range".into());
+        }
+    };
+    let cond = match parse_expire_condition(&args[2..]) {
+        Ok(c) => c,
+        Err(e) => return e,
+    };
+
+    let now = Instant::now();
+    let Some(deadline) = expire_deadline(n, millis, absolute, now) else {
+        return RespFrame::Error(format!("ERR invalid expire time in '{cmd}'"));
+    };
+
+    match store.write() {
+        Ok(mut guard) => {
+            if !guard.set_expiry(&key, deadline, cond) {
+                return reply::int(0);
+            }
+            // A deadline that has already passed deletes the key outright
+            // rather than leaving it for the eviction sweep.
+            if deadline <= now {
+                guard.del(std::slice::from_ref(&key));
+                effects.push(&["DEL", &key]);
+            } else {
+                let at = unix_millis_from_instant(deadline).to_string();
+                effects.push(&["PEXPIREAT", &key, &at]);
+            }
+            reply::int(1)
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+// ── PERSIST ───────────────────────────────────────────────────────────────
+
+pub(super) fn handle_persist(
+    args: Vec<RespFrame>,
+    store: &StoreAccess,
+    effects: &mut Effects,
+) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+
+    match store.write() {
+        Ok(mut guard) => {
+            let removed = guard.persist(&key);
+            if removed {
+                effects.push(&["PERSIST", &key]);
+            }
+            reply::int(removed as i64)
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
@@ -0,0 +1,606 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use crate::protocol::RespFrame;
+
+use super::bulk_to_string;
+
+/// Number of arguments a command accepts, not counting the command name.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Arity {
+    Exact(usize),
+    AtLeast(usize),
+    Range(usize, usize),
+}
+
+impl Arity {
+    pub(super) fn accepts(self, n: usize) -> bool {
+        match self {
+            Self::Exact(e) => n == e,
+            Self::AtLeast(min) => n >= min,
+            Self::Range(min, max) => (min..=max).contains(&n),
+        }
+    }
+}
+
+/// Modifies the dataset.
+pub(super) const WRITE: u8 = 1 << 0;
+/// May grow memory use, so is refused when over `maxmemory` and nothing
+/// can be evicted.
+pub(super) const DENYOOM: u8 = 1 << 1;
+/// ACL categories, named without the `@`, as `ACL CAT` lists them. Every
+/// command is also in `all`, which isn't listed.
+pub(super) const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "set",
+    "sortedset",
+    "list",
+    "hash",
+    "string",
+    "bitmap",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "dangerous",
+    "connection",
+    "transaction",
+];
+
+/// Where a command's key names sit among its arguments. Positions count
+/// the command name as 0, as in Redis' first/last/step key specs.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum KeySpec {
+    None,
+    /// Every `step`th argument from `first` to `last`, where a negative
+    /// `last` counts back from the end (-1 is the final argument).
+    Range {
+        first: usize,
+        last: isize,
+        step: usize,
+    },
+    /// A `numkeys` count at position 1, followed by that many keys.
+    NumKeys,
+}
+
+impl KeySpec {
+    /// The key arguments of `argv` (command name included), or `None` when
+    /// the command takes no keys or `argv` doesn't fit the spec.
+    pub(super) fn extract(self, argv: &[RespFrame]) -> Option<Vec<&RespFrame>> {
+        match self {
+            Self::None => None,
+            Self::Range { first, last, step } => {
+                let last = if last < 0 {
+                    argv.len().checked_sub(last.unsigned_abs())?
+                } else {
+                    last as usize
+                };
+                if first > last || last >= argv.len() {
+                    return None;
+                }
+                Some(argv[first..=last].iter().step_by(step).collect())
+            }
+            Self::NumKeys => {
+                let n = bulk_to_string(argv.get(1)?)?.parse::<usize>().ok()?;
+                if n == 0 {
+                    return None;
+                }
+                Some(argv.get(2..2 + n)?.iter().collect())
+            }
+        }
+    }
+}
+
+/// Static metadata for one command, checked by `dispatch` before the
+/// handler runs.
+#[derive(Debug)]
+pub(super) struct CommandSpec {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub flags: u8,
+    pub keys: KeySpec,
+    /// ACL categories, each one of [`CATEGORIES`].
+    pub categories: &'static [&'static str],
+    /// One-line description, as COMMAND DOCS reports it.
+    pub summary: &'static str,
+}
+
+impl CommandSpec {
+    pub(super) fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Whether the command is in ACL `category` (given without the `@`).
+    pub(super) fn in_category(&self, category: &str) -> bool {
+        category == "all" || self.categories.contains(&category)
+    }
+
+    const fn keys(mut self, first: usize, last: isize, step: usize) -> Self {
+        self.keys = KeySpec::Range { first, last, step };
+        self
+    }
+
+    const fn numkeys(mut self) -> Self {
+        self.keys = KeySpec::NumKeys;
+        self
+    }
+
+    const fn summary(mut self, summary: &'static str) -> Self {
+        self.summary = summary;
+        self
+    }
+}
+
+const fn spec(
+    name: &'static str,
+    arity: Arity,
+    flags: u8,
+    categories: &'static [&'static str],
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags,
+        keys: KeySpec::None,
+        categories,
+        summary: "",
+    }
+}
+
+use Arity::{AtLeast, Exact, Range};
+
+pub(super) const COMMANDS: &[CommandSpec] = &[
+    // Pub/Sub
+    spec("SUBSCRIBE", AtLeast(1), 0, &["pubsub", "slow"])
+        .summary("Listens for messages published to channels."),
+    spec("UNSUBSCRIBE", AtLeast(0), 0, &["pubsub", "slow"])
+        .summary("Stops listening to messages posted to channels."),
+    spec("PUBLISH", Exact(2), 0, &["pubsub", "fast"]).summary("Posts a message to a channel."),
+    spec("SSUBSCRIBE", AtLeast(1), 0, &["pubsub", "slow"])
+        .summary("Listens for messages published to shard channels."),
+    spec("SUNSUBSCRIBE", AtLeast(0), 0, &["pubsub", "slow"])
+        .summary("Stops listening to messages posted to shard channels."),
+    spec("SPUBLISH", Exact(2), 0, &["pubsub", "fast"])
+        .summary("Posts a message to a shard channel."),
+    spec("PUBSUB", AtLeast(1), 0, &["pubsub", "slow"])
+        .summary("Inspects the state of the Pub/Sub subsystem."),
+    // Connection / transactions
+    spec("MULTI", Exact(0), 0, &["transaction", "fast"]).summary("Starts a transaction."),
+    spec("EXEC", Exact(0), 0, &["transaction", "slow"])
+        .summary("Executes all commands in a transaction."),
+    spec("DISCARD", Exact(0), 0, &["transaction", "fast"]).summary("Discards a transaction."),
+    spec("RESET", Exact(0), 0, &["connection", "fast"]).summary("Resets the connection."),
+    spec("PING", Range(0, 1), 0, &["connection", "fast"])
+        .summary("Returns the server's liveliness response."),
+    spec("MONITOR", Exact(0), 0, &["admin", "slow", "dangerous"])
+        .summary("Listens for all requests received by the server in real-time."),
+    spec("ECHO", Exact(1), 0, &["connection", "fast"]).summary("Returns the given string."),
+    spec("CLIENT", AtLeast(1), 0, &["admin", "connection", "slow", "dangerous"])
+        .summary("A container for client connection commands."),
+    spec("DEBUG", AtLeast(1), 0, &["admin", "slow", "dangerous"])
+        .summary("A container for debugging commands."),
+    spec("OBJECT", AtLeast(1), 0, &["keyspace", "read", "slow"])
+        .summary("A container for object introspection commands."),
+    spec("MEMORY", AtLeast(1), 0, &["read", "slow"])
+        .keys(2, 2, 1)
+        .summary("A container for memory diagnostics commands."),
+    spec("SLOWLOG", AtLeast(1), 0, &["admin", "slow", "dangerous"])
+        .summary("A container for slow log commands."),
+    spec("INFO", AtLeast(0), 0, &["slow", "dangerous"])
+        .summary("Returns information and statistics about the server."),
+    spec("CONFIG", AtLeast(1), 0, &["admin", "slow", "dangerous"])
+        .summary("A container for server configuration commands."),
+    spec("CLUSTER", AtLeast(1), 0, &["slow"]).summary("A container for Redis Cluster commands."),
+    spec("COMMAND", AtLeast(0), 0, &["connection", "slow"])
+        .summary("Returns detailed information about all commands."),
+    spec("AUTH", Range(1, 2), 0, &["connection", "fast"]).summary("Authenticates the connection."),
+    spec("ACL", AtLeast(1), 0, &["admin", "slow", "dangerous"])
+        .summary("A container for Access List Control commands."),
+    // Replication
+    spec("SYNC", Exact(0), 0, &["admin", "slow", "dangerous"])
+        .summary("An internal command used in replication."),
+    spec("REPLICAOF", Exact(2), 0, &["admin", "slow", "dangerous"])
+        .summary("Makes the server a replica of another, or promotes it to a master."),
+    spec("FAILOVER", AtLeast(0), 0, &["admin", "slow", "dangerous"])
+        .summary("Starts a coordinated failover from a server to one of its replicas."),
+    spec("WAITAOF", Exact(3), 0, &["slow", "connection"])
+        .summary("Blocks until all of the preceding write commands sent by the connection are written to the append-only file of the master and/or replicas."),
+    spec("SHUTDOWN", Range(0, 1), 0, &["admin", "slow", "dangerous"])
+        .summary("Synchronously saves the database(s) to disk and shuts down the Redis server."),
+    // Strings / keyspace
+    spec(
+        "SET",
+        AtLeast(2),
+        WRITE | DENYOOM,
+        &["write", "string", "slow"],
+    )
+    .keys(1, 1, 1)
+    .summary("Sets the string value of a key, ignoring its type."),
+    spec("GET", Exact(1), 0, &["read", "string", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the string value of a key."),
+    spec("GETEX", AtLeast(1), WRITE, &["write", "string", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the string value of a key after setting its expiration time."),
+    spec("GETDEL", Exact(1), WRITE, &["write", "string", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the string value of a key after deleting the key."),
+    spec(
+        "GETSET",
+        Exact(2),
+        WRITE | DENYOOM,
+        &["write", "string", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Returns the previous string value of a key after setting it to a new value."),
+    spec("GETRANGE", Exact(3), 0, &["read", "string", "slow"])
+        .keys(1, 1, 1)
+        .summary("Returns a substring of the string stored at a key."),
+    spec("STRLEN", Exact(1), 0, &["read", "string", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the length of a string value."),
+    spec(
+        "SETRANGE",
+        Exact(3),
+        WRITE | DENYOOM,
+        &["write", "string", "slow"],
+    )
+    .keys(1, 1, 1)
+    .summary("Overwrites a part of a string value with another by an offset."),
+    spec(
+        "INCR",
+        Exact(1),
+        WRITE | DENYOOM,
+        &["write", "string", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Increments the integer value of a key by one."),
+    spec(
+        "INCRBY",
+        Exact(2),
+        WRITE | DENYOOM,
+        &["write", "string", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Increments the integer value of a key by a number."),
+    spec(
+        "DECR",
+        Exact(1),
+        WRITE | DENYOOM,
+        &["write", "string", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Decrements the integer value of a key by one."),
+    spec(
+        "DECRBY",
+        Exact(2),
+        WRITE | DENYOOM,
+        &["write", "string", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Decrements a number from the integer value of a key."),
+    spec(
+        "APPEND",
+        Exact(2),
+        WRITE | DENYOOM,
+        &["write", "string", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Appends a string to the value of a key."),
+    spec("LCS", AtLeast(2), 0, &["read", "string", "slow"])
+        .keys(1, 2, 1)
+        .summary("Finds the longest common substring."),
+    spec("DEL", AtLeast(1), WRITE, &["keyspace", "write", "slow"])
+        .keys(1, -1, 1)
+        .summary("Deletes one or more keys."),
+    spec("UNLINK", AtLeast(1), WRITE, &["keyspace", "write", "fast"])
+        .keys(1, -1, 1)
+        .summary("Asynchronously deletes one or more keys."),
+    spec(
+        "COPY",
+        Range(2, 3),
+        WRITE | DENYOOM,
+        &["keyspace", "write", "slow"],
+    )
+    .keys(1, 2, 1)
+    .summary("Copies the value of a key to a new key."),
+    spec("DUMP", Exact(1), 0, &["keyspace", "read", "slow"])
+        .keys(1, 1, 1)
+        .summary("Returns a serialized representation of the value stored at a key."),
+    spec(
+        "RESTORE",
+        AtLeast(3),
+        WRITE | DENYOOM,
+        &["keyspace", "write", "slow", "dangerous"],
+    )
+    .keys(1, 1, 1)
+    .summary("Creates a key from the serialized representation of a value."),
+    spec(
+        "CAS",
+        Exact(3),
+        WRITE | DENYOOM,
+        &["write", "string", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Sets the string value of a key only if it holds the expected value."),
+    spec("EXISTS", AtLeast(1), 0, &["keyspace", "read", "fast"])
+        .keys(1, -1, 1)
+        .summary("Determines whether one or more keys exist."),
+    spec("TOUCH", AtLeast(1), 0, &["keyspace", "read", "fast"])
+        .keys(1, -1, 1)
+        .summary("Updates the last access time of keys, returning how many exist."),
+    spec("DBSIZE", Exact(0), 0, &["keyspace", "read", "fast"])
+        .summary("Returns the number of keys in the database."),
+    spec(
+        "FLUSHDB",
+        Range(0, 1),
+        WRITE,
+        &["keyspace", "write", "slow", "dangerous"],
+    )
+    .summary("Removes all keys from the current database."),
+    spec(
+        "FLUSHALL",
+        Range(0, 1),
+        WRITE,
+        &["keyspace", "write", "slow", "dangerous"],
+    )
+    .summary("Removes all keys from all databases."),
+    spec("TTL", Exact(1), 0, &["keyspace", "read", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the expiration time in seconds of a key."),
+    spec("PTTL", Exact(1), 0, &["keyspace", "read", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the expiration time in milliseconds of a key."),
+    spec("EXPIRE", AtLeast(2), WRITE, &["keyspace", "write", "fast"])
+        .keys(1, 1, 1)
+        .summary("Sets the expiration time of a key in seconds."),
+    spec("PEXPIRE", AtLeast(2), WRITE, &["keyspace", "write", "fast"])
+        .keys(1, 1, 1)
+        .summary("Sets the expiration time of a key in milliseconds."),
+    spec(
+        "EXPIREAT",
+        AtLeast(2),
+        WRITE,
+        &["keyspace", "write", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Sets the expiration time of a key to a Unix timestamp."),
+    spec(
+        "PEXPIREAT",
+        AtLeast(2),
+        WRITE,
+        &["keyspace", "write", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Sets the expiration time of a key to a Unix milliseconds timestamp."),
+    spec("PERSIST", Exact(1), WRITE, &["keyspace", "write", "fast"])
+        .keys(1, 1, 1)
+        .summary("Removes the expiration time of a key."),
+    spec("EXPIRETIME", Exact(1), 0, &["keyspace", "read", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the expiration time of a key as a Unix timestamp."),
+    spec("PEXPIRETIME", Exact(1), 0, &["keyspace", "read", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the expiration time of a key as a Unix milliseconds timestamp."),
+    // Bits
+    spec(
+        "SETBIT",
+        Exact(3),
+        WRITE | DENYOOM,
+        &["write", "bitmap", "slow"],
+    )
+    .keys(1, 1, 1)
+    .summary("Sets or clears the bit at offset of the string value."),
+    spec("GETBIT", Exact(2), 0, &["read", "bitmap", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns a bit value by offset."),
+    spec("BITCOUNT", Range(1, 4), 0, &["read", "bitmap", "slow"])
+        .keys(1, 1, 1)
+        .summary("Counts the number of set bits (population counting) in a string."),
+    spec("BITPOS", Range(2, 5), 0, &["read", "bitmap", "slow"])
+        .keys(1, 1, 1)
+        .summary("Finds the first set (1) or clear (0) bit in a string."),
+    spec(
+        "BITOP",
+        AtLeast(3),
+        WRITE | DENYOOM,
+        &["write", "bitmap", "slow"],
+    )
+    .keys(2, -1, 1)
+    .summary("Performs bitwise operations on multiple strings, and stores the result."),
+    // Lists
+    spec(
+        "LPUSH",
+        AtLeast(2),
+        WRITE | DENYOOM,
+        &["write", "list", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Prepends one or more elements to a list."),
+    spec(
+        "RPUSH",
+        AtLeast(2),
+        WRITE | DENYOOM,
+        &["write", "list", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Appends one or more elements to a list."),
+    spec("LPOP", Range(1, 2), WRITE, &["write", "list", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns and removes the first elements of a list."),
+    spec("RPOP", Range(1, 2), WRITE, &["write", "list", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns and removes the last elements of a list."),
+    spec("LRANGE", Exact(3), 0, &["read", "list", "slow"])
+        .keys(1, 1, 1)
+        .summary("Returns a range of elements from a list."),
+    spec("LLEN", Exact(1), 0, &["read", "list", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the length of a list."),
+    spec("LPOS", AtLeast(2), 0, &["read", "list", "slow"])
+        .keys(1, 1, 1)
+        .summary("Returns the index of matching elements in a list."),
+    // Sets
+    spec(
+        "SADD",
+        AtLeast(2),
+        WRITE | DENYOOM,
+        &["write", "set", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Adds one or more members to a set."),
+    spec("SREM", AtLeast(2), WRITE, &["write", "set", "fast"])
+        .keys(1, 1, 1)
+        .summary("Removes one or more members from a set."),
+    spec("SMEMBERS", Exact(1), 0, &["read", "set", "slow"])
+        .keys(1, 1, 1)
+        .summary("Returns all members of a set."),
+    spec("SINTERCARD", AtLeast(2), 0, &["read", "set", "slow"])
+        .numkeys()
+        .summary("Returns the number of members of the intersect of multiple sets."),
+    spec("SRANDMEMBER", Range(1, 2), 0, &["read", "set", "slow"])
+        .keys(1, 1, 1)
+        .summary("Returns one or more random members from a set."),
+    spec("SSCAN", AtLeast(2), 0, &["read", "set", "slow"])
+        .keys(1, 1, 1)
+        .summary("Iterates over members of a set."),
+    // Hashes
+    spec(
+        "HSET",
+        AtLeast(3),
+        WRITE | DENYOOM,
+        &["write", "hash", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Creates or modifies the value of a field in a hash."),
+    spec("HGET", Exact(2), 0, &["read", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the value of a field in a hash."),
+    spec("HGETALL", Exact(1), 0, &["read", "hash", "slow"])
+        .keys(1, 1, 1)
+        .summary("Returns all fields and values in a hash."),
+    spec(
+        "HINCRBY",
+        Exact(3),
+        WRITE | DENYOOM,
+        &["write", "hash", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Increments the integer value of a field in a hash by a number."),
+    spec("HDEL", AtLeast(2), WRITE, &["write", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Deletes one or more fields and their values from a hash."),
+    spec("HGETDEL", AtLeast(4), WRITE, &["write", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the value of a field and deletes it from the hash."),
+    spec("HGETEX", AtLeast(4), WRITE, &["write", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the values of hash fields and optionally sets their expiration."),
+    spec("HRANDFIELD", Range(1, 3), 0, &["read", "hash", "slow"])
+        .keys(1, 1, 1)
+        .summary("Returns one or more random fields from a hash."),
+    spec("HEXPIRE", AtLeast(5), WRITE, &["write", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Sets the expiration time of hash fields in seconds."),
+    spec("HPEXPIRE", AtLeast(5), WRITE, &["write", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Sets the expiration time of hash fields in milliseconds."),
+    spec("HEXPIREAT", AtLeast(5), WRITE, &["write", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Sets the expiration time of hash fields as a Unix timestamp."),
+    spec("HPEXPIREAT", AtLeast(5), WRITE, &["write", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Sets the expiration time of hash fields in Unix milliseconds."),
+    spec("HTTL", AtLeast(4), 0, &["read", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the TTL in seconds of hash fields."),
+    spec("HPTTL", AtLeast(4), 0, &["read", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the TTL in milliseconds of hash fields."),
+    spec("HPERSIST", AtLeast(4), WRITE, &["write", "hash", "fast"])
+        .keys(1, 1, 1)
+        .summary("Removes the expiration time of hash fields."),
+    spec("HSCAN", AtLeast(2), 0, &["read", "hash", "slow"])
+        .keys(1, 1, 1)
+        .summary("Iterates over fields and values of a hash."),
+    // Sorted sets
+    spec(
+        "ZADD",
+        AtLeast(3),
+        WRITE | DENYOOM,
+        &["write", "sortedset", "fast"],
+    )
+    .keys(1, 1, 1)
+    .summary("Adds one or more members to a sorted set, or updates their scores."),
+    spec("ZRANGE", AtLeast(3), 0, &["read", "sortedset", "slow"])
+        .keys(1, 1, 1)
+        .summary("Returns members in a sorted set within a range of indexes."),
+    spec("ZSCORE", Exact(2), 0, &["read", "sortedset", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the score of a member in a sorted set."),
+    spec("ZRANK", Exact(2), 0, &["read", "sortedset", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the index of a member in a sorted set ordered by ascending scores."),
+    spec("ZCARD", Exact(1), 0, &["read", "sortedset", "fast"])
+        .keys(1, 1, 1)
+        .summary("Returns the number of members in a sorted set."),
+    spec("ZREM", AtLeast(2), WRITE, &["write", "sortedset", "fast"])
+        .keys(1, 1, 1)
+        .summary("Removes one or more members from a sorted set."),
+    spec("ZCOUNT", Exact(3), 0, &["read", "sortedset", "fast"])
+        .keys(1, 1, 1)
+        .summary("Counts the members of a sorted set with scores within a range."),
+    spec("ZINTERCARD", AtLeast(2), 0, &["read", "sortedset", "slow"])
+        .numkeys()
+        .summary("Returns the number of members of the intersect of multiple sorted sets."),
+    spec("ZREVRANGE", AtLeast(3), 0, &["read", "sortedset", "slow"])
+        .keys(1, 1, 1)
+        .summary("Returns members in a sorted set within a range of indexes in reverse order."),
+    spec(
+        "ZRANDMEMBER",
+        Range(1, 3),
+        0,
+        &["read", "sortedset", "slow"],
+    )
+    .keys(1, 1, 1)
+    .summary("Returns one or more random members from a sorted set."),
+    spec("ZSCAN", AtLeast(2), 0, &["read", "sortedset", "slow"])
+        .keys(1, 1, 1)
+        .summary("Iterates over members and scores of a sorted set."),
+];
+
+static BY_NAME: LazyLock<HashMap<&'static str, &'static CommandSpec>> =
+    LazyLock::new(|| COMMANDS.iter().map(|c| (c.name, c)).collect());
+
+/// Look up a command by its upper-cased name.
+pub(super) fn lookup(name: &str) -> Option<&'static CommandSpec> {
+    BY_NAME.get(name).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories_are_known_and_match_flags() {
+        for spec in COMMANDS {
+            for category in spec.categories {
+                assert!(CATEGORIES.contains(category), "{}: @{category}", spec.name);
+            }
+            assert_eq!(
+                spec.has(WRITE),
+                spec.in_category("write"),
+                "{}: WRITE flag vs @write",
+                spec.name
+            );
+            assert!(
+                spec.in_category("fast") != spec.in_category("slow"),
+                "{}: exactly one of @fast and @slow",
+                spec.name
+            );
+            assert!(!spec.summary.is_empty(), "{}: no summary", spec.name);
+        }
+    }
+}
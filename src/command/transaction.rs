@@ -0,0 +1,44 @@
+use crate::protocol::{RespFrame, reply};
+use crate::server::client::ClientState;
+use crate::server::state::ServerState;
+use crate::store::StoreAccess;
+
+use super::handle_array;
+
+pub(super) fn handle_multi(client: &mut ClientState) -> RespFrame {
+    if client.multi.is_some() {
+        return RespFrame::Error("ERR MULTI calls can not be nested".into());
+    }
+    client.multi = Some(Vec::new());
+    reply::ok()
+}
+
+pub(super) fn handle_discard(client: &mut ClientState) -> RespFrame {
+    match client.multi.take() {
+        Some(_) => reply::ok(),
+        None => RespFrame::Error("ERR DISCARD without MULTI".into()),
+    }
+}
+
+/// Run every queued command in order and reply with an array of their
+/// replies. The store's write lock is held across the whole batch so no
+/// other connection's command interleaves with it.
+pub(super) fn handle_exec(server: &ServerState, client: &mut ClientState) -> RespFrame {
+    let Some(queued) = client.multi.take() else {
+        return RespFrame::Error("ERR EXEC without MULTI".into());
+    };
+
+    // Same lock order as a lone write: replication order, then the store.
+    let _order = server.replication.lock_order();
+    let mut guard = match server.store.write() {
+        Ok(g) => g,
+        Err(_) => return RespFrame::Error("ERR store lock poisoned".into()),
+    };
+    let store = StoreAccess::held(&mut guard);
+
+    let replies = queued
+        .into_iter()
+        .map(|items| handle_array(items, server, client, &store).unwrap_or(RespFrame::Null))
+        .collect();
+    RespFrame::Array(Some(replies))
+}
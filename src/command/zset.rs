@@ -1,18 +1,19 @@
 use bytes::Bytes;
 
-use crate::persistence::aof::AofWriter;
-use crate::protocol::RespFrame;
-use crate::store::SharedStore;
+use crate::propagate::Effects;
+use crate::protocol::{RespFrame, Sample, reply};
+use crate::store::number::parse_score;
+use crate::store::{StoreAccess, TypeError, random};
 
-use super::{bulk_to_bytes, bulk_to_string};
+use super::{bulk_to_bytes, bulk_to_string, parse_intercard_args, parse_randfield_args};
 
 pub(super) fn handle_zadd(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.len() < 3 || !(args.len() - 1).is_multiple_of(2) {
-        return RespFrame::Error("ERR wrong number of arguments for 'zadd'".into());
+    if !(args.len() - 1).is_multiple_of(2) {
+        return reply::wrong_args("zadd");
     }
 
     let key = match bulk_to_string(&args[0]) {
@@ -21,52 +22,39 @@ pub(super) fn handle_zadd(
     };
 
     let mut members = Vec::with_capacity((args.len() - 1) / 2);
-    let mut mem_strs: Vec<String> = Vec::new();
+    let mut propagated: Vec<Bytes> = Vec::with_capacity(args.len() - 1);
     let mut i = 1;
 
     while i < args.len() {
-        let score = match bulk_to_string(&args[i]).and_then(|s| s.parse::<f64>().ok()) {
+        let score = match bulk_to_bytes(&args[i]).and_then(|b| parse_score(&b)) {
             Some(v) => v,
             None => return RespFrame::Error("ERR score is not a valid float".into()),
         };
-        if !score.is_finite() {
-            return RespFrame::Error("ERR score is not a valid float".into());
-        }
         let member = match bulk_to_bytes(&args[i + 1]) {
             Some(b) => b,
             None => return RespFrame::Error("ERR member must be bulk string".into()),
         };
-        mem_strs.push(score.to_string());
-        mem_strs.push(String::from_utf8_lossy(&member).into_owned());
+        propagated.push(Bytes::from(score.to_string()));
+        propagated.push(member.clone());
         members.push((member, score));
         i += 2;
     }
 
     match store.write() {
         Ok(mut guard) => {
-            if !guard.is_type(&key, "zset") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            let added = guard.zadd(key.clone(), members);
-            if let Some(w) = aof {
-                let mut a: Vec<String> = vec!["ZADD".into(), key];
-                a.extend(mem_strs);
-                let refs: Vec<&str> = a.iter().map(|s| s.as_str()).collect();
-                w.append(&refs);
-            }
-            RespFrame::Integer(added as i64)
+            let Ok(added) = guard.zadd(key.clone(), members) else {
+                return reply::wrongtype();
+            };
+            let mut a: Vec<&[u8]> = vec![b"ZADD", key.as_bytes()];
+            a.extend(propagated.iter().map(|b| b.as_ref()));
+            effects.push_bytes(&a);
+            reply::int(added as i64)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-pub(super) fn handle_zrange(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() < 3 {
-        return RespFrame::Error("ERR wrong number of arguments for 'zrange'".into());
-    }
-
+pub(super) fn handle_zrange(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -92,7 +80,9 @@ pub(super) fn handle_zrange(args: Vec<RespFrame>, store: &SharedStore) -> RespFr
 
     match store.read() {
         Ok(guard) => {
-            let results = guard.zrange(&key, start, stop, with_scores);
+            let Ok(results) = guard.zrange(&key, start, stop, with_scores) else {
+                return reply::wrongtype();
+            };
             if results.is_empty() {
                 return RespFrame::Array(Some(Vec::new()));
             }
@@ -109,11 +99,7 @@ pub(super) fn handle_zrange(args: Vec<RespFrame>, store: &SharedStore) -> RespFr
     }
 }
 
-pub(super) fn handle_zscore(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() != 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'zscore'".into());
-    }
-
+pub(super) fn handle_zscore(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -125,26 +111,16 @@ pub(super) fn handle_zscore(args: Vec<RespFrame>, store: &SharedStore) -> RespFr
     };
 
     match store.read() {
-        Ok(guard) => {
-            if !guard.is_type(&key, "zset") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            match guard.zscore(&key, &member) {
-                Some(score) => RespFrame::BulkString(Some(Bytes::from(score.to_string()))),
-                None => RespFrame::Null,
-            }
-        }
+        Ok(guard) => match guard.zscore(&key, &member) {
+            Ok(Some(score)) => RespFrame::BulkString(Some(Bytes::from(score.to_string()))),
+            Ok(None) => RespFrame::Null,
+            Err(TypeError) => reply::wrongtype(),
+        },
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-pub(super) fn handle_zrank(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() != 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'zrank'".into());
-    }
-
+pub(super) fn handle_zrank(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -156,96 +132,66 @@ pub(super) fn handle_zrank(args: Vec<RespFrame>, store: &SharedStore) -> RespFra
     };
 
     match store.read() {
-        Ok(guard) => {
-            if !guard.is_type(&key, "zset") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            match guard.zrank(&key, &member) {
-                Some(rank) => RespFrame::Integer(rank as i64),
-                None => RespFrame::Null,
-            }
-        }
+        Ok(guard) => match guard.zrank(&key, &member) {
+            Ok(Some(rank)) => reply::int(rank as i64),
+            Ok(None) => RespFrame::Null,
+            Err(TypeError) => reply::wrongtype(),
+        },
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-pub(super) fn handle_zcard(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() != 1 {
-        return RespFrame::Error("ERR wrong number of arguments for 'zcard'".into());
-    }
-
+pub(super) fn handle_zcard(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
     };
 
     match store.read() {
-        Ok(guard) => {
-            if !guard.is_type(&key, "zset") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            RespFrame::Integer(guard.zcard(&key) as i64)
-        }
+        Ok(guard) => match guard.zcard(&key) {
+            Ok(card) => reply::int(card as i64),
+            Err(TypeError) => reply::wrongtype(),
+        },
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
 pub(super) fn handle_zrem(
     args: Vec<RespFrame>,
-    store: &SharedStore,
-    aof: Option<&AofWriter>,
+    store: &StoreAccess,
+    effects: &mut Effects,
 ) -> RespFrame {
-    if args.len() < 2 {
-        return RespFrame::Error("ERR wrong number of arguments for 'zrem'".into());
-    }
-
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
     };
 
     let mut members = Vec::with_capacity(args.len() - 1);
-    let mut mem_strs: Vec<String> = Vec::new();
     for arg in &args[1..] {
         let member = match bulk_to_bytes(arg) {
             Some(b) => b,
             None => return RespFrame::Error("ERR member must be bulk string".into()),
         };
-        mem_strs.push(String::from_utf8_lossy(&member).into_owned());
         members.push(member);
     }
 
     match store.write() {
         Ok(mut guard) => {
-            if !guard.is_type(&key, "zset") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            let removed = guard.zrem(&key, members);
-            if removed > 0
-                && let Some(w) = aof
-            {
-                let mut a: Vec<String> = vec!["ZREM".into(), key];
-                a.extend(mem_strs);
-                let refs: Vec<&str> = a.iter().map(|s| s.as_str()).collect();
-                w.append(&refs);
+            let Ok(removed) = guard.zrem(&key, members.clone()) else {
+                return reply::wrongtype();
+            };
+            if removed > 0 {
+                let mut a: Vec<&[u8]> = vec![b"ZREM", key.as_bytes()];
+                a.extend(members.iter().map(|m| m.as_ref()));
+                effects.push_bytes(&a);
             }
-            RespFrame::Integer(removed as i64)
+            reply::int(removed as i64)
         }
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-pub(super) fn handle_zcount(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() != 3 {
-        return RespFrame::Error("ERR wrong number of arguments for 'zcount'".into());
-    }
-
+pub(super) fn handle_zcount(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -262,23 +208,15 @@ pub(super) fn handle_zcount(args: Vec<RespFrame>, store: &SharedStore) -> RespFr
     };
 
     match store.read() {
-        Ok(guard) => {
-            if !guard.is_type(&key, "zset") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            RespFrame::Integer(guard.zcount(&key, min, max) as i64)
-        }
+        Ok(guard) => match guard.zcount(&key, min, max) {
+            Ok(count) => reply::int(count as i64),
+            Err(TypeError) => reply::wrongtype(),
+        },
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
 
-pub(super) fn handle_zrevrange(args: Vec<RespFrame>, store: &SharedStore) -> RespFrame {
-    if args.len() < 3 {
-        return RespFrame::Error("ERR wrong number of arguments for 'zrevrange'".into());
-    }
-
+pub(super) fn handle_zrevrange(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
     let key = match bulk_to_string(&args[0]) {
         Some(s) => s,
         None => return RespFrame::Error("ERR key must be bulk string".into()),
@@ -304,12 +242,9 @@ pub(super) fn handle_zrevrange(args: Vec<RespFrame>, store: &SharedStore) -> Res
 
     match store.read() {
         Ok(guard) => {
-            if !guard.is_type(&key, "zset") {
-                return RespFrame::Error(
-                    "WRONGTYPE Operation against a key holding the wrong kind of value".into(),
-                );
-            }
-            let results = guard.zrevrange(&key, start, stop, with_scores);
+            let Ok(results) = guard.zrevrange(&key, start, stop, with_scores) else {
+                return reply::wrongtype();
+            };
             if results.is_empty() {
                 return RespFrame::Array(Some(Vec::new()));
             }
@@ -325,3 +260,60 @@ pub(super) fn handle_zrevrange(args: Vec<RespFrame>, store: &SharedStore) -> Res
         Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
     }
 }
+
+pub(super) fn handle_zintercard(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let (keys, limit) = match parse_intercard_args(&args) {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+
+    match store.read() {
+        Ok(guard) => match guard.zintercard(&keys, limit) {
+            Ok(count) => reply::int(count as i64),
+            Err(TypeError) => reply::wrongtype(),
+        },
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
+
+/// ZRANDMEMBER key [count [WITHSCORES]]
+pub(super) fn handle_zrandmember(args: Vec<RespFrame>, store: &StoreAccess) -> RespFrame {
+    let key = match bulk_to_string(&args[0]) {
+        Some(s) => s,
+        None => return RespFrame::Error("ERR key must be bulk string".into()),
+    };
+    let (count, with_scores) = match parse_randfield_args(&args[1..], "WITHSCORES") {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+
+    match store.read() {
+        Ok(guard) => {
+            // A negative count draws with repeats, from every member.
+            let distinct = match count {
+                Some(n) => usize::try_from(n).unwrap_or(usize::MAX),
+                None => 1,
+            };
+            let Ok(picked) = guard.zrandmember(&key, distinct) else {
+                return reply::wrongtype();
+            };
+            let Some(count) = count else {
+                return RespFrame::BulkString(picked.into_iter().next().map(|(member, _)| member));
+            };
+            let mut frames = Vec::new();
+            for (member, score) in picked {
+                frames.push(RespFrame::BulkString(Some(member)));
+                if with_scores {
+                    frames.push(RespFrame::BulkString(Some(Bytes::from(score.to_string()))));
+                }
+            }
+            if count < 0 {
+                let group = if with_scores { 2 } else { 1 };
+                let picks = count.unsigned_abs() as usize;
+                return RespFrame::Sample(Sample::new(frames, group, picks, random::next_u64()));
+            }
+            RespFrame::Array(Some(frames))
+        }
+        Err(_) => RespFrame::Error("ERR store lock poisoned".into()),
+    }
+}
@@ -1,23 +1,92 @@
+use std::ffi::OsString;
+use std::io::{self, Write};
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::Parser;
+use clap::builder::RangedU64ValueParser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser};
+
+use crate::protocol::DEFAULT_MAX_BULK_LEN;
 
 /// CLI configuration for the Redis-like server.
 #[derive(Debug, Clone, Parser)]
 pub struct Config {
-    /// Address to bind for the main server, e.g. 127.0.0.1:6379
-    #[arg(long, env = "RFS_BIND", default_value = "127.0.0.1:6379")]
-    pub bind: SocketAddr,
+    /// Read settings from a redis.conf-style file of `name value` lines.
+    /// Flags given on the command line or in the environment win over it.
+    #[arg(long, env = "RFS_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Addresses to bind for the main server, e.g. 127.0.0.1:6379. Takes a
+    /// comma-separated list, or repeat the flag.
+    #[arg(
+        long,
+        env = "RFS_BIND",
+        value_delimiter = ',',
+        default_value = "127.0.0.1:6379"
+    )]
+    pub bind: Vec<SocketAddr>,
+
+    /// Also listen for clients on a Unix domain socket at this path
+    #[arg(long, env = "RFS_UNIXSOCKET")]
+    pub unixsocket: Option<PathBuf>,
+
+    /// Addresses to accept TLS clients on, alongside the plain `--bind`
+    /// listeners. Requires `--tls-cert` and `--tls-key`.
+    #[arg(
+        long,
+        env = "RFS_TLS_BIND",
+        value_delimiter = ',',
+        requires_all = ["tls_cert", "tls_key"]
+    )]
+    pub tls_bind: Vec<SocketAddr>,
+
+    /// PEM certificate chain presented to TLS clients
+    #[arg(long, env = "RFS_TLS_CERT", requires_all = ["tls_key", "tls_bind"])]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for `--tls-cert`
+    #[arg(long, env = "RFS_TLS_KEY", requires_all = ["tls_cert", "tls_bind"])]
+    pub tls_key: Option<PathBuf>,
 
     /// Optional address to expose Prometheus metrics, e.g. 127.0.0.1:9900
     #[arg(long, env = "RFS_METRICS_BIND")]
     pub metrics_bind: Option<SocketAddr>,
 
-    /// Maximum simultaneous client connections
+    /// Log verbosity, using Redis' level names: "debug", "verbose",
+    /// "notice" or "warning". `RUST_LOG`, when set, overrides it.
+    #[arg(
+        long,
+        env = "RFS_LOGLEVEL",
+        value_parser = ["debug", "verbose", "notice", "warning"]
+    )]
+    pub loglevel: Option<String>,
+
+    /// Write logs to this file, appending, instead of standard output
+    #[arg(long, env = "RFS_LOGFILE")]
+    pub logfile: Option<PathBuf>,
+
+    /// Log line format: "text" for people, or "json" (one object per line)
+    /// for log aggregators
+    #[arg(
+        long,
+        env = "RFS_LOG_FORMAT",
+        default_value = "text",
+        value_parser = ["text", "json"]
+    )]
+    pub log_format: String,
+
+    /// Maximum simultaneous client connections. Clients beyond it get an
+    /// error and are disconnected.
     #[arg(long, env = "RFS_MAX_CONNECTIONS", default_value_t = 1024)]
     pub max_connections: usize,
 
+    /// Working directory, changed into at startup, so a relative
+    /// `--aof-path` names a file inside it.
+    #[arg(long, env = "RFS_DIR")]
+    pub dir: Option<PathBuf>,
+
     /// Path to append-only file. If set, enables AOF persistence.
     #[arg(long, env = "RFS_AOF_PATH")]
     pub aof_path: Option<PathBuf>,
@@ -25,10 +94,587 @@ pub struct Config {
     /// Fsync policy: "always", "everysec", or "no"
     #[arg(long, env = "RFS_AOF_FSYNC", default_value = "everysec")]
     pub aof_fsync: String,
+
+    /// What to do when writing to the AOF fails: "continue" keeps accepting
+    /// writes, "stop" rejects them with MISCONF until the AOF recovers.
+    #[arg(long, env = "RFS_AOF_ON_WRITE_ERROR", default_value = "continue")]
+    pub aof_on_write_error: String,
+
+    /// Reject write commands from clients, as a replica does
+    #[arg(long, env = "RFS_READ_ONLY")]
+    pub read_only: bool,
+
+    /// Per-client output buffer size past which the connection stops
+    /// processing commands until pending replies drain. Bytes, or a size
+    /// such as 1mb.
+    #[arg(
+        long,
+        env = "RFS_CLIENT_OUTPUT_BUFFER_SOFT_LIMIT",
+        default_value_t = 1024 * 1024,
+        value_parser = memory_arg
+    )]
+    pub client_output_buffer_soft_limit: usize,
+
+    /// Per-client output buffer size past which the connection is closed.
+    /// Bytes, or a size such as 64mb. 0 disables the hard limit.
+    #[arg(
+        long,
+        env = "RFS_CLIENT_OUTPUT_BUFFER_HARD_LIMIT",
+        default_value_t = 0,
+        value_parser = memory_arg
+    )]
+    pub client_output_buffer_hard_limit: usize,
+
+    /// Largest bulk string (bytes) a client may send. A longer declared
+    /// length is a protocol error and closes the connection.
+    #[arg(long, env = "RFS_PROTO_MAX_BULK_LEN", default_value_t = DEFAULT_MAX_BULK_LEN)]
+    pub proto_max_bulk_len: usize,
+
+    /// Approximate memory limit for the dataset, in bytes or as a size such
+    /// as 100mb. 0 means unlimited.
+    #[arg(
+        long,
+        env = "RFS_MAXMEMORY",
+        default_value_t = 0,
+        value_parser = memory_arg
+    )]
+    pub maxmemory: usize,
+
+    /// What to do when maxmemory is reached: "noeviction", "allkeys-lfu",
+    /// or "volatile-lfu"
+    #[arg(long, env = "RFS_MAXMEMORY_POLICY", default_value = "noeviction")]
+    pub maxmemory_policy: String,
+
+    /// Log a warning for any reply larger than this many bytes, which
+    /// usually means a client asked for far more than it meant to (a
+    /// `KEYS *` or an unbounded LRANGE). 0 disables the warning.
+    #[arg(long, env = "RFS_WARN_REPLY_BYTES", default_value_t = 64 * 1024 * 1024)]
+    pub warn_reply_bytes: usize,
+
+    /// How many times a second background tasks, such as removing expired
+    /// keys, run (1 to 500)
+    #[arg(
+        long,
+        env = "RFS_HZ",
+        default_value_t = 10,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..=500)
+    )]
+    pub hz: usize,
+
+    /// How hard each background cycle works at removing expired keys, from
+    /// 1 to 10. Higher values reclaim memory sooner at the cost of longer
+    /// pauses.
+    #[arg(
+        long,
+        env = "RFS_ACTIVE_EXPIRE_EFFORT",
+        default_value_t = 1,
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..=10)
+    )]
+    pub active_expire_effort: usize,
+
+    /// Commands taking at least this many microseconds are recorded in the
+    /// slow log. Negative disables it; 0 logs every command.
+    #[arg(
+        long,
+        env = "RFS_SLOWLOG_LOG_SLOWER_THAN",
+        default_value_t = 10_000,
+        allow_negative_numbers = true
+    )]
+    pub slowlog_log_slower_than: i64,
+
+    /// Maximum number of slow log entries kept
+    #[arg(long, env = "RFS_SLOWLOG_MAX_LEN", default_value_t = 128)]
+    pub slowlog_max_len: usize,
+
+    /// Hashes with at most this many fields report the listpack encoding
+    #[arg(long, env = "RFS_HASH_MAX_LISTPACK_ENTRIES", default_value_t = 128)]
+    pub hash_max_listpack_entries: usize,
+
+    /// Hashes with a longer field or value report the hashtable encoding
+    #[arg(long, env = "RFS_HASH_MAX_LISTPACK_VALUE", default_value_t = 64)]
+    pub hash_max_listpack_value: usize,
+
+    /// Sets of at most this many members, all of them integers, report the
+    /// intset encoding
+    #[arg(long, env = "RFS_SET_MAX_INTSET_ENTRIES", default_value_t = 512)]
+    pub set_max_intset_entries: usize,
+
+    /// Sets with at most this many members report the listpack encoding
+    #[arg(long, env = "RFS_SET_MAX_LISTPACK_ENTRIES", default_value_t = 128)]
+    pub set_max_listpack_entries: usize,
+
+    /// Sets with a longer member report the hashtable encoding
+    #[arg(long, env = "RFS_SET_MAX_LISTPACK_VALUE", default_value_t = 64)]
+    pub set_max_listpack_value: usize,
+
+    /// Sorted sets with at most this many members report the listpack
+    /// encoding
+    #[arg(long, env = "RFS_ZSET_MAX_LISTPACK_ENTRIES", default_value_t = 128)]
+    pub zset_max_listpack_entries: usize,
+
+    /// Sorted sets with a longer member report the skiplist encoding
+    #[arg(long, env = "RFS_ZSET_MAX_LISTPACK_VALUE", default_value_t = 64)]
+    pub zset_max_listpack_value: usize,
+
+    /// Lists that fit one quicklist node of this size report the listpack
+    /// encoding: a positive entry count, or -1 through -5 for 4 KB through
+    /// 64 KB
+    #[arg(
+        long,
+        env = "RFS_LIST_MAX_LISTPACK_SIZE",
+        default_value_t = -2,
+        allow_negative_numbers = true
+    )]
+    pub list_max_listpack_size: i64,
 }
 
 impl Config {
     pub fn from_args() -> Self {
-        Self::parse()
+        Self::try_from_args(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Parse `args`, program name first. Settings from the `--config` file,
+    /// if one is named, fill in whatever the arguments and environment
+    /// leave at its default.
+    pub fn try_from_args<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+        let matches = Self::command().try_get_matches_from(&args)?;
+        let Some(path) = matches.get_one::<PathBuf>("config") else {
+            return Self::from_arg_matches(&matches);
+        };
+        let invalid = |msg: String| {
+            let msg = format!("config file '{}': {msg}", path.display());
+            Self::command().error(ErrorKind::InvalidValue, msg)
+        };
+        let text = std::fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let flags = config_file_flags(&text).map_err(invalid)?;
+
+        // Rebuild the command line with the file's settings in front, so
+        // clap parses and validates them like any other flag.
+        let mut merged: Vec<OsString> = args.iter().take(1).cloned().collect();
+        for (id, value) in flags {
+            let explicit = matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            );
+            if !explicit {
+                merged.push(format!("--{}={value}", id.replace('_', "-")).into());
+            }
+        }
+        merged.extend(args.into_iter().skip(1));
+        Self::from_arg_matches(&Self::command().try_get_matches_from(merged)?)
+    }
+}
+
+/// A memory size that [`parse_memory`] can't read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("argument must be a memory value")]
+pub struct InvalidMemory;
+
+/// Parse a memory size the way Redis' config does: a plain byte count, or
+/// one followed by a case-insensitive unit. `k`, `m` and `g` are powers of
+/// 1000, while `kb`, `mb` and `gb` are powers of 1024; `b` is bytes.
+pub fn parse_memory(s: &str) -> Result<u64, InvalidMemory> {
+    let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+    let (number, unit) = s.split_at(digits);
+    let multiplier: u64 = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err(InvalidMemory),
+    };
+    let n: u64 = number.parse().map_err(|_| InvalidMemory)?;
+    n.checked_mul(multiplier).ok_or(InvalidMemory)
+}
+
+/// [`parse_memory`] for flags held as `usize`.
+fn memory_arg(s: &str) -> Result<usize, InvalidMemory> {
+    parse_memory(s).and_then(|n| usize::try_from(n).map_err(|_| InvalidMemory))
+}
+
+/// Config-file directives that set one flag from a single value, as
+/// (Redis name, flag id).
+const DIRECTIVES: &[(&str, &str)] = &[
+    ("unixsocket", "unixsocket"),
+    ("loglevel", "loglevel"),
+    ("logfile", "logfile"),
+    ("maxclients", "max_connections"),
+    ("dir", "dir"),
+    ("appendfsync", "aof_fsync"),
+    ("proto-max-bulk-len", "proto_max_bulk_len"),
+    ("maxmemory", "maxmemory"),
+    ("maxmemory-policy", "maxmemory_policy"),
+    ("hz", "hz"),
+    ("active-expire-effort", "active_expire_effort"),
+    ("slowlog-log-slower-than", "slowlog_log_slower_than"),
+    ("slowlog-max-len", "slowlog_max_len"),
+    ("hash-max-listpack-entries", "hash_max_listpack_entries"),
+    ("hash-max-listpack-value", "hash_max_listpack_value"),
+    ("set-max-intset-entries", "set_max_intset_entries"),
+    ("set-max-listpack-entries", "set_max_listpack_entries"),
+    ("set-max-listpack-value", "set_max_listpack_value"),
+    ("zset-max-listpack-entries", "zset_max_listpack_entries"),
+    ("zset-max-listpack-value", "zset_max_listpack_value"),
+    ("list-max-listpack-size", "list_max_listpack_size"),
+];
+
+/// Translate a redis.conf-style file into (flag id, value) pairs, one per
+/// flag. As in Redis, a directive given twice takes its later value, and
+/// `bind` hosts listen on `port` (6379 unless set).
+fn config_file_flags(text: &str) -> Result<Vec<(&'static str, String)>, String> {
+    let mut flags: Vec<(&'static str, String)> = Vec::new();
+    let mut set = |id: &'static str, value: String| match flags.iter_mut().find(|f| f.0 == id) {
+        Some(flag) => flag.1 = value,
+        None => flags.push((id, value)),
+    };
+    let mut hosts: Option<Vec<String>> = None;
+    let mut port: Option<String> = None;
+    let mut appendonly = false;
+    let mut appendfilename = String::from("appendonly.aof");
+
+    for (n, line) in text.lines().enumerate() {
+        let at_line = |msg: String| format!("line {}: {msg}", n + 1);
+        let words = split_config_line(line).map_err(|e| at_line(e.into()))?;
+        let Some((name, values)) = words.split_first() else {
+            continue;
+        };
+        let name = name.to_ascii_lowercase();
+        let directive = DIRECTIVES.iter().find(|d| d.0 == name).map(|d| d.1);
+        match (name.as_str(), values, directive) {
+            ("bind", [_, ..], _) => hosts = Some(values.to_vec()),
+            ("port", [value], _) => port = Some(value.clone()),
+            ("appendonly", [value], _) => {
+                appendonly = match value.to_ascii_lowercase().as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => {
+                        return Err(at_line(format!(
+                            "appendonly must be yes or no, not '{value}'"
+                        )));
+                    }
+                }
+            }
+            ("appendfilename", [value], _) => appendfilename = value.clone(),
+            ("client-output-buffer-limit", [class, hard, soft, _seconds], _) => {
+                if !class.eq_ignore_ascii_case("normal") {
+                    return Err(at_line(format!(
+                        "only the normal client-output-buffer-limit class is supported, not '{class}'"
+                    )));
+                }
+                set("client_output_buffer_hard_limit", hard.clone());
+                set("client_output_buffer_soft_limit", soft.clone());
+            }
+            // An empty logfile means standard output, the default.
+            ("logfile", [value], _) if value.is_empty() => {}
+            (_, [value], Some(id)) => set(id, value.clone()),
+            (
+                "bind" | "port" | "appendonly" | "appendfilename" | "client-output-buffer-limit",
+                _,
+                _,
+            )
+            | (_, _, Some(_)) => {
+                return Err(at_line(format!("wrong number of arguments for '{name}'")));
+            }
+            _ => return Err(at_line(format!("unknown directive '{name}'"))),
+        }
+    }
+
+    if hosts.is_some() || port.is_some() {
+        let port = port.as_deref().unwrap_or("6379");
+        let hosts = hosts.unwrap_or_else(|| vec!["127.0.0.1".into()]);
+        let addrs: Vec<String> = hosts
+            .iter()
+            .map(|host| {
+                // A leading '-' marks an address Redis may skip if it is
+                // unavailable; here every address must bind.
+                match host.trim_start_matches('-') {
+                    "*" => format!("0.0.0.0:{port}"),
+                    "::*" => format!("[::]:{port}"),
+                    host if host.contains(':') => format!("[{host}]:{port}"),
+                    host => format!("{host}:{port}"),
+                }
+            })
+            .collect();
+        set("bind", addrs.join(","));
+    }
+    if appendonly {
+        set("aof_path", appendfilename);
+    }
+    Ok(flags)
+}
+
+/// Header of the block CONFIG REWRITE appends for settings the file
+/// didn't have.
+const REWRITE_SIGNATURE: &str = "# Generated by CONFIG REWRITE";
+
+/// Write `settings`, (directive, value) pairs, into the config file at
+/// `path`, as CONFIG REWRITE does. The new file replaces the old one in a
+/// single rename, so a crash leaves one or the other.
+pub fn rewrite_config_file(path: &Path, settings: &[(&str, String)]) -> io::Result<()> {
+    let text = std::fs::read_to_string(path)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = std::fs::File::create(&tmp)?;
+    file.write_all(rewrite_config_text(&text, settings).as_bytes())?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)
+}
+
+/// The config file `text` with `settings` applied. Each directive's first
+/// line takes its new value and any later ones are dropped; comments and
+/// other lines stay as they are. Settings the file lacks are appended
+/// unless they're at their default.
+fn rewrite_config_text(text: &str, settings: &[(&str, String)]) -> String {
+    let mut written = vec![false; settings.len()];
+    let mut out = String::with_capacity(text.len());
+    for line in text.lines() {
+        let name = split_config_line(line)
+            .ok()
+            .and_then(|words| words.into_iter().next())
+            .map(|name| name.to_ascii_lowercase());
+        match name.and_then(|name| settings.iter().position(|s| s.0 == name)) {
+            Some(i) if written[i] => continue,
+            Some(i) => {
+                written[i] = true;
+                out.push_str(&format!("{} {}", settings[i].0, settings[i].1));
+            }
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+
+    let command = Config::command();
+    let default = |name: &str| {
+        let id = DIRECTIVES.iter().find(|d| d.0 == name)?.1;
+        let arg = command.get_arguments().find(|a| a.get_id() == id)?;
+        Some(
+            arg.get_default_values()
+                .first()?
+                .to_string_lossy()
+                .into_owned(),
+        )
+    };
+    let missing: Vec<_> = settings
+        .iter()
+        .zip(written)
+        .filter(|((name, value), written)| {
+            !written && default(name).as_deref() != Some(value.as_str())
+        })
+        .map(|(setting, _)| setting)
+        .collect();
+    // Only the first rewrite adds the header.
+    let signed = text.lines().any(|line| line.trim() == REWRITE_SIGNATURE);
+    if !missing.is_empty() && !signed {
+        out.push_str(REWRITE_SIGNATURE);
+        out.push('\n');
+    }
+    for (name, value) in missing {
+        out.push_str(&format!("{name} {value}\n"));
+    }
+    out
+}
+
+/// Split a config line into words as Redis does. Words are separated by
+/// whitespace; "double quotes" take backslash escapes and 'single quotes'
+/// are literal but for \'. A `#` starting a word comments out the rest of
+/// the line.
+fn split_config_line(line: &str) -> Result<Vec<String>, &'static str> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            break;
+        };
+        if first == '#' {
+            break;
+        }
+        let mut word = String::new();
+        if first == '"' || first == '\'' {
+            chars.next();
+            loop {
+                match (chars.next(), first) {
+                    (None, _) => return Err("unbalanced quotes"),
+                    (Some(c), _) if c == first => break,
+                    (Some('\\'), '"') => match chars.next() {
+                        Some('n') => word.push('\n'),
+                        Some('r') => word.push('\r'),
+                        Some('t') => word.push('\t'),
+                        Some(c) => word.push(c),
+                        None => return Err("unbalanced quotes"),
+                    },
+                    (Some('\\'), _) if chars.peek() == Some(&'\'') => {
+                        word.push('\'');
+                        chars.next();
+                    }
+                    (Some(c), _) => word.push(c),
+                }
+            }
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return Err("closing quote must be followed by a space");
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                word.push(c);
+            }
+        }
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::path::PathBuf;
+
+    use super::{Config, InvalidMemory, parse_memory, rewrite_config_text, split_config_line};
+
+    #[test]
+    fn parse_memory_units() {
+        for (input, bytes) in [
+            ("0", 0),
+            ("1024", 1024),
+            ("10b", 10),
+            ("1k", 1000),
+            ("1kb", 1024),
+            ("512KB", 512 * 1024),
+            ("2m", 2_000_000),
+            ("100mb", 100 * 1024 * 1024),
+            ("3g", 3_000_000_000),
+            ("1gb", 1024 * 1024 * 1024),
+            ("1Gb", 1024 * 1024 * 1024),
+        ] {
+            assert_eq!(parse_memory(input), Ok(bytes), "{input}");
+        }
+        for bad in [
+            "",
+            "mb",
+            "-1",
+            "+1",
+            " 1",
+            "1 mb",
+            "1.5gb",
+            "1tb",
+            "1kbb",
+            "0x10",
+            "18446744073709551616",
+            "17179869184gb",
+        ] {
+            assert_eq!(parse_memory(bad), Err(InvalidMemory), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn split_config_line_handles_quotes_and_comments() {
+        let split = |line| split_config_line(line).map(|w| w.join("|"));
+        assert_eq!(
+            split("  bind 127.0.0.1 ::1 # local"),
+            Ok("bind|127.0.0.1|::1".into())
+        );
+        assert_eq!(
+            split(r#"logfile "/tmp/my log""#),
+            Ok("logfile|/tmp/my log".into())
+        );
+        assert_eq!(
+            split(r#"x "a\"b\tc" 'it\'s' 'a\b' pa#ss"#),
+            Ok("x|a\"b\tc|it's|a\\b|pa#ss".into())
+        );
+        assert_eq!(split("# a comment"), Ok("".into()));
+        assert_eq!(split("   "), Ok("".into()));
+        assert!(split(r#"x "open"#).is_err());
+        assert!(split(r#"x "a"b"#).is_err());
+    }
+
+    #[test]
+    fn config_file_fills_in_unset_flags() {
+        let path = std::env::temp_dir().join(format!("rfs-config-{}.conf", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"# A sample config
+bind 127.0.0.1 ::1
+port 6400
+maxmemory 1mb
+MAXMEMORY-POLICY allkeys-lfu
+appendonly yes
+appendfilename "my data.aof"
+appendfsync always
+slowlog-log-slower-than -1
+hz 50
+client-output-buffer-limit normal 0 2kb 0
+logfile ""
+"#,
+        )
+        .unwrap();
+        let file = path.to_str().unwrap();
+
+        let config =
+            Config::try_from_args(["rfs-rs", "--config", file, "--aof-fsync", "no"]).unwrap();
+        let bind: Vec<SocketAddr> = vec![
+            "127.0.0.1:6400".parse().unwrap(),
+            "[::1]:6400".parse().unwrap(),
+        ];
+        assert_eq!(config.bind, bind);
+        assert_eq!(config.maxmemory, 1048576);
+        assert_eq!(config.maxmemory_policy, "allkeys-lfu");
+        assert_eq!(config.aof_path, Some(PathBuf::from("my data.aof")));
+        assert_eq!(config.slowlog_log_slower_than, -1);
+        assert_eq!(config.hz, 50);
+        assert_eq!(config.client_output_buffer_soft_limit, 2048);
+        assert_eq!(config.client_output_buffer_hard_limit, 0);
+        assert_eq!(config.logfile, None);
+        // The command line wins; anything neither sets keeps its default.
+        assert_eq!(config.aof_fsync, "no");
+        assert_eq!(config.max_connections, 1024);
+
+        for (text, error) in [
+            (
+                "maxmemory 1 2\n",
+                "line 1: wrong number of arguments for 'maxmemory'",
+            ),
+            ("\nnosuch yes\n", "line 2: unknown directive 'nosuch'"),
+            ("appendonly maybe\n", "appendonly must be yes or no"),
+            ("maxmemory lots\n", "invalid value 'lots'"),
+            ("active-expire-effort 11\n", "invalid value '11'"),
+        ] {
+            std::fs::write(&path, text).unwrap();
+            let err = Config::try_from_args(["rfs-rs", "--config", file]).unwrap_err();
+            assert!(err.to_string().contains(error), "{err}");
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+    #[test]
+    fn rewrite_keeps_comments_and_updates_directives() {
+        let text = "# my server\nport 6400\nhz 20 # twice a tick\nHZ 30\n\n";
+        let settings = [
+            ("hz", "50".to_string()),
+            ("active-expire-effort", "1".to_string()),
+            ("set-max-intset-entries", "100".to_string()),
+        ];
+        let rewritten = rewrite_config_text(text, &settings);
+        // Defaults the file never set stay out of it.
+        assert_eq!(
+            rewritten,
+            "# my server\nport 6400\nhz 50\n\n# Generated by CONFIG REWRITE\nset-max-intset-entries 100\n"
+        );
+
+        // A second rewrite finds everything in place.
+        let settings = [
+            ("hz", "10".to_string()),
+            ("set-max-intset-entries", "200".to_string()),
+        ];
+        assert_eq!(
+            rewrite_config_text(&rewritten, &settings),
+            "# my server\nport 6400\nhz 10\n\n# Generated by CONFIG REWRITE\nset-max-intset-entries 200\n"
+        );
     }
 }
@@ -1,20 +1,40 @@
+mod acl;
 mod command;
 mod config;
 mod metrics;
+mod monitor;
 mod observability;
 mod persistence;
+mod propagate;
 mod protocol;
+mod pubsub;
+mod replication;
 mod server;
+mod slowlog;
 mod store;
 
 #[tokio::main]
 async fn main() {
     let config = config::Config::from_args();
 
-    observability::init_tracing();
-    metrics::init_metrics();
+    // Logging comes first so nothing later is lost; until then, errors can
+    // only go to stderr.
+    if let Err(err) = observability::init_tracing(&config) {
+        eprintln!("failed to set up logging: {err}");
+        std::process::exit(1);
+    }
+    if let Err(err) = metrics::init_metrics(&config) {
+        tracing::error!(error = %err, "failed to set up metrics");
+        observability::flush_logs();
+        std::process::exit(1);
+    }
 
-    if let Err(err) = server::run(config).await {
+    let result = server::run(config).await;
+    if let Err(err) = &result {
         tracing::error!(error = %err, "server exited with error");
     }
+    observability::flush_logs();
+    if result.is_err() {
+        std::process::exit(1);
+    }
 }
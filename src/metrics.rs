@@ -1,8 +1,19 @@
-use metrics_exporter_prometheus::PrometheusBuilder;
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder};
 
-/// Install a global Prometheus recorder. HTTP serving can be added later.
-pub fn init_metrics() {
-    if let Err(err) = PrometheusBuilder::new().install_recorder() {
-        tracing::warn!(error = %err, "failed to install prometheus metrics recorder");
+use crate::config::Config;
+
+/// Install the global Prometheus recorder, serving it over HTTP on
+/// `--metrics-bind` when that is set.
+pub fn init_metrics(config: &Config) -> Result<(), BuildError> {
+    let builder = PrometheusBuilder::new();
+    match config.metrics_bind {
+        Some(addr) => {
+            builder.with_http_listener(addr).install()?;
+            tracing::info!(%addr, "serving Prometheus metrics");
+        }
+        None => {
+            builder.install_recorder()?;
+        }
     }
+    Ok(())
 }
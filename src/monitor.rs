@@ -0,0 +1,126 @@
+//! MONITOR: a live feed of every command the server executes, sent to each
+//! connection that asked for it.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+use crate::protocol::RespFrame;
+use crate::server::client::PushSender;
+
+/// Shown in place of each argument of a command whose arguments are secret.
+const REDACTED: &str = "\"(redacted)\"";
+
+/// The connections in MONITOR mode.
+#[derive(Debug, Default)]
+pub struct Monitors {
+    /// Push queues of monitoring connections, keyed by client id.
+    watchers: Mutex<HashMap<u64, PushSender>>,
+    /// `watchers.len()`, readable without the lock so unwatched commands
+    /// cost one atomic load.
+    count: AtomicUsize,
+}
+
+impl Monitors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, client_id: u64, tx: PushSender) {
+        let mut watchers = self.watchers.lock().unwrap();
+        watchers.insert(client_id, tx);
+        self.count.store(watchers.len(), Ordering::Relaxed);
+    }
+
+    pub fn remove(&self, client_id: u64) {
+        let mut watchers = self.watchers.lock().unwrap();
+        watchers.remove(&client_id);
+        self.count.store(watchers.len(), Ordering::Relaxed);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.count.load(Ordering::Relaxed) > 0
+    }
+
+    /// Send `argv` (command name included), run by the client at `addr`, to
+    /// every monitor. With `redact`, only the command name is shown.
+    pub fn feed(&self, addr: &str, argv: &[Bytes], redact: bool) {
+        let line = RespFrame::SimpleString(format_line(SystemTime::now(), addr, argv, redact));
+        let mut watchers = self.watchers.lock().unwrap();
+        watchers.retain(|_, tx| tx.send(line.clone()).is_ok());
+        self.count.store(watchers.len(), Ordering::Relaxed);
+    }
+}
+
+/// A monitor line as Redis prints it:
+/// `1339518083.107412 [0 127.0.0.1:60866] "set" "key" "value"`.
+fn format_line(now: SystemTime, addr: &str, argv: &[Bytes], redact: bool) -> String {
+    let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let mut line = format!(
+        "{}.{:06} [0 {addr}]",
+        since_epoch.as_secs(),
+        since_epoch.subsec_micros()
+    );
+    for (i, arg) in argv.iter().enumerate() {
+        line.push(' ');
+        if redact && i > 0 {
+            line.push_str(REDACTED);
+        } else {
+            quote(&mut line, arg);
+        }
+    }
+    line
+}
+
+/// Append `arg` double-quoted, escaping it the way Redis' `sdscatrepr` does
+/// so the line stays printable.
+fn quote(out: &mut String, arg: &[u8]) {
+    out.push('"');
+    for &b in arg {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b if b.is_ascii_graphic() || b == b' ' => out.push(b as char),
+            b => {
+                let _ = write!(out, "\\x{b:02x}");
+            }
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn lines_quote_and_redact_arguments() {
+        let at = UNIX_EPOCH + Duration::from_micros(1_339_518_083_107_412);
+        let argv = [
+            Bytes::from_static(b"set"),
+            Bytes::from_static(b"k"),
+            Bytes::from_static(b"a \"b\"\r\n\x00\xff"),
+        ];
+        assert_eq!(
+            format_line(at, "127.0.0.1:60866", &argv, false),
+            r#"1339518083.107412 [0 127.0.0.1:60866] "set" "k" "a \"b\"\r\n\x00\xff""#
+        );
+
+        let argv = [Bytes::from_static(b"AUTH"), Bytes::from_static(b"secret")];
+        assert_eq!(
+            format_line(at, "c", &argv, true),
+            r#"1339518083.107412 [0 c] "AUTH" "(redacted)""#
+        );
+    }
+}
@@ -1,14 +1,68 @@
-use tracing_subscriber::{EnvFilter, Registry, fmt, layer::SubscriberExt};
+use std::fs::OpenOptions;
+use std::sync::{Mutex, PoisonError};
 
-/// Initialize structured logging with a reasonable default filter.
-pub fn init_tracing() {
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, layer::SubscriberExt};
+
+use crate::config::Config;
+
+/// Filter used when neither `RUST_LOG` nor `--loglevel` is given.
+const DEFAULT_FILTER: &str = "info,tokio=info,rfs_rs=debug";
+
+/// The tracing filter for a Redis `loglevel` name. Redis has four levels
+/// where tracing has five, so `debug` takes in tracing's `trace` too.
+fn level_filter(loglevel: &str) -> &'static str {
+    match loglevel {
+        "debug" => "trace",
+        "verbose" => "debug",
+        "notice" => "info",
+        _ => "warn",
+    }
+}
+
+/// Flushes the `--logfile` writer thread when dropped; see [`flush_logs`].
+static LOG_GUARD: Mutex<Option<WorkerGuard>> = Mutex::new(None);
+
+/// Initialize structured logging as `config` asks. `RUST_LOG` takes
+/// precedence over `--loglevel`. With `--logfile`, lines are appended to it
+/// from a background thread, which [`flush_logs`] must drain before exit.
+pub fn init_tracing(config: &Config) -> std::io::Result<()> {
+    let loglevel = config.loglevel.as_deref();
     let env_filter = EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| EnvFilter::new("info,tokio=info,rfs_rs=debug"));
+        .unwrap_or_else(|_| EnvFilter::new(loglevel.map_or(DEFAULT_FILTER, level_filter)));
+
+    let (writer, guard) = match &config.logfile {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let (writer, guard) = tracing_appender::non_blocking(file);
+            (BoxMakeWriter::new(writer), Some(guard))
+        }
+        None => (BoxMakeWriter::new(std::io::stdout), None),
+    };
 
-    let subscriber = Registry::default()
-        .with(env_filter)
-        .with(fmt::layer().with_target(true).with_thread_ids(true));
+    let layer = fmt::layer()
+        .with_target(true)
+        .with_thread_ids(true)
+        .with_writer(writer);
+    let layer = if config.log_format == "json" {
+        layer.json().boxed()
+    } else {
+        layer.with_ansi(guard.is_none()).boxed()
+    };
+    let subscriber = Registry::default().with(env_filter).with(layer);
+
+    tracing::subscriber::set_global_default(subscriber).map_err(std::io::Error::other)?;
+    *LOG_GUARD.lock().unwrap_or_else(PoisonError::into_inner) = guard;
+    Ok(())
+}
 
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("failed to install tracing subscriber");
+/// Write out any log lines still queued for `--logfile`. Call on the way
+/// out, since `std::process::exit` skips the destructors that would;
+/// anything logged afterwards is lost.
+pub fn flush_logs() {
+    LOG_GUARD
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
 }
@@ -1,15 +1,19 @@
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, BufRead, BufReader, BufWriter, Write};
-use std::path::Path;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 
+use crate::persistence::serial;
 use crate::protocol::RespFrame;
 use crate::protocol::encoder::encode_frame;
-use crate::store::SharedStore;
+use crate::store::expire::{ExpireCondition, instant_from_unix_millis, unix_millis_from_instant};
+use crate::store::number::{parse_i64, parse_score};
 use crate::store::value::Value;
+use crate::store::{Database, SharedStore};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FsyncPolicy {
@@ -28,16 +32,43 @@ impl FsyncPolicy {
     }
 }
 
+/// How the server reacts once AOF writes start failing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AofErrorPolicy {
+    /// Keep accepting writes; durability is lost until the AOF recovers.
+    #[default]
+    Continue,
+    /// Refuse write commands with MISCONF until an AOF write succeeds.
+    Stop,
+}
+
+impl AofErrorPolicy {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "continue" => Some(Self::Continue),
+            "stop" => Some(Self::Stop),
+            _ => None,
+        }
+    }
+}
+
 /// Shared handle to the AOF writer.
 #[derive(Clone)]
 pub struct AofWriter {
     inner: Arc<Mutex<AofInner>>,
+    /// Whether the most recent write, flush or fsync succeeded. Kept outside
+    /// the mutex so INFO and dispatch can read it without queueing behind
+    /// disk I/O.
+    last_write_ok: Arc<AtomicBool>,
 }
 
 struct AofInner {
+    path: PathBuf,
     writer: BufWriter<File>,
     policy: FsyncPolicy,
     last_fsync: Instant,
+    /// Data has been written since the last fsync.
+    dirty: bool,
 }
 
 impl AofWriter {
@@ -47,56 +78,124 @@ impl AofWriter {
 
         Ok(Self {
             inner: Arc::new(Mutex::new(AofInner {
+                path: path.to_path_buf(),
                 writer: BufWriter::new(file),
                 policy,
                 last_fsync: Instant::now(),
+                dirty: false,
             })),
+            last_write_ok: Arc::new(AtomicBool::new(true)),
         })
     }
 
+    pub fn last_write_ok(&self) -> bool {
+        self.last_write_ok.load(Ordering::Relaxed)
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.inner.lock().unwrap().path.clone()
+    }
+
+    /// Rewrite the AOF from `db` and reopen it, so later appends land in the
+    /// new file rather than the unlinked old one.
+    pub fn rewrite(&self, db: &Database) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.writer.flush()?;
+        rewrite_aof(&inner.path, db)?;
+        let file = OpenOptions::new().append(true).open(&inner.path)?;
+        inner.writer = BufWriter::new(file);
+        inner.last_fsync = Instant::now();
+        inner.dirty = false;
+        Ok(())
+    }
+
     /// Append a command (as RESP array of bulk strings) to the AOF.
-    pub fn append(&self, args: &[&str]) {
-        let frame = RespFrame::Array(Some(
-            args.iter()
-                .map(|s| RespFrame::BulkString(Some(Bytes::copy_from_slice(s.as_bytes()))))
-                .collect(),
-        ));
+    pub fn append_frame(&self, frame: &RespFrame) {
         let mut buf = bytes::BytesMut::new();
-        encode_frame(&frame, &mut buf);
+        encode_frame(frame, &mut buf);
+
+        let mut inner = self.inner.lock().unwrap();
+        let result = inner.write(&buf);
+        self.record_result(&result);
+    }
 
+    /// Flush and fsync data that has waited a second or more. Driven by a
+    /// timer under `EverySec`, so the last writes before a quiet spell reach
+    /// disk without waiting for another append. An inline fsync resets the
+    /// clock, so the two never sync the same data twice.
+    pub fn sync_if_due(&self) {
         let mut inner = self.inner.lock().unwrap();
-        if let Err(e) = inner.writer.write_all(&buf) {
-            tracing::error!(error = %e, "aof write error");
+        if !inner.dirty || inner.last_fsync.elapsed() < Duration::from_secs(1) {
             return;
         }
+        let result = inner.sync();
+        self.record_result(&result);
+    }
 
-        match inner.policy {
-            FsyncPolicy::Always => {
-                let _ = inner.writer.flush();
-                if let Ok(f) = inner.writer.get_ref().try_clone() {
-                    let _ = f.sync_all();
-                }
-                inner.last_fsync = Instant::now();
-            }
-            FsyncPolicy::EverySec => {
-                if inner.last_fsync.elapsed() >= Duration::from_secs(1) {
-                    let _ = inner.writer.flush();
-                    if let Ok(f) = inner.writer.get_ref().try_clone() {
-                        let _ = f.sync_all();
-                    }
-                    inner.last_fsync = Instant::now();
-                }
+    /// Flush and fsync whatever is buffered, regardless of the policy.
+    pub fn sync(&self) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let result = inner.sync();
+        self.record_result(&result);
+        result
+    }
+
+    /// Track the outcome of an AOF write for INFO, metrics and MISCONF.
+    fn record_result(&self, result: &io::Result<()>) {
+        let ok = match result {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::error!(error = %e, "aof write error");
+                metrics::counter!("rfs_aof_write_errors_total").increment(1);
+                false
             }
-            FsyncPolicy::No => {
-                // Let the OS handle flushing.
+        };
+        if self.last_write_ok.swap(ok, Ordering::Relaxed) != ok {
+            metrics::gauge!("rfs_aof_last_write_ok").set(if ok { 1.0 } else { 0.0 });
+            if ok {
+                tracing::info!("aof writes succeeding again");
             }
         }
     }
 }
 
+impl AofInner {
+    /// Buffer `buf`, then flush and fsync if the policy says it's time.
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)?;
+        self.dirty = true;
+        let due = match self.policy {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::EverySec => self.last_fsync.elapsed() >= Duration::from_secs(1),
+            // Let the OS handle flushing.
+            FsyncPolicy::No => false,
+        };
+        if due {
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        self.last_fsync = Instant::now();
+        self.dirty = false;
+        Ok(())
+    }
+}
+
 /// Replay the AOF to rebuild state on startup.
 pub fn replay_aof(path: &Path, store: &SharedStore) -> io::Result<usize> {
-    if !path.exists() {
+    let mut guard = store.write().unwrap();
+    load_aof(path, &mut guard)
+}
+
+/// Apply every command in the AOF at `path` to `db`.
+pub fn load_aof(path: &Path, db: &mut Database) -> io::Result<usize> {
+    // Only a regular file holds anything to replay; a device such as
+    // /dev/null would otherwise be read as an endless stream.
+    if !path.is_file() {
         return Ok(0);
     }
 
@@ -105,11 +204,12 @@ pub fn replay_aof(path: &Path, store: &SharedStore) -> io::Result<usize> {
     let mut count: usize = 0;
 
     loop {
-        let mut line = String::new();
-        let n = reader.read_line(&mut line)?;
+        let mut line = Vec::new();
+        let n = reader.read_until(b'\n', &mut line)?;
         if n == 0 {
             break; // EOF
         }
+        let line = String::from_utf8_lossy(&line);
         let line = line.trim();
         if !line.starts_with('*') {
             continue; // skip malformed
@@ -120,13 +220,14 @@ pub fn replay_aof(path: &Path, store: &SharedStore) -> io::Result<usize> {
             Err(_) => continue,
         };
 
-        let mut args: Vec<String> = Vec::with_capacity(num_args);
+        let mut args: Vec<Bytes> = Vec::with_capacity(num_args);
         for _ in 0..num_args {
             // Read $<len>\r\n
-            let mut len_line = String::new();
-            if reader.read_line(&mut len_line)? == 0 {
+            let mut len_line = Vec::new();
+            if reader.read_until(b'\n', &mut len_line)? == 0 {
                 break;
             }
+            let len_line = String::from_utf8_lossy(&len_line);
             let len_line = len_line.trim();
             if !len_line.starts_with('$') {
                 break;
@@ -136,114 +237,195 @@ pub fn replay_aof(path: &Path, store: &SharedStore) -> io::Result<usize> {
                 Err(_) => break,
             };
 
-            // Read <data>\r\n
-            let mut data_line = String::new();
-            if reader.read_line(&mut data_line)? == 0 {
+            // Read exactly <len> bytes of data plus the trailing \r\n, so
+            // binary values containing newlines survive.
+            let mut data = vec![0u8; len + 2];
+            if reader.read_exact(&mut data).is_err() {
                 break;
             }
-            let data = &data_line[..data_line.trim_end().len()];
-            if data.len() != len {
-                // Best effort
-            }
-            args.push(data.to_string());
+            data.truncate(len);
+            args.push(Bytes::from(data));
         }
 
         if args.len() != num_args || args.is_empty() {
             continue;
         }
 
-        replay_command(&args, store);
+        replay_command(&args, db);
         count += 1;
     }
 
     Ok(count)
 }
 
+fn arg_str(arg: &Bytes) -> String {
+    String::from_utf8_lossy(arg).into_owned()
+}
+
+/// Deadline from a replayed SET's trailing options. PXAT/EXAT are absolute
+/// and survive restarts; EX/PX (written by older versions) can only be
+/// measured from the moment of replay. One too far off to represent leaves
+/// the key without a deadline.
+fn replay_set_deadline(opts: &[Bytes]) -> Option<Instant> {
+    let [opt, n] = opts else {
+        return None;
+    };
+    let n: u64 = arg_str(n).parse().ok()?;
+    match arg_str(opt).to_ascii_uppercase().as_str() {
+        "PXAT" => Some(instant_from_unix_millis(n)),
+        "EXAT" => Some(instant_from_unix_millis(n.saturating_mul(1000))),
+        "PX" => Instant::now().checked_add(Duration::from_millis(n)),
+        "EX" => Instant::now().checked_add(Duration::from_secs(n)),
+        _ => None,
+    }
+}
+
 /// Execute a single command from the AOF replay against the store.
-fn replay_command(args: &[String], store: &SharedStore) {
-    let cmd = args[0].to_ascii_uppercase();
-    let mut guard = store.write().unwrap();
+fn replay_command(args: &[Bytes], guard: &mut Database) {
+    let cmd = arg_str(&args[0]).to_ascii_uppercase();
 
     match cmd.as_str() {
         "SET" if args.len() >= 3 => {
-            let key = args[1].clone();
-            let val = Value::String(Bytes::copy_from_slice(args[2].as_bytes()));
-            // TODO: handle EX/PX from AOF replay
-            guard.set(key, val);
+            let key = arg_str(&args[1]);
+            let val = Value::String(args[2].clone());
+            match replay_set_deadline(&args[3..]) {
+                Some(deadline) => guard.set_with_deadline(key, val, deadline),
+                None => guard.set(key, val),
+            }
+        }
+        "SETRANGE" if args.len() >= 4 => {
+            if let Ok(offset) = arg_str(&args[2]).parse::<usize>() {
+                let _ = guard.setrange(&arg_str(&args[1]), offset, &args[3]);
+            }
+        }
+        "INCRBY" if args.len() >= 3 => {
+            if let Some(delta) = parse_i64(&args[2]) {
+                let _ = guard.incr_by(&arg_str(&args[1]), delta);
+            }
+        }
+        "SETBIT" if args.len() >= 4 => {
+            let on = args[3][..] == *b"1";
+            if let Ok(offset) = arg_str(&args[2]).parse::<usize>() {
+                let _ = guard.setbit(&arg_str(&args[1]), offset, on);
+            }
+        }
+        "APPEND" if args.len() >= 3 => {
+            let _ = guard.append(&arg_str(&args[1]), &args[2]);
+        }
+        "PEXPIREAT" if args.len() >= 3 => {
+            if let Ok(ms) = arg_str(&args[2]).parse::<u64>() {
+                guard.set_expiry(
+                    &arg_str(&args[1]),
+                    instant_from_unix_millis(ms),
+                    ExpireCondition::default(),
+                );
+            }
+        }
+        "PERSIST" if args.len() >= 2 => {
+            guard.persist(&arg_str(&args[1]));
         }
         "DEL" if args.len() >= 2 => {
-            let keys: Vec<String> = args[1..].to_vec();
+            let keys: Vec<String> = args[1..].iter().map(arg_str).collect();
             guard.del(&keys);
         }
-        "LPUSH" if args.len() >= 3 => {
-            let key = args[1].clone();
-            let vals: Vec<Bytes> = args[2..]
+        "FLUSHDB" | "FLUSHALL" => {
+            guard.flush();
+        }
+        "RESTORE" if args.len() >= 4 => {
+            // Written as `RESTORE key ttl payload REPLACE [ABSTTL]`.
+            let Ok(value) = serial::restore(&args[3]) else {
+                tracing::warn!("skipping AOF RESTORE with a bad payload");
+                return;
+            };
+            let key = arg_str(&args[1]);
+            let absttl = args[4..]
                 .iter()
-                .map(|s| Bytes::copy_from_slice(s.as_bytes()))
-                .collect();
-            guard.lpush(key, vals);
+                .any(|a| arg_str(a).eq_ignore_ascii_case("ABSTTL"));
+            match arg_str(&args[2]).parse::<u64>() {
+                Ok(0) | Err(_) => guard.set(key, value),
+                Ok(ms) if absttl => {
+                    guard.set_with_deadline(key, value, instant_from_unix_millis(ms))
+                }
+                // RESTORE itself takes no TTL past i64::MAX.
+                Ok(ms) => match Instant::now()
+                    .checked_add(Duration::from_millis(ms))
+                    .filter(|_| i64::try_from(ms).is_ok())
+                {
+                    Some(deadline) => guard.set_with_deadline(key, value, deadline),
+                    None => tracing::warn!("skipping AOF RESTORE with an invalid TTL"),
+                },
+            }
+        }
+        "COPY" if args.len() >= 3 => {
+            let replace = args
+                .get(3)
+                .is_some_and(|a| arg_str(a).eq_ignore_ascii_case("REPLACE"));
+            guard.copy(&arg_str(&args[1]), &arg_str(&args[2]), replace);
+        }
+        "LPUSH" if args.len() >= 3 => {
+            let _ = guard.lpush(arg_str(&args[1]), args[2..].to_vec());
         }
         "RPUSH" if args.len() >= 3 => {
-            let key = args[1].clone();
-            let vals: Vec<Bytes> = args[2..]
-                .iter()
-                .map(|s| Bytes::copy_from_slice(s.as_bytes()))
-                .collect();
-            guard.rpush(key, vals);
+            let _ = guard.rpush(arg_str(&args[1]), args[2..].to_vec());
         }
         "LPOP" if args.len() >= 2 => {
-            guard.lpop(&args[1]);
+            let _ = guard.lpop(&arg_str(&args[1]), 1);
         }
         "RPOP" if args.len() >= 2 => {
-            guard.rpop(&args[1]);
+            let _ = guard.rpop(&arg_str(&args[1]), 1);
         }
         "SADD" if args.len() >= 3 => {
-            let key = args[1].clone();
-            let members: Vec<Bytes> = args[2..]
-                .iter()
-                .map(|s| Bytes::copy_from_slice(s.as_bytes()))
-                .collect();
-            guard.sadd(key, members);
+            let _ = guard.sadd(arg_str(&args[1]), args[2..].to_vec());
         }
         "SREM" if args.len() >= 3 => {
-            let members: Vec<Bytes> = args[2..]
-                .iter()
-                .map(|s| Bytes::copy_from_slice(s.as_bytes()))
-                .collect();
-            guard.srem(&args[1], members);
+            let _ = guard.srem(&arg_str(&args[1]), args[2..].to_vec());
         }
         "HSET" if args.len() >= 4 && (args.len() - 2).is_multiple_of(2) => {
-            let key = args[1].clone();
+            let key = arg_str(&args[1]);
             let mut fields = Vec::new();
             let mut i = 2;
             while i + 1 < args.len() {
-                fields.push((
-                    Bytes::copy_from_slice(args[i].as_bytes()),
-                    Bytes::copy_from_slice(args[i + 1].as_bytes()),
-                ));
+                fields.push((args[i].clone(), args[i + 1].clone()));
                 i += 2;
             }
-            guard.hset(key, fields);
+            let _ = guard.hset(key, fields);
         }
         "ZADD" if args.len() >= 4 && (args.len() - 2).is_multiple_of(2) => {
-            let key = args[1].clone();
-            let mut members = Vec::new();
-            let mut i = 2;
-            while i + 1 < args.len() {
-                if let Ok(score) = args[i].parse::<f64>() {
-                    let member = Bytes::copy_from_slice(args[i + 1].as_bytes());
-                    members.push((member, score));
+            // Like ZADD itself, one bad score refuses the whole command.
+            let members: Option<Vec<(Bytes, f64)>> = args[2..]
+                .chunks_exact(2)
+                .map(|pair| Some((pair[1].clone(), parse_score(&pair[0])?)))
+                .collect();
+            match members {
+                Some(members) => {
+                    let _ = guard.zadd(arg_str(&args[1]), members);
                 }
-                i += 2;
+                None => tracing::warn!("skipping AOF ZADD with an invalid score"),
             }
-            guard.zadd(key, members);
+        }
+        "HPEXPIREAT" if args.len() >= 6 => {
+            if let Ok(ms) = arg_str(&args[2]).parse::<u64>() {
+                let _ = guard.hexpire(
+                    &arg_str(&args[1]),
+                    &args[5..],
+                    instant_from_unix_millis(ms),
+                    ExpireCondition::default(),
+                );
+            }
+        }
+        "HINCRBY" if args.len() >= 4 => {
+            if let Some(delta) = parse_i64(&args[3]) {
+                let _ = guard.hincrby(arg_str(&args[1]), args[2].clone(), delta);
+            }
+        }
+        "HDEL" if args.len() >= 3 => {
+            let _ = guard.hdel(&arg_str(&args[1]), &args[2..]);
+        }
+        "HPERSIST" if args.len() >= 5 => {
+            let _ = guard.hpersist(&arg_str(&args[1]), &args[4..]);
         }
         "ZREM" if args.len() >= 3 => {
-            let members: Vec<Bytes> = args[2..]
-                .iter()
-                .map(|s| Bytes::copy_from_slice(s.as_bytes()))
-                .collect();
-            guard.zrem(&args[1], members);
+            let _ = guard.zrem(&arg_str(&args[1]), args[2..].to_vec());
         }
         _ => {
             tracing::debug!(cmd = %cmd, "skipping unknown AOF command during replay");
@@ -251,85 +433,83 @@ fn replay_command(args: &[String], store: &SharedStore) {
     }
 }
 
+/// Commands that recreate `db` from empty: one per live key, followed by
+/// HPEXPIREATs for hash fields with a TTL and a PEXPIREAT for keys with one.
+pub fn snapshot_commands(db: &Database) -> Vec<Vec<Bytes>> {
+    let mut commands = Vec::new();
+    for (key, value, deadline) in db.snapshot_for_aof() {
+        let key = Bytes::copy_from_slice(key.as_bytes());
+        let mut field_ttls = Vec::new();
+        let args: Vec<Bytes> = match value {
+            Value::String(b) => vec![Bytes::from_static(b"SET"), key.clone(), b],
+            Value::List(deque) => {
+                let mut args = vec![Bytes::from_static(b"RPUSH"), key.clone()];
+                args.extend(deque);
+                args
+            }
+            Value::Set(hs) => {
+                let mut args = vec![Bytes::from_static(b"SADD"), key.clone()];
+                args.extend(hs);
+                args
+            }
+            Value::Hash(hm) => {
+                let mut args = vec![Bytes::from_static(b"HSET"), key.clone()];
+                for (f, v) in &hm {
+                    args.push(f.clone());
+                    args.push(v.clone());
+                }
+                for (f, deadline) in hm.deadlines() {
+                    let at = unix_millis_from_instant(deadline).to_string();
+                    field_ttls.push(vec![
+                        Bytes::from_static(b"HPEXPIREAT"),
+                        key.clone(),
+                        Bytes::from(at),
+                        Bytes::from_static(b"FIELDS"),
+                        Bytes::from_static(b"1"),
+                        f.clone(),
+                    ]);
+                }
+                args
+            }
+            Value::ZSet(vec) => {
+                let mut args = vec![Bytes::from_static(b"ZADD"), key.clone()];
+                for (m, s) in vec {
+                    args.push(Bytes::from(s.to_string()));
+                    args.push(m);
+                }
+                args
+            }
+        };
+        // Empty collections have no command that recreates them.
+        if args.len() < 3 {
+            continue;
+        }
+
+        commands.push(args);
+        commands.extend(field_ttls);
+        if let Some(deadline) = deadline {
+            let at = unix_millis_from_instant(deadline).to_string();
+            commands.push(vec![Bytes::from_static(b"PEXPIREAT"), key, Bytes::from(at)]);
+        }
+    }
+    commands
+}
+
 /// Rewrite the AOF: snapshot current state to a temp file, then atomically rename.
-#[allow(dead_code)]
-pub fn rewrite_aof(path: &Path, store: &SharedStore) -> io::Result<()> {
+pub fn rewrite_aof(path: &Path, db: &Database) -> io::Result<()> {
     let tmp_path = path.with_extension("tmp");
     {
         let file = File::create(&tmp_path)?;
         let mut w = BufWriter::new(file);
-        let guard = store.read().unwrap();
-
-        // We need access to the internal data. Since Database fields are private,
-        // we use the snapshot method we'll add.
-        let snapshot = guard.snapshot_for_aof();
-        for (key, value) in &snapshot {
-            let mut buf = bytes::BytesMut::new();
-            match value {
-                Value::String(b) => {
-                    let cmd = RespFrame::Array(Some(vec![
-                        RespFrame::BulkString(Some(Bytes::from_static(b"SET"))),
-                        RespFrame::BulkString(Some(Bytes::copy_from_slice(key.as_bytes()))),
-                        RespFrame::BulkString(Some(b.clone())),
-                    ]));
-                    encode_frame(&cmd, &mut buf);
-                }
-                Value::List(deque) => {
-                    if !deque.is_empty() {
-                        let mut args = vec![
-                            RespFrame::BulkString(Some(Bytes::from_static(b"RPUSH"))),
-                            RespFrame::BulkString(Some(Bytes::copy_from_slice(key.as_bytes()))),
-                        ];
-                        for item in deque {
-                            args.push(RespFrame::BulkString(Some(item.clone())));
-                        }
-                        encode_frame(&RespFrame::Array(Some(args)), &mut buf);
-                    }
-                }
-                Value::Set(hs) => {
-                    if !hs.is_empty() {
-                        let mut args = vec![
-                            RespFrame::BulkString(Some(Bytes::from_static(b"SADD"))),
-                            RespFrame::BulkString(Some(Bytes::copy_from_slice(key.as_bytes()))),
-                        ];
-                        for item in hs {
-                            args.push(RespFrame::BulkString(Some(item.clone())));
-                        }
-                        encode_frame(&RespFrame::Array(Some(args)), &mut buf);
-                    }
-                }
-                Value::Hash(hm) => {
-                    if !hm.is_empty() {
-                        let mut args = vec![
-                            RespFrame::BulkString(Some(Bytes::from_static(b"HSET"))),
-                            RespFrame::BulkString(Some(Bytes::copy_from_slice(key.as_bytes()))),
-                        ];
-                        for (f, v) in hm {
-                            args.push(RespFrame::BulkString(Some(f.clone())));
-                            args.push(RespFrame::BulkString(Some(v.clone())));
-                        }
-                        encode_frame(&RespFrame::Array(Some(args)), &mut buf);
-                    }
-                }
-                Value::ZSet(vec) => {
-                    if !vec.is_empty() {
-                        let mut args = vec![
-                            RespFrame::BulkString(Some(Bytes::from_static(b"ZADD"))),
-                            RespFrame::BulkString(Some(Bytes::copy_from_slice(key.as_bytes()))),
-                        ];
-                        for (m, s) in vec {
-                            args.push(RespFrame::BulkString(Some(Bytes::copy_from_slice(
-                                s.to_string().as_bytes(),
-                            ))));
-                            args.push(RespFrame::BulkString(Some(m.clone())));
-                        }
-                        encode_frame(&RespFrame::Array(Some(args)), &mut buf);
-                    }
-                }
-            }
+
+        let mut buf = bytes::BytesMut::new();
+        for cmd in snapshot_commands(db) {
+            encode_frame(&RespFrame::command(cmd), &mut buf);
             w.write_all(&buf)?;
+            buf.clear();
         }
         w.flush()?;
+        w.get_ref().sync_all()?;
     }
 
     fs::rename(&tmp_path, path)?;
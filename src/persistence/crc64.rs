@@ -0,0 +1,50 @@
+//! CRC-64 with the Jones polynomial, as Redis uses to checksum RDB files and
+//! DUMP payloads: reflected input and output, initial value 0, no final
+//! XOR.
+
+/// The Jones polynomial, bit-reversed for the reflected algorithm.
+const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+const TABLE: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Extend `crc` over `data`. Start from 0; feeding a buffer in pieces gives
+/// the same result as feeding it whole.
+pub fn crc64(mut crc: u64, data: &[u8]) -> u64 {
+    for &b in data {
+        crc = TABLE[((crc ^ u64::from(b)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crc64;
+
+    #[test]
+    fn matches_redis_check_values() {
+        assert_eq!(crc64(0, b""), 0);
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+
+        let text = b"This is a test of the emergency broadcast system.";
+        let (head, tail) = text.split_at(10);
+        assert_eq!(crc64(crc64(0, head), tail), crc64(0, text));
+    }
+}
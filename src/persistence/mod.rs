@@ -1 +1,3 @@
 pub mod aof;
+pub mod crc64;
+pub mod serial;
@@ -0,0 +1,331 @@
+//! Binary value encoding modelled on Redis' RDB format: lengths and strings
+//! use RDB's variable-length integers. Strings are stored verbatim, without
+//! RDB's integer or LZF encodings.
+//!
+//! A DUMP payload is a type byte and the encoded value, followed by a footer
+//! as in Redis: the 2-byte RDB version, then a CRC-64 of everything before
+//! it, both little-endian.
+
+use std::collections::{HashSet, VecDeque};
+
+use bytes::Bytes;
+
+use crate::persistence::crc64::crc64;
+use crate::store::member_order;
+use crate::store::value::{Hash, Value};
+
+/// RDB version written in DUMP footers. Payloads from a later version are
+/// refused, as they may hold types this server can't read.
+const RDB_VERSION: u16 = 11;
+
+/// RDB type bytes. Lists and sorted sets use the plain encodings, a list of
+/// strings and `ZSET_2` with binary scores.
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+
+/// Version and checksum.
+const FOOTER_LEN: usize = 2 + 8;
+
+/// Why RESTORE refused a payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpError {
+    /// Too short for a footer, from a newer version, or failing its
+    /// checksum.
+    Footer,
+    /// The footer checks out but the value doesn't decode.
+    Format,
+}
+
+/// Serialize `value` the way DUMP returns it. Hash field TTLs aren't
+/// included, the same as a key's own TTL.
+pub fn dump(value: &Value) -> Vec<u8> {
+    let mut out = vec![match value {
+        Value::String(_) => TYPE_STRING,
+        Value::List(_) => TYPE_LIST,
+        Value::Set(_) => TYPE_SET,
+        Value::Hash(_) => TYPE_HASH,
+        Value::ZSet(_) => TYPE_ZSET_2,
+    }];
+    encode_value(value, &mut out);
+    out.extend_from_slice(&RDB_VERSION.to_le_bytes());
+    let crc = crc64(0, &out);
+    out.extend_from_slice(&crc.to_le_bytes());
+    out
+}
+
+/// Check a DUMP payload's footer and decode the value it holds.
+pub fn restore(payload: &[u8]) -> Result<Value, DumpError> {
+    let Some(body_len) = payload.len().checked_sub(FOOTER_LEN) else {
+        return Err(DumpError::Footer);
+    };
+    let (versioned, crc) = payload.split_at(body_len + 2);
+    let version = u16::from_le_bytes([versioned[body_len], versioned[body_len + 1]]);
+    if version > RDB_VERSION || crc64(0, versioned) != u64::from_le_bytes(crc.try_into().unwrap()) {
+        return Err(DumpError::Footer);
+    }
+    let mut reader = Reader(&versioned[..body_len]);
+    let value = reader.value().ok_or(DumpError::Format)?;
+    if !reader.0.is_empty() {
+        return Err(DumpError::Format);
+    }
+    Ok(value)
+}
+
+/// Encode `value`'s payload.
+pub fn encode_value(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::String(b) => encode_string(b, out),
+        Value::List(items) => {
+            encode_len(items.len() as u64, out);
+            items.iter().for_each(|b| encode_string(b, out));
+        }
+        Value::Set(members) => {
+            encode_len(members.len() as u64, out);
+            members.iter().for_each(|b| encode_string(b, out));
+        }
+        Value::Hash(fields) => {
+            encode_len(fields.len() as u64, out);
+            for (field, val) in fields {
+                encode_string(field, out);
+                encode_string(val, out);
+            }
+        }
+        Value::ZSet(members) => {
+            encode_len(members.len() as u64, out);
+            for (member, score) in members {
+                encode_string(member, out);
+                out.extend_from_slice(&score.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// Bytes `value`'s payload occupies once encoded, as reported by
+/// `DEBUG OBJECT`'s `serializedlength`.
+pub fn serialized_len(value: &Value) -> usize {
+    let mut out = Vec::new();
+    encode_value(value, &mut out);
+    out.len()
+}
+
+fn encode_string(b: &Bytes, out: &mut Vec<u8>) {
+    encode_len(b.len() as u64, out);
+    out.extend_from_slice(b);
+}
+
+/// RDB length encoding: 6, 14, 32 or 64 bits, tagged in the top two bits of
+/// the first byte.
+fn encode_len(len: u64, out: &mut Vec<u8>) {
+    if len < 1 << 6 {
+        out.push(len as u8);
+    } else if len < 1 << 14 {
+        out.extend_from_slice(&(0x4000 | len as u16).to_be_bytes());
+    } else if let Ok(len) = u32::try_from(len) {
+        out.push(0x80);
+        out.extend_from_slice(&len.to_be_bytes());
+    } else {
+        out.push(0x81);
+        out.extend_from_slice(&len.to_be_bytes());
+    }
+}
+
+/// Cursor over an encoded value. Every read returns `None` on malformed
+/// input rather than panicking.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    /// The inverse of [`encode_len`].
+    fn len(&mut self) -> Option<usize> {
+        let first = self.byte()?;
+        let len = match first >> 6 {
+            0 => u64::from(first),
+            1 => u64::from(u16::from_be_bytes([first & 0x3f, self.byte()?])),
+            _ if first == 0x80 => u64::from(u32::from_be_bytes(self.take(4)?.try_into().ok()?)),
+            _ if first == 0x81 => u64::from_be_bytes(self.take(8)?.try_into().ok()?),
+            _ => return None,
+        };
+        // No element takes less than a byte, so a count beyond what's left
+        // is corrupt, and must not be trusted to size an allocation.
+        usize::try_from(len).ok().filter(|&n| n <= self.0.len())
+    }
+
+    fn string(&mut self) -> Option<Bytes> {
+        let len = self.len()?;
+        self.take(len).map(Bytes::copy_from_slice)
+    }
+
+    /// A collection's element count. Collections are never stored empty.
+    fn count(&mut self) -> Option<usize> {
+        self.len().filter(|&n| n > 0)
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        Some(match self.byte()? {
+            TYPE_STRING => Value::String(self.string()?),
+            TYPE_LIST => {
+                let n = self.count()?;
+                let items = (0..n)
+                    .map(|_| self.string())
+                    .collect::<Option<VecDeque<_>>>()?;
+                Value::List(items)
+            }
+            TYPE_SET => {
+                let n = self.count()?;
+                let mut members = HashSet::with_capacity(n);
+                for _ in 0..n {
+                    if !members.insert(self.string()?) {
+                        return None;
+                    }
+                }
+                Value::Set(members)
+            }
+            TYPE_HASH => {
+                let n = self.count()?;
+                let mut fields = Hash::default();
+                for _ in 0..n {
+                    let (field, val) = (self.string()?, self.string()?);
+                    if fields.insert(field, val).is_some() {
+                        return None;
+                    }
+                }
+                Value::Hash(fields)
+            }
+            TYPE_ZSET_2 => {
+                let n = self.count()?;
+                let mut members = Vec::with_capacity(n);
+                for _ in 0..n {
+                    let member = self.string()?;
+                    let score = f64::from_le_bytes(self.take(8)?.try_into().ok()?);
+                    if !score.is_finite() {
+                        return None;
+                    }
+                    members.push((member, score));
+                }
+                members.sort_by(member_order);
+                if members.windows(2).any(|w| w[0].0 == w[1].0) {
+                    return None;
+                }
+                Value::ZSet(members)
+            }
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_len_boundaries() {
+        let cases: &[(u64, &[u8])] = &[
+            (0, &[0x00]),
+            (63, &[0x3f]),
+            (64, &[0x40, 0x40]),
+            (16383, &[0x7f, 0xff]),
+            (16384, &[0x80, 0x00, 0x00, 0x40, 0x00]),
+            (u32::MAX as u64, &[0x80, 0xff, 0xff, 0xff, 0xff]),
+            (u32::MAX as u64 + 1, &[0x81, 0, 0, 0, 0x01, 0, 0, 0, 0]),
+        ];
+        for &(len, expected) in cases {
+            let mut out = Vec::new();
+            encode_len(len, &mut out);
+            assert_eq!(out, expected, "len {len}");
+        }
+    }
+    fn sample_values() -> Vec<Value> {
+        let b = |s: &'static str| Bytes::from_static(s.as_bytes());
+        let mut hash = Hash::default();
+        hash.insert(b("field"), b("value"));
+        hash.insert(b(""), b(""));
+        vec![
+            Value::String(b("")),
+            Value::String(Bytes::from(vec![b'x'; 100])),
+            Value::List([b("a"), b(""), b("a")].into()),
+            Value::Set([b("a"), b("b")].into()),
+            Value::Hash(hash),
+            Value::ZSet(vec![(b("low"), -1.5), (b("a"), 2.0), (b("b"), 2.0)]),
+        ]
+    }
+
+    #[test]
+    fn dump_round_trips() {
+        for value in sample_values() {
+            assert_eq!(restore(&dump(&value)), Ok(value));
+        }
+    }
+
+    #[test]
+    fn flipped_byte_fails_the_checksum() {
+        for value in sample_values() {
+            let payload = dump(&value);
+            for i in 0..payload.len() {
+                let mut corrupt = payload.clone();
+                corrupt[i] ^= 0x01;
+                assert_eq!(restore(&corrupt), Err(DumpError::Footer), "byte {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn newer_version_is_refused() {
+        let mut payload = dump(&Value::String(Bytes::from_static(b"v")));
+        let at = payload.len() - FOOTER_LEN;
+        payload[at..at + 2].copy_from_slice(&(RDB_VERSION + 1).to_le_bytes());
+        let crc = crc64(0, &payload[..at + 2]);
+        payload[at + 2..].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(restore(&payload), Err(DumpError::Footer));
+
+        // Older versions are still read.
+        payload[at..at + 2].copy_from_slice(&(RDB_VERSION - 1).to_le_bytes());
+        let crc = crc64(0, &payload[..at + 2]);
+        payload[at + 2..].copy_from_slice(&crc.to_le_bytes());
+        assert!(restore(&payload).is_ok());
+    }
+
+    #[test]
+    fn malformed_bodies_are_refused() {
+        // A valid footer around a body that doesn't decode.
+        let with_footer = |body: &[u8]| {
+            let mut payload = body.to_vec();
+            payload.extend_from_slice(&RDB_VERSION.to_le_bytes());
+            let crc = crc64(0, &payload);
+            payload.extend_from_slice(&crc.to_le_bytes());
+            payload
+        };
+        for body in [
+            &b""[..],
+            &[TYPE_STRING, 5, b'a'],
+            &[TYPE_STRING, 1, b'a', b'b'],
+            &[TYPE_LIST, 0],
+            &[TYPE_SET, 2, 1, b'a', 1, b'a'],
+            &[
+                TYPE_LIST, 0x81, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            ],
+            &[3, 0],
+        ] {
+            assert_eq!(
+                restore(&with_footer(body)),
+                Err(DumpError::Format),
+                "{body:?}"
+            );
+        }
+        assert_eq!(restore(b"short"), Err(DumpError::Footer));
+    }
+}
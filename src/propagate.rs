@@ -0,0 +1,60 @@
+//! The single exit for the effects of write commands.
+//!
+//! Write handlers describe what they changed as [`Effects`]; `dispatch` hands
+//! each effect to [`propagate`], which appends it to the AOF and streams it
+//! to replicas. Handlers never talk to either sink directly.
+
+use bytes::Bytes;
+
+use crate::protocol::RespFrame;
+use crate::server::state::ServerState;
+
+/// Commands a write handler asks to propagate, in the form they should be
+/// replayed. This is not always the command the client sent: relative
+/// expiries become absolute deadlines, a counted pop becomes single pops,
+/// and a write that changed nothing records nothing.
+#[derive(Debug, Default)]
+pub struct Effects(Vec<Vec<Bytes>>);
+
+impl Effects {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, args: &[&str]) {
+        self.0.push(
+            args.iter()
+                .map(|a| Bytes::copy_from_slice(a.as_bytes()))
+                .collect(),
+        );
+    }
+
+    /// Binary-safe variant of [`push`](Self::push).
+    pub fn push_bytes(&mut self, args: &[&[u8]]) {
+        self.0
+            .push(args.iter().map(|a| Bytes::copy_from_slice(a)).collect());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl IntoIterator for Effects {
+    type Item = Vec<Bytes>;
+    type IntoIter = std::vec::IntoIter<Vec<Bytes>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Send one applied write to every sink. Callers must hold the replication
+/// order lock so the sinks see writes in the order they were applied.
+pub fn propagate(server: &ServerState, args: Vec<Bytes>) {
+    let frame = RespFrame::command(args);
+    if let Some(w) = server.aof.as_ref() {
+        w.append_frame(&frame);
+    }
+    server.replication.feed(&frame);
+}
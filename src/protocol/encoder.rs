@@ -24,6 +24,11 @@ pub fn encode_frame(frame: &RespFrame, dst: &mut BytesMut) {
             dst.extend_from_slice(f.to_string().as_bytes());
             dst.extend_from_slice(b"\r\n");
         }
+        RespFrame::BigNumber(s) => {
+            dst.put_u8(b'(');
+            dst.extend_from_slice(s.as_bytes());
+            dst.extend_from_slice(b"\r\n");
+        }
         RespFrame::Boolean(b) => {
             dst.put_u8(b'#');
             dst.put_u8(if *b { b't' } else { b'f' });
@@ -42,6 +47,15 @@ pub fn encode_frame(frame: &RespFrame, dst: &mut BytesMut) {
             }
             None => dst.extend_from_slice(b"$-1\r\n"),
         },
+        RespFrame::Verbatim { format, data } => {
+            dst.put_u8(b'=');
+            dst.extend_from_slice((format.len() + 1 + data.len()).to_string().as_bytes());
+            dst.extend_from_slice(b"\r\n");
+            dst.extend_from_slice(format);
+            dst.put_u8(b':');
+            dst.extend_from_slice(data);
+            dst.extend_from_slice(b"\r\n");
+        }
         RespFrame::Array(opt) => match opt {
             Some(items) => {
                 dst.put_u8(b'*');
@@ -84,5 +98,58 @@ pub fn encode_frame(frame: &RespFrame, dst: &mut BytesMut) {
                 encode_frame(item, dst);
             }
         }
+        RespFrame::Sample(sample) => {
+            encode_array_header(sample.len(), dst);
+            for item in sample.items() {
+                encode_frame(item, dst);
+            }
+        }
+    }
+}
+
+/// The `*<len>` line opening an array.
+pub fn encode_array_header(len: usize, dst: &mut BytesMut) {
+    dst.put_u8(b'*');
+    dst.extend_from_slice(len.to_string().as_bytes());
+    dst.extend_from_slice(b"\r\n");
+}
+
+/// Bytes `frame` takes once encoded, worked out without encoding it. A
+/// [`RespFrame::Sample`] is only estimated, from the average size of what it
+/// picks from, since its items are not drawn until it is written.
+pub fn encoded_len(frame: &RespFrame) -> usize {
+    // A type byte, the text, and CRLF.
+    let line = |text_len: usize| 1 + text_len + 2;
+    let digits = |n: usize| n.checked_ilog10().map_or(1, |d| d as usize + 1);
+    let nested = |n: usize, items: usize| line(digits(n)) + items;
+    match frame {
+        RespFrame::SimpleString(s) | RespFrame::Error(s) | RespFrame::BigNumber(s) => line(s.len()),
+        RespFrame::Integer(i) => line(i.to_string().len()),
+        RespFrame::Double(f) => line(f.to_string().len()),
+        RespFrame::Boolean(_) => line(1),
+        RespFrame::Null => line(0),
+        RespFrame::BulkString(Some(bytes)) => line(digits(bytes.len())) + bytes.len() + 2,
+        RespFrame::Verbatim { data, .. } => {
+            let len = 4 + data.len();
+            line(digits(len)) + len + 2
+        }
+        RespFrame::BulkString(None)
+        | RespFrame::Array(None)
+        | RespFrame::Set(None)
+        | RespFrame::Map(None) => line(2),
+        RespFrame::Array(Some(items)) | RespFrame::Set(Some(items)) | RespFrame::Push(items) => {
+            nested(items.len(), items.iter().map(encoded_len).sum())
+        }
+        RespFrame::Map(Some(pairs)) => nested(
+            pairs.len(),
+            pairs
+                .iter()
+                .map(|(k, v)| encoded_len(k) + encoded_len(v))
+                .sum(),
+        ),
+        RespFrame::Sample(sample) => nested(
+            sample.len(),
+            sample.len().saturating_mul(sample.mean_item_len()),
+        ),
     }
 }
@@ -1,4 +1,7 @@
 pub mod encoder;
 pub mod parser;
+pub mod reply;
+pub mod sample;
 
-pub use parser::{RespCodec, RespFrame};
+pub use parser::{DEFAULT_MAX_BULK_LEN, RespCodec, RespFrame};
+pub use sample::Sample;
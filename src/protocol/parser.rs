@@ -1,9 +1,10 @@
 use std::io;
 
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::protocol::encoder::encode_frame;
+use crate::protocol::sample::Sample;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum RespFrame {
@@ -11,13 +12,33 @@ pub enum RespFrame {
     Error(String),
     Integer(i64),
     Double(f64),
+    /// An integer of any size, kept as its decimal digits.
+    BigNumber(String),
     Boolean(bool),
     Null,
     BulkString(Option<bytes::Bytes>),
+    /// A bulk string tagged with a three-byte format such as `txt` or `mkd`.
+    Verbatim {
+        format: [u8; 3],
+        data: bytes::Bytes,
+    },
     Array(Option<Vec<RespFrame>>),
     Map(Option<Vec<(RespFrame, RespFrame)>>),
     Set(Option<Vec<RespFrame>>),
     Push(Vec<RespFrame>),
+    /// An array picked at random as it is written; only ever a reply.
+    Sample(Sample),
+}
+
+impl RespFrame {
+    /// A command as sent on the wire: an array of bulk strings.
+    pub fn command(args: impl IntoIterator<Item = bytes::Bytes>) -> Self {
+        RespFrame::Array(Some(
+            args.into_iter()
+                .map(|a| RespFrame::BulkString(Some(a)))
+                .collect(),
+        ))
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -37,15 +58,40 @@ impl From<RespError> for io::Error {
     }
 }
 
-#[derive(Debug, Default)]
-pub struct RespCodec;
+/// Largest bulk string accepted by default, as Redis' `proto-max-bulk-len`.
+pub const DEFAULT_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RespCodec {
+    /// Bulk strings declaring a longer length are rejected as soon as the
+    /// header arrives, before any of the payload is buffered.
+    max_bulk_len: usize,
+}
+
+impl RespCodec {
+    pub fn new(max_bulk_len: usize) -> Self {
+        Self { max_bulk_len }
+    }
+
+    /// Whether `src` starts with a whole frame (or an error), so decoding it
+    /// won't have to wait for more input. Nothing is consumed.
+    pub fn has_frame(&self, src: &BytesMut) -> bool {
+        !matches!(parse_frame(src, self.max_bulk_len), Ok(None))
+    }
+}
+
+impl Default for RespCodec {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_BULK_LEN)
+    }
+}
 
 impl Decoder for RespCodec {
     type Item = RespFrame;
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
-        match parse_frame(src) {
+        match parse_frame(src, self.max_bulk_len) {
             Ok(Some((frame, used))) => {
                 src.advance(used);
                 Ok(Some(frame))
@@ -65,7 +111,20 @@ impl Encoder<RespFrame> for RespCodec {
     }
 }
 
-fn parse_frame(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
+/// Already encoded bytes, for replies written out a piece at a time.
+impl Encoder<Bytes> for RespCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        dst.extend_from_slice(&item);
+        Ok(())
+    }
+}
+
+fn parse_frame(
+    buf: &BytesMut,
+    max_bulk_len: usize,
+) -> Result<Option<(RespFrame, usize)>, RespError> {
     if buf.is_empty() {
         return Ok(None);
     }
@@ -74,14 +133,16 @@ fn parse_frame(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError>
         b'+' => parse_simple_string(buf),
         b'-' => parse_error(buf),
         b':' => parse_integer(buf),
-        b'$' => parse_bulk_string(buf),
-        b'*' => parse_array(buf),
+        b'$' => parse_bulk_string(buf, max_bulk_len),
+        b'*' => parse_array(buf, max_bulk_len),
         b'_' => parse_null(buf),
         b'#' => parse_boolean(buf),
         b',' => parse_double(buf),
-        b'%' => parse_map(buf),
-        b'~' => parse_set(buf),
-        b'>' => parse_push(buf),
+        b'(' => parse_big_number(buf),
+        b'=' => parse_verbatim(buf, max_bulk_len),
+        b'%' => parse_map(buf, max_bulk_len),
+        b'~' => parse_set(buf, max_bulk_len),
+        b'>' => parse_push(buf, max_bulk_len),
         _ => Err(RespError::Protocol("unknown prefix".into())),
     }
 }
@@ -138,7 +199,62 @@ fn parse_double(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError>
     Ok(Some((RespFrame::Double(num), consumed)))
 }
 
-fn parse_bulk_string(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
+fn parse_big_number(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
+    let Some(line_end) = find_crlf(&buf[1..]).map(|i| i + 1) else {
+        return Ok(None);
+    };
+    let line = &buf[1..line_end];
+    let consumed = line_end + 2;
+    let digits = line.strip_prefix(b"-").unwrap_or(line);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return Err(RespError::Protocol("invalid big number".into()));
+    }
+    // All ASCII, checked above.
+    let s = String::from_utf8_lossy(line).into_owned();
+    Ok(Some((RespFrame::BigNumber(s), consumed)))
+}
+
+fn parse_verbatim(
+    buf: &BytesMut,
+    max_bulk_len: usize,
+) -> Result<Option<(RespFrame, usize)>, RespError> {
+    let Some(line_end) = find_crlf(&buf[1..]).map(|i| i + 1) else {
+        return Ok(None);
+    };
+    let len_bytes = &buf[1..line_end];
+    let len: usize = std::str::from_utf8(len_bytes)
+        .map_err(|e: std::str::Utf8Error| RespError::Protocol(e.to_string()))?
+        .parse()
+        .map_err(|e: std::num::ParseIntError| RespError::Protocol(e.to_string()))?;
+    if len > max_bulk_len {
+        return Err(RespError::Protocol("invalid bulk length".into()));
+    }
+
+    let consumed_head = line_end + 2;
+    let needed = consumed_head + len + 2;
+    if buf.len() < needed {
+        return Ok(None);
+    }
+
+    if &buf[consumed_head + len..needed] != b"\r\n" {
+        return Err(RespError::Protocol("verbatim string missing CRLF".into()));
+    }
+
+    let body = &buf[consumed_head..consumed_head + len];
+    let Some((format, [b':', data @ ..])) = body.split_first_chunk::<3>() else {
+        return Err(RespError::Protocol("verbatim string missing format".into()));
+    };
+    let frame = RespFrame::Verbatim {
+        format: *format,
+        data: data.to_vec().into(),
+    };
+    Ok(Some((frame, needed)))
+}
+
+fn parse_bulk_string(
+    buf: &BytesMut,
+    max_bulk_len: usize,
+) -> Result<Option<(RespFrame, usize)>, RespError> {
     let Some(line_end) = find_crlf(&buf[1..]).map(|i| i + 1) else {
         return Ok(None);
     };
@@ -148,7 +264,7 @@ fn parse_bulk_string(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespE
         .parse()
         .map_err(|e: std::num::ParseIntError| RespError::Protocol(e.to_string()))?;
 
-    if len < -1 {
+    if len < -1 || (len > 0 && len as usize > max_bulk_len) {
         return Err(RespError::Protocol("invalid bulk length".into()));
     }
 
@@ -173,7 +289,10 @@ fn parse_bulk_string(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespE
     Ok(Some((RespFrame::BulkString(Some(data.into())), consumed)))
 }
 
-fn parse_array(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
+fn parse_array(
+    buf: &BytesMut,
+    max_bulk_len: usize,
+) -> Result<Option<(RespFrame, usize)>, RespError> {
     let Some(line_end) = find_crlf(&buf[1..]).map(|i| i + 1) else {
         return Ok(None);
     };
@@ -197,7 +316,7 @@ fn parse_array(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError>
     let mut items = Vec::with_capacity(len);
     for _ in 0..len {
         let slice = BytesMut::from(&buf[consumed..]);
-        match parse_frame(&slice)? {
+        match parse_frame(&slice, max_bulk_len)? {
             Some((frame, used)) => {
                 consumed += used;
                 items.push(frame);
@@ -209,7 +328,7 @@ fn parse_array(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError>
     Ok(Some((RespFrame::Array(Some(items)), consumed)))
 }
 
-fn parse_set(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
+fn parse_set(buf: &BytesMut, max_bulk_len: usize) -> Result<Option<(RespFrame, usize)>, RespError> {
     let Some(line_end) = find_crlf(&buf[1..]).map(|i| i + 1) else {
         return Ok(None);
     };
@@ -233,7 +352,7 @@ fn parse_set(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
     let mut items = Vec::with_capacity(len);
     for _ in 0..len {
         let slice = BytesMut::from(&buf[consumed..]);
-        match parse_frame(&slice)? {
+        match parse_frame(&slice, max_bulk_len)? {
             Some((frame, used)) => {
                 consumed += used;
                 items.push(frame);
@@ -245,7 +364,7 @@ fn parse_set(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
     Ok(Some((RespFrame::Set(Some(items)), consumed)))
 }
 
-fn parse_map(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
+fn parse_map(buf: &BytesMut, max_bulk_len: usize) -> Result<Option<(RespFrame, usize)>, RespError> {
     let Some(line_end) = find_crlf(&buf[1..]).map(|i| i + 1) else {
         return Ok(None);
     };
@@ -269,13 +388,13 @@ fn parse_map(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
     let mut items = Vec::with_capacity(len);
     for _ in 0..len {
         let slice_key = BytesMut::from(&buf[consumed..]);
-        let Some((key, used_key)) = parse_frame(&slice_key)? else {
+        let Some((key, used_key)) = parse_frame(&slice_key, max_bulk_len)? else {
             return Ok(None);
         };
         consumed += used_key;
 
         let slice_val = BytesMut::from(&buf[consumed..]);
-        let Some((val, used_val)) = parse_frame(&slice_val)? else {
+        let Some((val, used_val)) = parse_frame(&slice_val, max_bulk_len)? else {
             return Ok(None);
         };
         consumed += used_val;
@@ -286,7 +405,10 @@ fn parse_map(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
     Ok(Some((RespFrame::Map(Some(items)), consumed)))
 }
 
-fn parse_push(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
+fn parse_push(
+    buf: &BytesMut,
+    max_bulk_len: usize,
+) -> Result<Option<(RespFrame, usize)>, RespError> {
     let Some(line_end) = find_crlf(&buf[1..]).map(|i| i + 1) else {
         return Ok(None);
     };
@@ -306,7 +428,7 @@ fn parse_push(buf: &BytesMut) -> Result<Option<(RespFrame, usize)>, RespError> {
     let mut items = Vec::with_capacity(len);
     for _ in 0..len {
         let slice = BytesMut::from(&buf[consumed..]);
-        match parse_frame(&slice)? {
+        match parse_frame(&slice, max_bulk_len)? {
             Some((frame, used)) => {
                 consumed += used;
                 items.push(frame);
@@ -350,9 +472,10 @@ pub fn frame_to_bytes(frame: &RespFrame) -> BytesMut {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocol::encoder::encoded_len;
 
     fn decode_all(buf: &[u8]) -> Vec<RespFrame> {
-        let mut codec = RespCodec;
+        let mut codec = RespCodec::default();
         let mut bytes = BytesMut::from(buf);
         let mut out = Vec::new();
         while let Some(frame) = codec.decode(&mut bytes).unwrap() {
@@ -396,6 +519,18 @@ mod tests {
         assert_eq!(decode_all(&bytes), vec![frame]);
     }
 
+    #[test]
+    fn empty_bulk_string_is_not_null() {
+        let frames = decode_all(b"$0\r\n\r\n$-1\r\n");
+        assert_eq!(
+            frames,
+            vec![
+                RespFrame::BulkString(Some(bytes::Bytes::new())),
+                RespFrame::BulkString(None)
+            ]
+        );
+    }
+
     #[test]
     fn array_roundtrip() {
         let frame = RespFrame::Array(Some(vec![
@@ -431,6 +566,82 @@ mod tests {
         assert_eq!(decode_all(&bytes), vec![frame]);
     }
 
+    #[test]
+    fn big_number_roundtrip() {
+        for digits in ["3492890328409238509324850943850943825024385", "-17", "0"] {
+            let frame = RespFrame::BigNumber(digits.into());
+            let bytes = frame_to_bytes(&frame);
+            assert_eq!(decode_all(&bytes), vec![frame]);
+        }
+    }
+
+    #[test]
+    fn malformed_big_number_is_rejected() {
+        for bad in [&b"(12a\r\n"[..], b"(\r\n", b"(-\r\n"] {
+            let mut bytes = BytesMut::from(bad);
+            assert!(RespCodec::default().decode(&mut bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn verbatim_roundtrip() {
+        let frame = RespFrame::Verbatim {
+            format: *b"txt",
+            data: BytesMut::from("Some string\r\nover two lines").freeze(),
+        };
+        let bytes = frame_to_bytes(&frame);
+        assert_eq!(&bytes[..7], b"=31\r\ntx");
+        assert_eq!(decode_all(&bytes), vec![frame]);
+    }
+
+    #[test]
+    fn verbatim_without_format_is_rejected() {
+        let mut bytes = BytesMut::from(&b"=3\r\ntxt\r\n"[..]);
+        assert!(RespCodec::default().decode(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn oversized_bulk_length_is_rejected_before_the_payload() {
+        let mut codec = RespCodec::new(16);
+        let mut bytes = BytesMut::from(&b"$16\r\n"[..]);
+        assert_eq!(codec.decode(&mut bytes).unwrap(), None);
+
+        // Inside a command too, with none of the payload sent yet.
+        let mut bytes = BytesMut::from(&b"*2\r\n$3\r\nSET\r\n$17\r\n"[..]);
+        let err = codec.decode(&mut bytes).unwrap_err();
+        assert_eq!(err.to_string(), "invalid bulk length");
+    }
+
+    #[test]
+    fn encoded_len_matches_encoding() {
+        let bulk =
+            |s: &'static str| RespFrame::BulkString(Some(bytes::Bytes::from_static(s.as_bytes())));
+        let frames = [
+            RespFrame::SimpleString("OK".into()),
+            RespFrame::Integer(-1234),
+            RespFrame::Double(1.5),
+            RespFrame::BigNumber("123456789012345678901234567890".into()),
+            RespFrame::Null,
+            RespFrame::BulkString(None),
+            bulk(""),
+            bulk("0123456789"),
+            RespFrame::Verbatim {
+                format: *b"txt",
+                data: bytes::Bytes::from_static(b"hello"),
+            },
+            RespFrame::Array(Some((0..12).map(|_| bulk("x")).collect())),
+            RespFrame::Map(Some(vec![(bulk("k"), RespFrame::Boolean(true))])),
+            RespFrame::Push(vec![RespFrame::Set(None), RespFrame::Array(Some(vec![]))]),
+        ];
+        for frame in frames {
+            assert_eq!(
+                encoded_len(&frame),
+                frame_to_bytes(&frame).len(),
+                "{frame:?}"
+            );
+        }
+    }
+
     #[test]
     fn null_roundtrip() {
         let frame = RespFrame::Null;
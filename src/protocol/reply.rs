@@ -0,0 +1,92 @@
+//! Constructors for replies shared across many commands, so each frame is
+//! spelled out in exactly one place.
+
+use super::RespFrame;
+
+/// Error text for a command applied to a key of another type.
+pub const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+/// `+OK`.
+pub fn ok() -> RespFrame {
+    RespFrame::SimpleString("OK".into())
+}
+
+/// The reply for a missing value.
+pub fn nil() -> RespFrame {
+    RespFrame::BulkString(None)
+}
+
+pub fn int(n: i64) -> RespFrame {
+    RespFrame::Integer(n)
+}
+
+pub fn wrongtype() -> RespFrame {
+    RespFrame::Error(WRONGTYPE.into())
+}
+
+/// Arity error for `cmd`, named in lowercase as Redis does.
+pub fn wrong_args(cmd: &str) -> RespFrame {
+    RespFrame::Error(format!(
+        "ERR wrong number of arguments for '{}'",
+        cmd.to_ascii_lowercase()
+    ))
+}
+
+/// The reply to `<cmd> HELP`: a header, one simple string per line of
+/// `lines`, then the entry for HELP itself, laid out as Redis does.
+pub fn help(cmd: &str, lines: &[&str]) -> RespFrame {
+    let header = format!("{cmd} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:");
+    let footer = ["HELP", "    Print this help."];
+    RespFrame::Array(Some(
+        std::iter::once(header)
+            .chain(lines.iter().chain(&footer).map(|l| l.to_string()))
+            .map(RespFrame::SimpleString)
+            .collect(),
+    ))
+}
+
+/// Error for a subcommand `cmd` doesn't have (or one given the wrong
+/// number of arguments), pointing at `<cmd> HELP`.
+pub fn unknown_subcommand(cmd: &str, sub: &str) -> RespFrame {
+    RespFrame::Error(format!(
+        "ERR unknown subcommand or wrong number of arguments for '{sub}'. Try {cmd} HELP."
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+    use crate::protocol::encoder::encode_frame;
+
+    fn encoded(frame: RespFrame) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        encode_frame(&frame, &mut buf);
+        buf.to_vec()
+    }
+
+    #[test]
+    fn helpers_encode_to_the_literal_frames() {
+        assert_eq!(encoded(ok()), b"+OK\r\n");
+        assert_eq!(encoded(nil()), b"$-1\r\n");
+        assert_eq!(encoded(int(-5)), b":-5\r\n");
+        assert_eq!(
+            encoded(wrongtype()),
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+        assert_eq!(
+            encoded(wrong_args("GET")),
+            b"-ERR wrong number of arguments for 'get'\r\n"
+        );
+        assert_eq!(
+            encoded(help("PING", &["    Reply with PONG."])),
+            b"*4\r\n+PING <subcommand> [<arg> [value] [opt] ...]. Subcommands are:\r\n\
+              +    Reply with PONG.\r\n+HELP\r\n+    Print this help.\r\n"
+        );
+        assert_eq!(
+            encoded(unknown_subcommand("OBJECT", "nope")),
+            b"-ERR unknown subcommand or wrong number of arguments for 'nope'. Try OBJECT HELP.\r\n"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use super::encoder::encoded_len;
+use super::parser::RespFrame;
+
+/// An array reply drawn at random, with repeats, from a pool of frames, as
+/// SRANDMEMBER with a negative count sends. The items are only picked as the
+/// reply is encoded, so a huge count costs time on the wire rather than
+/// memory. Picks follow from `seed`, so every encoding gives the same items.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// Candidates, `group` consecutive frames to each (a field and its
+    /// value, say), picked as a whole.
+    pool: Arc<[RespFrame]>,
+    group: usize,
+    picks: usize,
+    seed: u64,
+}
+
+impl Sample {
+    /// `picks` groups of `group` frames from `pool`, whose length must be a
+    /// multiple of `group`. An empty pool gives an empty array.
+    pub fn new(pool: Vec<RespFrame>, group: usize, picks: usize, seed: u64) -> Self {
+        assert!(
+            group > 0 && pool.len().is_multiple_of(group),
+            "ragged sample pool"
+        );
+        let picks = if pool.is_empty() { 0 } else { picks };
+        Self {
+            pool: pool.into(),
+            group,
+            picks,
+            seed,
+        }
+    }
+
+    /// Number of frames in the array.
+    pub fn len(&self) -> usize {
+        self.picks.saturating_mul(self.group)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.picks == 0
+    }
+
+    /// The frames of the array, in order.
+    pub fn items(&self) -> impl Iterator<Item = &RespFrame> {
+        let groups = (self.pool.len() / self.group) as u64;
+        (0..self.picks as u64).flat_map(move |i| {
+            let start = (mix(self.seed, i) % groups) as usize * self.group;
+            &self.pool[start..start + self.group]
+        })
+    }
+
+    /// Average encoded size of a frame of the array.
+    pub(super) fn mean_item_len(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        self.pool.iter().map(encoded_len).sum::<usize>() / self.pool.len()
+    }
+}
+
+/// SplitMix64 of the `i`th step from `seed`, so picks can be made in any
+/// order without keeping generator state.
+fn mix(seed: u64, i: u64) -> u64 {
+    let mut z = seed.wrapping_add(i.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::Sample;
+    use crate::protocol::RespFrame;
+
+    fn bulk(s: &'static str) -> RespFrame {
+        RespFrame::BulkString(Some(Bytes::from_static(s.as_bytes())))
+    }
+
+    #[test]
+    fn picks_are_independent_and_repeatable() {
+        let pool = vec![bulk("a"), bulk("b"), bulk("c")];
+        let sample = Sample::new(pool.clone(), 1, 3000, 42);
+        assert_eq!(sample.len(), 3000);
+        let picked: Vec<_> = sample.items().collect();
+        assert_eq!(picked.len(), 3000);
+        // Every element should turn up, each roughly a third of the time.
+        for item in &pool {
+            let n = picked.iter().filter(|p| **p == item).count();
+            assert!((700..1300).contains(&n), "{item:?} drawn {n} times");
+        }
+        assert!(sample.items().eq(picked));
+        assert!(Sample::new(Vec::new(), 1, 5, 42).is_empty());
+    }
+
+    #[test]
+    fn groups_are_picked_whole() {
+        let pool = vec![bulk("f1"), bulk("v1"), bulk("f2"), bulk("v2")];
+        let sample = Sample::new(pool, 2, 100, 7);
+        assert_eq!(sample.len(), 200);
+        let picked: Vec<_> = sample.items().collect();
+        for pair in picked.chunks(2) {
+            assert!(
+                (pair[0], pair[1]) == (&bulk("f1"), &bulk("v1"))
+                    || (pair[0], pair[1]) == (&bulk("f2"), &bulk("v2")),
+                "{pair:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+
+use crate::protocol::RespFrame;
+use crate::server::client::PushSender;
+use crate::store::glob::glob_match;
+
+/// Subscribers of one channel namespace.
+#[derive(Debug, Default)]
+struct Registry {
+    /// Maps channel → (client id → that client's push queue)
+    channels: Mutex<HashMap<Bytes, HashMap<u64, PushSender>>>,
+}
+
+impl Registry {
+    fn subscribe(&self, channel: Bytes, client_id: u64, tx: PushSender) {
+        let mut channels = self.channels.lock().unwrap();
+        channels.entry(channel).or_default().insert(client_id, tx);
+    }
+
+    fn unsubscribe(&self, channel: &Bytes, client_id: u64) {
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(subs) = channels.get_mut(channel) {
+            subs.remove(&client_id);
+            if subs.is_empty() {
+                channels.remove(channel);
+            }
+        }
+    }
+
+    /// Push `message` to every subscriber of `channel` as a `kind` frame,
+    /// returning how many clients received it.
+    fn publish(&self, kind: &'static str, channel: &Bytes, message: Bytes) -> usize {
+        let channels = self.channels.lock().unwrap();
+        let Some(subs) = channels.get(channel) else {
+            return 0;
+        };
+        let frame = RespFrame::Array(Some(vec![
+            RespFrame::BulkString(Some(Bytes::from_static(kind.as_bytes()))),
+            RespFrame::BulkString(Some(channel.clone())),
+            RespFrame::BulkString(Some(message)),
+        ]));
+        subs.values()
+            .filter(|tx| tx.send(frame.clone()).is_ok())
+            .count()
+    }
+
+    /// Channels with at least one subscriber, optionally filtered by a glob.
+    fn active(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        let channels = self.channels.lock().unwrap();
+        channels
+            .keys()
+            .filter(|c| pattern.is_none_or(|p| glob_match(p, c)))
+            .cloned()
+            .collect()
+    }
+
+    fn numsub(&self, channel: &Bytes) -> usize {
+        let channels = self.channels.lock().unwrap();
+        channels.get(channel).map_or(0, HashMap::len)
+    }
+}
+
+/// Channel registry fanning published messages out to subscriber queues.
+/// Shard channels (SSUBSCRIBE/SPUBLISH) live in their own namespace, so a
+/// shard channel and a regular one of the same name never see each other's
+/// messages.
+#[derive(Debug, Default)]
+pub struct Broker {
+    channels: Registry,
+    shard_channels: Registry,
+}
+
+impl Broker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, channel: Bytes, client_id: u64, tx: PushSender) {
+        self.channels.subscribe(channel, client_id, tx);
+    }
+
+    pub fn unsubscribe(&self, channel: &Bytes, client_id: u64) {
+        self.channels.unsubscribe(channel, client_id);
+    }
+
+    /// Deliver `message` to every subscriber of `channel`, returning how many
+    /// clients received it.
+    pub fn publish(&self, channel: &Bytes, message: Bytes) -> usize {
+        self.channels.publish("message", channel, message)
+    }
+
+    pub fn ssubscribe(&self, channel: Bytes, client_id: u64, tx: PushSender) {
+        self.shard_channels.subscribe(channel, client_id, tx);
+    }
+
+    pub fn sunsubscribe(&self, channel: &Bytes, client_id: u64) {
+        self.shard_channels.unsubscribe(channel, client_id);
+    }
+
+    /// Deliver `message` to the subscribers of shard channel `channel` as an
+    /// `smessage`, returning how many clients received it.
+    pub fn spublish(&self, channel: &Bytes, message: Bytes) -> usize {
+        self.shard_channels.publish("smessage", channel, message)
+    }
+
+    /// Shard channels with subscribers, for PUBSUB SHARDCHANNELS.
+    pub fn shard_channels(&self, pattern: Option<&[u8]>) -> Vec<Bytes> {
+        self.shard_channels.active(pattern)
+    }
+
+    /// Subscriber count of a shard channel, for PUBSUB SHARDNUMSUB.
+    pub fn shard_numsub(&self, channel: &Bytes) -> usize {
+        self.shard_channels.numsub(channel)
+    }
+}
@@ -0,0 +1,184 @@
+//! Minimal master/replica replication.
+//!
+//! A replica connects to its master and sends `SYNC`. The master answers by
+//! pushing its dataset as a stream of commands (the same form as an AOF
+//! rewrite), then forwards every successful write command as it executes.
+//! The replica applies everything it receives through the normal command
+//! path. There is no partial resync: a dropped link means a fresh full sync.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::{Notify, watch};
+use tokio_util::codec::Framed;
+
+use crate::command;
+use crate::propagate::propagate;
+use crate::protocol::encoder::encoded_len;
+use crate::protocol::{RespCodec, RespFrame};
+use crate::server::client::{ClientState, PushSender, push_channel};
+use crate::server::state::ServerState;
+use crate::store::random;
+
+/// Hex digits in a replication id, as in Redis.
+const REPLID_LEN: usize = 40;
+
+/// Delay before reconnecting after the link to the master drops.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Replication state shared by the whole server: the replicas fed by this
+/// server and the master it follows, if any.
+#[derive(Debug)]
+pub struct Replication {
+    /// Held from the start of a write command until it has been fed to
+    /// replicas, and by SYNC while it snapshots. This keeps the feed in the
+    /// order writes were applied and stops a new replica from seeing a
+    /// write both in its snapshot and in the stream.
+    order: Mutex<()>,
+    /// Push queues of connected replicas, keyed by client id.
+    replicas: Mutex<HashMap<u64, PushSender>>,
+    /// `host:port` of the master to follow, or `None` when this is a master.
+    master: watch::Sender<Option<String>>,
+    /// Identifies this server's replication history, as INFO reports it.
+    replid: Mutex<String>,
+    /// Bytes of write commands fed so far, RESP-encoded: INFO's
+    /// `master_repl_offset`. Only ever grows.
+    offset: AtomicU64,
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Self {
+            order: Mutex::new(()),
+            replicas: Mutex::new(HashMap::new()),
+            master: watch::channel(None).0,
+            replid: Mutex::new(random::hex_id(REPLID_LEN)),
+            offset: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Replication {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lock_order(&self) -> MutexGuard<'_, ()> {
+        self.order.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn add_replica(&self, client_id: u64, tx: PushSender) {
+        self.replicas.lock().unwrap().insert(client_id, tx);
+    }
+
+    pub fn remove_replica(&self, client_id: u64) {
+        self.replicas.lock().unwrap().remove(&client_id);
+    }
+
+    /// Forward one command to every replica, dropping any whose connection
+    /// has gone away, and advance the offset past it.
+    pub fn feed(&self, frame: &RespFrame) {
+        self.offset
+            .fetch_add(encoded_len(frame) as u64, Ordering::Relaxed);
+        self.replicas
+            .lock()
+            .unwrap()
+            .retain(|_, tx| tx.send(frame.clone()).is_ok());
+    }
+
+    /// Start following `master` (`host:port`), or stop with `None`.
+    pub fn set_master(&self, master: Option<String>) {
+        self.master.send_replace(master);
+    }
+
+    pub fn master(&self) -> Option<String> {
+        self.master.borrow().clone()
+    }
+
+    pub fn replica_count(&self) -> usize {
+        self.replicas.lock().unwrap().len()
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset.load(Ordering::Relaxed)
+    }
+
+    pub fn replid(&self) -> String {
+        self.replid.lock().unwrap().clone()
+    }
+
+    /// Start a new replication history (DEBUG CHANGE-REPL-ID).
+    pub fn change_replid(&self) {
+        *self.replid.lock().unwrap() = random::hex_id(REPLID_LEN);
+    }
+}
+
+/// Follow whichever master REPLICAOF last selected, reconnecting when the
+/// link drops. Runs for the life of the server.
+pub async fn run_replica(server: Arc<ServerState>) {
+    let mut target = server.replication.master.subscribe();
+    loop {
+        let master = target.borrow_and_update().clone();
+        let Some(master) = master else {
+            if target.changed().await.is_err() {
+                return;
+            }
+            continue;
+        };
+
+        tokio::select! {
+            res = sync_from(&master, &server) => {
+                match res {
+                    Ok(()) => tracing::warn!(%master, "master closed replication link"),
+                    Err(e) => tracing::warn!(%master, error = %e, "replication link failed"),
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+                    changed = target.changed() => if changed.is_err() { return },
+                }
+            }
+            changed = target.changed() => if changed.is_err() { return },
+        }
+    }
+}
+
+/// Full-sync from `master`, then apply its command stream until the link
+/// closes.
+async fn sync_from(master: &str, server: &ServerState) -> std::io::Result<()> {
+    let stream = TcpStream::connect(master).await?;
+    let mut framed = Framed::new(stream, RespCodec::default());
+    framed
+        .send(RespFrame::command([Bytes::from_static(b"SYNC")]))
+        .await?;
+    tracing::info!(%master, "connected to master, starting full sync");
+
+    // The snapshot that follows replaces whatever this server held. The
+    // flush is propagated like any other write, so this server's AOF and
+    // replicas drop the old keys too rather than merging the snapshot in.
+    {
+        let _order = server.replication.lock_order();
+        if let Ok(mut guard) = server.store.write() {
+            guard.flush();
+        }
+        propagate(server, vec![Bytes::from_static(b"FLUSHALL")]);
+    }
+
+    // Replies produced while applying the stream are discarded; the push
+    // queue only needs to stay open.
+    let (push_tx, _push_rx) = push_channel(0, Arc::new(Notify::new()));
+    let mut client = ClientState::new(server.next_client_id(), master.to_string(), push_tx);
+    client.is_master = true;
+
+    while let Some(frame) = framed.next().await {
+        let frame = frame?;
+        if let RespFrame::Array(Some(_)) = frame {
+            command::dispatch(frame, server, &mut client);
+        }
+    }
+    Ok(())
+}
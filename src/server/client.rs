@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use tokio::sync::Notify;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender, error::SendError};
+
+use crate::protocol::RespFrame;
+use crate::protocol::encoder::encoded_len;
+use crate::pubsub::Broker;
+
+/// A connection's queue of out-of-band frames, with the encoded bytes still
+/// queued counted against `limit` (0 = unbounded), the output buffer hard
+/// limit. A send that would pass it is refused and `kill` notified, so a
+/// subscriber that can't keep up is disconnected rather than queued for
+/// without end, as in Redis.
+pub fn push_channel(limit: usize, kill: Arc<Notify>) -> (PushSender, PushReceiver) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let queued = Arc::new(AtomicUsize::new(0));
+    let sender = PushSender {
+        tx,
+        queued: queued.clone(),
+        limit,
+        kill,
+    };
+    (sender, PushReceiver { rx, queued })
+}
+
+#[derive(Debug, Clone)]
+pub struct PushSender {
+    tx: UnboundedSender<(RespFrame, usize)>,
+    queued: Arc<AtomicUsize>,
+    limit: usize,
+    kill: Arc<Notify>,
+}
+
+impl PushSender {
+    /// Queue `frame`, failing once the connection is gone or over its limit.
+    pub fn send(&self, frame: RespFrame) -> Result<(), SendError<RespFrame>> {
+        let len = encoded_len(&frame);
+        let queued = self.queued.fetch_add(len, Ordering::Relaxed) + len;
+        if self.limit > 0 && queued > self.limit {
+            self.queued.fetch_sub(len, Ordering::Relaxed);
+            tracing::warn!(
+                queued,
+                limit = self.limit,
+                "client push queue exceeded the output buffer hard limit, closing connection"
+            );
+            self.kill.notify_one();
+            return Err(SendError(frame));
+        }
+        self.tx
+            .send((frame, len))
+            .map_err(|SendError((frame, len))| {
+                self.queued.fetch_sub(len, Ordering::Relaxed);
+                SendError(frame)
+            })
+    }
+}
+
+#[derive(Debug)]
+pub struct PushReceiver {
+    rx: UnboundedReceiver<(RespFrame, usize)>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl PushReceiver {
+    pub async fn recv(&mut self) -> Option<RespFrame> {
+        let (frame, len) = self.rx.recv().await?;
+        self.queued.fetch_sub(len, Ordering::Relaxed);
+        Some(frame)
+    }
+}
+
+/// Per-connection state that outlives a single command.
+#[derive(Debug)]
+pub struct ClientState {
+    pub id: u64,
+    /// Peer address, as reported in SLOWLOG entries.
+    pub addr: String,
+    /// Queue for out-of-band frames (pub/sub messages and confirmations),
+    /// written to the socket by the connection loop.
+    pub push_tx: PushSender,
+    /// Channels this connection is subscribed to.
+    pub channels: HashSet<Bytes>,
+    /// Shard channels this connection is subscribed to.
+    pub shard_channels: HashSet<Bytes>,
+    /// Commands queued since MULTI, or `None` outside a transaction.
+    pub multi: Option<Vec<Vec<RespFrame>>>,
+    /// This is the replication link to our master, whose writes must apply
+    /// even though a replica is otherwise read-only.
+    pub is_master: bool,
+    /// Set by MONITOR: the connection only receives the command feed.
+    pub monitor: bool,
+    /// The user this connection is logged in as: `None` until it AUTHs or
+    /// its first command logs it in as the default user.
+    pub user: Option<String>,
+}
+
+impl ClientState {
+    pub fn new(id: u64, addr: String, push_tx: PushSender) -> Self {
+        Self {
+            id,
+            addr,
+            push_tx,
+            channels: HashSet::new(),
+            shard_channels: HashSet::new(),
+            multi: None,
+            is_master: false,
+            monitor: false,
+            user: None,
+        }
+    }
+
+    /// Return the connection to its just-connected state: abort any open
+    /// transaction, drop every subscription and forget the AUTHed user.
+    pub fn reset(&mut self, broker: &Broker) {
+        self.multi = None;
+        self.user = None;
+        for channel in self.channels.drain() {
+            broker.unsubscribe(&channel, self.id);
+        }
+        for channel in self.shard_channels.drain() {
+            broker.sunsubscribe(&channel, self.id);
+        }
+    }
+
+    /// A connection with at least one subscription, of either kind, is in
+    /// subscriber mode.
+    pub fn is_subscriber(&self) -> bool {
+        !self.channels.is_empty() || !self.shard_channels.is_empty()
+    }
+}
+
+/// Which connections CLIENT KILL closes. Every filter given must match.
+#[derive(Debug, Default)]
+pub struct KillFilter {
+    pub id: Option<u64>,
+    pub addr: Option<String>,
+    /// The connection issuing the kill, spared unless `SKIPME no`.
+    pub skip: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Registered {
+    addr: String,
+    kill: Arc<Notify>,
+}
+
+/// Every open connection, so that one client can act on another.
+#[derive(Debug, Default)]
+pub struct Clients {
+    conns: Mutex<HashMap<u64, Registered>>,
+}
+
+impl Clients {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Track connection `id` from `addr`. The connection closes once the
+    /// returned handle is notified.
+    pub fn register(&self, id: u64, addr: String) -> Arc<Notify> {
+        let kill = Arc::new(Notify::new());
+        self.conns.lock().unwrap().insert(
+            id,
+            Registered {
+                addr,
+                kill: kill.clone(),
+            },
+        );
+        kill
+    }
+
+    pub fn remove(&self, id: u64) {
+        self.conns.lock().unwrap().remove(&id);
+    }
+
+    /// Tell every connection matching `filter` to close, returning how many
+    /// there were. They stop tracking at once, so none is counted twice.
+    pub fn kill(&self, filter: &KillFilter) -> usize {
+        let mut killed = 0;
+        self.conns.lock().unwrap().retain(|&id, conn| {
+            let matches = filter.id.is_none_or(|want| want == id)
+                && filter.addr.as_ref().is_none_or(|want| *want == conn.addr)
+                && filter.skip != Some(id);
+            if matches {
+                // A stored permit: the loop sees it even if it isn't
+                // waiting right now.
+                conn.kill.notify_one();
+                killed += 1;
+            }
+            !matches
+        });
+        metrics::counter!("rfs_clients_killed_total").increment(killed as u64);
+        killed
+    }
+}
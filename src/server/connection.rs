@@ -1,35 +1,246 @@
+use std::sync::Arc;
+
+use bytes::BytesMut;
 use futures::{SinkExt, StreamExt};
-use tokio::net::TcpStream;
-use tokio_util::codec::Framed;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Encoder, Framed};
 
 use crate::command;
-use crate::persistence::aof::AofWriter;
-use crate::protocol::RespCodec;
-use crate::store::SharedStore;
-
-pub async fn handle_connection(
-    stream: TcpStream,
-    store: SharedStore,
-    aof: Option<AofWriter>,
-) -> std::io::Result<()> {
-    let mut framed = Framed::new(stream, RespCodec);
-
-    while let Some(frame) = framed.next().await {
-        match frame {
-            Ok(request) => {
-                let response = command::dispatch(request, &store, aof.as_ref());
-                // Ignore send errors (e.g., client closed) by breaking out.
-                if let Err(err) = framed.send(response).await {
-                    tracing::warn!(error = %err, "failed to send response");
-                    break;
-                }
-            }
-            Err(err) => {
-                tracing::warn!(error = %err, "protocol error");
+use crate::protocol::encoder::{encode_array_header, encode_frame};
+use crate::protocol::{RespCodec, RespFrame};
+use crate::server::client::{ClientState, push_channel};
+use crate::server::state::ServerState;
+
+/// Bounds on the bytes of encoded replies a connection may hold, modelled on
+/// Redis' `client-output-buffer-limit`.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputBufferLimits {
+    /// Stop reading commands until the buffer drains below this size.
+    pub soft: usize,
+    /// Close the connection once the buffer grows past this size (0 = off).
+    pub hard: usize,
+}
+
+/// Serve one client until it disconnects. `addr` is the peer address as
+/// reported to the client and in SLOWLOG entries.
+pub async fn handle_connection<S>(
+    stream: S,
+    addr: String,
+    server: Arc<ServerState>,
+    limits: OutputBufferLimits,
+    codec: RespCodec,
+) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut framed = Framed::new(stream, codec);
+    // `feed` flushes before queueing more once the buffer reaches this size,
+    // which is what applies the soft limit.
+    framed.set_backpressure_boundary(limits.soft);
+
+    let id = server.next_client_id();
+    let killed = server.clients.register(id, addr.clone());
+    let (push_tx, mut push_rx) = push_channel(limits.hard, killed.clone());
+    let mut client = ClientState::new(id, addr, push_tx);
+
+    loop {
+        // Biased, so pushes queued while running a command, such as a
+        // SUBSCRIBE confirmation, go out before the reply to the next
+        // pipelined one.
+        let reply = tokio::select! {
+            biased;
+            () = killed.notified() => {
+                tracing::debug!(addr = %client.addr, "connection killed");
                 break;
             }
+            Some(push) = push_rx.recv() => Some(push),
+            frame = framed.next() => match frame {
+                Some(Ok(request)) => command::dispatch(request, &server, &mut client),
+                Some(Err(err)) => {
+                    tracing::warn!(error = %err, "protocol error");
+                    // Tell the client why before hanging up, as Redis does.
+                    if err.kind() == std::io::ErrorKind::InvalidData {
+                        let reply = RespFrame::Error(format!("ERR Protocol error: {err}"));
+                        let _ = framed.send(reply).await;
+                    }
+                    break;
+                }
+                None => break,
+            },
+        };
+
+        if let Some(reply) = reply
+            && !send_reply(&mut framed, reply, limits).await
+        {
+            break;
         }
     }
 
+    for channel in &client.channels {
+        server.pubsub.unsubscribe(channel, client.id);
+    }
+    for channel in &client.shard_channels {
+        server.pubsub.sunsubscribe(channel, client.id);
+    }
+    server.replication.remove_replica(client.id);
+    server.monitors.remove(client.id);
+    server.clients.remove(client.id);
+
     Ok(())
 }
+
+/// Sampled replies are encoded and queued this many bytes at a time.
+const SAMPLE_CHUNK: usize = 16 * 1024;
+
+/// Queue `reply` for the client, enforcing the output buffer limits.
+/// Returns false when the connection should be closed.
+async fn send_reply<S>(
+    framed: &mut Framed<S, RespCodec>,
+    reply: RespFrame,
+    limits: OutputBufferLimits,
+) -> bool
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let queued = match reply {
+        // Written a chunk at a time, so its items never all sit in memory.
+        RespFrame::Sample(sample) => {
+            let mut chunk = BytesMut::new();
+            encode_array_header(sample.len(), &mut chunk);
+            for item in sample.items() {
+                encode_frame(item, &mut chunk);
+                if chunk.len() >= SAMPLE_CHUNK
+                    && !queue(framed, chunk.split().freeze(), limits).await
+                {
+                    return false;
+                }
+            }
+            queue(framed, chunk.freeze(), limits).await
+        }
+        reply => queue(framed, reply, limits).await,
+    };
+    if !queued {
+        return false;
+    }
+    // Batch replies for pipelined requests; flush once the next request
+    // isn't wholly buffered, since reading it may have to wait on the client.
+    if !framed.codec().has_frame(framed.read_buffer())
+        && let Err(err) = SinkExt::<RespFrame>::flush(framed).await
+    {
+        tracing::warn!(error = %err, "failed to send response");
+        return false;
+    }
+    true
+}
+
+/// Feed `item` to the write buffer, then check it against the hard limit.
+/// Returns false when the connection should be closed.
+async fn queue<S, I>(framed: &mut Framed<S, RespCodec>, item: I, limits: OutputBufferLimits) -> bool
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    RespCodec: Encoder<I, Error = std::io::Error>,
+{
+    // Ignore send errors (e.g., client closed) by breaking out.
+    if let Err(err) = framed.feed(item).await {
+        tracing::warn!(error = %err, "failed to send response");
+        return false;
+    }
+    let pending = framed.write_buffer().len();
+    if limits.hard > 0 && pending > limits.hard {
+        tracing::warn!(
+            pending,
+            limit = limits.hard,
+            "client output buffer hard limit exceeded, closing connection"
+        );
+        return false;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::persistence::aof::AofErrorPolicy;
+    use crate::slowlog::SlowLog;
+    use crate::store::encoding::EncodingThresholds;
+    use crate::store::evict::MaxMemory;
+    use crate::store::new_shared;
+
+    fn test_server() -> Arc<ServerState> {
+        Arc::new(ServerState::new(
+            new_shared(),
+            None,
+            AofErrorPolicy::default(),
+            MaxMemory::default(),
+            SlowLog::new(10_000, 128),
+            false,
+            EncodingThresholds::default(),
+        ))
+    }
+
+    fn command(args: &[&'static str]) -> RespFrame {
+        RespFrame::command(args.iter().map(|a| Bytes::from_static(a.as_bytes())))
+    }
+
+    #[tokio::test]
+    async fn set_get_over_in_memory_stream() {
+        let (client, server_end) = tokio::io::duplex(4096);
+        let limits = OutputBufferLimits {
+            soft: 64 * 1024,
+            hard: 0,
+        };
+        let conn = tokio::spawn(handle_connection(
+            server_end,
+            "duplex".into(),
+            test_server(),
+            limits,
+            RespCodec::default(),
+        ));
+        let mut client = Framed::new(client, RespCodec::default());
+
+        client.send(command(&["SET", "k", "v"])).await.unwrap();
+        let reply = client.next().await.unwrap().unwrap();
+        assert_eq!(reply, RespFrame::SimpleString("OK".into()));
+
+        client.send(command(&["GET", "k"])).await.unwrap();
+        let reply = client.next().await.unwrap().unwrap();
+        assert_eq!(reply, RespFrame::BulkString(Some(Bytes::from_static(b"v"))));
+
+        // Closing our end ends the connection cleanly.
+        drop(client);
+        conn.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn reply_is_flushed_while_the_next_request_is_partial() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (mut client, server_end) = tokio::io::duplex(4096);
+        let limits = OutputBufferLimits {
+            soft: 64 * 1024,
+            hard: 0,
+        };
+        tokio::spawn(handle_connection(
+            server_end,
+            "duplex".into(),
+            test_server(),
+            limits,
+            RespCodec::default(),
+        ));
+
+        // A whole PING, then the start of another that the client holds back
+        // until it has the first reply.
+        client
+            .write_all(b"*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPI")
+            .await
+            .unwrap();
+        let mut buf = [0u8; 7];
+        let read = tokio::time::timeout(Duration::from_secs(2), client.read_exact(&mut buf));
+        read.await.expect("reply was not flushed").unwrap();
+        assert_eq!(&buf, b"+PONG\r\n");
+    }
+}
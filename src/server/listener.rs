@@ -0,0 +1,127 @@
+use std::future::{self, Future};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::server::TlsStream;
+
+/// A socket clients connect to, so one accept loop can serve TCP, Unix and
+/// TLS listeners alike.
+pub trait Listener: Send + 'static {
+    /// What `accept_client` hands back, before any handshake.
+    type Socket: Send + 'static;
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Wait for the next client, returning its socket and the address to
+    /// report for it.
+    fn accept_client(&self) -> impl Future<Output = io::Result<(Self::Socket, String)>> + Send;
+
+    /// Turn an accepted socket into a stream to serve. This runs on the
+    /// connection's own task, so a slow TLS handshake doesn't hold up
+    /// accepting other clients.
+    fn establish(
+        &self,
+        socket: Self::Socket,
+    ) -> impl Future<Output = io::Result<Self::Stream>> + Send + 'static;
+}
+
+impl Listener for TcpListener {
+    type Socket = TcpStream;
+    type Stream = TcpStream;
+
+    async fn accept_client(&self) -> io::Result<(TcpStream, String)> {
+        let (stream, addr) = self.accept().await?;
+        Ok((stream, addr.to_string()))
+    }
+
+    fn establish(
+        &self,
+        socket: TcpStream,
+    ) -> impl Future<Output = io::Result<TcpStream>> + Send + 'static {
+        future::ready(Ok(socket))
+    }
+}
+
+impl Listener for UnixListener {
+    type Socket = UnixStream;
+    type Stream = UnixStream;
+
+    async fn accept_client(&self) -> io::Result<(UnixStream, String)> {
+        let (stream, _) = self.accept().await?;
+        // Unix clients are unnamed, so report the socket path the way Redis
+        // does.
+        let path = self.local_addr()?;
+        let path = path.as_pathname().unwrap_or(Path::new(""));
+        Ok((stream, format!("{}:0", path.display())))
+    }
+
+    fn establish(
+        &self,
+        socket: UnixStream,
+    ) -> impl Future<Output = io::Result<UnixStream>> + Send + 'static {
+        future::ready(Ok(socket))
+    }
+}
+
+/// A TCP listener whose clients must complete a TLS handshake.
+pub struct TlsListener {
+    tcp: TcpListener,
+    acceptor: TlsAcceptor,
+}
+
+impl TlsListener {
+    pub fn new(tcp: TcpListener, acceptor: TlsAcceptor) -> Self {
+        Self { tcp, acceptor }
+    }
+}
+
+impl Listener for TlsListener {
+    type Socket = TcpStream;
+    type Stream = TlsStream<TcpStream>;
+
+    async fn accept_client(&self) -> io::Result<(TcpStream, String)> {
+        self.tcp.accept_client().await
+    }
+
+    fn establish(
+        &self,
+        socket: TcpStream,
+    ) -> impl Future<Output = io::Result<TlsStream<TcpStream>>> + Send + 'static {
+        self.acceptor.accept(socket)
+    }
+}
+
+/// Build the TLS acceptor from a PEM certificate chain and private key, so
+/// that a bad pair is reported at startup rather than on the first client.
+pub fn tls_acceptor(cert: &Path, key: &Path) -> io::Result<TlsAcceptor> {
+    let invalid = |path: &Path, err: &dyn std::fmt::Display| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid TLS file '{}': {err}", path.display()),
+        )
+    };
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(cert, &e))?;
+    if certs.is_empty() {
+        return Err(invalid(cert, &"no certificates found"));
+    }
+    let key_der = PrivateKeyDer::from_pem_file(key).map_err(|e| invalid(key, &e))?;
+
+    // Name the provider explicitly: other crates in the tree enable a
+    // second rustls backend, which makes the process default ambiguous.
+    let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_single_cert(certs, key_der)
+        .map_err(|e| invalid(key, &e))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
@@ -1,17 +1,45 @@
 use std::io;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 
-use tokio::net::TcpListener;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, UnixListener};
 use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 
 use crate::config::Config;
-use crate::persistence::aof::{self, AofWriter, FsyncPolicy};
-use crate::server::connection::handle_connection;
-use crate::store::{SharedStore, new_shared};
+use crate::persistence::aof::{self, AofErrorPolicy, AofWriter, FsyncPolicy};
+use crate::protocol::RespCodec;
+use crate::replication;
+use crate::server::connection::{OutputBufferLimits, handle_connection};
+use crate::server::listener::{Listener, TlsListener, tls_acceptor};
+use crate::server::state::ServerState;
+use crate::slowlog::SlowLog;
+use crate::store::encoding::EncodingThresholds;
+use crate::store::evict::{EvictionPolicy, MaxMemory};
+use crate::store::expire::active_expire_budget;
+use crate::store::{ExpireHook, SharedStore, new_shared};
 
+pub mod client;
 pub mod connection;
+mod listener;
+pub mod state;
 
 pub async fn run(config: Config) -> io::Result<()> {
+    // Resolved before changing directory, so CONFIG REWRITE finds the file
+    // that was loaded.
+    let config_file = config
+        .config
+        .as_deref()
+        .map(std::path::absolute)
+        .transpose()?;
+    if let Some(dir) = &config.dir {
+        std::env::set_current_dir(dir).map_err(|e| {
+            io::Error::new(e.kind(), format!("can't chdir to '{}': {e}", dir.display()))
+        })?;
+        tracing::info!(dir = %dir.display(), "changed working directory");
+    }
+
     let store: SharedStore = new_shared();
 
     // AOF: replay on startup, then open writer.
@@ -24,6 +52,9 @@ pub async fn run(config: Config) -> io::Result<()> {
         match AofWriter::open(path, policy) {
             Ok(w) => {
                 tracing::info!(path = %path.display(), ?policy, "AOF writer opened");
+                if policy == FsyncPolicy::EverySec {
+                    spawn_aof_fsync(w.clone());
+                }
                 Some(w)
             }
             Err(e) => {
@@ -35,20 +66,115 @@ pub async fn run(config: Config) -> io::Result<()> {
         None
     };
 
-    let listener = TcpListener::bind(config.bind).await?;
-    let limiter = Arc::new(Semaphore::new(config.max_connections));
+    if let Ok(mut guard) = store.write() {
+        guard.set_expire_hook(ExpireHook::new(|key| {
+            metrics::counter!("rfs_expired_keys_total").increment(1);
+            tracing::trace!(key, "key expired");
+        }));
+    }
+
+    let policy = EvictionPolicy::from_str(&config.maxmemory_policy).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown maxmemory policy '{}'", config.maxmemory_policy),
+        )
+    })?;
+    let maxmemory = MaxMemory {
+        limit: config.maxmemory.into(),
+        policy,
+    };
+    let aof_error_policy =
+        AofErrorPolicy::from_str(&config.aof_on_write_error).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown aof-on-write-error '{}'", config.aof_on_write_error),
+            )
+        })?;
+    let slowlog = SlowLog::new(config.slowlog_log_slower_than, config.slowlog_max_len);
+    let encoding = EncodingThresholds {
+        hash_max_listpack_entries: config.hash_max_listpack_entries.into(),
+        hash_max_listpack_value: config.hash_max_listpack_value.into(),
+        set_max_intset_entries: config.set_max_intset_entries.into(),
+        set_max_listpack_entries: config.set_max_listpack_entries.into(),
+        set_max_listpack_value: config.set_max_listpack_value.into(),
+        zset_max_listpack_entries: config.zset_max_listpack_entries.into(),
+        zset_max_listpack_value: config.zset_max_listpack_value.into(),
+        list_max_listpack_size: config.list_max_listpack_size.into(),
+    };
+    let mut state = ServerState::new(
+        store.clone(),
+        aof,
+        aof_error_policy,
+        maxmemory,
+        slowlog,
+        config.read_only,
+        encoding,
+    );
+    state.config_file = config_file;
+    state.unixsocket = config.unixsocket.clone();
+    state.warn_reply_bytes = config.warn_reply_bytes;
+    *state.hz.get_mut() = config.hz;
+    *state.active_expire_effort.get_mut() = config.active_expire_effort;
+    let server = Arc::new(state);
+    tokio::spawn(replication::run_replica(server.clone()));
+
+    // clap ensures the cert and key come with any TLS address.
+    let tls_acceptor = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(tls_acceptor(cert, key)?),
+        _ => None,
+    };
 
-    tracing::info!(addr = %config.bind, "server listening");
+    let mut listeners = Vec::with_capacity(config.bind.len());
+    for addr in &config.bind {
+        listeners.push(TcpListener::bind(addr).await?);
+        tracing::info!(%addr, "server listening");
+    }
+    let unix_listener = match &config.unixsocket {
+        Some(path) => {
+            // A socket file left behind by an earlier run would make the
+            // bind fail.
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+            let listener = UnixListener::bind(path)?;
+            tracing::info!(path = %path.display(), "server listening");
+            Some(listener)
+        }
+        None => None,
+    };
+    let mut tls_listeners = Vec::with_capacity(config.tls_bind.len());
+    if let Some(acceptor) = tls_acceptor {
+        for addr in &config.tls_bind {
+            let tcp = TcpListener::bind(addr).await?;
+            tls_listeners.push(TlsListener::new(tcp, acceptor.clone()));
+            tracing::info!(%addr, "server listening for TLS");
+        }
+    }
+    let limiter = Arc::new(Semaphore::new(config.max_connections));
+    let limits = OutputBufferLimits {
+        soft: config.client_output_buffer_soft_limit,
+        hard: config.client_output_buffer_hard_limit,
+    };
+    let codec = RespCodec::new(config.proto_max_bulk_len);
 
     // Spawn periodic eviction task.
     {
-        let store = store.clone();
+        let server = server.clone();
         tokio::spawn(async move {
-            let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
             loop {
-                interval.tick().await;
-                if let Ok(mut guard) = store.write() {
-                    let evicted = guard.evict_expired();
+                // Re-read each time round, so CONFIG SET hz takes effect on
+                // the next cycle.
+                let hz = server.hz.load(Ordering::Relaxed).clamp(1, 500);
+                tokio::time::sleep(std::time::Duration::from_millis(1000 / hz as u64)).await;
+                if !server.active_expire.load(Ordering::Relaxed) {
+                    continue;
+                }
+                let budget =
+                    active_expire_budget(server.active_expire_effort.load(Ordering::Relaxed));
+                if let Ok(mut guard) = server.store.write() {
+                    let volatile = guard.volatile_count();
+                    let evicted = guard.evict_expired(budget);
+                    server.stats.record_expire_cycle(evicted, volatile);
                     if evicted > 0 {
                         tracing::debug!(evicted, "expired keys evicted");
                     }
@@ -57,23 +183,129 @@ pub async fn run(config: Config) -> io::Result<()> {
         });
     }
 
+    // Every listener shares the connection limit.
+    let mut accept_loops = JoinSet::new();
+    for listener in listeners {
+        accept_loops.spawn(accept_loop(
+            listener,
+            server.clone(),
+            limiter.clone(),
+            limits,
+            codec,
+        ));
+    }
+    if let Some(listener) = unix_listener {
+        accept_loops.spawn(accept_loop(
+            listener,
+            server.clone(),
+            limiter.clone(),
+            limits,
+            codec,
+        ));
+    }
+    for listener in tls_listeners {
+        accept_loops.spawn(accept_loop(
+            listener,
+            server.clone(),
+            limiter.clone(),
+            limits,
+            codec,
+        ));
+    }
+    while let Some(result) = accept_loops.join_next().await {
+        result.map_err(io::Error::other)??;
+    }
+    Ok(())
+}
+
+/// How long a new connection may take to become usable, which for TLS
+/// means completing the handshake.
+const HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Pause after an accept error such as running out of file descriptors,
+/// so the loop doesn't spin while the condition lasts.
+const ACCEPT_BACKOFF: std::time::Duration = std::time::Duration::from_millis(100);
+
+async fn accept_loop(
+    listener: impl Listener,
+    server: Arc<ServerState>,
+    limiter: Arc<Semaphore>,
+    limits: OutputBufferLimits,
+    codec: RespCodec,
+) -> io::Result<()> {
     loop {
-        let (socket, addr) = listener.accept().await?;
+        // A failed accept costs at most that client, never the listener.
+        // Connections that died while queued are simply skipped; anything
+        // else (EMFILE, ENFILE, ENOBUFS...) tends to persist for a while.
+        let (socket, addr) = match listener.accept_client().await {
+            Ok(accepted) => accepted,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset
+                ) =>
+            {
+                tracing::debug!(error = %err, "connection dropped before accept");
+                continue;
+            }
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to accept connection");
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+                continue;
+            }
+        };
         tracing::debug!(?addr, "accepted connection");
 
-        let permit = limiter
-            .clone()
-            .acquire_owned()
-            .await
-            .expect("semaphore closed");
-        let store = store.clone();
-        let aof = aof.clone();
+        let server = server.clone();
+        let limiter = limiter.clone();
+        let establishing = listener.establish(socket);
 
         tokio::spawn(async move {
-            let _permit = permit;
-            if let Err(err) = handle_connection(socket, store, aof).await {
+            // A client that never finishes its TLS handshake is dropped
+            // rather than kept forever.
+            let mut stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, establishing).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(err)) => {
+                    tracing::warn!(error = %err, %addr, "failed to establish connection");
+                    return;
+                }
+                Err(_) => {
+                    tracing::warn!(%addr, "connection not established in time");
+                    return;
+                }
+            };
+            // Only an established connection takes a slot. Over the limit
+            // the client is told so and dropped, as in Redis, rather than
+            // left waiting for one.
+            let Some(_permit) = limiter.try_acquire_owned().ok() else {
+                tracing::warn!(%addr, "rejecting connection: max number of clients reached");
+                let _ = stream
+                    .write_all(b"-ERR max number of clients reached\r\n")
+                    .await;
+                let _ = stream.shutdown().await;
+                return;
+            };
+            if let Err(err) = handle_connection(stream, addr, server, limits, codec).await {
                 tracing::warn!(error = %err, "connection handler exited with error");
             }
         });
     }
 }
+
+/// Background fsync for the `everysec` policy. The fsync itself blocks, so it
+/// runs on the blocking pool rather than a runtime worker.
+fn spawn_aof_fsync(writer: AofWriter) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let writer = writer.clone();
+            if tokio::task::spawn_blocking(move || writer.sync_if_due())
+                .await
+                .is_err()
+            {
+                tracing::error!("aof fsync task panicked");
+            }
+        }
+    });
+}
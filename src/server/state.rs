@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use crate::acl::Acl;
+use crate::monitor::Monitors;
+use crate::persistence::aof::{AofErrorPolicy, AofWriter};
+use crate::pubsub::Broker;
+use crate::replication::Replication;
+use crate::server::client::Clients;
+use crate::slowlog::SlowLog;
+use crate::store::SharedStore;
+use crate::store::encoding::EncodingThresholds;
+use crate::store::evict::MaxMemory;
+use crate::store::random;
+
+/// Hex digits in a run id, as in Redis.
+const RUN_ID_LEN: usize = 40;
+
+/// Counters reported in INFO's Stats section.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Keys evicted to stay under `maxmemory`.
+    pub evicted_keys: AtomicU64,
+    /// Share of keys with a TTL that the latest expiry cycle found past
+    /// their deadline, in hundredths of a percent.
+    pub expired_stale_bp: AtomicU64,
+}
+
+impl Stats {
+    /// Record an expiry cycle that removed `expired` of `volatile` keys.
+    /// The keys themselves are counted by the store, along with those
+    /// found expired on access.
+    pub fn record_expire_cycle(&self, expired: usize, volatile: usize) {
+        let stale = match volatile {
+            0 => 0,
+            n => expired as u64 * 10_000 / n as u64,
+        };
+        self.expired_stale_bp.store(stale, Ordering::Relaxed);
+    }
+}
+
+/// Server-wide state shared by every connection.
+pub struct ServerState {
+    pub store: SharedStore,
+    pub aof: Option<AofWriter>,
+    pub aof_error_policy: AofErrorPolicy,
+    pub pubsub: Broker,
+    pub monitors: Monitors,
+    /// Open connections, for CLIENT KILL.
+    pub clients: Clients,
+    pub acl: Acl,
+    pub maxmemory: MaxMemory,
+    pub slowlog: SlowLog,
+    pub replication: Replication,
+    /// Set by `--read-only`. Replicas are read-only regardless; see
+    /// [`ServerState::is_read_only`].
+    pub read_only: bool,
+    pub encoding: EncodingThresholds,
+    /// Whether the background task evicts expired keys. Cleared by
+    /// `DEBUG SET-ACTIVE-EXPIRE 0`, after which keys expire only when
+    /// accessed.
+    pub active_expire: AtomicBool,
+    /// Background task runs per second, from `--hz`.
+    pub hz: AtomicUsize,
+    /// How many expired keys each background run may remove; see
+    /// [`active_expire_budget`](crate::store::expire::active_expire_budget).
+    pub active_expire_effort: AtomicUsize,
+    /// Random id of this server process, reported by INFO.
+    pub run_id: String,
+    pub stats: Stats,
+    /// The `--config` file, as an absolute path, for CONFIG REWRITE.
+    pub config_file: Option<PathBuf>,
+    /// The `--unixsocket` path, removed on SHUTDOWN.
+    pub unixsocket: Option<PathBuf>,
+    /// Replies larger than this many bytes are logged (0 = never). Set from
+    /// `--warn-reply-bytes`.
+    pub warn_reply_bytes: usize,
+    next_client_id: AtomicU64,
+}
+
+impl ServerState {
+    pub fn new(
+        store: SharedStore,
+        aof: Option<AofWriter>,
+        aof_error_policy: AofErrorPolicy,
+        maxmemory: MaxMemory,
+        slowlog: SlowLog,
+        read_only: bool,
+        encoding: EncodingThresholds,
+    ) -> Self {
+        Self {
+            store,
+            aof,
+            aof_error_policy,
+            pubsub: Broker::new(),
+            monitors: Monitors::new(),
+            clients: Clients::new(),
+            acl: Acl::new(),
+            maxmemory,
+            slowlog,
+            replication: Replication::new(),
+            read_only,
+            encoding,
+            active_expire: AtomicBool::new(true),
+            hz: AtomicUsize::new(10),
+            active_expire_effort: AtomicUsize::new(1),
+            run_id: random::hex_id(RUN_ID_LEN),
+            stats: Stats::default(),
+            config_file: None,
+            unixsocket: None,
+            warn_reply_bytes: 0,
+            next_client_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Whether clients are refused write commands: when configured so, or
+    /// while replicating from a master.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only || self.replication.master().is_some()
+    }
+
+    pub fn next_client_id(&self) -> u64 {
+        self.next_client_id.fetch_add(1, Ordering::Relaxed)
+    }
+}
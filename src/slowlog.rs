@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+/// At most this many arguments are kept per entry; the last slot notes how
+/// many were dropped.
+const SLOWLOG_ENTRY_MAX_ARGC: usize = 32;
+/// Arguments longer than this are truncated.
+const SLOWLOG_ENTRY_MAX_STRING: usize = 128;
+
+/// One recorded slow command.
+#[derive(Debug, Clone)]
+pub struct SlowLogEntry {
+    pub id: u64,
+    /// Unix time in seconds at which the command finished.
+    pub timestamp: u64,
+    pub duration_micros: u64,
+    pub args: Vec<Bytes>,
+    pub client_addr: String,
+}
+
+/// Bounded log of commands slower than a threshold, newest first.
+#[derive(Debug)]
+pub struct SlowLog {
+    /// Commands at or above this many microseconds are logged; negative
+    /// disables the log.
+    threshold_micros: i64,
+    max_len: usize,
+    inner: Mutex<SlowLogInner>,
+}
+
+#[derive(Debug, Default)]
+struct SlowLogInner {
+    entries: VecDeque<SlowLogEntry>,
+    next_id: u64,
+}
+
+impl SlowLog {
+    pub fn new(threshold_micros: i64, max_len: usize) -> Self {
+        Self {
+            threshold_micros,
+            max_len,
+            inner: Mutex::new(SlowLogInner::default()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.threshold_micros >= 0 && self.max_len > 0
+    }
+
+    /// Record `args` if `elapsed` reaches the threshold.
+    pub fn record(&self, args: &[Bytes], elapsed: Duration, client_addr: &str) {
+        let micros = elapsed.as_micros() as u64;
+        if !self.enabled() || micros < self.threshold_micros as u64 {
+            return;
+        }
+
+        let mut kept: Vec<Bytes> = args
+            .iter()
+            .take(if args.len() > SLOWLOG_ENTRY_MAX_ARGC {
+                SLOWLOG_ENTRY_MAX_ARGC - 1
+            } else {
+                SLOWLOG_ENTRY_MAX_ARGC
+            })
+            .map(truncate_arg)
+            .collect();
+        if args.len() > SLOWLOG_ENTRY_MAX_ARGC {
+            let more = args.len() - kept.len();
+            kept.push(Bytes::from(format!("... ({more} more arguments)")));
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.entries.push_front(SlowLogEntry {
+            id,
+            timestamp,
+            duration_micros: micros,
+            args: kept,
+            client_addr: client_addr.to_string(),
+        });
+        inner.entries.truncate(self.max_len);
+    }
+
+    /// Up to `count` newest entries, or all of them when `count` is `None`.
+    pub fn get(&self, count: Option<usize>) -> Vec<SlowLogEntry> {
+        let inner = self.inner.lock().unwrap();
+        let n = count.unwrap_or(inner.entries.len());
+        inner.entries.iter().take(n).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    pub fn reset(&self) {
+        self.inner.lock().unwrap().entries.clear();
+    }
+}
+
+fn truncate_arg(arg: &Bytes) -> Bytes {
+    if arg.len() <= SLOWLOG_ENTRY_MAX_STRING {
+        return arg.clone();
+    }
+    let more = arg.len() - SLOWLOG_ENTRY_MAX_STRING;
+    let mut out = arg[..SLOWLOG_ENTRY_MAX_STRING].to_vec();
+    out.extend_from_slice(format!("... ({more} more bytes)").as_bytes());
+    Bytes::from(out)
+}
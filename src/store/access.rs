@@ -0,0 +1,91 @@
+//! How a command reaches the database.
+//!
+//! Command handlers lock the shared store for as long as one command runs.
+//! EXEC instead takes the write lock once for its whole batch and lends it
+//! to each queued command through [`StoreAccess::Held`], so no other
+//! connection's command lands between two of a transaction's.
+
+use std::cell::{Ref, RefCell, RefMut};
+use std::ops::{Deref, DerefMut};
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+use super::{Database, SharedStore};
+
+pub enum StoreAccess<'a> {
+    /// Lock the shared store for each access.
+    Shared(&'a SharedStore),
+    /// A write lock the caller already holds.
+    Held(RefCell<&'a mut Database>),
+}
+
+/// A writer panicked while holding the store lock.
+#[derive(Debug)]
+pub struct Poisoned;
+
+impl<'a> StoreAccess<'a> {
+    pub fn held(db: &'a mut Database) -> Self {
+        Self::Held(RefCell::new(db))
+    }
+
+    /// Whether the store is already locked for the caller.
+    pub fn is_held(&self) -> bool {
+        matches!(self, Self::Held(_))
+    }
+
+    pub fn read(&self) -> Result<ReadGuard<'_>, Poisoned> {
+        match self {
+            Self::Shared(store) => store.read().map(ReadGuard::Lock).map_err(|_| Poisoned),
+            Self::Held(db) => Ok(ReadGuard::Held(Ref::map(db.borrow(), |db| &**db))),
+        }
+    }
+
+    pub fn write(&self) -> Result<WriteGuard<'_>, Poisoned> {
+        match self {
+            Self::Shared(store) => store.write().map(WriteGuard::Lock).map_err(|_| Poisoned),
+            Self::Held(db) => Ok(WriteGuard::Held(RefMut::map(db.borrow_mut(), |db| {
+                &mut **db
+            }))),
+        }
+    }
+}
+
+pub enum ReadGuard<'a> {
+    Lock(RwLockReadGuard<'a, Database>),
+    Held(Ref<'a, Database>),
+}
+
+impl Deref for ReadGuard<'_> {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        match self {
+            Self::Lock(guard) => guard,
+            Self::Held(db) => db,
+        }
+    }
+}
+
+pub enum WriteGuard<'a> {
+    Lock(RwLockWriteGuard<'a, Database>),
+    Held(RefMut<'a, Database>),
+}
+
+impl Deref for WriteGuard<'_> {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        match self {
+            Self::Lock(guard) => guard,
+            Self::Held(db) => db,
+        }
+    }
+}
+
+impl DerefMut for WriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Database {
+        match self {
+            Self::Lock(guard) => guard,
+            Self::Held(db) => db,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use bytes::Bytes;
+
+use super::value::Value;
+use super::{Database, TypeError};
+
+impl Database {
+    /// Borrow the raw bytes of a live string key.
+    fn string_bytes(&mut self, key: &str) -> Option<&Bytes> {
+        if self.expire_if_needed(key) {
+            return None;
+        }
+        match self.lookup(key) {
+            Some(Value::String(b)) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// Set the bit at `offset` (bit 0 is the MSB of byte 0) of the string at
+    /// `key`, growing it with zero bytes as needed. Returns the previous bit.
+    /// Any existing TTL is kept.
+    pub fn setbit(&mut self, key: &str, offset: usize, on: bool) -> Result<u8, TypeError> {
+        let mut buf = self.take_string(key)?;
+        let byte = offset / 8;
+        let mask = 0x80u8 >> (offset % 8);
+        if buf.len() <= byte {
+            buf.resize(byte + 1, 0);
+        }
+        let old = u8::from(buf[byte] & mask != 0);
+        if on {
+            buf[byte] |= mask;
+        } else {
+            buf[byte] &= !mask;
+        }
+        self.insert_value(key.to_string(), Value::String(buf.freeze()));
+        Ok(old)
+    }
+
+    /// Read the bit at `offset`; bits past the end of the string are 0.
+    pub fn getbit(&mut self, key: &str, offset: usize) -> u8 {
+        let Some(bytes) = self.string_bytes(key) else {
+            return 0;
+        };
+        match bytes.get(offset / 8) {
+            Some(b) => u8::from(b & (0x80 >> (offset % 8)) != 0),
+            None => 0,
+        }
+    }
+
+    /// Count set bits, optionally restricted to an inclusive `(start, end)`
+    /// range of bytes, or of bits when `bit_unit` is set. Negative indices
+    /// count from the end.
+    pub fn bitcount(&mut self, key: &str, range: Option<(i64, i64)>, bit_unit: bool) -> usize {
+        let Some(bytes) = self.string_bytes(key) else {
+            return 0;
+        };
+        let Some((start, end)) = range else {
+            return bytes.iter().map(|b| b.count_ones() as usize).sum();
+        };
+
+        let len = if bit_unit {
+            bytes.len() * 8
+        } else {
+            bytes.len()
+        };
+        let Some((s, e)) = super::normalize_range(len, start, end) else {
+            return 0;
+        };
+        if bit_unit {
+            (s..=e)
+                .filter(|&i| bytes[i / 8] & (0x80 >> (i % 8)) != 0)
+                .count()
+        } else {
+            bytes[s..=e].iter().map(|b| b.count_ones() as usize).sum()
+        }
+    }
+}
+
+/// The operation BITOP applies across its source strings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+impl Database {
+    /// Position of the first bit equal to `bit`, or -1. `range` is an
+    /// inclusive `(start, end)` of bytes, or of bits when `bit_unit` is set,
+    /// with `end` defaulting to the last one. Without an explicit end the
+    /// string counts as padded with zeros, so a clear bit is always found.
+    pub fn bitpos(
+        &mut self,
+        key: &str,
+        bit: bool,
+        range: Option<(i64, Option<i64>)>,
+        bit_unit: bool,
+    ) -> i64 {
+        let Some(bytes) = self.string_bytes(key) else {
+            return if bit { -1 } else { 0 };
+        };
+        let (start, end) = range.unwrap_or((0, None));
+        let len = if bit_unit {
+            bytes.len() * 8
+        } else {
+            bytes.len()
+        };
+        let Some((s, e)) = super::normalize_range(len, start, end.unwrap_or(-1)) else {
+            return -1;
+        };
+        let (first, last) = if bit_unit { (s, e) } else { (s * 8, e * 8 + 7) };
+
+        // Bytes that can't contain the bit are skipped whole.
+        let skip = if bit { 0x00 } else { 0xff };
+        let mut i = first;
+        while i <= last {
+            let byte = bytes[i / 8];
+            if i % 8 == 0 && last - i >= 7 && byte == skip {
+                i += 8;
+                continue;
+            }
+            if (byte & (0x80 >> (i % 8)) != 0) == bit {
+                return i as i64;
+            }
+            i += 1;
+        }
+        if !bit && end.is_none() {
+            return (last + 1) as i64;
+        }
+        -1
+    }
+
+    /// Store `op` applied to the strings at `keys` in `dest`, replacing it
+    /// and its TTL. Shorter and missing sources are zero-extended to the
+    /// longest one. An empty result deletes `dest` instead. Returns the
+    /// result.
+    pub fn bitop(&mut self, op: BitOp, dest: &str, keys: &[String]) -> Bytes {
+        let sources: Vec<Bytes> = keys
+            .iter()
+            .map(|key| self.string_bytes(key).cloned().unwrap_or_default())
+            .collect();
+        let len = sources.iter().map(Bytes::len).max().unwrap_or(0);
+        let byte_at = |src: &Bytes, i: usize| src.get(i).copied().unwrap_or(0);
+
+        let result: Vec<u8> = (0..len)
+            .map(|i| {
+                let mut bytes = sources.iter().map(|src| byte_at(src, i));
+                let first = bytes.next().unwrap_or(0);
+                match op {
+                    BitOp::And => bytes.fold(first, |acc, b| acc & b),
+                    BitOp::Or => bytes.fold(first, |acc, b| acc | b),
+                    BitOp::Xor => bytes.fold(first, |acc, b| acc ^ b),
+                    BitOp::Not => !first,
+                }
+            })
+            .collect();
+
+        let result = Bytes::from(result);
+        if result.is_empty() {
+            self.del(&[dest.to_string()]);
+        } else {
+            self.set(dest.to_string(), Value::String(result.clone()));
+        }
+        result
+    }
+}
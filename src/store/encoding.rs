@@ -0,0 +1,190 @@
+//! The encoding names Redis would report for a value.
+//!
+//! Values here always use the same in-memory representation, but Redis
+//! switches small collections to a compact listpack (or, for sets of
+//! integers, an intset) and converts them once they outgrow configurable
+//! limits. Clients and test suites assert on those
+//! names, so OBJECT ENCODING derives them from the value's size against the
+//! same limits.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+
+use bytes::Bytes;
+
+use super::number::parse_i64;
+use super::value::Value;
+
+/// Size limits for the compact encodings, adjustable at runtime through
+/// CONFIG SET.
+#[derive(Debug)]
+pub struct EncodingThresholds {
+    pub hash_max_listpack_entries: AtomicUsize,
+    pub hash_max_listpack_value: AtomicUsize,
+    /// Sets of at most this many members, all integers, report `intset`.
+    pub set_max_intset_entries: AtomicUsize,
+    pub set_max_listpack_entries: AtomicUsize,
+    pub set_max_listpack_value: AtomicUsize,
+    pub zset_max_listpack_entries: AtomicUsize,
+    pub zset_max_listpack_value: AtomicUsize,
+    /// Redis' `list-max-listpack-size`: a positive entry count, or -1
+    /// through -5 for a byte budget of 4 KB through 64 KB.
+    pub list_max_listpack_size: AtomicI64,
+}
+
+/// Redis' limits, which are also the config defaults.
+impl Default for EncodingThresholds {
+    fn default() -> Self {
+        Self {
+            hash_max_listpack_entries: 128.into(),
+            hash_max_listpack_value: 64.into(),
+            set_max_intset_entries: 512.into(),
+            set_max_listpack_entries: 128.into(),
+            set_max_listpack_value: 64.into(),
+            zset_max_listpack_entries: 128.into(),
+            zset_max_listpack_value: 64.into(),
+            list_max_listpack_size: (-2).into(),
+        }
+    }
+}
+
+impl EncodingThresholds {
+    /// Encoding name reported for `value`.
+    pub fn encoding(&self, value: &Value) -> &'static str {
+        match value {
+            Value::String(s) => string_encoding(s),
+            Value::List(items) => {
+                let fill = self.list_max_listpack_size.load(Ordering::Relaxed);
+                if list_fits_listpack(items, fill) {
+                    "listpack"
+                } else {
+                    "quicklist"
+                }
+            }
+            Value::Hash(hm) => {
+                let items = hm.iter().flat_map(|(f, v)| [f, v]);
+                let (entries, max) = (
+                    &self.hash_max_listpack_entries,
+                    &self.hash_max_listpack_value,
+                );
+                if fits_listpack(hm.len(), items, entries, max) {
+                    "listpack"
+                } else {
+                    "hashtable"
+                }
+            }
+            Value::Set(hs) => {
+                let (entries, max) = (&self.set_max_listpack_entries, &self.set_max_listpack_value);
+                if fits_intset(hs.len(), hs.iter(), &self.set_max_intset_entries) {
+                    "intset"
+                } else if fits_listpack(hs.len(), hs.iter(), entries, max) {
+                    "listpack"
+                } else {
+                    "hashtable"
+                }
+            }
+            Value::ZSet(members) => {
+                let items = members.iter().map(|(m, _)| m);
+                let (entries, max) = (
+                    &self.zset_max_listpack_entries,
+                    &self.zset_max_listpack_value,
+                );
+                if fits_listpack(members.len(), items, entries, max) {
+                    "listpack"
+                } else {
+                    "skiplist"
+                }
+            }
+        }
+    }
+}
+
+/// A listpack's header and terminator.
+pub(super) const LISTPACK_OVERHEAD: usize = 7;
+
+/// Framing around each listpack entry: its encoding byte and back-length.
+pub(super) const LISTPACK_ENTRY_OVERHEAD: usize = 2;
+
+/// Byte budgets selected by `list-max-listpack-size` -1 through -5.
+const LIST_NODE_BYTES: [usize; 5] = [4096, 8192, 16384, 32768, 65536];
+
+/// Cap Redis puts on a listpack sized by entry count instead of bytes.
+const LIST_SAFETY_BYTES: usize = 8192;
+
+/// Whether `items` fit in the single node Redis keeps a small list in,
+/// given `fill` (see [`EncodingThresholds::list_max_listpack_size`]).
+fn list_fits_listpack(items: &VecDeque<Bytes>, fill: i64) -> bool {
+    let (max_entries, max_bytes) = match usize::try_from(fill) {
+        Ok(count) => (count.max(1), LIST_SAFETY_BYTES),
+        Err(_) => {
+            let level = (fill.unsigned_abs() as usize - 1).min(LIST_NODE_BYTES.len() - 1);
+            (usize::MAX, LIST_NODE_BYTES[level])
+        }
+    };
+    // Every entry costs at least its framing, which bounds the walk below.
+    if items.len() > max_entries
+        || LISTPACK_OVERHEAD + items.len() * LISTPACK_ENTRY_OVERHEAD > max_bytes
+    {
+        return false;
+    }
+    let bytes: usize = items
+        .iter()
+        .map(|b| b.len() + LISTPACK_ENTRY_OVERHEAD)
+        .sum();
+    LISTPACK_OVERHEAD + bytes <= max_bytes
+}
+
+/// Longest string Redis stores inline with its object header.
+const EMBSTR_MAX_LEN: usize = 44;
+
+/// `int` for a canonical decimal i64 (one that prints back to the same
+/// bytes, so no `+` or leading zeros), otherwise `embstr` or `raw` by length.
+fn string_encoding(s: &[u8]) -> &'static str {
+    if parse_i64(s).is_some() {
+        "int"
+    } else if s.len() <= EMBSTR_MAX_LEN {
+        "embstr"
+    } else {
+        "raw"
+    }
+}
+
+/// Whether a set of `len` members, `items`, is small enough for an intset
+/// and holds only integers Redis would store as one.
+fn fits_intset<'a>(
+    len: usize,
+    mut items: impl Iterator<Item = &'a Bytes>,
+    max_entries: &AtomicUsize,
+) -> bool {
+    len <= max_entries.load(Ordering::Relaxed) && items.all(|b| parse_i64(b).is_some())
+}
+
+/// Bytes per member of an intset holding `items`: the narrowest of 2, 4
+/// or 8 that fits every one of them.
+pub(super) fn intset_width<'a>(items: impl Iterator<Item = &'a Bytes>) -> usize {
+    items
+        .filter_map(|b| parse_i64(b))
+        .map(|n| {
+            if i16::try_from(n).is_ok() {
+                2
+            } else if i32::try_from(n).is_ok() {
+                4
+            } else {
+                8
+            }
+        })
+        .max()
+        .unwrap_or(2)
+}
+
+/// Whether `len` elements, `items` being their strings, stay within a
+/// listpack's entry count and per-element byte limits.
+fn fits_listpack<'a>(
+    len: usize,
+    mut items: impl Iterator<Item = &'a Bytes>,
+    max_entries: &AtomicUsize,
+    max_value: &AtomicUsize,
+) -> bool {
+    let max_value = max_value.load(Ordering::Relaxed);
+    len <= max_entries.load(Ordering::Relaxed) && items.all(|b| b.len() <= max_value)
+}
@@ -0,0 +1,293 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::Database;
+use super::encoding::{
+    EncodingThresholds, LISTPACK_ENTRY_OVERHEAD, LISTPACK_OVERHEAD, intset_width,
+};
+use super::value::Value;
+
+/// Which keys may be evicted when `maxmemory` is exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// Refuse commands that would grow memory instead of evicting.
+    #[default]
+    NoEviction,
+    /// Evict the least frequently used key among all keys.
+    AllKeysLfu,
+    /// Evict the least frequently used key among keys with a TTL.
+    VolatileLfu,
+}
+
+impl EvictionPolicy {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "noeviction" => Some(Self::NoEviction),
+            "allkeys-lfu" => Some(Self::AllKeysLfu),
+            "volatile-lfu" => Some(Self::VolatileLfu),
+            _ => None,
+        }
+    }
+
+    pub fn is_lfu(self) -> bool {
+        matches!(self, Self::AllKeysLfu | Self::VolatileLfu)
+    }
+}
+
+/// Memory ceiling and what to do when it is reached. A `limit` of 0 means
+/// unlimited; CONFIG SET can change it at runtime.
+#[derive(Debug, Default)]
+pub struct MaxMemory {
+    pub limit: AtomicUsize,
+    pub policy: EvictionPolicy,
+}
+
+/// Keys sampled per eviction; the least frequently used of them goes.
+const EVICTION_SAMPLES: usize = 5;
+
+/// Elements a collection is sized from for [`Database::used_memory`], so
+/// keeping the total current costs the same however big the collection.
+const ACCOUNTING_SAMPLES: usize = 64;
+
+/// Rough per-key bookkeeping cost on top of the key and value bytes.
+const KEY_OVERHEAD: usize = 48;
+
+/// Rough cost of a collection's own header and table, on top of its
+/// elements.
+const CONTAINER_OVERHEAD: usize = 48;
+
+/// An intset's encoding and length fields.
+const INTSET_HEADER: usize = 8;
+
+/// A sorted set score as a listpack entry.
+const LISTPACK_SCORE: usize = 8 + LISTPACK_ENTRY_OVERHEAD;
+
+impl Value {
+    /// Approximate heap bytes held by this value. A collection with more
+    /// than `samples` elements is sized from its first `samples`, scaled up
+    /// to its length; 0 walks every element.
+    ///
+    /// Hashes, sets and sorted sets that Redis would keep as a listpack
+    /// (see [`EncodingThresholds`]) are sized as one: elements packed back
+    /// to back with two bytes of framing each, rather than a table with an
+    /// allocation per element. They are not stored any differently here;
+    /// following Redis' layout keeps MEMORY USAGE comparable with it and
+    /// makes growing past a threshold cost what it would there. Sets of
+    /// integers small enough for an intset are sized as a packed array of
+    /// the narrowest integer width that holds them all.
+    pub fn estimated_size_sampled(&self, samples: usize, encoding: &EncodingThresholds) -> usize {
+        let name = encoding.encoding(self);
+        let listpack = name == "listpack";
+        match self {
+            Value::String(b) => b.len(),
+            Value::List(items) => {
+                CONTAINER_OVERHEAD + extrapolate(items.iter(), samples, |b| b.len() + 16)
+            }
+            Value::Set(items) if name == "intset" => {
+                INTSET_HEADER + items.len() * intset_width(items.iter())
+            }
+            Value::Set(items) if listpack => {
+                LISTPACK_OVERHEAD
+                    + extrapolate(items.iter(), samples, |b| b.len() + LISTPACK_ENTRY_OVERHEAD)
+            }
+            Value::Set(items) => {
+                CONTAINER_OVERHEAD + extrapolate(items.iter(), samples, |b| b.len() + 24)
+            }
+            Value::Hash(fields) if listpack => {
+                LISTPACK_OVERHEAD
+                    + extrapolate(fields.stored(), samples, |(f, v)| {
+                        f.len() + v.len() + 2 * LISTPACK_ENTRY_OVERHEAD
+                    })
+            }
+            Value::Hash(fields) => {
+                CONTAINER_OVERHEAD
+                    + extrapolate(fields.stored(), samples, |(f, v)| f.len() + v.len() + 32)
+            }
+            Value::ZSet(members) if listpack => {
+                LISTPACK_OVERHEAD
+                    + extrapolate(members.iter(), samples, |(m, _)| {
+                        m.len() + LISTPACK_ENTRY_OVERHEAD + LISTPACK_SCORE
+                    })
+            }
+            Value::ZSet(members) => {
+                CONTAINER_OVERHEAD + extrapolate(members.iter(), samples, |(m, _)| m.len() + 24)
+            }
+        }
+    }
+}
+
+/// Total of `size` over `items`, estimated from the first `samples` when
+/// there are more than that.
+fn extrapolate<I: ExactSizeIterator>(
+    items: I,
+    samples: usize,
+    size: impl Fn(I::Item) -> usize,
+) -> usize {
+    let len = items.len();
+    if samples == 0 || len <= samples {
+        return items.map(size).sum();
+    }
+    let sampled: usize = items.take(samples).map(size).sum();
+    sampled * len / samples
+}
+
+impl Database {
+    /// Approximate bytes used by all keys and values. Kept as a running
+    /// total, so this only sizes the keys written since it was last asked.
+    pub fn used_memory(&mut self, encoding: &EncodingThresholds) -> usize {
+        for key in std::mem::take(&mut self.unsized_keys) {
+            if let Some(entry) = self.data.get_mut(&key) {
+                let size = key.len()
+                    + KEY_OVERHEAD
+                    + entry
+                        .value
+                        .estimated_size_sampled(ACCOUNTING_SAMPLES, encoding);
+                self.used_bytes = self.used_bytes - entry.size + size;
+                entry.size = size;
+            }
+        }
+        self.used_bytes
+    }
+
+    /// Approximate bytes used by `key` and its value, or `None` if it
+    /// doesn't exist. See [`Value::estimated_size_sampled`] for `samples`.
+    pub fn memory_usage(
+        &mut self,
+        key: &str,
+        samples: usize,
+        encoding: &EncodingThresholds,
+    ) -> Option<usize> {
+        let value = self.inspect(key)?;
+        Some(key.len() + KEY_OVERHEAD + value.estimated_size_sampled(samples, encoding))
+    }
+
+    /// Evict keys according to `max.policy` until usage fits `max.limit`.
+    /// Returns the evicted keys and whether usage now fits.
+    pub fn evict_to_fit(
+        &mut self,
+        max: &MaxMemory,
+        encoding: &EncodingThresholds,
+    ) -> (Vec<String>, bool) {
+        let mut evicted = Vec::new();
+        let limit = max.limit.load(Ordering::Relaxed);
+        if limit == 0 {
+            return (evicted, true);
+        }
+
+        while self.used_memory(encoding) > limit {
+            let Some(key) = self.eviction_candidate(max.policy) else {
+                break;
+            };
+            self.remove_entry(&key);
+            self.expiry.remove(&key);
+            evicted.push(key);
+        }
+        (evicted, self.used_bytes <= limit)
+    }
+
+    /// The least frequently used of a few randomly sampled keys.
+    fn eviction_candidate(&mut self, policy: EvictionPolicy) -> Option<String> {
+        let mut best: Option<(String, u8)> = None;
+        for _ in 0..EVICTION_SAMPLES {
+            let key = match policy {
+                EvictionPolicy::NoEviction => return None,
+                EvictionPolicy::AllKeysLfu => self.random_key(),
+                EvictionPolicy::VolatileLfu => self.expiry.random_key().cloned(),
+            };
+            let Some(key) = key else {
+                break;
+            };
+            let Some(freq) = self.data.get(&key).map(|e| e.lfu.freq()) else {
+                continue;
+            };
+            if best.as_ref().is_none_or(|(_, least)| freq < *least) {
+                best = Some((key, freq));
+            }
+        }
+        best.map(|(key, _)| key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+
+    use super::{ACCOUNTING_SAMPLES, EvictionPolicy, KEY_OVERHEAD, MaxMemory};
+    use crate::store::Database;
+    use crate::store::encoding::EncodingThresholds;
+    use crate::store::value::Value;
+
+    /// What `used_memory` should come to, sized from scratch.
+    fn recount(db: &Database, encoding: &EncodingThresholds) -> usize {
+        db.data
+            .iter()
+            .map(|(k, e)| {
+                k.len()
+                    + KEY_OVERHEAD
+                    + e.value.estimated_size_sampled(ACCOUNTING_SAMPLES, encoding)
+            })
+            .sum()
+    }
+
+    fn bytes(n: usize) -> Vec<Bytes> {
+        (0..n).map(|i| Bytes::from(format!("member-{i}"))).collect()
+    }
+
+    #[test]
+    fn used_memory_keeps_up_with_writes() {
+        let encoding = EncodingThresholds::default();
+        let mut db = Database::new();
+        db.set("s".into(), Value::String(Bytes::from_static(b"hello")));
+        db.sadd("set".into(), bytes(300)).unwrap();
+        db.rpush("list".into(), bytes(10)).unwrap();
+        assert_eq!(db.used_memory(&encoding), recount(&db, &encoding));
+
+        // Growing in place, shrinking, and removing keys all count.
+        db.append("s", &[b'x'; 1000]).unwrap();
+        db.sadd("set".into(), bytes(600)).unwrap();
+        db.lpop("list", 4).unwrap();
+        db.del(&["missing".into(), "set".into()]);
+        let past = Instant::now() - Duration::from_millis(1);
+        db.set_with_deadline("gone".into(), Value::String(Bytes::new()), past);
+        db.evict_expired(usize::MAX);
+        assert_eq!(db.used_memory(&encoding), recount(&db, &encoding));
+
+        db.flush();
+        assert_eq!(db.used_memory(&encoding), 0);
+    }
+
+    #[test]
+    fn eviction_draws_only_live_keys() {
+        let encoding = EncodingThresholds::default();
+        let mut db = Database::new();
+        let value = || Value::String(Bytes::from(vec![0u8; 100]));
+        // Churn leaves plenty of removed keys behind for the draws to skip.
+        for i in 0..1000 {
+            db.set(format!("old{i}"), value());
+        }
+        db.del(&(0..1000).map(|i| format!("old{i}")).collect::<Vec<_>>());
+        let deadline = Instant::now() + Duration::from_secs(60);
+        for i in 0..100 {
+            db.set(format!("k{i}"), value());
+            db.set_with_deadline(format!("v{i}"), value(), deadline);
+        }
+
+        let volatile = MaxMemory {
+            limit: (db.used_memory(&encoding) * 3 / 4).into(),
+            policy: EvictionPolicy::VolatileLfu,
+        };
+        let (evicted, fits) = db.evict_to_fit(&volatile, &encoding);
+        assert!(fits);
+        assert!(evicted.iter().all(|k| k.starts_with('v')), "{evicted:?}");
+
+        let all = MaxMemory {
+            limit: 1.into(),
+            policy: EvictionPolicy::AllKeysLfu,
+        };
+        let (more, fits) = db.evict_to_fit(&all, &encoding);
+        assert!(fits);
+        assert_eq!(evicted.len() + more.len(), 200);
+        assert_eq!(db.dbsize(), 0);
+    }
+}
@@ -1,6 +1,54 @@
 use std::cmp::Reverse;
 use std::collections::{BinaryHeap, HashMap};
-use std::time::Instant;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::random;
+
+/// Condition under which a new expiry deadline is applied, built from
+/// EXPIRE's NX/XX/GT/LT flags. The default applies unconditionally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ExpireCondition {
+    /// Only if the key has no expiry.
+    pub nx: bool,
+    /// Only if the key already has an expiry.
+    pub xx: bool,
+    /// Only if the new deadline is later than the current one.
+    pub gt: bool,
+    /// Only if the new deadline is sooner than the current one.
+    pub lt: bool,
+}
+
+impl ExpireCondition {
+    /// A key without an expiry counts as never expiring: GT never applies
+    /// to it and LT always does.
+    pub fn allows(self, current: Option<Instant>, new: Instant) -> bool {
+        match current {
+            None => !self.xx && !self.gt,
+            Some(cur) => !self.nx && (!self.gt || new > cur) && (!self.lt || new < cur),
+        }
+    }
+}
+
+/// Keys an expiry cycle looks at per pass at the lowest effort, as in
+/// Redis.
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+
+/// Passes an expiry cycle makes before yielding to clients. Redis checks
+/// its time limit this often; here it caps the cycle outright.
+const ACTIVE_EXPIRE_LOOPS: usize = 16;
+
+/// How many expired keys one background cycle may remove at
+/// `active-expire-effort` `effort` (1 to 10). As in Redis, each step of
+/// effort adds a quarter to the keys looked at per pass.
+pub fn active_expire_budget(effort: usize) -> usize {
+    let effort = effort.clamp(1, 10) - 1;
+    let per_loop = ACTIVE_EXPIRE_KEYS_PER_LOOP + ACTIVE_EXPIRE_KEYS_PER_LOOP / 4 * effort;
+    per_loop * ACTIVE_EXPIRE_LOOPS
+}
+
+/// Heap entries [`Expiry::random_key`] looks at before giving up.
+const RANDOM_KEY_DRAWS: usize = 16;
 
 /// Tracks key expiration deadlines using a min-heap + map.
 #[derive(Debug, Default)]
@@ -43,13 +91,35 @@ impl Expiry {
         self.deadlines.get(key).copied()
     }
 
-    /// Drain all expired keys, returning them for removal from the store.
-    pub fn drain_expired(&mut self) -> Vec<String> {
+    /// How many keys currently have a deadline.
+    pub fn volatile_count(&self) -> usize {
+        self.deadlines.len()
+    }
+
+    /// Keys that currently have a deadline.
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.deadlines.keys()
+    }
+
+    /// A key with a deadline, drawn at random from the heap, or `None` if a
+    /// few draws turn up only entries superseded since they were pushed.
+    pub fn random_key(&self) -> Option<&String> {
+        let heap = self.heap.as_slice();
+        (0..RANDOM_KEY_DRAWS)
+            .take_while(|_| !heap.is_empty())
+            .map(|_| &heap[random::below(heap.len())].0)
+            .find(|(deadline, key)| self.deadlines.get(key) == Some(deadline))
+            .map(|(_, key)| key)
+    }
+
+    /// Drain up to `limit` expired keys, soonest deadline first, returning
+    /// them for removal from the store.
+    pub fn drain_expired(&mut self, limit: usize) -> Vec<String> {
         let now = Instant::now();
         let mut expired = Vec::new();
 
         while let Some(Reverse((deadline, _key))) = self.heap.peek() {
-            if *deadline > now {
+            if *deadline > now || expired.len() >= limit {
                 break;
             }
             let Reverse((deadline, key)) = self.heap.pop().unwrap();
@@ -69,3 +139,33 @@ impl Expiry {
         expired
     }
 }
+
+/// Monotonic/wall-clock pair captured on first use. All conversions between
+/// `Instant` deadlines and Unix time go through this one reference, so a
+/// deadline converted to Unix time and back lands on the same instant.
+static EPOCH: LazyLock<(Instant, SystemTime)> =
+    LazyLock::new(|| (Instant::now(), SystemTime::now()));
+
+/// Convert an absolute Unix time in milliseconds to a monotonic deadline.
+/// Times in the past map to an instant that is already expired.
+pub fn instant_from_unix_millis(ms: u64) -> Instant {
+    let (base, wall) = *EPOCH;
+    let target = UNIX_EPOCH + Duration::from_millis(ms);
+    match target.duration_since(wall) {
+        Ok(ahead) => base + ahead,
+        Err(behind) => base.checked_sub(behind.duration()).unwrap_or(base),
+    }
+}
+
+/// Convert a monotonic deadline to an absolute Unix time in milliseconds.
+pub fn unix_millis_from_instant(deadline: Instant) -> u64 {
+    let (base, wall) = *EPOCH;
+    let at = if deadline >= base {
+        wall + (deadline - base)
+    } else {
+        wall - (base - deadline)
+    };
+    at.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -1,40 +1,260 @@
+use std::time::Instant;
+
 use bytes::Bytes;
 
-use super::Database;
-use super::value::Value;
+use super::expire::ExpireCondition;
+use super::number::{IncrError, parse_i64};
+use super::random;
+use super::value::{Hash, Value};
+use super::{Database, TypeError};
+
+/// What HGETEX does to the deadlines of the fields it reads.
+#[derive(Debug, Clone, Copy)]
+pub enum FieldTtl {
+    /// Expire them at this instant; one already past deletes them.
+    At(Instant),
+    /// Drop their deadlines.
+    Persist,
+}
 
 impl Database {
-    pub fn hset(&mut self, key: String, fields: Vec<(Bytes, Bytes)>) -> usize {
-        let hash = self
-            .data
-            .entry(key)
-            .or_insert_with(|| Value::Hash(Default::default()));
-        if let Value::Hash(hm) = hash {
-            let mut added = 0;
-            for (f, v) in fields {
-                if hm.insert(f, v).is_none() {
-                    added += 1;
-                }
+    /// Borrow the hash at `key`, counting an access. A missing key is
+    /// `Ok(None)`.
+    pub(super) fn lookup_hash(&self, key: &str) -> Result<Option<&Hash>, TypeError> {
+        match self.lookup(key) {
+            Some(Value::Hash(hm)) => Ok(Some(hm)),
+            Some(_) => Err(TypeError),
+            None => Ok(None),
+        }
+    }
+
+    fn lookup_hash_mut(&mut self, key: &str) -> Result<Option<&mut Hash>, TypeError> {
+        match self.lookup_mut(key) {
+            Some(Value::Hash(hm)) => Ok(Some(hm)),
+            Some(_) => Err(TypeError),
+            None => Ok(None),
+        }
+    }
+
+    /// After field deadlines at `key` changed: delete the hash if no live
+    /// field is left, otherwise make sure the eviction sweep visits it by
+    /// its soonest field deadline.
+    pub(super) fn track_field_expiry(&mut self, key: &str) {
+        self.remove_if_empty(key);
+        if let Some(Value::Hash(hash)) = self.peek(key)
+            && let Some(next) = hash.next_deadline()
+            && self.field_expiry.get_deadline(key).is_none_or(|d| d > next)
+        {
+            self.field_expiry.set_deadline(key.to_string(), next);
+        }
+    }
+
+    /// Set `fields` in the hash at `key`, creating it if missing. Returns
+    /// how many fields were new.
+    pub fn hset(&mut self, key: String, fields: Vec<(Bytes, Bytes)>) -> Result<usize, TypeError> {
+        let Value::Hash(hm) = self.value_or_insert_with(key, || Value::Hash(Default::default()))
+        else {
+            return Err(TypeError);
+        };
+        let mut added = 0;
+        for (f, v) in fields {
+            if hm.insert(f, v).is_none() {
+                added += 1;
             }
-            added
-        } else {
-            0
         }
+        Ok(added)
     }
 
-    pub fn hget(&self, key: &str, field: &Bytes) -> Option<Bytes> {
-        if let Some(Value::Hash(hm)) = self.data.get(key) {
-            hm.get(field).cloned()
-        } else {
-            None
+    /// Add `delta` to the integer in `field` of the hash at `key`, creating
+    /// either if missing. Returns the new value. A field TTL is kept.
+    pub fn hincrby(&mut self, key: String, field: Bytes, delta: i64) -> Result<i64, IncrError> {
+        let Value::Hash(hm) = self.value_or_insert_with(key, || Value::Hash(Default::default()))
+        else {
+            return Err(IncrError::WrongType);
+        };
+        let current = match hm.get(&field) {
+            Some(bytes) => parse_i64(bytes).ok_or(IncrError::NotInteger)?,
+            None => 0,
+        };
+        let n = current.checked_add(delta).ok_or(IncrError::Overflow)?;
+        let deadline = hm.get(&field).and_then(|_| hm.deadline(&field));
+        hm.insert(field.clone(), n.to_string().into());
+        if let Some(deadline) = deadline {
+            hm.set_deadline(&field, deadline);
         }
+        Ok(n)
     }
 
-    pub fn hgetall(&self, key: &str) -> Vec<(Bytes, Bytes)> {
-        if let Some(Value::Hash(hm)) = self.data.get(key) {
-            hm.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
-        } else {
-            Vec::new()
+    pub fn hget(&self, key: &str, field: &Bytes) -> Result<Option<Bytes>, TypeError> {
+        Ok(self.lookup_hash(key)?.and_then(|hm| hm.get(field).cloned()))
+    }
+
+    /// Field/value pairs of the hash at `key`, in no particular order,
+    /// borrowed so a reply can be built without copying the hash first.
+    pub fn hgetall(&self, key: &str) -> Result<impl Iterator<Item = (&Bytes, &Bytes)>, TypeError> {
+        Ok(self.lookup_hash(key)?.into_iter().flatten())
+    }
+
+    /// Remove `fields` from the hash at `key`, deleting the key once no
+    /// field is left. Returns how many fields were removed.
+    pub fn hdel(&mut self, key: &str, fields: &[Bytes]) -> Result<usize, TypeError> {
+        let Some(hash) = self.lookup_hash_mut(key)? else {
+            return Ok(0);
+        };
+        let removed = fields.iter().filter(|f| hash.remove(f)).count();
+        self.track_field_expiry(key);
+        Ok(removed)
+    }
+
+    /// The values of `fields` in the hash at `key`, removing those fields
+    /// and deleting the key once no field is left.
+    pub fn hgetdel(
+        &mut self,
+        key: &str,
+        fields: &[Bytes],
+    ) -> Result<Vec<Option<Bytes>>, TypeError> {
+        let Some(hash) = self.lookup_hash_mut(key)? else {
+            return Ok(vec![None; fields.len()]);
+        };
+        let values = fields
+            .iter()
+            .map(|field| {
+                let value = hash.get(field).cloned();
+                hash.remove(field);
+                value
+            })
+            .collect();
+        self.track_field_expiry(key);
+        Ok(values)
+    }
+
+    /// The values of `fields` in the hash at `key`, applying `ttl`, if
+    /// given, to each field that exists.
+    pub fn hgetex(
+        &mut self,
+        key: &str,
+        fields: &[Bytes],
+        ttl: Option<FieldTtl>,
+    ) -> Result<Vec<Option<Bytes>>, TypeError> {
+        let now = Instant::now();
+        let Some(hash) = self.lookup_hash_mut(key)? else {
+            return Ok(vec![None; fields.len()]);
+        };
+        let values = fields
+            .iter()
+            .map(|field| {
+                let value = hash.get(field).cloned();
+                if value.is_some() {
+                    match ttl {
+                        Some(FieldTtl::At(deadline)) if deadline <= now => {
+                            hash.remove(field);
+                        }
+                        Some(FieldTtl::At(deadline)) => {
+                            hash.set_deadline(field, deadline);
+                        }
+                        Some(FieldTtl::Persist) => {
+                            hash.persist(field);
+                        }
+                        None => {}
+                    }
+                }
+                value
+            })
+            .collect();
+        self.track_field_expiry(key);
+        Ok(values)
+    }
+
+    /// Up to `count` distinct random field/value pairs of the hash at `key`.
+    pub fn hrandfield(&self, key: &str, count: usize) -> Result<Vec<(Bytes, Bytes)>, TypeError> {
+        let pairs: Vec<(&Bytes, &Bytes)> = self.lookup_hash(key)?.into_iter().flatten().collect();
+        Ok(random::sample(&pairs, count)
+            .into_iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    /// Give each of `fields` in the hash at `key` the `deadline`, where
+    /// `cond` allows it. Returns a code per field, as HEXPIRE replies: -2
+    /// no such field, 0 condition not met, 1 deadline set, 2 deleted because
+    /// `deadline` has already passed.
+    pub fn hexpire(
+        &mut self,
+        key: &str,
+        fields: &[Bytes],
+        deadline: Instant,
+        cond: ExpireCondition,
+    ) -> Result<Vec<i64>, TypeError> {
+        let now = Instant::now();
+        let Some(hash) = self.lookup_hash_mut(key)? else {
+            return Ok(vec![-2; fields.len()]);
+        };
+        let codes = fields
+            .iter()
+            .map(|field| {
+                if hash.get(field).is_none() {
+                    -2
+                } else if !cond.allows(hash.deadline(field), deadline) {
+                    0
+                } else if deadline <= now {
+                    hash.remove(field);
+                    2
+                } else {
+                    hash.set_deadline(field, deadline);
+                    1
+                }
+            })
+            .collect();
+        self.track_field_expiry(key);
+        Ok(codes)
+    }
+
+    /// Remaining TTL in milliseconds of each of `fields`, -1 for a field
+    /// without one and -2 for a missing field.
+    pub fn httl_millis(&self, key: &str, fields: &[Bytes]) -> Result<Vec<i64>, TypeError> {
+        let hash = self.lookup_hash(key)?;
+        let now = Instant::now();
+        Ok(fields
+            .iter()
+            .map(|field| match hash.filter(|h| h.get(field).is_some()) {
+                None => -2,
+                Some(h) => h
+                    .deadline(field)
+                    .map_or(-1, |d| d.saturating_duration_since(now).as_millis() as i64),
+            })
+            .collect())
+    }
+
+    /// Drop the deadlines of `fields`. Returns a code per field, as HPERSIST
+    /// replies: -2 no such field, -1 no deadline, 1 deadline removed.
+    pub fn hpersist(&mut self, key: &str, fields: &[Bytes]) -> Result<Vec<i64>, TypeError> {
+        let Some(hash) = self.lookup_hash_mut(key)? else {
+            return Ok(vec![-2; fields.len()]);
+        };
+        Ok(fields
+            .iter()
+            .map(|field| {
+                if hash.get(field).is_none() {
+                    -2
+                } else if hash.persist(field) {
+                    1
+                } else {
+                    -1
+                }
+            })
+            .collect())
+    }
+
+    /// Delete the expired fields of up to `limit` hashes, and hashes left
+    /// empty by that.
+    pub(super) fn evict_expired_fields(&mut self, limit: usize) {
+        let now = Instant::now();
+        for key in self.field_expiry.drain_expired(limit) {
+            if let Some(Value::Hash(hash)) = self.data.get_mut(&key).map(|e| &mut e.value) {
+                hash.remove_expired(now);
+                self.mark_unsized(&key);
+                self.track_field_expiry(&key);
+            }
         }
     }
 }
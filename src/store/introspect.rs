@@ -0,0 +1,149 @@
+//! DEBUG OBJECT's description of how a key is stored.
+
+use super::Database;
+use super::encoding::EncodingThresholds;
+use super::value::Value;
+use crate::persistence::serial;
+
+/// Byte budget of one quicklist node, matching Redis' default
+/// `list-max-listpack-size -2` (8 KB).
+const QUICKLIST_NODE_BYTES: usize = 8 * 1024;
+
+/// Highest level a Redis skiplist node can reach.
+const ZSKIPLIST_MAXLEVEL: usize = 32;
+
+impl Database {
+    /// Redis-style `key:value` line describing the live `key`: the fields
+    /// every type shares, then ones for its type. `None` if there is no
+    /// such key.
+    pub fn debug_object(&self, key: &str, thresholds: &EncodingThresholds) -> Option<String> {
+        if self.expiry.is_expired(key) {
+            return None;
+        }
+        let value = self.peek(key)?;
+        let mut info = format!(
+            "Value at:0x0 refcount:1 encoding:{} serializedlength:{} lru:0 lru_seconds_idle:0",
+            thresholds.encoding(value),
+            serial::serialized_len(value)
+        );
+        match value {
+            Value::String(_) => {}
+            Value::List(items) => {
+                let nodes = quicklist_nodes(items.iter().map(|b| b.len()));
+                info.push_str(&format!(
+                    " ql_nodes:{nodes} ql_avg_node:{:.2}",
+                    items.len() as f64 / nodes as f64
+                ));
+            }
+            Value::Hash(fields) => info.push_str(&format!(" fields:{}", fields.len())),
+            Value::Set(members) => info.push_str(&format!(" members:{}", members.len())),
+            Value::ZSet(members) => info.push_str(&format!(
+                " zsl_length:{} zsl_level:{}",
+                members.len(),
+                skiplist_level(members.len())
+            )),
+        }
+        Some(info)
+    }
+}
+
+/// Nodes a quicklist would need for elements of the given sizes, packing
+/// each node up to [`QUICKLIST_NODE_BYTES`]. An oversized element gets a
+/// node of its own.
+fn quicklist_nodes(sizes: impl Iterator<Item = usize>) -> usize {
+    let mut nodes = 0;
+    let mut fill = 0;
+    for size in sizes {
+        if nodes == 0 || fill + size > QUICKLIST_NODE_BYTES {
+            nodes += 1;
+            fill = 0;
+        }
+        fill += size;
+    }
+    nodes
+}
+
+/// The level a Redis skiplist of `len` nodes typically reaches. Each level
+/// holds a quarter of the nodes of the one below, so that's one more than
+/// the base-4 logarithm of the length.
+fn skiplist_level(len: usize) -> usize {
+    let mut level = 1;
+    let mut span = 4;
+    while span <= len && level < ZSKIPLIST_MAXLEVEL {
+        level += 1;
+        span = span.saturating_mul(4);
+    }
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn field<'a>(info: &'a str, name: &str) -> &'a str {
+        info.split(' ')
+            .find_map(|tok| tok.strip_prefix(&format!("{name}:")))
+            .unwrap_or_else(|| panic!("no {name} in {info}"))
+    }
+
+    #[test]
+    fn describes_each_type() {
+        let mut db = Database::new();
+        let b = |s: &str| Bytes::copy_from_slice(s.as_bytes());
+        db.set("s".into(), Value::String(b("12")));
+        db.rpush("l".into(), vec![b("a"), b("b"), b("c")]).unwrap();
+        db.hset("h".into(), vec![(b("f1"), b("v")), (b("f2"), b("v"))])
+            .unwrap();
+        db.sadd("set".into(), vec![b("1"), b("2")]).unwrap();
+        let members = (0..20).map(|i| (b(&format!("m{i}")), i as f64)).collect();
+        db.zadd("z".into(), members).unwrap();
+        let t = EncodingThresholds::default();
+
+        let info = db.debug_object("s", &t).unwrap();
+        assert!(
+            info.starts_with("Value at:0x0 refcount:1 encoding:int "),
+            "{info}"
+        );
+        assert!(!info.contains("ql_nodes"), "{info}");
+
+        let info = db.debug_object("l", &t).unwrap();
+        assert_eq!(field(&info, "encoding"), "listpack");
+        assert_eq!(field(&info, "ql_nodes"), "1");
+        assert_eq!(field(&info, "ql_avg_node"), "3.00");
+
+        let info = db.debug_object("h", &t).unwrap();
+        assert_eq!(field(&info, "fields"), "2");
+
+        let info = db.debug_object("set", &t).unwrap();
+        assert_eq!(field(&info, "encoding"), "intset");
+        assert_eq!(field(&info, "members"), "2");
+
+        let info = db.debug_object("z", &t).unwrap();
+        assert_eq!(field(&info, "zsl_length"), "20");
+        assert_eq!(field(&info, "zsl_level"), "3");
+
+        assert_eq!(db.debug_object("missing", &t), None);
+        db.set_with_deadline("gone".into(), Value::String(b("x")), Instant::now());
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(db.debug_object("gone", &t), None);
+    }
+
+    #[test]
+    fn skiplist_level_grows_by_powers_of_four() {
+        for (len, level) in [
+            (0, 1),
+            (1, 1),
+            (3, 1),
+            (4, 2),
+            (15, 2),
+            (16, 3),
+            (usize::MAX, 32),
+        ] {
+            assert_eq!(skiplist_level(len), level, "len {len}");
+        }
+    }
+}
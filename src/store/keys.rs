@@ -1,47 +1,158 @@
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
-use super::Database;
+use bytes::{Bytes, BytesMut};
+
+use super::expire::{ExpireCondition, unix_millis_from_instant};
+use super::number::{IncrError, parse_i64};
 use super::value::Value;
+use super::{Database, TypeError, normalize_range};
 
 impl Database {
     pub fn set(&mut self, key: String, value: Value) {
         self.expiry.remove(&key);
-        self.data.insert(key, value);
+        self.insert_value(key, value);
     }
 
-    pub fn set_with_expiry(&mut self, key: String, value: Value, ttl: Duration) {
-        let deadline = Instant::now() + ttl;
-        self.data.insert(key.clone(), value);
+    pub fn set_with_deadline(&mut self, key: String, value: Value, deadline: Instant) {
+        self.insert_value(key.clone(), value);
         self.expiry.set_deadline(key, deadline);
     }
 
-    pub fn get(&mut self, key: &str) -> Option<Value> {
-        if self.expiry.is_expired(key) {
-            self.data.remove(key);
-            self.expiry.remove(key);
-            return None;
+    /// The bytes of a live string key. Other types are only peeked at, so a
+    /// GET on a huge list doesn't copy the list just to reject it.
+    pub fn get_string(&mut self, key: &str) -> Result<Option<Bytes>, TypeError> {
+        if self.expire_if_needed(key) {
+            return Ok(None);
+        }
+        match self.lookup(key) {
+            Some(Value::String(bytes)) => Ok(Some(bytes.clone())),
+            Some(_) => Err(TypeError),
+            None => Ok(None),
+        }
+    }
+
+    /// Take the string at `key` out to edit in place, leaving it empty until
+    /// it is stored again; a missing key gives an empty buffer. The
+    /// allocation is reused unless a reply still shares it.
+    pub(super) fn take_string(&mut self, key: &str) -> Result<BytesMut, TypeError> {
+        if self.expire_if_needed(key) {
+            return Ok(BytesMut::new());
+        }
+        match self.lookup_mut(key) {
+            Some(Value::String(bytes)) => Ok(std::mem::take(bytes)
+                .try_into_mut()
+                .unwrap_or_else(|shared| BytesMut::from(&shared[..]))),
+            Some(_) => Err(TypeError),
+            None => Ok(BytesMut::new()),
+        }
+    }
+
+    /// The bytes of the string at `key` within the inclusive `start..=end`
+    /// range (see [`normalize_range`]). A missing key or an empty range is
+    /// an empty string rather than nil.
+    pub fn getrange(&mut self, key: &str, start: i64, end: i64) -> Result<Bytes, TypeError> {
+        let Some(bytes) = self.get_string(key)? else {
+            return Ok(Bytes::new());
+        };
+        Ok(match normalize_range(bytes.len(), start, end) {
+            Some((start, end)) => bytes.slice(start..=end),
+            None => Bytes::new(),
+        })
+    }
+
+    /// Overwrite the string at `key` with `value` from byte `offset`,
+    /// zero-padding it first if it is shorter. Returns the new length. An
+    /// empty `value` changes nothing, so it doesn't create a missing key.
+    /// Any existing TTL is kept.
+    pub fn setrange(&mut self, key: &str, offset: usize, value: &[u8]) -> Result<usize, TypeError> {
+        if value.is_empty() {
+            return Ok(self.get_string(key)?.map_or(0, |b| b.len()));
         }
-        self.data.get(key).cloned()
+        let mut buf = self.take_string(key)?;
+        let end = offset + value.len();
+        if buf.len() < end {
+            buf.resize(end, 0);
+        }
+        buf[offset..end].copy_from_slice(value);
+        let len = buf.len();
+        self.insert_value(key.to_string(), Value::String(buf.freeze()));
+        Ok(len)
+    }
+
+    /// Add `delta` to the integer stored at `key`, a missing key counting
+    /// as 0. Returns the new value. Any existing TTL is kept.
+    pub fn incr_by(&mut self, key: &str, delta: i64) -> Result<i64, IncrError> {
+        let current = match self.get_string(key) {
+            Ok(Some(bytes)) => parse_i64(&bytes).ok_or(IncrError::NotInteger)?,
+            Ok(None) => 0,
+            Err(TypeError) => return Err(IncrError::WrongType),
+        };
+        let n = current.checked_add(delta).ok_or(IncrError::Overflow)?;
+        self.insert_value(key.to_string(), Value::String(n.to_string().into()));
+        Ok(n)
+    }
+
+    /// Append `value` to the string at `key`, creating it if missing.
+    /// Returns the new length. Any existing TTL is kept.
+    pub fn append(&mut self, key: &str, value: &[u8]) -> Result<usize, TypeError> {
+        let mut buf = self.take_string(key)?;
+        buf.extend_from_slice(value);
+        let len = buf.len();
+        self.insert_value(key.to_string(), Value::String(buf.freeze()));
+        Ok(len)
+    }
+
+    /// Number of keys held, counting expired ones not yet evicted, as Redis'
+    /// DBSIZE does.
+    pub fn dbsize(&self) -> usize {
+        self.data.len()
     }
 
+    /// Count the live keys among `keys`. As in Redis, a key named twice is
+    /// counted twice.
     pub fn exists(&mut self, keys: &[String]) -> usize {
-        keys.iter()
-            .filter(|k| {
-                if self.expiry.is_expired(k) {
-                    self.data.remove(k.as_str());
-                    self.expiry.remove(k);
-                    false
-                } else {
-                    self.data.contains_key(k.as_str())
-                }
-            })
-            .count()
+        let mut live = 0;
+        for key in keys {
+            if !self.expire_if_needed(key) && self.data.contains_key(key) {
+                live += 1;
+            }
+        }
+        live
+    }
+
+    /// Count the live keys among `keys`, expiring any that are stale and
+    /// recording an access on the rest.
+    pub fn touch(&mut self, keys: &[String]) -> usize {
+        let live = self.exists(keys);
+        for key in keys {
+            self.lookup(key);
+        }
+        live
+    }
+
+    /// Access frequency of a live key, without counting this as an access.
+    pub fn object_freq(&mut self, key: &str) -> Option<u8> {
+        if self.expire_if_needed(key) {
+            return None;
+        }
+        self.data.get(key).map(|e| e.lfu.freq())
     }
 
+    /// Value of a live key, without counting this as an access. For
+    /// introspection such as DEBUG OBJECT.
+    pub fn inspect(&mut self, key: &str) -> Option<&Value> {
+        if self.expire_if_needed(key) {
+            return None;
+        }
+        self.peek(key)
+    }
+
+    /// Remove `keys`, returning how many were live. A key that has expired
+    /// but not yet been evicted is expired rather than counted.
     pub fn del(&mut self, keys: &[String]) -> usize {
         let mut removed = 0;
         for key in keys {
-            if self.data.remove(key).is_some() {
+            if !self.expire_if_needed(key) && self.remove_entry(key).is_some() {
                 self.expiry.remove(key);
                 removed += 1;
             }
@@ -49,10 +160,116 @@ impl Database {
         removed
     }
 
+    /// Copy the value at `src` to `dst`, along with its expiry deadline if
+    /// it has one. Returns false when `src` doesn't exist, or `dst` does and
+    /// `replace` is false.
+    ///
+    /// The copy shares its `Bytes` payloads with the source. That's safe
+    /// because stored `Bytes` are only edited in place while nothing else
+    /// holds them (see `take_string`), so the two keys still change
+    /// independently.
+    pub fn copy(&mut self, src: &str, dst: &str, replace: bool) -> bool {
+        for key in [src, dst] {
+            self.expire_if_needed(key);
+        }
+        if !replace && self.data.contains_key(dst) {
+            return false;
+        }
+        let Some(value) = self.lookup(src).cloned() else {
+            return false;
+        };
+        match self.expiry.get_deadline(src) {
+            Some(deadline) => self.set_with_deadline(dst.to_string(), value, deadline),
+            None => self.set(dst.to_string(), value),
+        }
+        self.track_field_expiry(dst);
+        true
+    }
+
+    /// Replace the string at `key` with `new` if it currently equals
+    /// `expected`. Like SET, a successful swap clears any TTL. Returns false
+    /// when the key is missing, holds another type, or holds another value.
+    pub fn compare_and_swap(&mut self, key: &str, expected: &Bytes, new: Bytes) -> bool {
+        if self.expire_if_needed(key) {
+            return false;
+        }
+        if !matches!(self.lookup(key), Some(Value::String(current)) if current == expected) {
+            return false;
+        }
+        self.set(key.to_string(), Value::String(new));
+        true
+    }
+
+    /// Remove `keys` and hand back their owned values so the caller can
+    /// decide where to drop them (see UNLINK).
+    pub fn unlink(&mut self, keys: &[String]) -> Vec<Value> {
+        let mut removed = Vec::new();
+        for key in keys {
+            if self.expire_if_needed(key) {
+                continue;
+            }
+            if let Some(entry) = self.remove_entry(key) {
+                self.expiry.remove(key);
+                removed.push(entry.value);
+            }
+        }
+        removed
+    }
+
+    /// Empty the database, handing back its old contents so the caller can
+    /// decide where to drop them (see FLUSHALL ASYNC).
+    pub fn flush(&mut self) -> Database {
+        self.replace_contents(Database::new())
+    }
+
+    /// Swap in the keys of `other`, handing back the old ones. The expired
+    /// key count and expire hook stay with `self`.
+    pub fn replace_contents(&mut self, mut other: Database) -> Database {
+        std::mem::swap(&mut self.data, &mut other.data);
+        std::mem::swap(&mut self.expiry, &mut other.expiry);
+        std::mem::swap(&mut self.field_expiry, &mut other.field_expiry);
+        std::mem::swap(&mut self.used_bytes, &mut other.used_bytes);
+        std::mem::swap(&mut self.unsized_keys, &mut other.unsized_keys);
+        std::mem::swap(&mut self.key_pool, &mut other.key_pool);
+        other
+    }
+
+    /// Set an expiry deadline on an existing key if `cond` holds against its
+    /// current deadline. Returns false if the key doesn't exist or the
+    /// condition isn't met.
+    pub fn set_expiry(&mut self, key: &str, deadline: Instant, cond: ExpireCondition) -> bool {
+        if self.expire_if_needed(key) {
+            return false;
+        }
+        if !self.data.contains_key(key) {
+            return false;
+        }
+        if !cond.allows(self.expiry.get_deadline(key), deadline) {
+            return false;
+        }
+        self.expiry.set_deadline(key.to_string(), deadline);
+        true
+    }
+
+    /// Remove the expiry from a key. Returns true if a deadline was removed.
+    pub fn persist(&mut self, key: &str) -> bool {
+        if self.expire_if_needed(key) {
+            return false;
+        }
+        if self.expiry.get_deadline(key).is_none() || !self.data.contains_key(key) {
+            return false;
+        }
+        self.expiry.remove(key);
+        true
+    }
+
+    /// The expiry deadline of `key`, if it has one.
+    pub fn deadline(&self, key: &str) -> Option<Instant> {
+        self.expiry.get_deadline(key)
+    }
+
     pub fn ttl_millis(&mut self, key: &str) -> i64 {
-        if self.expiry.is_expired(key) {
-            self.data.remove(key);
-            self.expiry.remove(key);
+        if self.expire_if_needed(key) {
             return -2; // key does not exist
         }
         if !self.data.contains_key(key) {
@@ -67,18 +284,38 @@ impl Database {
         }
     }
 
-    /// Drain expired keys (called periodically).
-    pub fn evict_expired(&mut self) -> usize {
-        let expired = self.expiry.drain_expired();
+    /// Absolute expiry as Unix time in milliseconds, with the same -2/-1
+    /// sentinels as [`ttl_millis`](Self::ttl_millis).
+    pub fn expire_time_millis(&mut self, key: &str) -> i64 {
+        match self.ttl_millis(key) {
+            ms if ms < 0 => ms,
+            _ => self
+                .expiry
+                .get_deadline(key)
+                .map_or(-1, |d| unix_millis_from_instant(d) as i64),
+        }
+    }
+
+    /// How many keys have an expiry deadline, including any that have
+    /// passed it but not been removed yet.
+    pub fn volatile_count(&self) -> usize {
+        self.expiry.volatile_count()
+    }
+
+    /// Drain up to `limit` expired keys, and hashes with up to `limit`
+    /// expired fields (called periodically). Returns how many keys expired.
+    pub fn evict_expired(&mut self, limit: usize) -> usize {
+        let expired = self.expiry.drain_expired(limit);
         let count = expired.len();
         for key in expired {
-            self.data.remove(&key);
+            self.expire_key(&key);
         }
+        self.evict_expired_fields(limit);
         count
     }
 
     pub fn is_type(&self, key: &str, expected: &str) -> bool {
-        match self.data.get(key) {
+        match self.peek(key) {
             None => true, // key doesn't exist, any type is fine
             Some(Value::String(_)) => expected == "string",
             Some(Value::List(_)) => expected == "list",
@@ -88,12 +325,13 @@ impl Database {
         }
     }
 
-    /// Snapshot current data for AOF rewrite.
-    #[allow(dead_code)]
-    pub fn snapshot_for_aof(&self) -> Vec<(String, Value)> {
+    /// Snapshot current data for AOF rewrite: every live key with its value
+    /// and expiry deadline, if any.
+    pub fn snapshot_for_aof(&self) -> Vec<(String, Value, Option<Instant>)> {
         self.data
             .iter()
-            .map(|(k, v)| (k.clone(), v.clone()))
+            .filter(|(k, _)| !self.expiry.is_expired(k))
+            .map(|(k, e)| (k.clone(), e.value.clone(), self.expiry.get_deadline(k)))
             .collect()
     }
 }
@@ -0,0 +1,80 @@
+//! Approximate per-key access frequency, modelled on Redis's LFU: an 8-bit
+//! logarithmic counter that grows more slowly the higher it gets and loses
+//! one point for every minute the key goes untouched.
+
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+use std::time::Instant;
+
+use super::random;
+
+/// Counter value for a freshly created key, so new keys aren't evicted
+/// before they have had a chance to be read.
+const LFU_INIT_VAL: u8 = 5;
+/// Higher values make the counter saturate more slowly.
+const LFU_LOG_FACTOR: f64 = 10.0;
+/// Minutes of idleness per point of decay.
+const LFU_DECAY_MINUTES: u32 = 1;
+
+static START: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+fn now_minutes() -> u32 {
+    (START.elapsed().as_secs() / 60) as u32
+}
+
+/// Atomics let readers holding only the store's read lock record accesses.
+#[derive(Debug)]
+pub(super) struct Lfu {
+    counter: AtomicU8,
+    last_decrement: AtomicU32,
+}
+
+impl Lfu {
+    pub(super) fn new() -> Self {
+        Self {
+            counter: AtomicU8::new(LFU_INIT_VAL),
+            last_decrement: AtomicU32::new(now_minutes()),
+        }
+    }
+
+    /// Current frequency with decay applied, without counting an access.
+    pub(super) fn freq(&self) -> u8 {
+        self.decayed(now_minutes())
+    }
+
+    /// Record an access.
+    pub(super) fn touch(&self) {
+        let now = now_minutes();
+        let counter = log_incr(self.decayed(now));
+        self.counter.store(counter, Ordering::Relaxed);
+        self.last_decrement.store(now, Ordering::Relaxed);
+    }
+
+    fn decayed(&self, now: u32) -> u8 {
+        let idle = now.saturating_sub(self.last_decrement.load(Ordering::Relaxed));
+        let periods = (idle / LFU_DECAY_MINUTES).min(u8::MAX as u32) as u8;
+        self.counter.load(Ordering::Relaxed).saturating_sub(periods)
+    }
+}
+
+impl Clone for Lfu {
+    fn clone(&self) -> Self {
+        Self {
+            counter: AtomicU8::new(self.counter.load(Ordering::Relaxed)),
+            last_decrement: AtomicU32::new(self.last_decrement.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Increment with probability `1 / ((counter - LFU_INIT_VAL) * factor + 1)`.
+fn log_incr(counter: u8) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    if random::next_f64() < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+        counter + 1
+    } else {
+        counter
+    }
+}
@@ -1,93 +1,133 @@
+use std::collections::VecDeque;
+
 use bytes::Bytes;
 
 use crate::store::value::Value;
 
-use super::Database;
+use super::{Database, TypeError, normalize_range};
 
 impl Database {
-    pub fn lpush(&mut self, key: String, values: Vec<Bytes>) -> usize {
-        self.expiry.remove(&key);
-        let list = self
-            .data
-            .entry(key)
-            .or_insert_with(|| Value::List(Default::default()));
-        if let Value::List(deque) = list {
-            for v in values {
-                deque.push_front(v);
-            }
-            deque.len()
-        } else {
-            0
+    /// Borrow the list at `key`, counting an access. A missing key is
+    /// `Ok(None)`.
+    pub(super) fn lookup_list(&self, key: &str) -> Result<Option<&VecDeque<Bytes>>, TypeError> {
+        match self.lookup(key) {
+            Some(Value::List(deque)) => Ok(Some(deque)),
+            Some(_) => Err(TypeError),
+            None => Ok(None),
         }
     }
 
-    pub fn rpush(&mut self, key: String, values: Vec<Bytes>) -> usize {
-        self.expiry.remove(&key);
-        let list = self
-            .data
-            .entry(key)
-            .or_insert_with(|| Value::List(Default::default()));
-        if let Value::List(deque) = list {
-            for v in values {
-                deque.push_back(v);
-            }
-            deque.len()
-        } else {
-            0
-        }
+    pub fn lpush(&mut self, key: String, values: Vec<Bytes>) -> Result<usize, TypeError> {
+        self.push(key, values, VecDeque::push_front)
     }
 
-    pub fn lpop(&mut self, key: &str) -> Option<Bytes> {
-        if let Some(Value::List(deque)) = self.data.get_mut(key) {
-            let val = deque.pop_front();
-            if deque.is_empty() {
-                self.data.remove(key);
-            }
-            val
-        } else {
-            None
-        }
+    pub fn rpush(&mut self, key: String, values: Vec<Bytes>) -> Result<usize, TypeError> {
+        self.push(key, values, VecDeque::push_back)
     }
 
-    pub fn rpop(&mut self, key: &str) -> Option<Bytes> {
-        if let Some(Value::List(deque)) = self.data.get_mut(key) {
-            let val = deque.pop_back();
-            if deque.is_empty() {
-                self.data.remove(key);
-            }
-            val
-        } else {
-            None
+    /// Add `values` to the list at `key` one at a time with `push`, creating
+    /// the list if missing. Returns the new length.
+    fn push(
+        &mut self,
+        key: String,
+        values: Vec<Bytes>,
+        push: fn(&mut VecDeque<Bytes>, Bytes),
+    ) -> Result<usize, TypeError> {
+        let list = self.value_or_insert_with(key.clone(), || Value::List(Default::default()));
+        let Value::List(deque) = list else {
+            return Err(TypeError);
+        };
+        for v in values {
+            push(deque, v);
         }
+        let len = deque.len();
+        self.expiry.remove(&key);
+        Ok(len)
     }
 
-    pub fn lrange(&mut self, key: &str, start: i64, stop: i64) -> Vec<Bytes> {
-        if let Some(Value::List(deque)) = self.data.get(key) {
-            let len = deque.len() as i64;
-            let s = if start < 0 {
-                (len + start).max(0)
-            } else {
-                start.min(len)
-            } as usize;
-            let e = if stop < 0 {
-                (len + stop).max(0)
-            } else {
-                stop.min(len - 1)
-            } as usize;
-            if s > e {
-                return Vec::new();
-            }
-            deque.iter().skip(s).take(e - s + 1).cloned().collect()
-        } else {
-            Vec::new()
-        }
+    /// Remove up to `count` elements from the head of the list at `key`.
+    pub fn lpop(&mut self, key: &str, count: usize) -> Result<Vec<Bytes>, TypeError> {
+        self.pop(key, count, VecDeque::pop_front)
+    }
+
+    /// Remove up to `count` elements from the tail of the list at `key`.
+    pub fn rpop(&mut self, key: &str, count: usize) -> Result<Vec<Bytes>, TypeError> {
+        self.pop(key, count, VecDeque::pop_back)
+    }
+
+    fn pop(
+        &mut self,
+        key: &str,
+        count: usize,
+        pop: fn(&mut VecDeque<Bytes>) -> Option<Bytes>,
+    ) -> Result<Vec<Bytes>, TypeError> {
+        let popped = match self.lookup_mut(key) {
+            Some(Value::List(deque)) => std::iter::from_fn(|| pop(deque)).take(count).collect(),
+            Some(_) => return Err(TypeError),
+            None => Vec::new(),
+        };
+        self.remove_if_empty(key);
+        Ok(popped)
+    }
+
+    /// Elements `start..=stop` of the list at `key`, borrowed.
+    pub fn lrange(
+        &self,
+        key: &str,
+        start: i64,
+        stop: i64,
+    ) -> Result<impl Iterator<Item = &Bytes>, TypeError> {
+        let range = self.lookup_list(key)?.and_then(|deque| {
+            normalize_range(deque.len(), start, stop).map(|(s, e)| deque.range(s..=e))
+        });
+        Ok(range.into_iter().flatten())
     }
 
-    pub fn llen(&self, key: &str) -> usize {
-        if let Some(Value::List(deque)) = self.data.get(key) {
-            deque.len()
+    pub fn llen(&self, key: &str) -> Result<usize, TypeError> {
+        Ok(self.lookup_list(key)?.map_or(0, VecDeque::len))
+    }
+
+    /// Indices (from the head) of elements equal to `element`.
+    ///
+    /// Matching starts at the `rank`-th match, scanning from the tail when
+    /// `rank` is negative. At most `count` indices are returned and at most
+    /// `maxlen` elements compared; 0 means unlimited for both.
+    pub fn lpos(
+        &self,
+        key: &str,
+        element: &Bytes,
+        rank: i64,
+        count: usize,
+        maxlen: usize,
+    ) -> Result<Vec<usize>, TypeError> {
+        let Some(deque) = self.lookup_list(key)? else {
+            return Ok(Vec::new());
+        };
+        let len = deque.len();
+        let maxlen = if maxlen == 0 { len } else { maxlen.min(len) };
+        let count = if count == 0 { len } else { count };
+        let mut skip = rank.unsigned_abs() - 1;
+
+        let indices: Box<dyn Iterator<Item = usize>> = if rank > 0 {
+            Box::new(0..maxlen)
         } else {
-            0
+            Box::new((len - maxlen..len).rev())
+        };
+
+        let mut found = Vec::new();
+        for i in indices {
+            if deque[i] != *element {
+                continue;
+            }
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            found.push(i);
+            if found.len() == count {
+                break;
+            }
         }
+        Ok(found)
     }
 }
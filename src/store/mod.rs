@@ -1,32 +1,510 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, RwLock};
 
+pub mod access;
+pub mod encoding;
+pub mod evict;
 pub mod expire;
+pub mod glob;
+pub mod number;
+pub mod random;
+pub mod scan;
 pub mod value;
 
+mod bitops;
 mod hash;
+mod introspect;
 mod keys;
+mod lfu;
 mod list;
 mod set;
 mod zset;
 
+pub use access::StoreAccess;
+pub use bitops::BitOp;
+pub use hash::FieldTtl;
+pub use zset::member_order;
+
 use expire::Expiry;
+use lfu::Lfu;
 use value::Value;
 
+/// A stored value together with its access metadata.
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Value,
+    lfu: Lfu,
+    /// What this entry adds to [`Database::used_memory`], as of when it was
+    /// last sized.
+    size: usize,
+}
+
+impl Entry {
+    fn new(value: Value) -> Self {
+        Self {
+            value,
+            lfu: Lfu::new(),
+            size: 0,
+        }
+    }
+}
+
+/// The key holds a value of another type than the operation works on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeError;
+
+/// Called with the name of each key removed because its TTL passed, for
+/// keyspace notifications.
+pub struct ExpireHook(Box<dyn Fn(&str) + Send + Sync>);
+
+impl ExpireHook {
+    pub fn new(hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        Self(Box::new(hook))
+    }
+}
+
+impl std::fmt::Debug for ExpireHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ExpireHook")
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Database {
-    data: HashMap<String, Value>,
+    data: HashMap<String, Entry>,
     expiry: Expiry,
+    /// Hashes with field TTLs, each due when its soonest field expires.
+    field_expiry: Expiry,
+    /// Keys removed by [`expire_key`](Self::expire_key), whether found
+    /// stale on access or by the background sweep. Survives flushes and
+    /// reloads, as INFO's `expired_keys` does in Redis.
+    expired_keys: u64,
+    on_expire: Option<ExpireHook>,
+    /// Running total of the entries' sizes; see [`Database::used_memory`].
+    used_bytes: usize,
+    /// Keys written since they were last sized, at most one per key.
+    unsized_keys: HashSet<String>,
+    /// Every key, and some since removed, so eviction can draw keys at
+    /// random without walking `data`; see [`Database::random_key`].
+    key_pool: Vec<String>,
 }
 
 impl Database {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Borrow a value, counting it as an access.
+    fn lookup(&self, key: &str) -> Option<&Value> {
+        let entry = self.data.get(key)?;
+        entry.lfu.touch();
+        Some(&entry.value)
+    }
+
+    /// Mutably borrow a value, counting it as an access.
+    fn lookup_mut(&mut self, key: &str) -> Option<&mut Value> {
+        let entry = self.data.get_mut(key)?;
+        entry.lfu.touch();
+        if !self.unsized_keys.contains(key) {
+            self.unsized_keys.insert(key.to_string());
+        }
+        Some(&mut entry.value)
+    }
+
+    /// Note that `key`'s value may have changed size since it was last sized.
+    fn mark_unsized(&mut self, key: &str) {
+        if !self.unsized_keys.contains(key) {
+            self.unsized_keys.insert(key.to_string());
+        }
+    }
+
+    /// Borrow a value without counting an access.
+    fn peek(&self, key: &str) -> Option<&Value> {
+        self.data.get(key).map(|e| &e.value)
+    }
+
+    /// Store `value` under `key`. Overwriting counts as an access to the
+    /// existing key rather than resetting its frequency.
+    fn insert_value(&mut self, key: String, value: Value) {
+        match self.data.get_mut(&key) {
+            Some(entry) => {
+                entry.value = value;
+                entry.lfu.touch();
+            }
+            None => {
+                self.track_new_key(&key);
+                self.data.insert(key.clone(), Entry::new(value));
+            }
+        }
+        self.unsized_keys.insert(key);
+    }
+
+    /// Remove `key`'s entry, taking it out of the memory count.
+    fn remove_entry(&mut self, key: &str) -> Option<Entry> {
+        let entry = self.data.remove(key)?;
+        self.used_bytes -= entry.size;
+        Some(entry)
+    }
+
+    /// Add a key about to be inserted to the pool eviction draws from,
+    /// rebuilding the pool if removed keys have come to dominate it.
+    fn track_new_key(&mut self, key: &str) {
+        self.key_pool.push(key.to_string());
+        if self.key_pool.len() > 2 * self.data.len() + KEY_POOL_SLACK {
+            self.key_pool = self.data.keys().cloned().collect();
+            self.key_pool.push(key.to_string());
+        }
+    }
+
+    /// A key drawn uniformly at random, or `None` if there are none.
+    /// Removed keys are dropped from the pool as they turn up; since they
+    /// are never most of it, that takes a couple of draws at worst.
+    fn random_key(&mut self) -> Option<String> {
+        while !self.key_pool.is_empty() {
+            let i = random::below(self.key_pool.len());
+            if self.data.contains_key(&self.key_pool[i]) {
+                return Some(self.key_pool[i].clone());
+            }
+            self.key_pool.swap_remove(i);
+        }
+        None
+    }
+
+    /// Delete `key`, and its expiry, if it holds a collection with nothing
+    /// left in it. Every operation that removes elements ends with this, so
+    /// an empty collection is never visible.
+    fn remove_if_empty(&mut self, key: &str) {
+        let empty = match self.peek(key) {
+            Some(Value::List(deque)) => deque.is_empty(),
+            Some(Value::Set(hs)) => hs.is_empty(),
+            Some(Value::Hash(hm)) => hm.is_empty(),
+            Some(Value::ZSet(members)) => members.is_empty(),
+            Some(Value::String(_)) | None => false,
+        };
+        if empty {
+            self.remove_entry(key);
+            self.expiry.remove(key);
+        }
+    }
+
+    /// Remove `key` because its TTL has passed: drop its value and
+    /// deadline, count it in [`expired_keys`](Self::expired_keys) and tell
+    /// the expire hook. Every path that finds a stale key goes through here.
+    fn expire_key(&mut self, key: &str) {
+        self.remove_entry(key);
+        self.expiry.remove(key);
+        self.expired_keys += 1;
+        if let Some(hook) = &self.on_expire {
+            (hook.0)(key);
+        }
+    }
+
+    /// Remove `key` if its TTL has passed. Returns whether it did.
+    fn expire_if_needed(&mut self, key: &str) -> bool {
+        let expired = self.expiry.is_expired(key);
+        if expired {
+            self.expire_key(key);
+        }
+        expired
+    }
+
+    /// How many keys have expired since the server started.
+    pub fn expired_keys(&self) -> u64 {
+        self.expired_keys
+    }
+
+    /// Call `hook` with each key that expires from now on.
+    pub fn set_expire_hook(&mut self, hook: ExpireHook) {
+        self.on_expire = Some(hook);
+    }
+
+    /// Check the structural invariants the rest of the store relies on,
+    /// describing the first one broken. Walks the whole keyspace, so it's
+    /// meant for DEBUG SELF-TEST rather than regular use. `field_expiry`
+    /// isn't checked: it only schedules sweeps, and an entry outliving its
+    /// hash's field TTLs is skipped when it comes due.
+    pub fn verify_invariants(&self) -> Result<(), String> {
+        for (key, entry) in &self.data {
+            let empty = match &entry.value {
+                Value::String(_) => false,
+                Value::List(deque) => deque.is_empty(),
+                Value::Set(hs) => hs.is_empty(),
+                Value::Hash(hm) => hm.stored().len() == 0,
+                Value::ZSet(members) => members.is_empty(),
+            };
+            if empty {
+                return Err(format!("empty collection left at '{key}'"));
+            }
+            if let Value::ZSet(members) = &entry.value
+                && !members.is_sorted_by(|a, b| zset::member_order(a, b).is_le())
+            {
+                return Err(format!("sorted set '{key}' is out of order"));
+            }
+        }
+        if let Some(key) = self.expiry.keys().find(|k| !self.data.contains_key(*k)) {
+            return Err(format!("expiry deadline for missing key '{key}'"));
+        }
+        Ok(())
+    }
+
+    /// Mutably borrow the value at `key`, creating it with `default` first
+    /// if missing.
+    fn value_or_insert_with(&mut self, key: String, default: impl FnOnce() -> Value) -> &mut Value {
+        if !self.data.contains_key(&key) {
+            self.track_new_key(&key);
+        }
+        self.mark_unsized(&key);
+        let entry = self
+            .data
+            .entry(key)
+            .or_insert_with(|| Entry::new(default()));
+        entry.lfu.touch();
+        &mut entry.value
+    }
 }
 
+/// Removed keys [`Database::key_pool`] may hold beyond as many as there are
+/// live ones, so a small keyspace isn't rebuilt on every insert.
+const KEY_POOL_SLACK: usize = 64;
+
 pub type SharedStore = Arc<RwLock<Database>>;
 
 pub fn new_shared() -> SharedStore {
     Arc::new(RwLock::new(Database::new()))
 }
+
+/// Normalize a Redis-style inclusive `start..=stop` range against a
+/// collection of `len` elements.
+///
+/// Negative indices count from the tail (`-1` is the last element). After
+/// conversion a negative `start` clamps to 0 and a `stop` past the end
+/// clamps to the last element. Returns `None` when the resulting range is
+/// empty: the collection is empty, `start` is past the end, or
+/// `start > stop`. Otherwise returns inclusive `(start, stop)` indices.
+pub fn normalize_range(len: usize, start: i64, stop: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start >= len || stop < 0 || start > stop {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::value::Value;
+    use super::{Database, ExpireHook, TypeError, normalize_range};
+
+    #[test]
+    fn normalize_range_table() {
+        let cases = [
+            // Full range, explicit and via -1.
+            (5, 0, 4, Some((0, 4))),
+            (5, 0, -1, Some((0, 4))),
+            // Negative start/stop.
+            (5, -2, -1, Some((3, 4))),
+            (5, -1, -1, Some((4, 4))),
+            (5, -5, -5, Some((0, 0))),
+            // Negative start beyond the head clamps to 0.
+            (5, -100, 1, Some((0, 1))),
+            (5, -100, -100, None),
+            // Stop beyond the tail clamps to the last element.
+            (5, 2, 100, Some((2, 4))),
+            // Start past the end is empty.
+            (5, 5, 10, None),
+            (5, 100, -1, None),
+            // Inverted ranges are empty.
+            (5, 3, 1, None),
+            (5, -1, -2, None),
+            // Empty collection is always empty.
+            (0, 0, -1, None),
+            (0, 0, 0, None),
+            (0, -1, -1, None),
+        ];
+        for (len, start, stop, expected) in cases {
+            assert_eq!(
+                normalize_range(len, start, stop),
+                expected,
+                "len={len} start={start} stop={stop}"
+            );
+        }
+    }
+
+    #[test]
+    fn get_string_rejects_other_types() {
+        let mut db = Database::new();
+        db.set("s".into(), Value::String(Bytes::from_static(b"v")));
+        db.rpush("l".into(), vec![Bytes::from_static(b"a")])
+            .unwrap();
+        assert_eq!(db.get_string("s"), Ok(Some(Bytes::from_static(b"v"))));
+        assert_eq!(db.get_string("l"), Err(TypeError));
+        assert_eq!(db.get_string("missing"), Ok(None));
+    }
+
+    #[test]
+    fn append_grows_in_place_unless_shared() {
+        let mut db = Database::new();
+        let mut buffers = std::collections::HashSet::new();
+        for _ in 0..10_000 {
+            db.append("s", b"x").unwrap();
+            buffers.insert(db.get_string("s").unwrap().unwrap().as_ptr());
+        }
+        // Amortized growth: a handful of reallocations, not one per append.
+        assert!(buffers.len() < 64, "{} buffers", buffers.len());
+
+        // A copy shares the buffer, so editing either one leaves the other.
+        db.copy("s", "t", false);
+        db.setrange("s", 0, b"y").unwrap();
+        db.append("t", b"z").unwrap();
+        assert_eq!(db.get_string("s").unwrap().unwrap()[..2], *b"yx");
+        let t = db.get_string("t").unwrap().unwrap();
+        assert_eq!((t[0], t.len()), (b'x', 10_001));
+    }
+
+    #[test]
+    fn collection_writes_reject_other_types() {
+        let mut db = Database::new();
+        let v = Bytes::from_static(b"v");
+        db.set("s".into(), Value::String(v.clone()));
+        assert_eq!(db.sadd("s".into(), vec![v.clone()]), Err(TypeError));
+        assert_eq!(
+            db.hset("s".into(), vec![(v.clone(), v.clone())]),
+            Err(TypeError)
+        );
+        assert_eq!(db.zadd("s".into(), vec![(v.clone(), 1.0)]), Err(TypeError));
+        assert_eq!(db.rpush("s".into(), vec![v.clone()]), Err(TypeError));
+        assert_eq!(db.lpop("s", 0), Err(TypeError));
+        assert_eq!(db.srem("s", vec![v.clone()]), Err(TypeError));
+        assert_eq!(db.zrem("s", vec![v.clone()]), Err(TypeError));
+        assert_eq!(db.get_string("s"), Ok(Some(v.clone())));
+
+        assert_eq!(db.sadd("set".into(), vec![v.clone(), v.clone()]), Ok(1));
+        assert_eq!(db.hset("set".into(), vec![(v.clone(), v)]), Err(TypeError));
+    }
+
+    #[test]
+    fn zadd_orders_equal_scores_by_member() {
+        let mut db = Database::new();
+        let members = |names: &[&'static str], score: f64| {
+            names
+                .iter()
+                .map(|m| (Bytes::from_static(m.as_bytes()), score))
+                .collect::<Vec<_>>()
+        };
+        let range = |db: &Database| -> Vec<Bytes> {
+            let range = db.zrange("z", 0, -1, false).unwrap();
+            range.into_iter().map(|(m, _)| m).collect()
+        };
+
+        db.zadd("z".into(), members(&["c", "a", "b"], 1.0)).unwrap();
+        db.zadd("z".into(), members(&["inf"], f64::INFINITY))
+            .unwrap();
+        db.zadd("z".into(), members(&["-inf"], f64::NEG_INFINITY))
+            .unwrap();
+        assert_eq!(range(&db), ["-inf", "a", "b", "c", "inf"]);
+
+        // Moving members into an existing bucket places them by name, not
+        // at the end of it.
+        db.zadd("z".into(), members(&["inf", "-inf"], 1.0)).unwrap();
+        assert_eq!(range(&db), ["-inf", "a", "b", "c", "inf"]);
+        db.zadd("z".into(), members(&["b"], 0.5)).unwrap();
+        assert_eq!(range(&db), ["b", "-inf", "a", "c", "inf"]);
+        db.zadd("z".into(), members(&["b"], 1.0)).unwrap();
+        assert_eq!(range(&db), ["-inf", "a", "b", "c", "inf"]);
+        assert_eq!(db.zrank("z", &Bytes::from_static(b"c")), Ok(Some(3)));
+    }
+
+    #[test]
+    fn verify_invariants_catches_corruption() {
+        let mut db = Database::new();
+        let v = Bytes::from_static(b"v");
+        db.rpush("l".into(), vec![v.clone()]).unwrap();
+        db.zadd("z".into(), vec![(v.clone(), 1.0)]).unwrap();
+        db.set("s".into(), Value::String(v.clone()));
+        assert_eq!(db.verify_invariants(), Ok(()));
+
+        db.set("empty".into(), Value::List(Default::default()));
+        assert!(db.verify_invariants().unwrap_err().contains("'empty'"));
+        db.del(&["empty".into()]);
+
+        db.zadd("z".into(), vec![(Bytes::from_static(b"w"), 2.0)])
+            .unwrap();
+        if let Some(Value::ZSet(members)) = db.data.get_mut("z").map(|e| &mut e.value) {
+            members.reverse();
+        }
+        assert!(db.verify_invariants().unwrap_err().contains("'z'"));
+        db.del(&["z".into()]);
+
+        db.expiry
+            .set_deadline("ghost".into(), std::time::Instant::now());
+        assert!(db.verify_invariants().unwrap_err().contains("'ghost'"));
+        db.expiry.remove("ghost");
+        assert_eq!(db.verify_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn lazy_expiry_counts_and_notifies() {
+        use std::sync::{Arc, Mutex};
+        use std::time::Instant;
+
+        let mut db = Database::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hook_seen = seen.clone();
+        db.set_expire_hook(ExpireHook::new(move |key| {
+            hook_seen.lock().unwrap().push(key.to_string());
+        }));
+        let v = Value::String(Bytes::from_static(b"v"));
+        for key in ["a", "b", "c"] {
+            db.set_with_deadline(key.into(), v.clone(), Instant::now());
+        }
+        db.set("live".into(), v);
+
+        assert_eq!(db.get_string("a"), Ok(None));
+        assert_eq!(db.exists(&["b".into(), "live".into()]), 1);
+        assert_eq!(db.ttl_millis("c"), -2);
+        assert_eq!(db.ttl_millis("live"), -1);
+        assert_eq!(db.expired_keys(), 3);
+        assert_eq!(*seen.lock().unwrap(), ["a", "b", "c"]);
+
+        // Already removed, so neither the sweep nor a flush counts them again.
+        assert_eq!(db.evict_expired(usize::MAX), 0);
+        db.flush();
+        assert_eq!(db.expired_keys(), 3);
+    }
+    #[test]
+    fn expiry_sweep_stops_at_its_limit() {
+        use std::time::{Duration, Instant};
+
+        use super::expire::active_expire_budget;
+
+        let mut db = Database::new();
+        let v = Value::String(Bytes::from_static(b"v"));
+        let now = Instant::now();
+        for i in 0..10 {
+            let deadline = now - Duration::from_millis(10 - i);
+            db.set_with_deadline(format!("k{i}"), v.clone(), deadline);
+        }
+
+        // Soonest deadline first, and no more than asked for.
+        assert_eq!(db.evict_expired(4), 4);
+        assert_eq!(db.dbsize(), 6);
+        assert!(db.peek("k3").is_none() && db.peek("k4").is_some());
+        assert_eq!(db.evict_expired(usize::MAX), 6);
+        assert_eq!(db.dbsize(), 0);
+
+        assert_eq!(active_expire_budget(1), 320);
+        assert_eq!(active_expire_budget(10), 1040);
+        assert_eq!(active_expire_budget(0), active_expire_budget(1));
+    }
+}
@@ -0,0 +1,95 @@
+//! Numbers stored as strings: integers parsed as strictly as Redis'
+//! `string2ll`, and sorted set scores.
+
+/// Why an INCR-style update failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncrError {
+    WrongType,
+    /// The stored value isn't an integer.
+    NotInteger,
+    Overflow,
+}
+
+/// Parse `s` as a decimal i64 only if it is written exactly as the number
+/// prints: an optional `-`, then digits with no leading zero. Whitespace,
+/// a `+` sign, `-0` and anything else that wouldn't round-trip are refused,
+/// so a string that parses is also one OBJECT ENCODING reports as `int`.
+pub fn parse_i64(s: &[u8]) -> Option<i64> {
+    if s == b"0" {
+        return Some(0);
+    }
+    let (negative, digits) = match s.split_first() {
+        Some((b'-', rest)) => (true, rest),
+        _ => (false, s),
+    };
+    if !matches!(digits.first(), Some(b'1'..=b'9')) || digits.len() > 19 {
+        return None;
+    }
+    // Accumulate towards the negative side so i64::MIN fits.
+    let mut n: i64 = 0;
+    for &d in digits {
+        if !d.is_ascii_digit() {
+            return None;
+        }
+        n = n.checked_mul(10)?.checked_sub(i64::from(d - b'0'))?;
+    }
+    if negative { Some(n) } else { n.checked_neg() }
+}
+
+/// Parse `s` as a sorted set score. Infinities and NaN are refused: ZADD
+/// rejects them, and AOF replay must not let them in behind its back.
+pub fn parse_score(s: &[u8]) -> Option<f64> {
+    std::str::from_utf8(s)
+        .ok()?
+        .parse::<f64>()
+        .ok()
+        .filter(|score| score.is_finite())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_i64, parse_score};
+
+    #[test]
+    fn parse_i64_is_strict() {
+        assert_eq!(parse_i64(b"10"), Some(10));
+        assert_eq!(parse_i64(b"-10"), Some(-10));
+        assert_eq!(parse_i64(b"0"), Some(0));
+        assert_eq!(parse_i64(b"9223372036854775807"), Some(i64::MAX));
+        assert_eq!(parse_i64(b"-9223372036854775808"), Some(i64::MIN));
+
+        for bad in [
+            &b""[..],
+            b"+10",
+            b" 10",
+            b"10 ",
+            b"0x10",
+            b"010",
+            b"-0",
+            b"-",
+            b"+",
+            b"1e3",
+            b"9223372036854775808",
+            b"-9223372036854775809",
+            b"99999999999999999999",
+        ] {
+            assert_eq!(parse_i64(bad), None, "{:?}", String::from_utf8_lossy(bad));
+        }
+    }
+    #[test]
+    fn parse_score_refuses_non_finite() {
+        assert_eq!(parse_score(b"1.5"), Some(1.5));
+        assert_eq!(parse_score(b"-3"), Some(-3.0));
+        for bad in [
+            &b"inf"[..],
+            b"-inf",
+            b"+inf",
+            b"nan",
+            b"NaN",
+            b"1e999",
+            b"x",
+        ] {
+            assert_eq!(parse_score(bad), None, "{:?}", String::from_utf8_lossy(bad));
+        }
+    }
+}
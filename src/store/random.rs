@@ -0,0 +1,76 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::fmt::Write;
+use std::hash::{BuildHasher, Hasher};
+
+thread_local! {
+    static STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+}
+
+/// Cheap per-thread xorshift generator. Good enough for sampling and
+/// probabilistic counters; not for anything security-sensitive.
+pub fn next_u64() -> u64 {
+    STATE.with(|s| {
+        let mut x = s.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        s.set(x);
+        x
+    })
+}
+
+/// `len` random lowercase hex digits, as used for run and replication ids.
+pub fn hex_id(len: usize) -> String {
+    let mut id = String::with_capacity(len + 16);
+    while id.len() < len {
+        let _ = write!(id, "{:016x}", next_u64());
+    }
+    id.truncate(len);
+    id
+}
+
+/// Uniform float in `[0, 1)`.
+pub fn next_f64() -> f64 {
+    (next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Uniform index in `0..n`. `n` must be non-zero.
+pub fn below(n: usize) -> usize {
+    (next_u64() % n as u64) as usize
+}
+
+/// `count` distinct elements of `items` (at most all of them) in random
+/// order, as SRANDMEMBER picks for a non-negative count.
+pub fn sample<T: Clone>(items: &[T], count: usize) -> Vec<T> {
+    let count = count.min(items.len());
+    // Shuffle indices rather than the items, so only the picked ones are
+    // cloned. Partial Fisher-Yates: after step i, order[..=i] is a uniform
+    // sample.
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    for i in 0..count {
+        let j = i + below(order.len() - i);
+        order.swap(i, j);
+    }
+    order[..count].iter().map(|&i| items[i].clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::sample;
+
+    #[test]
+    fn sample_is_distinct_and_bounded() {
+        let items: Vec<u32> = (0..10).collect();
+        for count in [0, 1, 5, 10, 11, 1000] {
+            for _ in 0..200 {
+                let picked = sample(&items, count);
+                assert_eq!(picked.len(), count.min(items.len()));
+                let distinct: HashSet<_> = picked.iter().collect();
+                assert_eq!(distinct.len(), picked.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use bytes::Bytes;
+
+use super::glob::glob_match;
+use super::{Database, TypeError};
+
+/// One page of a cursor scan over a collection.
+pub struct ScanPage<T> {
+    /// Cursor to pass back for the next page; 0 once the scan is complete.
+    pub cursor: usize,
+    pub items: Vec<T>,
+}
+
+impl<T> ScanPage<T> {
+    fn map<U>(self, f: impl FnMut(T) -> U) -> ScanPage<U> {
+        ScanPage {
+            cursor: self.cursor,
+            items: self.items.into_iter().map(f).collect(),
+        }
+    }
+}
+
+/// Take `count` elements of `items` starting at `cursor`, then keep those
+/// whose name matches `pattern`. Filtering after paging mirrors Redis: a
+/// page may come back short or empty while the cursor is still non-zero.
+///
+/// `items` must be sorted by name so that cursors stay meaningful between
+/// calls while the collection is unchanged.
+fn page<T>(
+    items: Vec<T>,
+    name: impl Fn(&T) -> &[u8],
+    cursor: usize,
+    count: usize,
+    pattern: Option<&[u8]>,
+) -> ScanPage<T> {
+    let end = cursor.saturating_add(count).min(items.len());
+    let next = if end >= items.len() { 0 } else { end };
+    let items = items
+        .into_iter()
+        .skip(cursor)
+        .take(end.saturating_sub(cursor))
+        .filter(|item| pattern.is_none_or(|p| glob_match(p, name(item))))
+        .collect();
+    ScanPage {
+        cursor: next,
+        items,
+    }
+}
+
+impl Database {
+    pub fn hscan(
+        &self,
+        key: &str,
+        cursor: usize,
+        count: usize,
+        pattern: Option<&[u8]>,
+    ) -> Result<ScanPage<(Bytes, Bytes)>, TypeError> {
+        let mut pairs: Vec<(&Bytes, &Bytes)> =
+            self.lookup_hash(key)?.into_iter().flatten().collect();
+        pairs.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        Ok(page(pairs, |(f, _)| f, cursor, count, pattern).map(|(f, v)| (f.clone(), v.clone())))
+    }
+
+    /// Members come in byte order, so an SSCAN whose COUNT covers the set
+    /// returns all of it sorted: the one deterministic way to list a set.
+    pub fn sscan(
+        &self,
+        key: &str,
+        cursor: usize,
+        count: usize,
+        pattern: Option<&[u8]>,
+    ) -> Result<ScanPage<Bytes>, TypeError> {
+        let mut members: Vec<&Bytes> = self.lookup_set(key)?.into_iter().flatten().collect();
+        members.sort_unstable();
+        Ok(page(members, |m| m, cursor, count, pattern).map(Bytes::clone))
+    }
+
+    /// Sorted sets are paged in member order rather than score order, so a
+    /// score update doesn't move a member across the cursor.
+    pub fn zscan(
+        &self,
+        key: &str,
+        cursor: usize,
+        count: usize,
+        pattern: Option<&[u8]>,
+    ) -> Result<ScanPage<(Bytes, f64)>, TypeError> {
+        let mut pairs: Vec<&(Bytes, f64)> = self.lookup_zset(key)?.into_iter().flatten().collect();
+        pairs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        Ok(page(pairs, |(m, _)| m, cursor, count, pattern).map(Clone::clone))
+    }
+}
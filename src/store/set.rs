@@ -1,49 +1,89 @@
+use std::collections::HashSet;
+
 use bytes::Bytes;
 
-use super::Database;
+use super::random;
 use super::value::Value;
+use super::{Database, TypeError};
 
 impl Database {
-    pub fn sadd(&mut self, key: String, members: Vec<Bytes>) -> usize {
-        let set = self
-            .data
-            .entry(key)
-            .or_insert_with(|| Value::Set(Default::default()));
-        if let Value::Set(hs) = set {
-            let mut added = 0;
-            for m in members {
-                if hs.insert(m) {
-                    added += 1;
-                }
-            }
-            added
-        } else {
-            0
+    /// Borrow the set at `key`, counting an access. A missing key is
+    /// `Ok(None)`.
+    pub(super) fn lookup_set(&self, key: &str) -> Result<Option<&HashSet<Bytes>>, TypeError> {
+        match self.lookup(key) {
+            Some(Value::Set(hs)) => Ok(Some(hs)),
+            Some(_) => Err(TypeError),
+            None => Ok(None),
         }
     }
 
-    pub fn srem(&mut self, key: &str, members: Vec<Bytes>) -> usize {
-        if let Some(Value::Set(hs)) = self.data.get_mut(key) {
-            let mut removed = 0;
-            for m in &members {
-                if hs.remove(m) {
-                    removed += 1;
-                }
+    /// Add `members` to the set at `key`, creating it if missing. Returns
+    /// how many were new.
+    pub fn sadd(&mut self, key: String, members: Vec<Bytes>) -> Result<usize, TypeError> {
+        let Value::Set(hs) = self.value_or_insert_with(key, || Value::Set(Default::default()))
+        else {
+            return Err(TypeError);
+        };
+        let mut added = 0;
+        for m in members {
+            if hs.insert(m) {
+                added += 1;
             }
-            if hs.is_empty() {
-                self.data.remove(key);
-            }
-            removed
-        } else {
-            0
         }
+        Ok(added)
+    }
+
+    pub fn srem(&mut self, key: &str, members: Vec<Bytes>) -> Result<usize, TypeError> {
+        let removed = match self.lookup_mut(key) {
+            Some(Value::Set(hs)) => members.iter().filter(|m| hs.remove(*m)).count(),
+            Some(_) => return Err(TypeError),
+            None => 0,
+        };
+        self.remove_if_empty(key);
+        Ok(removed)
     }
 
-    pub fn smembers(&self, key: &str) -> Vec<Bytes> {
-        if let Some(Value::Set(hs)) = self.data.get(key) {
-            hs.iter().cloned().collect()
-        } else {
-            Vec::new()
+    /// Members of the set at `key`, borrowed so a reply can be built
+    /// without copying the set first. They come in no particular order, as
+    /// in Redis; SSCAN is the ordered view (see [`Database::sscan`]).
+    pub fn smembers(&self, key: &str) -> Result<impl Iterator<Item = &Bytes>, TypeError> {
+        Ok(self.lookup_set(key)?.into_iter().flatten())
+    }
+
+    /// Up to `count` distinct random members of the set at `key`.
+    pub fn srandmember(&self, key: &str, count: usize) -> Result<Vec<Bytes>, TypeError> {
+        let members: Vec<&Bytes> = self.lookup_set(key)?.into_iter().flatten().collect();
+        Ok(random::sample(&members, count)
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    /// Size of the intersection of the sets at `keys`, counting no further
+    /// than `limit` (0 = unlimited). A missing key makes the result 0, but
+    /// every key is still type-checked.
+    pub fn sintercard(&self, keys: &[String], limit: usize) -> Result<usize, TypeError> {
+        let mut sets = Vec::with_capacity(keys.len());
+        for key in keys {
+            sets.push(self.lookup_set(key)?);
+        }
+        let Some(mut sets) = sets.into_iter().collect::<Option<Vec<_>>>() else {
+            return Ok(0);
+        };
+        sets.sort_by_key(|hs| hs.len());
+        let Some((smallest, rest)) = sets.split_first() else {
+            return Ok(0);
+        };
+
+        let mut count = 0;
+        for member in smallest.iter() {
+            if rest.iter().all(|hs| hs.contains(member)) {
+                count += 1;
+                if count == limit {
+                    break;
+                }
+            }
         }
+        Ok(count)
     }
 }
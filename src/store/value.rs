@@ -1,5 +1,6 @@
 use bytes::Bytes;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque, hash_map};
+use std::time::Instant;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
@@ -7,6 +8,138 @@ pub enum Value {
     String(Bytes),
     List(VecDeque<Bytes>),
     Set(HashSet<Bytes>),
-    Hash(HashMap<Bytes, Bytes>),
+    Hash(Hash),
     ZSet(Vec<(Bytes, f64)>), // Sorted by score
 }
+
+/// A hash's fields, some of which may carry their own expiry deadline
+/// (HEXPIRE). A field past its deadline reads as absent until the eviction
+/// sweep removes it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hash {
+    fields: HashMap<Bytes, Bytes>,
+    deadlines: HashMap<Bytes, Instant>,
+}
+
+impl Hash {
+    fn is_live(&self, field: &[u8], now: Instant) -> bool {
+        self.deadlines.get(field).is_none_or(|d| *d > now)
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        self.fields
+            .get(field)
+            .filter(|_| self.is_live(field, Instant::now()))
+    }
+
+    /// Live field/value pairs.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            fields: self.fields.iter(),
+            deadlines: &self.deadlines,
+            now: Instant::now(),
+        }
+    }
+
+    /// Number of live fields.
+    pub fn len(&self) -> usize {
+        if self.deadlines.is_empty() {
+            return self.fields.len();
+        }
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every stored pair, expired fields not yet swept included, since
+    /// those still take up memory.
+    pub fn stored(&self) -> hash_map::Iter<'_, Bytes, Bytes> {
+        self.fields.iter()
+    }
+
+    /// Set `field`, dropping any deadline it had. Returns the previous
+    /// value if the field was live.
+    pub fn insert(&mut self, field: Bytes, value: Bytes) -> Option<Bytes> {
+        let was_live = self.is_live(&field, Instant::now());
+        self.deadlines.remove(&field);
+        self.fields.insert(field, value).filter(|_| was_live)
+    }
+
+    /// Delete `field`, returning whether it was live.
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        let was_live = self.is_live(field, Instant::now());
+        self.deadlines.remove(field);
+        self.fields.remove(field).is_some() && was_live
+    }
+
+    /// Deadline of `field`, if it has one.
+    pub fn deadline(&self, field: &[u8]) -> Option<Instant> {
+        self.deadlines.get(field).copied()
+    }
+
+    /// Give the live `field` a deadline. Returns false if there is no such
+    /// field.
+    pub fn set_deadline(&mut self, field: &Bytes, deadline: Instant) -> bool {
+        if self.get(field).is_none() {
+            return false;
+        }
+        self.deadlines.insert(field.clone(), deadline);
+        true
+    }
+
+    /// Drop `field`'s deadline. Returns whether it had one.
+    pub fn persist(&mut self, field: &[u8]) -> bool {
+        self.deadlines.remove(field).is_some()
+    }
+
+    /// Soonest field deadline, if any field has one.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadlines.values().min().copied()
+    }
+
+    /// Fields with a deadline, for rewriting them to the AOF.
+    pub fn deadlines(&self) -> impl Iterator<Item = (&Bytes, Instant)> {
+        self.deadlines.iter().map(|(f, d)| (f, *d))
+    }
+
+    /// Delete fields whose deadline is at or before `now`.
+    pub fn remove_expired(&mut self, now: Instant) {
+        let fields = &mut self.fields;
+        self.deadlines.retain(|f, d| {
+            let live = *d > now;
+            if !live {
+                fields.remove(f);
+            }
+            live
+        });
+    }
+}
+
+impl<'a> IntoIterator for &'a Hash {
+    type Item = (&'a Bytes, &'a Bytes);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over a [`Hash`]'s live fields.
+pub struct Iter<'a> {
+    fields: hash_map::Iter<'a, Bytes, Bytes>,
+    deadlines: &'a HashMap<Bytes, Instant>,
+    now: Instant,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a Bytes, &'a Bytes);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (deadlines, now) = (self.deadlines, self.now);
+        self.fields
+            .by_ref()
+            .find(|(f, _)| deadlines.get(*f).is_none_or(|d| *d > now))
+    }
+}
@@ -1,90 +1,131 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
 use bytes::Bytes;
 
-use super::Database;
 use super::value::Value;
+use super::{Database, TypeError, normalize_range, random};
+
+/// Sorted set order: by score, then by member bytes for equal scores as in
+/// Redis, so the order never depends on insertion history.
+pub fn member_order(a: &(Bytes, f64), b: &(Bytes, f64)) -> Ordering {
+    a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0))
+}
 
 impl Database {
-    pub fn zadd(&mut self, key: String, members: Vec<(Bytes, f64)>) -> usize {
-        let zset = self
-            .data
-            .entry(key)
-            .or_insert_with(|| Value::ZSet(Default::default()));
-        if let Value::ZSet(vec) = zset {
-            let mut added = 0;
-            for (m, s) in members {
-                if let Some(pos) = vec.iter().position(|(mb, _)| mb == &m) {
-                    vec[pos] = (m, s); // update score
-                } else {
-                    vec.push((m, s));
-                    added += 1;
-                }
+    /// Borrow the sorted set at `key`, counting an access. A missing key is
+    /// `Ok(None)`.
+    pub(super) fn lookup_zset(&self, key: &str) -> Result<Option<&Vec<(Bytes, f64)>>, TypeError> {
+        match self.lookup(key) {
+            Some(Value::ZSet(vec)) => Ok(Some(vec)),
+            Some(_) => Err(TypeError),
+            None => Ok(None),
+        }
+    }
+
+    /// Add `members` to the sorted set at `key`, creating it if missing and
+    /// updating the score of members already present. Returns how many were
+    /// new.
+    pub fn zadd(&mut self, key: String, members: Vec<(Bytes, f64)>) -> Result<usize, TypeError> {
+        let Value::ZSet(vec) = self.value_or_insert_with(key, || Value::ZSet(Default::default()))
+        else {
+            return Err(TypeError);
+        };
+        let mut added = 0;
+        for (m, s) in members {
+            if let Some(pos) = vec.iter().position(|(mb, _)| mb == &m) {
+                vec[pos] = (m, s); // update score
+            } else {
+                vec.push((m, s));
+                added += 1;
             }
-            vec.sort_by(|a, b| {
-                let ord = a.1.partial_cmp(&b.1).unwrap();
-                if ord == std::cmp::Ordering::Equal {
-                    a.0.as_ref().cmp(b.0.as_ref())
-                } else {
-                    ord
-                }
-            });
-            added
-        } else {
-            0
         }
+        vec.sort_by(member_order);
+        Ok(added)
     }
 
-    pub fn zscore(&self, key: &str, member: &Bytes) -> Option<f64> {
-        if let Some(Value::ZSet(vec)) = self.data.get(key) {
+    pub fn zscore(&self, key: &str, member: &Bytes) -> Result<Option<f64>, TypeError> {
+        Ok(self.lookup_zset(key)?.and_then(|vec| {
             vec.iter()
                 .find(|(m, _)| m == member)
                 .map(|(_, score)| *score)
-        } else {
-            None
-        }
+        }))
     }
 
-    pub fn zrank(&self, key: &str, member: &Bytes) -> Option<usize> {
-        if let Some(Value::ZSet(vec)) = self.data.get(key) {
-            vec.iter().position(|(m, _)| m == member)
-        } else {
-            None
-        }
+    /// Up to `count` distinct random member/score pairs of the sorted set at
+    /// `key`.
+    pub fn zrandmember(&self, key: &str, count: usize) -> Result<Vec<(Bytes, f64)>, TypeError> {
+        Ok(self
+            .lookup_zset(key)?
+            .map_or_else(Vec::new, |vec| random::sample(vec, count)))
     }
 
-    pub fn zcard(&self, key: &str) -> usize {
-        if let Some(Value::ZSet(vec)) = self.data.get(key) {
-            vec.len()
-        } else {
-            0
-        }
+    pub fn zrank(&self, key: &str, member: &Bytes) -> Result<Option<usize>, TypeError> {
+        Ok(self
+            .lookup_zset(key)?
+            .and_then(|vec| vec.iter().position(|(m, _)| m == member)))
     }
 
-    pub fn zrem(&mut self, key: &str, members: Vec<Bytes>) -> usize {
-        if let Some(Value::ZSet(vec)) = self.data.get_mut(key) {
-            let mut removed = 0;
-            for m in &members {
-                if let Some(pos) = vec.iter().position(|(mb, _)| mb == m) {
-                    vec.remove(pos);
-                    removed += 1;
-                }
-            }
-            if vec.is_empty() {
-                self.data.remove(key);
+    pub fn zcard(&self, key: &str) -> Result<usize, TypeError> {
+        Ok(self.lookup_zset(key)?.map_or(0, Vec::len))
+    }
+
+    pub fn zrem(&mut self, key: &str, members: Vec<Bytes>) -> Result<usize, TypeError> {
+        let Some(value) = self.lookup_mut(key) else {
+            return Ok(0);
+        };
+        let Value::ZSet(vec) = value else {
+            return Err(TypeError);
+        };
+        let mut removed = 0;
+        for m in &members {
+            if let Some(pos) = vec.iter().position(|(mb, _)| mb == m) {
+                vec.remove(pos);
+                removed += 1;
             }
-            removed
-        } else {
-            0
         }
+        self.remove_if_empty(key);
+        Ok(removed)
     }
 
-    pub fn zcount(&self, key: &str, min: f64, max: f64) -> usize {
-        if let Some(Value::ZSet(vec)) = self.data.get(key) {
+    pub fn zcount(&self, key: &str, min: f64, max: f64) -> Result<usize, TypeError> {
+        Ok(self.lookup_zset(key)?.map_or(0, |vec| {
             vec.iter()
                 .filter(|(_, score)| *score >= min && *score <= max)
                 .count()
-        } else {
-            0
+        }))
+    }
+
+    /// Size of the intersection (by member) of the sorted sets at `keys`,
+    /// counting no further than `limit` (0 = unlimited). As with
+    /// [`Database::sintercard`], a missing key makes the result 0.
+    pub fn zintercard(&self, keys: &[String], limit: usize) -> Result<usize, TypeError> {
+        let mut zsets = Vec::with_capacity(keys.len());
+        for key in keys {
+            zsets.push(self.lookup_zset(key)?);
         }
+        let Some(mut zsets) = zsets.into_iter().collect::<Option<Vec<_>>>() else {
+            return Ok(0);
+        };
+        zsets.sort_by_key(|vec| vec.len());
+        let Some((smallest, rest)) = zsets.split_first() else {
+            return Ok(0);
+        };
+        let rest: Vec<HashSet<&Bytes>> = rest
+            .iter()
+            .map(|vec| vec.iter().map(|(m, _)| m).collect())
+            .collect();
+
+        let mut count = 0;
+        for (member, _) in smallest.iter() {
+            if rest.iter().all(|members| members.contains(member)) {
+                count += 1;
+                if count == limit {
+                    break;
+                }
+            }
+        }
+        Ok(count)
     }
 
     pub fn zrange(
@@ -93,39 +134,25 @@ impl Database {
         start: i64,
         stop: i64,
         with_scores: bool,
-    ) -> Vec<(Bytes, Option<f64>)> {
-        if let Some(Value::ZSet(vec)) = self.data.get(key) {
-            let len = vec.len() as i64;
-            if len == 0 {
-                return Vec::new();
-            }
-            let s = if start < 0 {
-                (len + start).max(0)
-            } else {
-                start.min(len)
-            } as usize;
-            let e = if stop < 0 {
-                (len + stop).max(-1) + 1
-            } else {
-                (stop + 1).min(len)
-            } as usize;
-            if s >= e {
-                return Vec::new();
-            }
-            vec.iter()
-                .skip(s)
-                .take(e - s)
-                .map(|(m, score)| {
-                    if with_scores {
-                        (m.clone(), Some(*score))
-                    } else {
-                        (m.clone(), None)
-                    }
-                })
-                .collect()
-        } else {
-            Vec::new()
-        }
+    ) -> Result<Vec<(Bytes, Option<f64>)>, TypeError> {
+        let Some(vec) = self.lookup_zset(key)? else {
+            return Ok(Vec::new());
+        };
+        let Some((s, e)) = normalize_range(vec.len(), start, stop) else {
+            return Ok(Vec::new());
+        };
+        Ok(vec
+            .iter()
+            .skip(s)
+            .take(e - s + 1)
+            .map(|(m, score)| {
+                if with_scores {
+                    (m.clone(), Some(*score))
+                } else {
+                    (m.clone(), None)
+                }
+            })
+            .collect())
     }
 
     pub fn zrevrange(
@@ -134,39 +161,25 @@ impl Database {
         start: i64,
         stop: i64,
         with_scores: bool,
-    ) -> Vec<(Bytes, Option<f64>)> {
-        if let Some(Value::ZSet(vec)) = self.data.get(key) {
-            let len = vec.len() as i64;
-            if len == 0 {
-                return Vec::new();
-            }
-            let s = if start < 0 {
-                (len + start).max(0)
-            } else {
-                start.min(len)
-            } as usize;
-            let e = if stop < 0 {
-                (len + stop).max(-1) + 1
-            } else {
-                (stop + 1).min(len)
-            } as usize;
-            if s >= e {
-                return Vec::new();
-            }
-            vec.iter()
-                .rev()
-                .skip(s)
-                .take(e - s)
-                .map(|(m, score)| {
-                    if with_scores {
-                        (m.clone(), Some(*score))
-                    } else {
-                        (m.clone(), None)
-                    }
-                })
-                .collect()
-        } else {
-            Vec::new()
-        }
+    ) -> Result<Vec<(Bytes, Option<f64>)>, TypeError> {
+        let Some(vec) = self.lookup_zset(key)? else {
+            return Ok(Vec::new());
+        };
+        let Some((s, e)) = normalize_range(vec.len(), start, stop) else {
+            return Ok(Vec::new());
+        };
+        Ok(vec
+            .iter()
+            .rev()
+            .skip(s)
+            .take(e - s + 1)
+            .map(|(m, score)| {
+                if with_scores {
+                    (m.clone(), Some(*score))
+                } else {
+                    (m.clone(), None)
+                }
+            })
+            .collect())
     }
 }
@@ -5,8 +5,14 @@ use std::time::Duration;
 
 /// Spawn the server on a given port. Returns the child process handle.
 fn spawn_server(port: u16) -> Child {
+    spawn_server_with_args(port, &[])
+}
+
+/// Spawn the server on a given port with extra CLI flags.
+fn spawn_server_with_args(port: u16, extra: &[&str]) -> Child {
     let child = Command::new(env!("CARGO_BIN_EXE_rfs-rs"))
         .args(["--bind", &format!("127.0.0.1:{port}")])
+        .args(extra)
         .spawn()
         .expect("failed to start rfs-rs");
 
@@ -73,6 +79,10 @@ fn test_set_get_del() {
     let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "foo"]));
     assert_eq!(resp, ":1\r\n");
 
+    // Each occurrence of a key counts.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "foo", "foo"]));
+    assert_eq!(resp, ":2\r\n");
+
     let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEL", "foo"]));
     assert_eq!(resp, ":1\r\n");
 
@@ -84,6 +94,42 @@ fn test_set_get_del() {
     server.wait().ok();
 }
 
+#[test]
+fn test_empty_strings_are_values() {
+    let port = 16477;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    // An empty value is stored and returned, never confused with a nil.
+    for (cmd, expected) in [
+        (&["SET", "k", ""][..], "+OK\r\n"),
+        (&["GET", "k"], "$0\r\n\r\n"),
+        (&["STRLEN", "k"], ":0\r\n"),
+        (&["EXISTS", "k"], ":1\r\n"),
+        (&["STRLEN", "missing"], ":0\r\n"),
+        (&["SET", "k", "", "GET"], "$0\r\n\r\n"),
+        (&["SET", "k", "x", "NX"], "$-1\r\n"),
+        // An empty key and an empty hash field are names like any other.
+        (&["SET", "", "v"], "+OK\r\n"),
+        (&["GET", ""], "$1\r\nv\r\n"),
+        (&["STRLEN", ""], ":1\r\n"),
+        (&["HSET", "h", "", ""], ":1\r\n"),
+        (&["HGET", "h", ""], "$0\r\n\r\n"),
+        (&["HGET", "h", "other"], "$-1\r\n"),
+        (&["HGETALL", "h"], "*2\r\n$0\r\n\r\n$0\r\n\r\n"),
+    ] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(cmd));
+        assert_eq!(resp, expected, "{cmd:?}");
+    }
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
 #[test]
 fn test_echo() {
     let port = 16381;
@@ -155,6 +201,19 @@ fn test_set_commands() {
     let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SMEMBERS", "myset"]));
     assert!(resp.starts_with("*2\r\n"));
 
+    // SSCAN pages in member order, so one page covering the set lists it
+    // deterministically.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "myset", "z", "0", "m"]));
+    assert_eq!(resp, ":3\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["SSCAN", "myset", "0", "COUNT", "100"]),
+    );
+    assert_eq!(
+        resp,
+        "*2\r\n$1\r\n0\r\n*5\r\n$1\r\n0\r\n$1\r\nb\r\n$1\r\nc\r\n$1\r\nm\r\n$1\r\nz\r\n"
+    );
+
     drop(stream);
     server.kill().ok();
     server.wait().ok();
@@ -214,6 +273,15 @@ fn test_expiry() {
     let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "temp"]));
     assert_eq!(resp, "$-1\r\n");
 
+    // Expired keys aren't counted as deleted, even before they're evicted.
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "a", "v", "PX", "100"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "b", "v", "PX", "100"]));
+    std::thread::sleep(Duration::from_millis(200));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEL", "a"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["UNLINK", "b"]));
+    assert_eq!(resp, ":0\r\n");
+
     drop(stream);
     server.kill().ok();
     server.wait().ok();
@@ -403,3 +471,4805 @@ fn test_zrevrange() {
     server.kill().ok();
     server.wait().ok();
 }
+
+#[test]
+fn test_unlink() {
+    let port = 16389;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "a", "1"]));
+
+    // A list large enough to be freed on the background task.
+    let mut push = vec!["RPUSH", "big"];
+    let items: Vec<String> = (0..200).map(|i| i.to_string()).collect();
+    push.extend(items.iter().map(|s| s.as_str()));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&push));
+    assert_eq!(resp, ":200\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["UNLINK", "a", "big", "missing"]));
+    assert_eq!(resp, ":2\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "a", "big"]));
+    assert_eq!(resp, ":0\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_touch() {
+    let port = 16390;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "a", "1"]));
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "b", "x"]));
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "gone", "v", "PX", "100"]));
+    std::thread::sleep(Duration::from_millis(200));
+
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["TOUCH", "a", "b", "gone", "missing"]),
+    );
+    assert_eq!(resp, ":2\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_output_buffer_hard_limit() {
+    let port = 16391;
+    let mut server = spawn_server_with_args(port, &["--client-output-buffer-hard-limit", "1024"]);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let value = "x".repeat(64);
+    let mut push = vec!["RPUSH", "big"];
+    push.extend(std::iter::repeat_n(value.as_str(), 100));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&push));
+    assert_eq!(resp, ":100\r\n");
+
+    // Small replies are unaffected.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LLEN", "big"]));
+    assert_eq!(resp, ":100\r\n");
+
+    // A ~7KB reply blows the 1KB hard limit: the server hangs up instead.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LRANGE", "big", "0", "-1"]));
+    assert_eq!(resp, "");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_slow_subscriber_is_disconnected_at_hard_limit() {
+    let port = 16496;
+    let mut server = spawn_server_with_args(port, &["--client-output-buffer-hard-limit", "256kb"]);
+
+    let mut sub = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    sub.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut publisher = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    publisher
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut sub, &resp_cmd(&["SUBSCRIBE", "news"]));
+    assert_eq!(resp, "*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+
+    // The subscriber stops reading. Once the socket backs up, messages
+    // queue on the server until they pass the hard limit, and then it
+    // drops the subscriber rather than queueing any more.
+    let message = "x".repeat(8 * 1024);
+    let publish = resp_cmd(&["PUBLISH", "news", &message]);
+    let mut buf = [0u8; 64];
+    let dropped = (0..4000).any(|_| {
+        publisher.write_all(&publish).unwrap();
+        let n = publisher.read(&mut buf).unwrap();
+        &buf[..n] == b":0\r\n"
+    });
+    assert!(dropped, "the subscriber was never dropped");
+
+    // What was already written still arrives, then the connection closes.
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        match sub.read(&mut buf) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(err) => panic!("subscriber was not disconnected: {err}"),
+        }
+    }
+
+    drop(sub);
+    drop(publisher);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_subscriber_mode_restricts_commands() {
+    let port = 16392;
+    let mut server = spawn_server(port);
+
+    let mut sub = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    sub.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut publisher = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    publisher
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut sub, &resp_cmd(&["SUBSCRIBE", "news"]));
+    assert_eq!(resp, "*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+
+    let resp = resp_roundtrip(&mut sub, &resp_cmd(&["GET", "foo"]));
+    assert_eq!(
+        resp,
+        "-ERR Can't execute 'get': only (S)SUBSCRIBE / (S)UNSUBSCRIBE / PING / RESET are allowed in this context\r\n"
+    );
+
+    let resp = resp_roundtrip(&mut publisher, &resp_cmd(&["PUBLISH", "news", "hi"]));
+    assert_eq!(resp, ":1\r\n");
+    let mut buf = vec![0u8; 4096];
+    let n = sub.read(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&buf[..n]),
+        "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n"
+    );
+
+    // Leaving the last channel lifts the restriction.
+    let resp = resp_roundtrip(&mut sub, &resp_cmd(&["UNSUBSCRIBE", "news"]));
+    assert_eq!(resp, "*3\r\n$11\r\nunsubscribe\r\n$4\r\nnews\r\n:0\r\n");
+
+    let resp = resp_roundtrip(&mut sub, &resp_cmd(&["GET", "foo"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    drop(sub);
+    drop(publisher);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_multi_exec() {
+    let port = 16393;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["MULTI"]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+    assert_eq!(resp, "+QUEUED\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "+QUEUED\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXEC"]));
+    assert_eq!(resp, "*2\r\n+OK\r\n$1\r\nv\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DISCARD"]));
+    assert_eq!(resp, "-ERR DISCARD without MULTI\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_reset() {
+    let port = 16394;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["MULTI"]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+    assert_eq!(resp, "+QUEUED\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["RESET"]));
+    assert_eq!(resp, "+RESET\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXEC"]));
+    assert_eq!(resp, "-ERR EXEC without MULTI\r\n");
+    // The queued SET never ran.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    // RESET also leaves subscriber mode.
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SUBSCRIBE", "c"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["RESET"]));
+    assert_eq!(resp, "+RESET\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_intercard() {
+    let port = 16395;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "s1", "a", "b", "c", "d"]));
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "s2", "b", "c", "d", "e"]));
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SINTERCARD", "2", "s1", "s2"]));
+    assert_eq!(resp, ":3\r\n");
+
+    // LIMIT below the true size stops counting early.
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["SINTERCARD", "2", "s1", "s2", "LIMIT", "2"]),
+    );
+    assert_eq!(resp, ":2\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SINTERCARD", "2", "s1", "nope"]));
+    assert_eq!(resp, ":0\r\n");
+
+    let _ = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ZADD", "z1", "1", "a", "2", "b", "3", "c"]),
+    );
+    let _ = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ZADD", "z2", "9", "b", "8", "c", "7", "x"]),
+    );
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["ZINTERCARD", "2", "z1", "z2"]));
+    assert_eq!(resp, ":2\r\n");
+
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ZINTERCARD", "2", "z1", "z2", "LIMIT", "1"]),
+    );
+    assert_eq!(resp, ":1\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SINTERCARD", "2", "s1", "z1"]));
+    assert_eq!(
+        resp,
+        "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+    );
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SINTERCARD", "3", "s1", "s2"]));
+    assert_eq!(
+        resp,
+        "-ERR Number of keys can't be greater than number of args\r\n"
+    );
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_lpos() {
+    let port = 16396;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["RPUSH", "l", "a", "b", "c", "1", "2", "3", "c", "c"]),
+    );
+    assert_eq!(resp, ":8\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LPOS", "l", "c"]));
+    assert_eq!(resp, ":2\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LPOS", "l", "c", "RANK", "2"]));
+    assert_eq!(resp, ":6\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LPOS", "l", "c", "RANK", "-1"]));
+    assert_eq!(resp, ":7\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LPOS", "l", "c", "COUNT", "2"]));
+    assert_eq!(resp, "*2\r\n:2\r\n:6\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LPOS", "l", "c", "COUNT", "0"]));
+    assert_eq!(resp, "*3\r\n:2\r\n:6\r\n:7\r\n");
+
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["LPOS", "l", "c", "RANK", "-1", "COUNT", "2"]),
+    );
+    assert_eq!(resp, "*2\r\n:7\r\n:6\r\n");
+
+    // MAXLEN bounds the scan to the first two elements.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LPOS", "l", "c", "MAXLEN", "2"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LPOS", "l", "zzz"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LPOS", "l", "zzz", "COUNT", "1"]));
+    assert_eq!(resp, "*0\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LPOS", "l", "c", "RANK", "0"]));
+    assert!(resp.starts_with("-ERR RANK can't be zero"));
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_getex() {
+    let port = 16397;
+    let path = std::env::temp_dir().join(format!("rfs-getex-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let args = [
+        "--aof-path",
+        path.to_str().unwrap(),
+        "--aof-fsync",
+        "always",
+    ];
+    let mut server = spawn_server_with_args(port, &args);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+
+    // No option: plain GET, TTL untouched.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETEX", "k"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "k"]));
+    assert_eq!(resp, ":-1\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETEX", "k", "EX", "100"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "k"]));
+    assert!(
+        resp == ":100\r\n" || resp == ":99\r\n",
+        "unexpected TTL {resp}"
+    );
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETEX", "k", "PERSIST"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "k"]));
+    assert_eq!(resp, ":-1\r\n");
+
+    // PXAT in the past deletes the key immediately.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETEX", "k", "PXAT", "1000"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "$-1\r\n");
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "gone", "v"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETEX", "gone", "EXAT", "1"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETEX", "missing", "EX", "10"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "list", "a"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETEX", "list", "EX", "10"]));
+    assert_eq!(
+        resp,
+        "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "list"]));
+    assert_eq!(resp, ":-1\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETEX", "k", "EX", "0"]));
+    assert_eq!(resp, "-ERR invalid expire time in 'getex'\r\n");
+    // Too far off to represent: an error, not a dropped connection.
+    for (opt, n) in [
+        ("EX", "18446744073709551615"),
+        ("EX", "9223372036854775807"),
+        ("EXAT", "9223372036854775807"),
+    ] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETEX", "k", opt, n]));
+        assert_eq!(resp, "-ERR invalid expire time in 'getex'\r\n", "{opt} {n}");
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    // The key with a passed deadline is logged as deleted, not expiring.
+    let logged = String::from_utf8_lossy(&std::fs::read(&path).unwrap()).into_owned();
+    let _ = std::fs::remove_file(&path);
+    assert!(logged.contains("$3\r\nDEL\r\n$4\r\ngone\r\n"), "{logged}");
+    assert!(!logged.contains("PEXPIREAT\r\n$4\r\ngone"), "{logged}");
+}
+
+#[test]
+fn test_bit_operations() {
+    let port = 16398;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    // Setting bit 7 creates a one-byte string 0x01.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SETBIT", "b", "7", "1"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "b"]));
+    assert_eq!(resp, "$1\r\n\x01\r\n");
+
+    // Growing well past the end pads with NUL bytes.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SETBIT", "b", "100", "1"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETBIT", "b", "100"]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETBIT", "b", "99"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETBIT", "b", "10000"]));
+    assert_eq!(resp, ":0\r\n");
+
+    // Flipping a bit back returns its old value.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SETBIT", "b", "100", "0"]));
+    assert_eq!(resp, ":1\r\n");
+
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "s", "foobar"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITCOUNT", "s"]));
+    assert_eq!(resp, ":26\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITCOUNT", "s", "0", "0"]));
+    assert_eq!(resp, ":4\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITCOUNT", "s", "1", "1"]));
+    assert_eq!(resp, ":6\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITCOUNT", "s", "5", "30", "BIT"]));
+    assert_eq!(resp, ":17\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITCOUNT", "missing"]));
+    assert_eq!(resp, ":0\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SETBIT", "s", "-1", "1"]));
+    assert_eq!(
+        resp,
+        "-ERR bit offset is not an integer or out of range\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SETBIT", "s", "1", "2"]));
+    assert_eq!(resp, "-ERR bit is not an integer or out of range\r\n");
+
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "l", "a"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SETBIT", "l", "0", "1"]));
+    assert_eq!(
+        resp,
+        "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+    );
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_aof_replays_binary_values() {
+    let port = 16399;
+    let path = std::env::temp_dir().join(format!("rfs-binary-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let aof = path.to_str().unwrap();
+    let args = ["--aof-path", aof, "--aof-fsync", "always"];
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    // 0x80 followed by 0x0A: not UTF-8, and contains a newline.
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SETBIT", "b", "0", "1"]));
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SETBIT", "b", "12", "1"]));
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SETBIT", "b", "14", "1"]));
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SETRANGE", "r", "2", "\n\r"]));
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "b", "1000"]));
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SETBIT", "b", "14", "1"]));
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    // SETBIT is logged as the bit flip, not the whole resulting string.
+    let logged = std::fs::read(&path).unwrap();
+    assert!(
+        !logged.windows(9).any(|w| w == b"$3\r\nSET\r\n"),
+        "{}",
+        String::from_utf8_lossy(&logged)
+    );
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    for (offset, bit) in [("0", ":1"), ("1", ":0"), ("12", ":1"), ("14", ":1")] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETBIT", "b", offset]));
+        assert_eq!(resp, format!("{bit}\r\n"), "offset {offset}");
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITCOUNT", "b"]));
+    assert_eq!(resp, ":3\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "b"]));
+    let ttl: i64 = resp.trim_start_matches(':').trim_end().parse().unwrap();
+    assert!(ttl > 900, "got {resp}");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "r"]));
+    assert_eq!(resp, "$4\r\n\0\0\n\r\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_aof_replay_skips_non_finite_zadd_scores() {
+    let port = 16474;
+    let path = std::env::temp_dir().join(format!("rfs-zadd-nan-{}.aof", std::process::id()));
+    let mut aof = resp_cmd(&["ZADD", "k", "1", "a"]);
+    aof.extend(resp_cmd(&["ZADD", "k", "inf", "m"]));
+    aof.extend(resp_cmd(&["ZADD", "k", "nan", "m2"]));
+    aof.extend(resp_cmd(&["ZADD", "k", "2", "b", "-inf", "m3"]));
+    aof.extend(resp_cmd(&["ZADD", "k", "3", "c"]));
+    std::fs::write(&path, aof).unwrap();
+
+    let mut server = spawn_server_with_args(port, &["--aof-path", path.to_str().unwrap()]);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ZRANGE", "k", "0", "-1", "WITHSCORES"]),
+    );
+    assert_eq!(resp, "*4\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nc\r\n$1\r\n3\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_aof_replay_survives_huge_set_ttl() {
+    let port = 16488;
+    let path = std::env::temp_dir().join(format!("rfs-set-ttl-{}.aof", std::process::id()));
+    let mut aof = resp_cmd(&["SET", "far", "v", "EX", "18446744073709551615"]);
+    aof.extend(resp_cmd(&["SET", "near", "v", "EX", "100"]));
+    std::fs::write(&path, aof).unwrap();
+
+    let mut server = spawn_server_with_args(port, &["--aof-path", path.to_str().unwrap()]);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    // A deadline past what can be represented is as good as none.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "far"]));
+    assert_eq!(resp, ":-1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "near"]));
+    assert!(
+        resp == ":100\r\n" || resp == ":99\r\n",
+        "unexpected TTL {resp}"
+    );
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_shutdown_nosave_exits() {
+    let port = 16475;
+    let dir = std::env::temp_dir();
+    let log = dir.join(format!("rfs-shutdown-{}.log", std::process::id()));
+    let sock = dir.join(format!("rfs-shutdown-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    let args = [
+        "--logfile",
+        log.to_str().unwrap(),
+        "--unixsocket",
+        sock.to_str().unwrap(),
+    ];
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SHUTDOWN", "BOGUS"]));
+    assert_eq!(resp, "-ERR syntax error\r\n");
+
+    // No reply: the connection just closes as the process exits.
+    stream
+        .write_all(&resp_cmd(&["SHUTDOWN", "NOSAVE"]))
+        .unwrap();
+    let mut buf = [0u8; 64];
+    assert_eq!(stream.read(&mut buf).unwrap_or(0), 0);
+    let status = server.wait().unwrap();
+    assert!(status.success(), "{status:?}");
+
+    // The last log lines still reach the file, and the socket is cleaned up.
+    let logged = std::fs::read_to_string(&log).unwrap();
+    assert!(logged.contains("ready to exit"), "{logged}");
+    assert!(!sock.exists());
+    let _ = std::fs::remove_file(&log);
+}
+
+#[test]
+fn test_shutdown_rewrites_aof() {
+    let port = 16476;
+    let path = std::env::temp_dir().join(format!("rfs-shutdown-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let args = ["--aof-path", path.to_str().unwrap(), "--aof-fsync", "no"];
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    for value in ["1", "2", "3"] {
+        resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", value]));
+    }
+    stream.write_all(&resp_cmd(&["SHUTDOWN"])).unwrap();
+    let status = server.wait().unwrap();
+    assert!(status.success(), "{status:?}");
+
+    // The rewrite leaves only the final state behind.
+    let aof = std::fs::read(&path).unwrap();
+    assert_eq!(aof, resp_cmd(&["SET", "k", "3"]));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_waitaof() {
+    let port = 16478;
+    let path = std::env::temp_dir().join(format!("rfs-waitaof-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // Without an AOF there is nothing to wait for locally.
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["WAITAOF", "1", "0", "0"]));
+    assert_eq!(
+        resp,
+        "-ERR WAITAOF cannot be used when numlocal is set but appendonly is disabled\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["WAITAOF", "0", "0", "0"]));
+    assert_eq!(resp, "*2\r\n:0\r\n:0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["WAITAOF", "0", "0", "-1"]));
+    assert_eq!(resp, "-ERR timeout is negative\r\n");
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    // With fsync left to the OS, WAITAOF is what gets the write to disk.
+    let args = ["--aof-path", path.to_str().unwrap(), "--aof-fsync", "no"];
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["WAITAOF", "1", "0", "0"]));
+    assert_eq!(resp, "*2\r\n:1\r\n:0\r\n");
+    assert_eq!(std::fs::read(&path).unwrap(), resp_cmd(&["SET", "k", "v"]));
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_client_kill() {
+    let port = 16479;
+    let mut server = spawn_server(port);
+    let connect = || {
+        let stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        stream
+    };
+    let mut killer = connect();
+    let mut victim = connect();
+    let mut by_addr = connect();
+
+    let resp = resp_roundtrip(&mut victim, &resp_cmd(&["CLIENT", "ID"]));
+    let id = resp.trim_start_matches(':').trim_end().to_string();
+    let resp = resp_roundtrip(&mut killer, &resp_cmd(&["CLIENT", "KILL", "ID", &id]));
+    assert_eq!(resp, ":1\r\n");
+    let mut buf = [0u8; 64];
+    assert_eq!(victim.read(&mut buf).unwrap_or(0), 0);
+    // Already gone, so there's nothing left to kill.
+    let resp = resp_roundtrip(&mut killer, &resp_cmd(&["CLIENT", "KILL", "ID", &id]));
+    assert_eq!(resp, ":0\r\n");
+
+    let addr = by_addr.local_addr().unwrap().to_string();
+    let resp = resp_roundtrip(&mut killer, &resp_cmd(&["CLIENT", "KILL", &addr]));
+    assert_eq!(resp, "+OK\r\n");
+    assert_eq!(by_addr.read(&mut buf).unwrap_or(0), 0);
+    let resp = resp_roundtrip(&mut killer, &resp_cmd(&["CLIENT", "KILL", &addr]));
+    assert_eq!(resp, "-ERR No such client\r\n");
+
+    // The caller is spared unless it asks otherwise.
+    let resp = resp_roundtrip(&mut killer, &resp_cmd(&["CLIENT", "ID"]));
+    let id = resp.trim_start_matches(':').trim_end().to_string();
+    let resp = resp_roundtrip(&mut killer, &resp_cmd(&["CLIENT", "KILL", "ID", &id]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(
+        &mut killer,
+        &resp_cmd(&["CLIENT", "KILL", "ID", &id, "SKIPME", "no"]),
+    );
+    assert_eq!(resp, ":1\r\n");
+    assert_eq!(killer.read(&mut buf).unwrap_or(0), 0);
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+/// Build a RESP array command from binary args.
+fn resp_cmd_bytes(args: &[&[u8]]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+    out
+}
+
+#[test]
+fn test_aof_replay_skips_restore_with_huge_ttl() {
+    let port = 16489;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "s", "v"]));
+    stream.write_all(&resp_cmd(&["DUMP", "s"])).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    let mut reply = vec![0u8; 4096];
+    let n = stream.read(&mut reply).unwrap();
+    let header_end = reply.windows(2).position(|w| w == b"\r\n").unwrap();
+    let payload = reply[header_end + 2..n - 2].to_vec();
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    let path = std::env::temp_dir().join(format!("rfs-restore-ttl-{}.aof", std::process::id()));
+    let mut aof = resp_cmd_bytes(&[
+        b"RESTORE",
+        b"far",
+        b"18446744073709551615",
+        &payload,
+        b"REPLACE",
+    ]);
+    aof.extend(resp_cmd_bytes(&[
+        b"RESTORE", b"near", b"0", &payload, b"REPLACE",
+    ]));
+    std::fs::write(&path, aof).unwrap();
+
+    let mut server = spawn_server_with_args(port, &["--aof-path", path.to_str().unwrap()]);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    // Past any TTL RESTORE accepts, so skipped rather than loaded.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "far"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "near"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_dump_restore() {
+    let port = 16481;
+    let path = std::env::temp_dir().join(format!("rfs-restore-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let args = [
+        "--aof-path",
+        path.to_str().unwrap(),
+        "--aof-fsync",
+        "always",
+    ];
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DUMP", "missing"]));
+    assert_eq!(resp, "$-1\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "l", "a", "", "c"]));
+    stream.write_all(&resp_cmd(&["DUMP", "l"])).unwrap();
+    let Reply::Bulk(Some(payload)) = read_reply(&mut reader) else {
+        panic!("DUMP should reply with a bulk string");
+    };
+
+    let restore = |key: &str, ttl: &str, payload: &[u8], opts: &[&str]| {
+        let mut args: Vec<&[u8]> = vec![b"RESTORE", key.as_bytes(), ttl.as_bytes(), payload];
+        args.extend(opts.iter().map(|o| o.as_bytes()));
+        resp_cmd_bytes(&args)
+    };
+    let resp = resp_roundtrip(&mut stream, &restore("copy", "0", &payload, &[]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LRANGE", "copy", "0", "-1"]));
+    assert_eq!(resp, "*3\r\n$1\r\na\r\n$0\r\n\r\n$1\r\nc\r\n");
+    let resp = resp_roundtrip(&mut stream, &restore("copy", "0", &payload, &[]));
+    assert_eq!(resp, "-BUSYKEY Target key name already exists.\r\n");
+    let resp = resp_roundtrip(&mut stream, &restore("copy", "0", &payload, &["REPLACE"]));
+    assert_eq!(resp, "+OK\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &restore("volatile", "100000", &payload, &[]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PTTL", "volatile"]));
+    let pttl: i64 = resp.trim_start_matches(':').trim_end().parse().unwrap();
+    assert!(pttl > 90_000 && pttl <= 100_000, "{pttl}");
+    // An absolute TTL in the past restores nothing.
+    let resp = resp_roundtrip(&mut stream, &restore("gone", "1", &payload, &["ABSTTL"]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "gone"]));
+    assert_eq!(resp, ":0\r\n");
+
+    let mut corrupt = payload.clone();
+    corrupt[2] ^= 0xff;
+    let resp = resp_roundtrip(&mut stream, &restore("bad", "0", &corrupt, &[]));
+    assert_eq!(resp, "-ERR DUMP payload version or checksum are wrong\r\n");
+    let resp = resp_roundtrip(&mut stream, &restore("bad", "-1", &payload, &[]));
+    assert_eq!(resp, "-ERR Invalid TTL value, must be >= 0\r\n");
+
+    drop(reader);
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    // The restored keys come back from the AOF.
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LRANGE", "copy", "0", "-1"]));
+    assert_eq!(resp, "*3\r\n$1\r\na\r\n$0\r\n\r\n$1\r\nc\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PTTL", "volatile"]));
+    let pttl: i64 = resp.trim_start_matches(':').trim_end().parse().unwrap();
+    assert!(pttl > 0 && pttl <= 100_000, "{pttl}");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_debug_noop_subcommands() {
+    let port = 16400;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    for sub in ["QUICKLIST-PACKED-THRESHOLD", "change-repl-id"] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", sub, "100"]));
+        assert_eq!(resp, "+OK\r\n", "DEBUG {sub}");
+    }
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "NOPE"]));
+    assert_eq!(resp, "-ERR unknown subcommand 'NOPE'. Try DEBUG HELP.\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_debug_sleep_holds_the_keyspace() {
+    let port = 16471;
+    let mut server = spawn_server(port);
+    let connect = || {
+        let stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        stream
+    };
+    let mut sleeper = connect();
+    let mut other = connect();
+
+    let resp = resp_roundtrip(&mut sleeper, &resp_cmd(&["DEBUG", "SLEEP", "soon"]));
+    assert_eq!(resp, "-ERR value is not a valid float\r\n");
+    let resp = resp_roundtrip(&mut sleeper, &resp_cmd(&["DEBUG", "SLEEP", "0"]));
+    assert_eq!(resp, "+OK\r\n");
+
+    sleeper
+        .write_all(&resp_cmd(&["DEBUG", "SLEEP", "0.6"]))
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    let start = std::time::Instant::now();
+
+    // Commands that don't touch keys still answer; key reads wait.
+    let resp = resp_roundtrip(&mut other, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+    let resp = resp_roundtrip(&mut other, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "$-1\r\n");
+    assert!(
+        start.elapsed() >= Duration::from_millis(400),
+        "{:?}",
+        start.elapsed()
+    );
+
+    let mut buf = [0u8; 64];
+    let n = sleeper.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"+OK\r\n");
+
+    drop(sleeper);
+    drop(other);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_debug_stringmatch_len() {
+    let port = 16451;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let cases: &[(&str, &str, bool)] = &[
+        // Runs of `*` behave like one, including at either end.
+        ("a***b", "ab", true),
+        ("a***b", "axxb", true),
+        ("**", "", true),
+        ("***x", "yyx", true),
+        ("a*a*a*a*b", &"a".repeat(40), false),
+        // `[` and `^` are literal inside a class; `]` first closes it empty.
+        ("[[]", "[", true),
+        ("[a[]x", "[x", true),
+        ("[^]]", "a", false),
+        ("[^^]", "^", false),
+        ("[]a", "a", false),
+        ("[z-a]", "m", true),
+        // Escapes, in and out of classes.
+        ("\\[a]", "[a]", true),
+        ("\\?", "?", true),
+        ("\\?", "x", false),
+        ("[\\]]", "]", true),
+        ("[\\-]", "-", true),
+        ("[\\-]", "\\", false),
+        ("a\\", "a\\", true),
+    ];
+    for &(pattern, s, expected) in cases {
+        let resp = resp_roundtrip(
+            &mut stream,
+            &resp_cmd(&["DEBUG", "STRINGMATCH-LEN", pattern, s]),
+        );
+        let want = if expected { ":1\r\n" } else { ":0\r\n" };
+        assert_eq!(resp, want, "{pattern:?} vs {s:?}");
+    }
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "STRINGMATCH-LEN", "x"]));
+    assert_eq!(
+        resp,
+        "-ERR unknown subcommand 'STRINGMATCH-LEN'. Try DEBUG HELP.\r\n"
+    );
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_expire_condition_flags() {
+    let port = 16401;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+
+    // No TTL yet: XX and GT refuse, NX applies.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "k", "100", "XX"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "k", "100", "GT"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "k", "100", "NX"]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "k", "200", "NX"]));
+    assert_eq!(resp, ":0\r\n");
+
+    // GT only extends, LT only shortens.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "k", "50", "GT"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "k", "200", "XX", "GT"]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PEXPIRE", "k", "300000", "LT"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "k", "50", "LT"]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "k"]));
+    assert!(resp == ":50\r\n" || resp == ":49\r\n", "got {resp}");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "k", "10", "NX", "GT"]));
+    assert_eq!(
+        resp,
+        "-ERR NX and XX, GT or LT options at the same time are not compatible\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "k", "10", "GT", "LT"]));
+    assert_eq!(
+        resp,
+        "-ERR GT and LT options at the same time are not compatible\r\n"
+    );
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PERSIST", "k"]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "k"]));
+    assert_eq!(resp, ":-1\r\n");
+
+    // A deadline in the past deletes the key.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIREAT", "k", "1"]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "k"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "missing", "10"]));
+    assert_eq!(resp, ":0\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_expiretime() {
+    let port = 16402;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PEXPIRETIME", "missing"]));
+    assert_eq!(resp, ":-2\r\n");
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRETIME", "k"]));
+    assert_eq!(resp, ":-1\r\n");
+
+    // An absolute deadline reads back exactly.
+    let at = "4102444800123"; // 2100-01-01T00:00:00.123Z
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PEXPIREAT", "k", at]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PEXPIRETIME", "k"]));
+    assert_eq!(resp, format!(":{at}\r\n"));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRETIME", "k"]));
+    assert_eq!(resp, ":4102444800\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_expiry_survives_aof_restart() {
+    let port = 16403;
+    let path = std::env::temp_dir().join(format!("rfs-expiry-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let aof = path.to_str().unwrap();
+    let args = ["--aof-path", aof, "--aof-fsync", "always"];
+    let now_secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let future = (now_secs + 1000).to_string();
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["SET", "relative", "v", "EX", "100"]),
+    );
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "future", "v"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIREAT", "future", &future]));
+    assert_eq!(resp, ":1\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "past", "v"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIREAT", "past", "1000"]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "past"]));
+    assert_eq!(resp, ":0\r\n");
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "relative"]));
+    let ttl: i64 = resp.trim_start_matches(':').trim().parse().unwrap();
+    assert!((95..=100).contains(&ttl), "ttl after restart: {ttl}");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRETIME", "future"]));
+    assert_eq!(resp, format!(":{future}\r\n"));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "past"]));
+    assert_eq!(resp, ":0\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_arity_errors() {
+    let port = 16404;
+    let mut server = spawn_server(port);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    for (cmd, name) in [
+        (&["GET"][..], "get"),
+        (&["ping", "a", "b"][..], "ping"),
+        (&["LPOP", "k", "1", "2"][..], "lpop"),
+        (&["HSET", "h", "f"][..], "hset"),
+        (&["MULTI", "x"][..], "multi"),
+    ] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(cmd));
+        assert_eq!(
+            resp,
+            format!("-ERR wrong number of arguments for '{name}'\r\n"),
+            "{cmd:?}"
+        );
+    }
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["NOSUCH", "a"]));
+    assert_eq!(resp, "-ERR unknown command 'NOSUCH'\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_debug_reload() {
+    let port = 16405;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "RELOAD"]));
+    assert_eq!(resp, "-ERR DEBUG RELOAD requires AOF persistence\r\n");
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    let port = 16406;
+    let path = std::env::temp_dir().join(format!("rfs-reload-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let aof = path.to_str().unwrap();
+    let args = ["--aof-path", aof, "--aof-fsync", "always"];
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let setup: &[&[&str]] = &[
+        &["SET", "str", "hello"],
+        &["SET", "ttl", "v", "EX", "1000"],
+        &["RPUSH", "list", "a", "b", "c"],
+        &["SADD", "set", "x", "y", "z"],
+        &["HSET", "hash", "f1", "v1", "f2", "v2"],
+        &["ZADD", "zset", "1.5", "m1", "-2", "m2", "inf", "m3"],
+        &["DEL", "str"],
+        &["SET", "str", "world"],
+    ];
+    for cmd in setup {
+        resp_roundtrip(&mut stream, &resp_cmd(cmd));
+    }
+
+    // Replies whose element order doesn't depend on hashing.
+    let checks: &[&[&str]] = &[
+        &["GET", "str"],
+        &["LRANGE", "list", "0", "-1"],
+        &["HGET", "hash", "f1"],
+        &["HGET", "hash", "f2"],
+        &["ZRANGE", "zset", "0", "-1", "WITHSCORES"],
+        &["EXPIRETIME", "ttl"],
+    ];
+    let snapshot = |stream: &mut TcpStream| -> Vec<String> {
+        let mut out: Vec<String> = checks
+            .iter()
+            .map(|cmd| resp_roundtrip(stream, &resp_cmd(cmd)))
+            .collect();
+        let members = resp_roundtrip(stream, &resp_cmd(&["SMEMBERS", "set"]));
+        let mut lines: Vec<&str> = members.split("\r\n").collect();
+        lines.sort_unstable();
+        out.push(lines.join("\r\n"));
+        out
+    };
+    let before = snapshot(&mut stream);
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "RELOAD"]));
+    assert_eq!(resp, "+OK\r\n");
+    assert_eq!(snapshot(&mut stream), before);
+
+    // Writes after the reload still reach the rewritten file.
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "after", "1"]));
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    assert_eq!(snapshot(&mut stream), before);
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "after"]));
+    assert_eq!(resp, "$1\r\n1\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_dir_holds_relative_aof_path() {
+    let port = 16470;
+    let dir = std::env::temp_dir().join(format!("rfs-dir-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // A missing directory stops the server instead of writing elsewhere.
+    let missing = dir.to_str().unwrap();
+    let args = ["--dir", missing, "--aof-path", "appendonly.aof"];
+    let mut server = spawn_server_with_args(port, &args);
+    let status = server.wait().unwrap();
+    assert!(!status.success());
+    assert!(TcpStream::connect(format!("127.0.0.1:{port}")).is_err());
+
+    std::fs::create_dir(&dir).unwrap();
+    let args = [&args[..], &["--aof-fsync", "always"]].concat();
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+    assert_eq!(resp, "+OK\r\n");
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    assert!(dir.join("appendonly.aof").is_file());
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_maxmemory_lfu_eviction() {
+    let port = 16407;
+    let args = ["--maxmemory", "2000", "--maxmemory-policy", "allkeys-lfu"];
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let value = "x".repeat(100);
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "hot", &value]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "FREQ", "hot"]));
+    assert_eq!(resp, ":5\r\n");
+    let gets: Vec<u8> = (0..30).flat_map(|_| resp_cmd(&["GET", "hot"])).collect();
+    resp_roundtrip(&mut stream, &gets);
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "FREQ", "hot"]));
+    let freq: i64 = resp.trim_start_matches(':').trim().parse().unwrap();
+    assert!(freq > 5, "freq after reads: {freq}");
+
+    let names: Vec<String> = (0..40).map(|i| format!("cold{i}")).collect();
+    for name in &names {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", name, &value]));
+        assert_eq!(resp, "+OK\r\n");
+    }
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "hot"]));
+    assert_eq!(resp, ":1\r\n");
+    let mut exists = vec!["EXISTS"];
+    exists.extend(names.iter().map(String::as_str));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&exists));
+    let live: usize = resp.trim_start_matches(':').trim().parse().unwrap();
+    assert!(live < names.len(), "nothing was evicted");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_maxmemory_noeviction() {
+    let port = 16408;
+    let mut server = spawn_server_with_args(port, &["--maxmemory", "300"]);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let value = "x".repeat(200);
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "a", &value]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "b", &value]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "c", &value]));
+    assert_eq!(
+        resp,
+        "-OOM command not allowed when used memory > 'maxmemory'.\r\n"
+    );
+    // Commands that free memory still work.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEL", "a"]));
+    assert_eq!(resp, ":1\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "FREQ", "b"]));
+    assert!(resp.starts_with("-ERR An LFU maxmemory policy is not selected"));
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_slowlog() {
+    let port = 16409;
+    let args = ["--slowlog-log-slower-than", "0", "--slowlog-max-len", "3"];
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "a", "1"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["GET", "a"]));
+    // The SLOWLOG call itself is logged only after it replies.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SLOWLOG", "LEN"]));
+    assert_eq!(resp, ":2\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SLOWLOG", "GET", "1"]));
+    assert!(resp.starts_with("*1\r\n*6\r\n:2\r\n"), "got {resp}");
+    assert!(
+        resp.contains("*2\r\n$7\r\nSLOWLOG\r\n$3\r\nLEN\r\n"),
+        "got {resp}"
+    );
+    assert!(resp.contains("\r\n127.0.0.1:"), "got {resp}");
+
+    // The buffer is bounded by --slowlog-max-len.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SLOWLOG", "GET", "-1"]));
+    assert!(resp.starts_with("*3\r\n"), "got {resp}");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SLOWLOG", "RESET"]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SLOWLOG", "LEN"]));
+    assert_eq!(resp, ":1\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SLOWLOG", "GET", "-2"]));
+    assert_eq!(resp, "-ERR count should be greater than or equal to -1\r\n");
+
+    // Credentials never reach the log: AUTH is left out entirely, and the
+    // passwords given to ACL SETUSER are masked.
+    resp_roundtrip(&mut stream, &resp_cmd(&["SLOWLOG", "RESET"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["AUTH", "hunter2"]));
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ACL", "SETUSER", "bob", "on", ">hunter2", "<hunter1"]),
+    );
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SLOWLOG", "GET", "-1"]));
+    assert!(!resp.contains("hunter"), "got {resp}");
+    assert!(!resp.contains("AUTH"), "got {resp}");
+    assert!(
+        resp.contains("$3\r\nbob\r\n$2\r\non\r\n$10\r\n(redacted)\r\n$10\r\n(redacted)\r\n"),
+        "got {resp}"
+    );
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_replicaof() {
+    let (master_port, replica_port) = (16410, 16411);
+    let mut master = spawn_server(master_port);
+    let mut replica = spawn_server(replica_port);
+    let mut m = TcpStream::connect(format!("127.0.0.1:{master_port}")).unwrap();
+    let mut r = TcpStream::connect(format!("127.0.0.1:{replica_port}")).unwrap();
+    m.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    r.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    // Written before the replica attaches, so it arrives via the full sync.
+    resp_roundtrip(&mut m, &resp_cmd(&["SET", "before", "1"]));
+    resp_roundtrip(&mut m, &resp_cmd(&["RPUSH", "list", "a", "b"]));
+    // Stale replica data is replaced by the master's dataset.
+    resp_roundtrip(&mut r, &resp_cmd(&["SET", "stale", "x"]));
+
+    let port = master_port.to_string();
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["REPLICAOF", "127.0.0.1", &port]));
+    assert_eq!(resp, "+OK\r\n");
+    std::thread::sleep(Duration::from_millis(300));
+
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["GET", "before"]));
+    assert_eq!(resp, "$1\r\n1\r\n");
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["LRANGE", "list", "0", "-1"]));
+    assert_eq!(resp, "*2\r\n$1\r\na\r\n$1\r\nb\r\n");
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["EXISTS", "stale"]));
+    assert_eq!(resp, ":0\r\n");
+    // A replica only takes writes from its master.
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["SET", "stale", "x"]));
+    assert!(resp.starts_with("-READONLY"), "got {resp}");
+
+    // Later writes are streamed.
+    resp_roundtrip(&mut m, &resp_cmd(&["SET", "after", "2"]));
+    resp_roundtrip(&mut m, &resp_cmd(&["DEL", "before"]));
+    std::thread::sleep(Duration::from_millis(200));
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["GET", "after"]));
+    assert_eq!(resp, "$1\r\n2\r\n");
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["EXISTS", "before"]));
+    assert_eq!(resp, ":0\r\n");
+
+    // Detached replicas keep their data but stop following.
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["REPLICAOF", "NO", "ONE"]));
+    assert_eq!(resp, "+OK\r\n");
+    std::thread::sleep(Duration::from_millis(200));
+    resp_roundtrip(&mut m, &resp_cmd(&["SET", "after", "3"]));
+    std::thread::sleep(Duration::from_millis(200));
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["GET", "after"]));
+    assert_eq!(resp, "$1\r\n2\r\n");
+
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["REPLICAOF", "127.0.0.1", "notaport"]));
+    assert_eq!(resp, "-ERR Invalid master port\r\n");
+
+    drop(m);
+    drop(r);
+    master.kill().ok();
+    master.wait().ok();
+    replica.kill().ok();
+    replica.wait().ok();
+}
+
+#[test]
+fn test_replica_sync_flushes_its_aof() {
+    let (master_port, replica_port) = (16493, 16494);
+    let path = std::env::temp_dir().join(format!("rfs-replica-aof-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let aof = path.to_str().unwrap();
+    let args = ["--aof-path", aof, "--aof-fsync", "always"];
+
+    let mut master = spawn_server(master_port);
+    let mut replica = spawn_server_with_args(replica_port, &args);
+    let mut m = TcpStream::connect(format!("127.0.0.1:{master_port}")).unwrap();
+    let mut r = TcpStream::connect(format!("127.0.0.1:{replica_port}")).unwrap();
+    m.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    r.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+
+    resp_roundtrip(&mut m, &resp_cmd(&["SET", "synced", "1"]));
+    resp_roundtrip(&mut r, &resp_cmd(&["SET", "stale", "x"]));
+    let port = master_port.to_string();
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["REPLICAOF", "127.0.0.1", &port]));
+    assert_eq!(resp, "+OK\r\n");
+    std::thread::sleep(Duration::from_millis(300));
+    drop(r);
+    replica.kill().ok();
+    replica.wait().ok();
+
+    // Replaying the replica's AOF gives the synced dataset, without the
+    // keys the sync replaced.
+    let mut replica = spawn_server_with_args(replica_port, &args);
+    let mut r = TcpStream::connect(format!("127.0.0.1:{replica_port}")).unwrap();
+    r.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["EXISTS", "stale"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut r, &resp_cmd(&["GET", "synced"]));
+    assert_eq!(resp, "$1\r\n1\r\n");
+
+    drop(m);
+    drop(r);
+    master.kill().ok();
+    master.wait().ok();
+    replica.kill().ok();
+    replica.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_srandmember() {
+    let port = 16412;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "s", "a", "b", "c"]));
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SRANDMEMBER", "s"]));
+    assert!(
+        ["$1\r\na\r\n", "$1\r\nb\r\n", "$1\r\nc\r\n"].contains(&resp.as_str()),
+        "got {resp}"
+    );
+
+    // A positive count never exceeds the set size and never repeats.
+    for _ in 0..20 {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SRANDMEMBER", "s", "2"]));
+        let members: Vec<&str> = resp.split("\r\n").skip(2).step_by(2).collect();
+        assert!(resp.starts_with("*2\r\n"), "got {resp}");
+        assert_ne!(members[0], members[1]);
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SRANDMEMBER", "s", "10"]));
+    assert!(resp.starts_with("*3\r\n"), "got {resp}");
+
+    // A negative count returns exactly that many, with repeats.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SRANDMEMBER", "s", "-10"]));
+    assert!(resp.starts_with("*10\r\n"), "got {resp}");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SRANDMEMBER", "s", "0"]));
+    assert_eq!(resp, "*0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SRANDMEMBER", "missing"]));
+    assert_eq!(resp, "$-1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SRANDMEMBER", "missing", "-3"]));
+    assert_eq!(resp, "*0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SRANDMEMBER", "s", "x"]));
+    assert_eq!(resp, "-ERR value is not an integer or out of range\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_random_counts_are_range_checked_and_streamed() {
+    let port = 16492;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "s", "a"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["HSET", "h", "f", "v"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["ZADD", "z", "1", "m"]));
+
+    // -i64::MIN has no i64 to fit in, and with values or scores the reply
+    // length must fit twice over.
+    let min = i64::MIN.to_string();
+    let half = (i64::MAX / 2 + 1).to_string();
+    let neg_half = (-(i64::MAX / 2) - 1).to_string();
+    for cmd in [
+        vec!["SRANDMEMBER", "s", min.as_str()],
+        vec!["HRANDFIELD", "h", min.as_str()],
+        vec!["ZRANDMEMBER", "z", min.as_str()],
+        vec!["HRANDFIELD", "h", neg_half.as_str(), "WITHVALUES"],
+        vec!["ZRANDMEMBER", "z", half.as_str(), "WITHSCORES"],
+    ] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&cmd));
+        assert_eq!(resp, "-ERR value is out of range\r\n", "{cmd:?}");
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+
+    // A huge negative count is written as it is drawn, not built up front,
+    // so the start of it arrives and the server survives the hang-up.
+    stream
+        .write_all(&resp_cmd(&["SRANDMEMBER", "s", "-2000000000"]))
+        .unwrap();
+    let mut buf = [0u8; 64];
+    let mut read = 0;
+    while read < buf.len() {
+        read += stream.read(&mut buf[read..]).unwrap();
+    }
+    assert!(
+        buf.starts_with(b"*2000000000\r\n$1\r\na\r\n$1\r\na\r\n"),
+        "got {:?}",
+        String::from_utf8_lossy(&buf)
+    );
+    drop(stream);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SMEMBERS", "s"]));
+    assert_eq!(resp, "*1\r\n$1\r\na\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_hrandfield_zrandmember() {
+    let port = 16413;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["HSET", "h", "f", "v"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["ZADD", "z", "1.5", "m"]));
+
+    // No count: a single bulk string, or nil for a missing key.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HRANDFIELD", "h"]));
+    assert_eq!(resp, "$1\r\nf\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["ZRANDMEMBER", "z"]));
+    assert_eq!(resp, "$1\r\nm\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HRANDFIELD", "missing"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    // A positive count is capped at the collection size.
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["HRANDFIELD", "h", "5", "WITHVALUES"]),
+    );
+    assert_eq!(resp, "*2\r\n$1\r\nf\r\n$1\r\nv\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ZRANDMEMBER", "z", "5", "WITHSCORES"]),
+    );
+    assert_eq!(resp, "*2\r\n$1\r\nm\r\n$3\r\n1.5\r\n");
+
+    // A negative count repeats to return exactly that many.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HRANDFIELD", "h", "-3"]));
+    assert_eq!(resp, "*3\r\n$1\r\nf\r\n$1\r\nf\r\n$1\r\nf\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ZRANDMEMBER", "z", "-2", "WITHSCORES"]),
+    );
+    assert_eq!(
+        resp,
+        "*4\r\n$1\r\nm\r\n$3\r\n1.5\r\n$1\r\nm\r\n$3\r\n1.5\r\n"
+    );
+
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ZRANDMEMBER", "z", "1", "WITHVALUES"]),
+    );
+    assert_eq!(resp, "-ERR syntax error\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HRANDFIELD", "z"]));
+    assert!(resp.starts_with("-WRONGTYPE"), "got {resp}");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_collection_scans() {
+    let port = 16414;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["SADD", "s", "user:1", "user:2", "item:1", "user:3"]),
+    );
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["HSET", "h", "a1", "x", "b1", "y", "a2", "z"]),
+    );
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ZADD", "z", "2", "m2", "1", "m1", "3", "n1"]),
+    );
+
+    // Pages follow sorted order; the cursor hits 0 on the last page.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SSCAN", "s", "0", "COUNT", "3"]));
+    assert_eq!(
+        resp,
+        "*2\r\n$1\r\n3\r\n*3\r\n$6\r\nitem:1\r\n$6\r\nuser:1\r\n$6\r\nuser:2\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SSCAN", "s", "3", "COUNT", "3"]));
+    assert_eq!(resp, "*2\r\n$1\r\n0\r\n*1\r\n$6\r\nuser:3\r\n");
+
+    // MATCH filters each page.
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["SSCAN", "s", "0", "MATCH", "user:*"]),
+    );
+    assert_eq!(
+        resp,
+        "*2\r\n$1\r\n0\r\n*3\r\n$6\r\nuser:1\r\n$6\r\nuser:2\r\n$6\r\nuser:3\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HSCAN", "h", "0", "MATCH", "a?"]));
+    assert_eq!(
+        resp,
+        "*2\r\n$1\r\n0\r\n*4\r\n$2\r\na1\r\n$1\r\nx\r\n$2\r\na2\r\n$1\r\nz\r\n"
+    );
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ZSCAN", "z", "0", "MATCH", "m*", "COUNT", "100"]),
+    );
+    assert_eq!(
+        resp,
+        "*2\r\n$1\r\n0\r\n*4\r\n$2\r\nm1\r\n$1\r\n1\r\n$2\r\nm2\r\n$1\r\n2\r\n"
+    );
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SSCAN", "missing", "0"]));
+    assert_eq!(resp, "*2\r\n$1\r\n0\r\n*0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SSCAN", "s", "abc"]));
+    assert_eq!(resp, "-ERR invalid cursor\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SSCAN", "s", "0", "COUNT", "0"]));
+    assert_eq!(resp, "-ERR syntax error\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HSCAN", "s", "0"]));
+    assert!(resp.starts_with("-WRONGTYPE"), "got {resp}");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_copy() {
+    let port = 16415;
+    let path = std::env::temp_dir().join(format!("rfs-copy-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let args = [
+        "--aof-path",
+        path.to_str().unwrap(),
+        "--aof-fsync",
+        "always",
+    ];
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "str", "v", "EX", "100"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "list", "a", "b"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "set", "m"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["HSET", "hash", "f", "v"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["ZADD", "zset", "1", "m"]));
+
+    for key in ["str", "list", "set", "hash", "zset"] {
+        let dst = format!("{key}-copy");
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["COPY", key, &dst]));
+        assert_eq!(resp, ":1\r\n", "COPY {key}");
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "str-copy"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LRANGE", "list-copy", "0", "-1"]));
+    assert_eq!(resp, "*2\r\n$1\r\na\r\n$1\r\nb\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SMEMBERS", "set-copy"]));
+    assert_eq!(resp, "*1\r\n$1\r\nm\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HGET", "hash-copy", "f"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["ZSCORE", "zset-copy", "m"]));
+    assert_eq!(resp, "$1\r\n1\r\n");
+
+    // The deadline comes along; keys without one stay persistent.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "str-copy"]));
+    let ttl: i64 = resp.trim_start_matches(':').trim().parse().unwrap();
+    assert!((95..=100).contains(&ttl), "ttl of copy: {ttl}");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "list-copy"]));
+    assert_eq!(resp, ":-1\r\n");
+
+    // The copy changes independently of its source.
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "list-copy", "c"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LLEN", "list"]));
+    assert_eq!(resp, ":2\r\n");
+
+    // An existing destination needs REPLACE, which may change its type.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["COPY", "set", "str-copy"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["COPY", "set", "str-copy", "REPLACE"]),
+    );
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "str-copy"]));
+    assert_eq!(resp, ":-1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SMEMBERS", "str-copy"]));
+    assert_eq!(resp, "*1\r\n$1\r\nm\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["COPY", "missing", "x"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["COPY", "str", "str"]));
+    assert_eq!(resp, "-ERR source and destination objects are the same\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["COPY", "str", "x", "BOGUS"]));
+    assert_eq!(resp, "-ERR syntax error\r\n");
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    // Copies are replayed from the AOF.
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LRANGE", "list-copy", "0", "-1"]));
+    assert_eq!(resp, "*3\r\n$1\r\na\r\n$1\r\nb\r\n$1\r\nc\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SMEMBERS", "str-copy"]));
+    assert_eq!(resp, "*1\r\n$1\r\nm\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_aof_write_errors() {
+    // Every write to /dev/full fails with ENOSPC.
+    let args = [
+        "--aof-path",
+        "/dev/full",
+        "--aof-fsync",
+        "always",
+        "--aof-on-write-error",
+        "stop",
+    ];
+    let port = 16416;
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["INFO", "persistence"]));
+    assert!(resp.contains("aof_last_write_status:ok\r\n"), "got {resp}");
+
+    // The write that hits the error has already been applied.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "a", "1"]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["INFO", "persistence"]));
+    assert!(resp.contains("aof_enabled:1\r\n"), "got {resp}");
+    assert!(resp.contains("aof_last_write_status:err\r\n"), "got {resp}");
+
+    // Later writes are refused; reads still work.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "b", "2"]));
+    assert!(resp.starts_with("-MISCONF "), "got {resp}");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "a"]));
+    assert_eq!(resp, "$1\r\n1\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_aof_everysec_syncs_when_idle() {
+    let port = 16417;
+    let path = std::env::temp_dir().join(format!("rfs-everysec-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let args = [
+        "--aof-path",
+        path.to_str().unwrap(),
+        "--aof-fsync",
+        "everysec",
+    ];
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+    assert_eq!(resp, "+OK\r\n");
+
+    // No further writes: only the background fsync can persist it before
+    // the server is killed.
+    std::thread::sleep(Duration::from_millis(2500));
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_command_getkeys() {
+    let port = 16418;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["COMMAND", "GETKEYS", "GET", "x"]));
+    assert_eq!(resp, "*1\r\n$1\r\nx\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["COMMAND", "GETKEYS", "SET", "k", "v", "EX", "10"]),
+    );
+    assert_eq!(resp, "*1\r\n$1\r\nk\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["COMMAND", "GETKEYS", "del", "a", "b"]),
+    );
+    assert_eq!(resp, "*2\r\n$1\r\na\r\n$1\r\nb\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["COMMAND", "GETKEYS", "COPY", "src", "dst", "REPLACE"]),
+    );
+    assert_eq!(resp, "*2\r\n$3\r\nsrc\r\n$3\r\ndst\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&[
+            "COMMAND",
+            "GETKEYS",
+            "SINTERCARD",
+            "2",
+            "a",
+            "b",
+            "LIMIT",
+            "1",
+        ]),
+    );
+    assert_eq!(resp, "*2\r\n$1\r\na\r\n$1\r\nb\r\n");
+
+    // No keys, a bad arity, or a bad numkeys are all rejected the same way.
+    for argv in [
+        &["COMMAND", "GETKEYS", "PING"][..],
+        &["COMMAND", "GETKEYS", "GET"],
+        &["COMMAND", "GETKEYS", "GET", "a", "b"],
+        &["COMMAND", "GETKEYS", "SINTERCARD", "3", "a", "b"],
+    ] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(argv));
+        assert_eq!(
+            resp, "-ERR Invalid arguments specified for command\r\n",
+            "{argv:?}"
+        );
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["COMMAND", "GETKEYS", "NOPE", "a"]));
+    assert_eq!(resp, "-ERR Invalid command specified\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_debug_object() {
+    let port = 16419;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    fn field(resp: &str, name: &str) -> usize {
+        resp.split_whitespace()
+            .find_map(|tok| tok.strip_prefix(&format!("{name}:")))
+            .unwrap_or_else(|| panic!("no {name} in {resp}"))
+            .trim()
+            .parse()
+            .unwrap()
+    }
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "small", "x"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "large", &"x".repeat(1000)]));
+    let small = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "OBJECT", "small"]));
+    assert!(small.starts_with("+Value at:"), "got {small}");
+    let large = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "OBJECT", "large"]));
+    assert!(field(&large, "serializedlength") > field(&small, "serializedlength"));
+    assert!(!large.contains("ql_nodes"));
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "list", "a", "b"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "OBJECT", "list"]));
+    assert_eq!(field(&resp, "ql_nodes"), 1);
+    let before = field(&resp, "serializedlength");
+    let big = "y".repeat(5000);
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "list", &big, &big]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "OBJECT", "list"]));
+    assert_eq!(field(&resp, "ql_nodes"), 2);
+    assert!(field(&resp, "serializedlength") > before);
+
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["HSET", "hash", "a", "1", "b", "2"]),
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "OBJECT", "hash"]));
+    assert_eq!(field(&resp, "fields"), 2);
+    resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "set", "x", "y", "z"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "OBJECT", "set"]));
+    assert!(resp.contains(" encoding:listpack "), "{resp}");
+    assert_eq!(field(&resp, "members"), 3);
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["ZADD", "zset", "1", "a", "2", "b"]),
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "OBJECT", "zset"]));
+    assert_eq!(field(&resp, "zsl_length"), 2);
+    assert_eq!(field(&resp, "zsl_level"), 1);
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "OBJECT", "missing"]));
+    assert_eq!(resp, "-ERR no such key\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_read_only() {
+    let port = 16420;
+    let mut server = spawn_server_with_args(port, &["--read-only"]);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+    assert_eq!(
+        resp,
+        "-READONLY You can't write against a read only replica.\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEL", "k"]));
+    assert!(resp.starts_with("-READONLY"), "got {resp}");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "$-1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["INFO"]));
+    assert!(resp.contains("# Persistence"), "got {resp}");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_object_encoding_thresholds() {
+    let port = 16421;
+    let mut server = spawn_server_with_args(port, &["--set-max-listpack-value", "8"]);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["HSET", "h", "a", "1", "b", "2"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "h"]));
+    assert_eq!(resp, "$8\r\nlistpack\r\n");
+
+    // Lowering the entry limit at runtime changes what's reported.
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["CONFIG", "SET", "hash-max-listpack-entries", "1"]),
+    );
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "h"]));
+    assert_eq!(resp, "$9\r\nhashtable\r\n");
+
+    // A member longer than the value limit (set from the command line).
+    resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "s", "short"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "s"]));
+    assert_eq!(resp, "$8\r\nlistpack\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "s", "much-too-long"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "s"]));
+    assert_eq!(resp, "$9\r\nhashtable\r\n");
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["ZADD", "z", "1", "a", "2", "b"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "z"]));
+    assert_eq!(resp, "$8\r\nlistpack\r\n");
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["CONFIG", "SET", "zset-max-listpack-entries", "1"]),
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "z"]));
+    assert_eq!(resp, "$8\r\nskiplist\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CONFIG", "GET", "set-max-*"]));
+    assert_eq!(
+        resp,
+        "*6\r\n$22\r\nset-max-intset-entries\r\n$3\r\n512\r\n\
+         $24\r\nset-max-listpack-entries\r\n$3\r\n128\r\n\
+         $22\r\nset-max-listpack-value\r\n$1\r\n8\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CONFIG", "SET", "nope", "1"]));
+    assert_eq!(
+        resp,
+        "-ERR Unknown option or number of arguments for CONFIG SET - 'nope'\r\n"
+    );
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["CONFIG", "SET", "set-max-listpack-value", "big"]),
+    );
+    assert!(resp.starts_with("-ERR CONFIG SET failed"), "got {resp}");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "missing"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_set_encoding_intset() {
+    let port = 16472;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let encoding = |stream: &mut TcpStream, key: &str| {
+        resp_roundtrip(stream, &resp_cmd(&["OBJECT", "ENCODING", key]))
+    };
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "s", "1", "-2", "300000"]));
+    assert_eq!(encoding(&mut stream, "s"), "$6\r\nintset\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "s", "x"]));
+    assert_eq!(encoding(&mut stream, "s"), "$8\r\nlistpack\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["SREM", "s", "x"]));
+    assert_eq!(encoding(&mut stream, "s"), "$6\r\nintset\r\n");
+
+    // Only canonical integers count, as for a string's `int` encoding.
+    for member in ["007", "+1", "1.0", "9223372036854775808"] {
+        resp_roundtrip(&mut stream, &resp_cmd(&["SADD", member, member]));
+        assert_eq!(
+            encoding(&mut stream, member),
+            "$8\r\nlistpack\r\n",
+            "{member}"
+        );
+    }
+
+    // Past set-max-intset-entries, an all-integer set falls back to the
+    // listpack and hashtable limits.
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["CONFIG", "SET", "set-max-intset-entries", "2"]),
+    );
+    assert_eq!(resp, "+OK\r\n");
+    assert_eq!(encoding(&mut stream, "s"), "$8\r\nlistpack\r\n");
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["CONFIG", "SET", "set-max-listpack-entries", "2"]),
+    );
+    assert_eq!(encoding(&mut stream, "s"), "$9\r\nhashtable\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_object_encoding_strings() {
+    let port = 16450;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let long = "x".repeat(45);
+    for (value, encoding) in [
+        ("123", "int"),
+        ("-9223372036854775808", "int"),
+        ("9223372036854775808", "embstr"),
+        ("0123", "embstr"),
+        ("+1", "embstr"),
+        ("12x", "embstr"),
+        (&long[..44], "embstr"),
+        (&long, "raw"),
+    ] {
+        resp_roundtrip(&mut stream, &resp_cmd(&["SET", "n", value]));
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "n"]));
+        assert_eq!(
+            resp,
+            format!("${}\r\n{encoding}\r\n", encoding.len()),
+            "value {value:?}"
+        );
+    }
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "n", "123"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SETRANGE", "n", "3", "x"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "n"]));
+    assert_eq!(resp, "$6\r\nembstr\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_cas() {
+    let port = 16422;
+    let path = std::env::temp_dir().join(format!("rfs-cas-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let args = [
+        "--aof-path",
+        path.to_str().unwrap(),
+        "--aof-fsync",
+        "always",
+    ];
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CAS", "k", "a", "b"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "k"]));
+    assert_eq!(resp, ":0\r\n");
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "a", "EX", "100"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CAS", "k", "x", "b"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "$1\r\na\r\n");
+
+    // A swap behaves like SET, dropping the TTL.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CAS", "k", "a", "b"]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "$1\r\nb\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "k"]));
+    assert_eq!(resp, ":-1\r\n");
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "list", "a"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CAS", "list", "a", "b"]));
+    assert!(resp.starts_with("-WRONGTYPE"), "{resp}");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CAS", "k", "b"]));
+    assert_eq!(resp, "-ERR wrong number of arguments for 'cas'\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    // Only the successful swap reaches the AOF, as a SET.
+    let aof = std::fs::read_to_string(&path).unwrap();
+    assert!(!aof.contains("CAS"), "{aof}");
+    assert!(aof.ends_with("*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nb\r\n*3\r\n$5\r\nRPUSH\r\n$4\r\nlist\r\n$1\r\na\r\n"), "{aof}");
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_getdel() {
+    let port = 16423;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "str", "v"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETDEL", "str"]));
+    assert_eq!(resp, "$1\r\nv\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETDEL", "str"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    // GETDEL refuses other types and leaves them alone...
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "list", "a", "b"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETDEL", "list"]));
+    assert_eq!(
+        resp,
+        "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LRANGE", "list", "0", "-1"]));
+    assert_eq!(resp, "*2\r\n$1\r\na\r\n$1\r\nb\r\n");
+
+    // ...while DEL removes a key whatever its type.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEL", "list"]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "list"]));
+    assert_eq!(resp, ":0\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_multiple_binds() {
+    // --bind repeats and takes comma-separated lists.
+    let mut server = spawn_server_with_args(16424, &["--bind", "127.0.0.1:16425,127.0.0.1:16426"]);
+
+    let mut streams = Vec::new();
+    for port in [16424, 16425, 16426] {
+        let stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        streams.push(stream);
+    }
+
+    // All listeners serve the same keyspace.
+    let resp = resp_roundtrip(&mut streams[0], &resp_cmd(&["SET", "k", "v"]));
+    assert_eq!(resp, "+OK\r\n");
+    for stream in &mut streams[1..] {
+        let resp = resp_roundtrip(stream, &resp_cmd(&["GET", "k"]));
+        assert_eq!(resp, "$1\r\nv\r\n");
+    }
+
+    drop(streams);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_unixsocket() {
+    use std::os::unix::net::UnixStream;
+
+    let path = std::env::temp_dir().join(format!("rfs-{}.sock", std::process::id()));
+    let mut server = spawn_server_with_args(16427, &["--unixsocket", path.to_str().unwrap()]);
+
+    let mut stream = UnixStream::connect(&path).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream.write_all(&resp_cmd(&["PING"])).unwrap();
+    let mut buf = [0u8; 64];
+    let n = stream.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"+PONG\r\n");
+
+    // TCP clients are still served alongside.
+    let mut tcp = TcpStream::connect("127.0.0.1:16427").unwrap();
+    tcp.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let resp = resp_roundtrip(&mut tcp, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+
+    drop(stream);
+    drop(tcp);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_tls() {
+    use std::sync::Arc;
+
+    use tokio_rustls::rustls::crypto::ring;
+    use tokio_rustls::rustls::pki_types::ServerName;
+    use tokio_rustls::rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+    let dir = std::env::temp_dir();
+    let cert_path = dir.join(format!("rfs-tls-{}.crt", std::process::id()));
+    let key_path = dir.join(format!("rfs-tls-{}.key", std::process::id()));
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+    std::fs::write(&key_path, certified.signing_key.serialize_pem()).unwrap();
+
+    let args = [
+        "--tls-bind",
+        "127.0.0.1:16429",
+        "--tls-cert",
+        cert_path.to_str().unwrap(),
+        "--tls-key",
+        key_path.to_str().unwrap(),
+    ];
+    let mut server = spawn_server_with_args(16428, &args);
+
+    let mut roots = RootCertStore::empty();
+    roots.add(certified.cert.der().clone()).unwrap();
+    let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from("localhost").unwrap();
+    let conn = ClientConnection::new(Arc::new(config), name).unwrap();
+    let tcp = TcpStream::connect("127.0.0.1:16429").unwrap();
+    tcp.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    let mut tls = StreamOwned::new(conn, tcp);
+    tls.write_all(&resp_cmd(&["PING"])).unwrap();
+    let mut buf = [0u8; 64];
+    let n = tls.read(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"+PONG\r\n");
+
+    // The plain listener keeps working next to the TLS one.
+    let mut plain = TcpStream::connect("127.0.0.1:16428").unwrap();
+    plain
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut plain, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+
+    drop(tls);
+    drop(plain);
+    server.kill().ok();
+    server.wait().ok();
+
+    // A key that doesn't parse stops the server at startup.
+    std::fs::write(&key_path, "not a key").unwrap();
+    let mut server = spawn_server_with_args(16428, &args);
+    let status = server.try_wait().unwrap();
+    assert!(status.is_some(), "server started with an invalid TLS key");
+    assert!(TcpStream::connect("127.0.0.1:16428").is_err());
+
+    let _ = std::fs::remove_file(&cert_path);
+    let _ = std::fs::remove_file(&key_path);
+}
+
+#[test]
+fn test_running_out_of_file_descriptors_is_survived() {
+    let port = 16495;
+    // A low descriptor limit, so clients alone exhaust it and accept fails
+    // with EMFILE.
+    let mut server = Command::new("sh")
+        .args(["-c", "ulimit -n 48 && exec \"$0\" \"$@\""])
+        .arg(env!("CARGO_BIN_EXE_rfs-rs"))
+        .args(["--bind", &format!("127.0.0.1:{port}")])
+        .spawn()
+        .expect("failed to start rfs-rs");
+    std::thread::sleep(Duration::from_millis(500));
+
+    let clients: Vec<TcpStream> = (0..80)
+        .map(|_| TcpStream::connect(format!("127.0.0.1:{port}")).unwrap())
+        .collect();
+    std::thread::sleep(Duration::from_millis(300));
+    drop(clients);
+    std::thread::sleep(Duration::from_millis(500));
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_stalled_tls_handshake_takes_no_client_slot() {
+    let dir = std::env::temp_dir();
+    let cert_path = dir.join(format!("rfs-tls-stall-{}.crt", std::process::id()));
+    let key_path = dir.join(format!("rfs-tls-stall-{}.key", std::process::id()));
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+    std::fs::write(&key_path, certified.signing_key.serialize_pem()).unwrap();
+
+    let args = [
+        "--max-connections",
+        "1",
+        "--tls-bind",
+        "127.0.0.1:16491",
+        "--tls-cert",
+        cert_path.to_str().unwrap(),
+        "--tls-key",
+        key_path.to_str().unwrap(),
+    ];
+    let mut server = spawn_server_with_args(16490, &args);
+
+    // Connect to the TLS port but never start the handshake.
+    let _stalled: Vec<TcpStream> = (0..3)
+        .map(|_| TcpStream::connect("127.0.0.1:16491").unwrap())
+        .collect();
+    std::thread::sleep(Duration::from_millis(100));
+
+    let mut plain = TcpStream::connect("127.0.0.1:16490").unwrap();
+    plain
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut plain, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+
+    drop(plain);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&cert_path);
+    let _ = std::fs::remove_file(&key_path);
+}
+
+#[test]
+fn test_debug_set_active_expire() {
+    let port = 16430;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "SET-ACTIVE-EXPIRE", "0"]));
+    assert_eq!(resp, "+OK\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v", "PX", "100"]));
+    // Long enough for the background task to have run at least once.
+    std::thread::sleep(Duration::from_millis(1500));
+
+    // Nothing evicted the key, so it's counted until an access expires it.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DBSIZE"]));
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "SET-ACTIVE-EXPIRE", "1"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v", "PX", "100"]));
+    std::thread::sleep(Duration::from_millis(1500));
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "SET-ACTIVE-EXPIRE", "x"]));
+    assert_eq!(resp, "-ERR syntax error\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_acl() {
+    let port = 16431;
+    let mut server = spawn_server(port);
+    let connect = || {
+        let stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        stream
+    };
+    let mut admin = connect();
+
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["ACL", "WHOAMI"]));
+    assert_eq!(resp, "$7\r\ndefault\r\n");
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["AUTH", "pw"]));
+    assert!(
+        resp.starts_with("-ERR AUTH <password> called without"),
+        "{resp}"
+    );
+
+    let rules = [
+        "ACL",
+        "SETUSER",
+        "alice",
+        "on",
+        ">secret",
+        "~cache:*",
+        "+@read",
+        "+set",
+        "-@dangerous",
+    ];
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&rules));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["ACL", "SETUSER", "bob", "+@nope"]));
+    assert_eq!(
+        resp,
+        "-ERR Error in ACL SETUSER modifier '+@nope': Unknown command or category name in ACL\r\n"
+    );
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["ACL", "SETUSER", "bob", "bogus"]));
+    assert_eq!(
+        resp,
+        "-ERR Error in ACL SETUSER modifier 'bogus': Syntax error\r\n"
+    );
+
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["ACL", "LIST"]));
+    assert!(
+        resp.contains("user default on nopass ~* +@all\r\n"),
+        "{resp}"
+    );
+    assert!(
+        resp.contains("~cache:* +@read +set -@dangerous\r\n"),
+        "{resp}"
+    );
+    assert!(!resp.contains("secret"), "{resp}");
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["ACL", "GETUSER", "alice"]));
+    assert!(
+        resp.starts_with("*8\r\n$5\r\nflags\r\n*1\r\n$2\r\non\r\n"),
+        "{resp}"
+    );
+    assert!(resp.ends_with("$4\r\nkeys\r\n$8\r\n~cache:*\r\n"), "{resp}");
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["ACL", "GETUSER", "nobody"]));
+    assert_eq!(resp, "$-1\r\n");
+
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["ACL", "CAT"]));
+    assert!(resp.starts_with("*16\r\n$8\r\nkeyspace\r\n"), "{resp}");
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["ACL", "CAT", "BITMAP"]));
+    assert_eq!(
+        resp,
+        "*5\r\n$6\r\nsetbit\r\n$6\r\ngetbit\r\n$8\r\nbitcount\r\n\
+         $6\r\nbitpos\r\n$5\r\nbitop\r\n"
+    );
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["ACL", "CAT", "nope"]));
+    assert_eq!(resp, "-ERR Unknown category 'nope'\r\n");
+
+    let mut alice = connect();
+    let resp = resp_roundtrip(&mut alice, &resp_cmd(&["AUTH", "alice", "wrong"]));
+    assert_eq!(
+        resp,
+        "-WRONGPASS invalid username-password pair or user is disabled.\r\n"
+    );
+    let resp = resp_roundtrip(&mut alice, &resp_cmd(&["AUTH", "alice", "secret"]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut alice, &resp_cmd(&["SET", "cache:a", "1"]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut alice, &resp_cmd(&["GET", "cache:a"]));
+    assert_eq!(resp, "$1\r\n1\r\n");
+    let resp = resp_roundtrip(&mut alice, &resp_cmd(&["GET", "other"]));
+    assert_eq!(resp, "-NOPERM No permissions to access a key\r\n");
+    let resp = resp_roundtrip(&mut alice, &resp_cmd(&["DEL", "cache:a"]));
+    assert_eq!(
+        resp,
+        "-NOPERM User alice has no permissions to run the 'del' command\r\n"
+    );
+    let resp = resp_roundtrip(&mut alice, &resp_cmd(&["DEBUG", "RELOAD"]));
+    assert_eq!(
+        resp,
+        "-NOPERM User alice has no permissions to run the 'debug' command\r\n"
+    );
+
+    // Once the default user needs a password, new connections must AUTH.
+    let resp = resp_roundtrip(
+        &mut admin,
+        &resp_cmd(&["ACL", "SETUSER", "default", ">adminpw"]),
+    );
+    assert_eq!(resp, "+OK\r\n");
+    let mut anon = connect();
+    let resp = resp_roundtrip(&mut anon, &resp_cmd(&["GET", "cache:a"]));
+    assert_eq!(resp, "-NOAUTH Authentication required.\r\n");
+    let resp = resp_roundtrip(&mut anon, &resp_cmd(&["AUTH", "adminpw"]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut anon, &resp_cmd(&["GET", "cache:a"]));
+    assert_eq!(resp, "$1\r\n1\r\n");
+    // Connections already acting as the default user aren't logged out.
+    let resp = resp_roundtrip(&mut admin, &resp_cmd(&["ACL", "WHOAMI"]));
+    assert_eq!(resp, "$7\r\ndefault\r\n");
+
+    drop((admin, alice, anon));
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_memory_usage() {
+    let port = 16432;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let usage = |stream: &mut TcpStream, args: &[&str]| -> i64 {
+        let resp = resp_roundtrip(stream, &resp_cmd(args));
+        resp.trim_start_matches(':').trim().parse().unwrap()
+    };
+
+    let value = "x".repeat(1000);
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "s", &value]));
+    let bytes = usage(&mut stream, &["MEMORY", "USAGE", "s"]);
+    assert!((1000..1200).contains(&bytes), "{bytes}");
+
+    // Identical elements, so sampling a few estimates the same total.
+    let mut rpush = vec!["RPUSH", "l"];
+    rpush.extend(std::iter::repeat_n("element", 500));
+    resp_roundtrip(&mut stream, &resp_cmd(&rpush));
+    let full = usage(&mut stream, &["MEMORY", "USAGE", "l"]);
+    let sampled = usage(&mut stream, &["MEMORY", "USAGE", "l", "SAMPLES", "5"]);
+    assert!(full > 500 * 7, "{full}");
+    assert_eq!(full, sampled);
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["MEMORY", "USAGE", "missing"]));
+    assert_eq!(resp, "$-1\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["MEMORY", "USAGE", "l", "SAMPLES", "x"]),
+    );
+    assert_eq!(resp, "-ERR value is not an integer or out of range\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["MEMORY", "DOCTOR"]));
+    assert!(resp.contains("No memory issues detected"), "{resp}");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_lcs() {
+    let port = 16433;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    // The examples from the Redis LCS documentation.
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "key1", "ohmytext"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "key2", "mynewtext"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LCS", "key1", "key2"]));
+    assert_eq!(resp, "$6\r\nmytext\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LCS", "key1", "key2", "LEN"]));
+    assert_eq!(resp, ":6\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LCS", "key1", "key2", "IDX"]));
+    assert_eq!(
+        resp,
+        "*4\r\n$7\r\nmatches\r\n*2\r\n\
+         *2\r\n*2\r\n:4\r\n:7\r\n*2\r\n:5\r\n:8\r\n\
+         *2\r\n*2\r\n:2\r\n:3\r\n*2\r\n:0\r\n:1\r\n\
+         $3\r\nlen\r\n:6\r\n"
+    );
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&[
+            "LCS",
+            "key1",
+            "key2",
+            "IDX",
+            "MINMATCHLEN",
+            "4",
+            "WITHMATCHLEN",
+        ]),
+    );
+    assert_eq!(
+        resp,
+        "*4\r\n$7\r\nmatches\r\n*1\r\n\
+         *3\r\n*2\r\n:4\r\n:7\r\n*2\r\n:5\r\n:8\r\n:4\r\n\
+         $3\r\nlen\r\n:6\r\n"
+    );
+
+    // A missing key is an empty string.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LCS", "key1", "missing", "LEN"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["LCS", "key1", "key2", "LEN", "IDX"]),
+    );
+    assert_eq!(
+        resp,
+        "-ERR If you want both the length and indexes, please just use IDX.\r\n"
+    );
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "list", "a"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LCS", "key1", "list"]));
+    assert!(resp.starts_with("-WRONGTYPE"), "{resp}");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_bitpos_bitop() {
+    let port = 16434;
+    let path = std::env::temp_dir().join(format!("rfs-bitop-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let aof = path.to_str().unwrap();
+    let args = ["--aof-path", aof, "--aof-fsync", "always"];
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let bitpos = |stream: &mut TcpStream, args: &[&str]| {
+        let mut cmd = vec!["BITPOS", "mykey"];
+        cmd.extend(args);
+        resp_roundtrip(stream, &resp_cmd(&cmd))
+    };
+
+    // The examples from the Redis BITPOS documentation.
+    resp_roundtrip(
+        &mut stream,
+        b"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$3\r\n\xff\xf0\x00\r\n",
+    );
+    assert_eq!(bitpos(&mut stream, &["0"]), ":12\r\n");
+    resp_roundtrip(
+        &mut stream,
+        b"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$3\r\n\x00\xff\xf0\r\n",
+    );
+    assert_eq!(bitpos(&mut stream, &["1", "0"]), ":8\r\n");
+    assert_eq!(bitpos(&mut stream, &["1", "2"]), ":16\r\n");
+    assert_eq!(bitpos(&mut stream, &["1", "2", "-1", "BYTE"]), ":16\r\n");
+    assert_eq!(bitpos(&mut stream, &["1", "7", "15", "BIT"]), ":8\r\n");
+    assert_eq!(bitpos(&mut stream, &["1", "7", "-3", "BIT"]), ":8\r\n");
+    resp_roundtrip(
+        &mut stream,
+        b"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$3\r\n\xff\xff\xff\r\n",
+    );
+    // Past the end the string reads as zeros, unless the end is explicit.
+    assert_eq!(bitpos(&mut stream, &["0"]), ":24\r\n");
+    assert_eq!(bitpos(&mut stream, &["0", "0", "-1"]), ":-1\r\n");
+    assert_eq!(bitpos(&mut stream, &["1", "5"]), ":-1\r\n");
+    assert_eq!(
+        bitpos(&mut stream, &["2"]),
+        "-ERR The bit argument must be 1 or 0.\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITPOS", "missing", "0"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITPOS", "missing", "1"]));
+    assert_eq!(resp, ":-1\r\n");
+
+    // The example from the Redis BITOP documentation.
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "key1", "foobar"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "key2", "abcdef"]));
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["BITOP", "AND", "dest", "key1", "key2"]),
+    );
+    assert_eq!(resp, ":6\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "dest"]));
+    assert_eq!(resp, "$6\r\n`bc`ab\r\n");
+
+    // Shorter and missing sources are zero-extended.
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "short", "a"]));
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["BITOP", "OR", "or", "short", "key1", "missing"]),
+    );
+    assert_eq!(resp, ":6\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "or"]));
+    assert_eq!(resp, "$6\r\ngoobar\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["BITOP", "XOR", "x", "short", "short"]),
+    );
+    assert_eq!(resp, ":1\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "x"]));
+    assert_eq!(resp, "$1\r\n\x00\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITOP", "NOT", "not", "mykey"]));
+    assert_eq!(resp, ":3\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "not"]));
+    assert_eq!(resp, "$3\r\n\x00\x00\x00\r\n");
+
+    // An empty result deletes the destination.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITOP", "AND", "x", "missing"]));
+    assert_eq!(resp, ":0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "x"]));
+    assert_eq!(resp, ":0\r\n");
+
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["BITOP", "NOT", "not", "key1", "key2"]),
+    );
+    assert_eq!(
+        resp,
+        "-ERR BITOP NOT must be called with a single source key.\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITOP", "NAND", "d", "key1"]));
+    assert_eq!(resp, "-ERR syntax error\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "l", "a"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["BITOP", "OR", "d", "key1", "l"]));
+    assert!(resp.starts_with("-WRONGTYPE"), "{resp}");
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    // Results are logged as plain SETs and come back on restart.
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "dest"]));
+    assert_eq!(resp, "$6\r\n`bc`ab\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "x"]));
+    assert_eq!(resp, ":0\r\n");
+    let log = std::fs::read(&path).unwrap();
+    assert!(!log.windows(5).any(|w| w == b"BITOP"));
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_shard_pubsub() {
+    let port = 16435;
+    let mut server = spawn_server(port);
+    let connect = || {
+        let stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        stream
+    };
+    let mut shard_sub = connect();
+    let mut sub = connect();
+    let mut publisher = connect();
+
+    let resp = resp_roundtrip(&mut shard_sub, &resp_cmd(&["SSUBSCRIBE", "news", "sport"]));
+    assert_eq!(
+        resp,
+        "*3\r\n$10\r\nssubscribe\r\n$4\r\nnews\r\n:1\r\n\
+         *3\r\n$10\r\nssubscribe\r\n$5\r\nsport\r\n:2\r\n"
+    );
+    let resp = resp_roundtrip(&mut shard_sub, &resp_cmd(&["GET", "foo"]));
+    assert!(resp.starts_with("-ERR Can't execute 'get'"), "{resp}");
+    let resp = resp_roundtrip(&mut sub, &resp_cmd(&["SUBSCRIBE", "news"]));
+    assert_eq!(resp, "*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+
+    // Shard channels and regular channels don't share messages.
+    let resp = resp_roundtrip(&mut publisher, &resp_cmd(&["SPUBLISH", "news", "hi"]));
+    assert_eq!(resp, ":1\r\n");
+    let mut buf = vec![0u8; 4096];
+    let n = shard_sub.read(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&buf[..n]),
+        "*3\r\n$8\r\nsmessage\r\n$4\r\nnews\r\n$2\r\nhi\r\n"
+    );
+    let resp = resp_roundtrip(&mut publisher, &resp_cmd(&["PUBLISH", "news", "hey"]));
+    assert_eq!(resp, ":1\r\n");
+    let n = sub.read(&mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&buf[..n]),
+        "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$3\r\nhey\r\n"
+    );
+
+    let resp = resp_roundtrip(
+        &mut publisher,
+        &resp_cmd(&["PUBSUB", "SHARDCHANNELS", "n*"]),
+    );
+    assert_eq!(resp, "*1\r\n$4\r\nnews\r\n");
+    let resp = resp_roundtrip(&mut publisher, &resp_cmd(&["PUBSUB", "SHARDCHANNELS"]));
+    assert!(resp.starts_with("*2\r\n"), "{resp}");
+    let resp = resp_roundtrip(
+        &mut publisher,
+        &resp_cmd(&["PUBSUB", "SHARDNUMSUB", "news", "other"]),
+    );
+    assert_eq!(resp, "*4\r\n$4\r\nnews\r\n:1\r\n$5\r\nother\r\n:0\r\n");
+    let resp = resp_roundtrip(&mut publisher, &resp_cmd(&["PUBSUB", "NOPE"]));
+    assert!(resp.starts_with("-ERR unknown subcommand"), "{resp}");
+
+    let resp = resp_roundtrip(&mut shard_sub, &resp_cmd(&["SUNSUBSCRIBE", "news"]));
+    assert_eq!(resp, "*3\r\n$12\r\nsunsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+    let resp = resp_roundtrip(&mut publisher, &resp_cmd(&["SPUBLISH", "news", "hi"]));
+    assert_eq!(resp, ":0\r\n");
+
+    // Disconnecting drops the remaining shard subscription.
+    drop(shard_sub);
+    std::thread::sleep(Duration::from_millis(100));
+    let resp = resp_roundtrip(
+        &mut publisher,
+        &resp_cmd(&["PUBSUB", "SHARDNUMSUB", "sport"]),
+    );
+    assert_eq!(resp, "*2\r\n$5\r\nsport\r\n:0\r\n");
+
+    drop(sub);
+    drop(publisher);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+/// Read `n` pipelined replies, each an integer or a bulk string.
+fn read_simple_replies(stream: &mut TcpStream, n: usize) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 65536];
+    let mut pos = 0;
+    let mut seen = 0;
+    while seen < n {
+        let line_end = buf[pos..].windows(2).position(|w| w == b"\r\n");
+        let Some(line_end) = line_end else {
+            let read = stream.read(&mut chunk).unwrap();
+            assert!(read > 0, "connection closed after {seen} replies");
+            buf.extend_from_slice(&chunk[..read]);
+            continue;
+        };
+        let line = &buf[pos..pos + line_end];
+        let mut next = pos + line_end + 2;
+        match line[0] {
+            b':' => {}
+            b'$' => {
+                let len: i64 = std::str::from_utf8(&line[1..]).unwrap().parse().unwrap();
+                if len >= 0 {
+                    next += len as usize + 2;
+                }
+            }
+            _ => panic!("unexpected reply {}", String::from_utf8_lossy(line)),
+        }
+        if next > buf.len() {
+            let read = stream.read(&mut chunk).unwrap();
+            assert!(read > 0, "connection closed after {seen} replies");
+            buf.extend_from_slice(&chunk[..read]);
+            continue;
+        }
+        pos = next;
+        seen += 1;
+    }
+}
+
+#[test]
+fn test_reads_on_large_collections_stay_cheap() {
+    let port = 16436;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+
+    const SIZE: usize = 10_000;
+    let members: Vec<String> = (0..SIZE).map(|i| format!("member:{i}")).collect();
+    let fill = |stream: &mut TcpStream, cmd: &[&str], per_member: &dyn Fn(&str) -> Vec<String>| {
+        let mut args: Vec<String> = cmd.iter().map(|s| s.to_string()).collect();
+        for m in &members {
+            args.extend(per_member(m));
+        }
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        stream.write_all(&resp_cmd(&args)).unwrap();
+        read_simple_replies(stream, 1);
+    };
+    fill(&mut stream, &["RPUSH", "list"], &|m| vec![m.into()]);
+    fill(&mut stream, &["SADD", "set"], &|m| vec![m.into()]);
+    fill(&mut stream, &["HSET", "hash"], &|m| {
+        vec![m.into(), "v".into()]
+    });
+    fill(&mut stream, &["ZADD", "zset"], &|m| {
+        vec!["1".into(), m.into()]
+    });
+
+    // Each of these would take milliseconds if it copied the collection, so
+    // a few thousand of them finishing quickly shows none does.
+    const ROUNDS: usize = 500;
+    let reads: [&[&str]; 7] = [
+        &["LLEN", "list"],
+        &["SINTERCARD", "1", "set", "LIMIT", "1"],
+        &["HGET", "hash", "member:7"],
+        &["ZSCORE", "zset", "member:7"],
+        &["SRANDMEMBER", "set"],
+        &["HRANDFIELD", "hash"],
+        &["ZRANDMEMBER", "zset"],
+    ];
+    let mut pipeline = Vec::new();
+    for _ in 0..ROUNDS {
+        for read in reads {
+            pipeline.extend(resp_cmd(read));
+        }
+    }
+    let started = std::time::Instant::now();
+    stream.write_all(&pipeline).unwrap();
+    read_simple_replies(&mut stream, ROUNDS * reads.len());
+    let elapsed = started.elapsed();
+    assert!(elapsed < Duration::from_secs(5), "reads took {elapsed:?}");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_collection_reads_reject_wrong_type() {
+    let port = 16437;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "str", "v"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "set", "a"]));
+    let reads: [&[&str]; 12] = [
+        &["ZRANGE", "str", "0", "-1"],
+        &["ZREVRANGE", "str", "0", "-1"],
+        &["ZCARD", "str"],
+        &["ZRANDMEMBER", "str"],
+        &["ZINTERCARD", "2", "set", "str"],
+        &["LRANGE", "str", "0", "-1"],
+        &["LPOS", "str", "v"],
+        &["SMEMBERS", "str"],
+        &["SINTERCARD", "2", "str", "set"],
+        &["HGETALL", "str"],
+        &["HSCAN", "set", "0"],
+        &["SSCAN", "str", "0"],
+    ];
+    for read in reads {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(read));
+        assert!(resp.starts_with("-WRONGTYPE"), "{read:?}: {resp}");
+    }
+
+    // A missing key is still an empty result, not an error.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["ZRANGE", "nokey", "0", "-1"]));
+    assert_eq!(resp, "*0\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SINTERCARD", "2", "set", "nokey"]));
+    assert_eq!(resp, ":0\r\n");
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_ping_while_subscribed() {
+    let port = 16438;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SUBSCRIBE", "news"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "*2\r\n$4\r\npong\r\n$0\r\n\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PING", "hello"]));
+    assert_eq!(resp, "*2\r\n$4\r\npong\r\n$5\r\nhello\r\n");
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["UNSUBSCRIBE"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PING", "hello"]));
+    assert_eq!(resp, "$5\r\nhello\r\n");
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_subscribe_confirms_each_channel_with_running_count() {
+    let port = 16469;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let confirm = |kind: &str, channel: &str, count: usize| {
+        format!(
+            "*3\r\n${}\r\n{kind}\r\n${}\r\n{channel}\r\n:{count}\r\n",
+            kind.len(),
+            channel.len()
+        )
+    };
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SUBSCRIBE", "a", "b", "c"]));
+    assert_eq!(
+        resp,
+        [
+            confirm("subscribe", "a", 1),
+            confirm("subscribe", "b", 2),
+            confirm("subscribe", "c", 3),
+        ]
+        .concat()
+    );
+
+    // Subscribing again is confirmed without raising the count.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SUBSCRIBE", "b"]));
+    assert_eq!(resp, confirm("subscribe", "b", 3));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["UNSUBSCRIBE", "a", "nope"]));
+    assert_eq!(
+        resp,
+        [
+            confirm("unsubscribe", "a", 2),
+            confirm("unsubscribe", "nope", 2)
+        ]
+        .concat()
+    );
+
+    // With no arguments, every remaining channel is confirmed, in no set
+    // order, counting down to zero.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["UNSUBSCRIBE"]));
+    let either = [
+        [
+            confirm("unsubscribe", "b", 1),
+            confirm("unsubscribe", "c", 0),
+        ]
+        .concat(),
+        [
+            confirm("unsubscribe", "c", 1),
+            confirm("unsubscribe", "b", 0),
+        ]
+        .concat(),
+    ];
+    assert!(either.contains(&resp), "{resp}");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["UNSUBSCRIBE"]));
+    assert_eq!(resp, "*3\r\n$11\r\nunsubscribe\r\n$-1\r\n:0\r\n");
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_subcommand_help() {
+    let port = 16439;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+
+    for cmd in [
+        "OBJECT", "CONFIG", "DEBUG", "COMMAND", "ACL", "SLOWLOG", "MEMORY", "PUBSUB",
+    ] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&[cmd, "help"]));
+        let header = format!("+{cmd} <subcommand> [<arg> [value] [opt] ...]. Subcommands are:\r\n");
+        assert!(resp.starts_with('*'), "{cmd}: {resp}");
+        assert!(resp.contains(&header), "{cmd}: {resp}");
+        assert!(
+            resp.ends_with("+HELP\r\n+    Print this help.\r\n"),
+            "{cmd}: {resp}"
+        );
+    }
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["MEMORY", "nope"]));
+    assert_eq!(
+        resp,
+        "-ERR unknown subcommand or wrong number of arguments for 'nope'. Try MEMORY HELP.\r\n"
+    );
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+/// A parsed RESP2 reply, for replies too large or nested to compare as text.
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Simple(String),
+    Int(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+/// Read one complete reply, panicking on an error reply or a malformed one.
+fn read_reply(reader: &mut impl std::io::BufRead) -> Reply {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let body = line
+        .strip_suffix("\r\n")
+        .unwrap_or_else(|| panic!("unterminated line {line:?}"));
+    let (kind, rest) = body.split_at(1);
+    match kind {
+        "+" => Reply::Simple(rest.to_string()),
+        ":" => Reply::Int(rest.parse().unwrap()),
+        "$" => {
+            let len: i64 = rest.parse().unwrap();
+            if len < 0 {
+                return Reply::Bulk(None);
+            }
+            let mut data = vec![0; len as usize + 2];
+            reader.read_exact(&mut data).unwrap();
+            assert_eq!(&data[len as usize..], b"\r\n");
+            data.truncate(len as usize);
+            Reply::Bulk(Some(data))
+        }
+        "*" => {
+            let len: usize = rest.parse().unwrap();
+            Reply::Array((0..len).map(|_| read_reply(reader)).collect())
+        }
+        _ => panic!("unexpected reply {body}"),
+    }
+}
+
+#[test]
+fn test_command_and_command_docs() {
+    let port = 16440;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["COMMAND", "COUNT"]));
+    let count: usize = resp.trim_start_matches(':').trim_end().parse().unwrap();
+
+    stream.write_all(&resp_cmd(&["COMMAND"])).unwrap();
+    let Reply::Array(infos) = read_reply(&mut reader) else {
+        panic!("COMMAND should reply with an array");
+    };
+    assert_eq!(infos.len(), count);
+    let get = infos
+        .iter()
+        .find_map(|info| match info {
+            Reply::Array(fields) if matches!(&fields[0], Reply::Bulk(Some(n)) if n == b"get") => {
+                Some(fields)
+            }
+            _ => None,
+        })
+        .expect("COMMAND lists get");
+    assert!(matches!(get[1], Reply::Int(2)), "{get:?}");
+    let Reply::Array(flags) = &get[2] else {
+        panic!("flags should be an array: {get:?}");
+    };
+    assert!(
+        flags
+            .iter()
+            .any(|f| matches!(f, Reply::Simple(s) if s == "readonly")),
+        "{flags:?}"
+    );
+    assert!(matches!(
+        get[3..6],
+        [Reply::Int(1), Reply::Int(1), Reply::Int(1)]
+    ));
+
+    stream.write_all(&resp_cmd(&["COMMAND", "DOCS"])).unwrap();
+    let Reply::Array(docs) = read_reply(&mut reader) else {
+        panic!("COMMAND DOCS should reply with an array");
+    };
+    assert_eq!(docs.len(), count * 2);
+    for pair in docs.chunks(2) {
+        let [Reply::Bulk(Some(_)), Reply::Array(fields)] = pair else {
+            panic!("malformed docs entry {pair:?}");
+        };
+        assert!(
+            matches!(&fields[..2], [Reply::Bulk(Some(k)), Reply::Bulk(Some(v))] if k == b"summary" && !v.is_empty()),
+            "{pair:?}"
+        );
+    }
+
+    // Named commands only, skipping unknown ones.
+    stream
+        .write_all(&resp_cmd(&["COMMAND", "DOCS", "set", "nope"]))
+        .unwrap();
+    let Reply::Array(docs) = read_reply(&mut reader) else {
+        panic!("COMMAND DOCS should reply with an array");
+    };
+    assert_eq!(docs.len(), 2);
+    assert!(matches!(&docs[0], Reply::Bulk(Some(n)) if n == b"set"));
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_getrange_setrange_edge_cases() {
+    let port = 16441;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "s", "Hello World"]));
+    let getrange_cases: &[(&str, &str, &str, &str)] = &[
+        ("s", "0", "4", "$5\r\nHello\r\n"),
+        ("s", "-5", "-1", "$5\r\nWorld\r\n"),
+        // Out-of-range indices clamp to the string.
+        ("s", "-100", "4", "$5\r\nHello\r\n"),
+        ("s", "6", "100", "$5\r\nWorld\r\n"),
+        ("s", "0", "-1", "$11\r\nHello World\r\n"),
+        // Empty ranges and missing keys give an empty string, never nil.
+        ("s", "5", "2", "$0\r\n\r\n"),
+        ("s", "-1", "-2", "$0\r\n\r\n"),
+        ("s", "11", "20", "$0\r\n\r\n"),
+        ("s", "100", "-1", "$0\r\n\r\n"),
+        ("missing", "0", "-1", "$0\r\n\r\n"),
+    ];
+    for (key, start, end, expected) in getrange_cases {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GETRANGE", key, start, end]));
+        assert_eq!(resp, *expected, "GETRANGE {key} {start} {end}");
+    }
+
+    let setrange_cases: &[(&str, &str, &str, &str, &str)] = &[
+        // key, offset, value, reply, resulting GET
+        ("s", "6", "Redis", ":11\r\n", "$11\r\nHello Redis\r\n"),
+        ("pad", "3", "ab", ":5\r\n", "$5\r\n\0\0\0ab\r\n"),
+        ("s", "11", "!", ":12\r\n", "$12\r\nHello Redis!\r\n"),
+        // A zero-length write neither changes nor creates anything.
+        ("s", "100", "", ":12\r\n", "$12\r\nHello Redis!\r\n"),
+        ("empty", "5", "", ":0\r\n", "$-1\r\n"),
+    ];
+    for (key, offset, value, expected, after) in setrange_cases {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SETRANGE", key, offset, value]));
+        assert_eq!(resp, *expected, "SETRANGE {key} {offset} {value:?}");
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", key]));
+        assert_eq!(resp, *after, "GET {key} after SETRANGE");
+    }
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SETRANGE", "s", "-1", "x"]));
+    assert_eq!(resp, "-ERR offset is out of range\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SETRANGE", "s", "536870912", "x"]));
+    assert!(
+        resp.starts_with("-ERR string exceeds maximum allowed size"),
+        "{resp}"
+    );
+
+    // SETRANGE keeps the TTL; both commands reject other types.
+    resp_roundtrip(&mut stream, &resp_cmd(&["EXPIRE", "s", "100"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SETRANGE", "s", "0", "J"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["TTL", "s"]));
+    assert_ne!(resp, ":-1\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["LPUSH", "list", "a"]));
+    for cmd in [
+        &["GETRANGE", "list", "0", "-1"][..],
+        &["SETRANGE", "list", "0", "x"],
+    ] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(cmd));
+        assert!(resp.starts_with("-WRONGTYPE"), "{cmd:?}: {resp}");
+    }
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+/// The `field:value` lines of an INFO reply, keyed by field.
+fn info_fields(resp: &str) -> std::collections::HashMap<String, String> {
+    resp.lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_info_run_id_and_replication() {
+    let port = 16442;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+
+    let info = info_fields(&resp_roundtrip(&mut stream, &resp_cmd(&["INFO"])));
+    let run_id = &info["run_id"];
+    assert_eq!(run_id.len(), 40, "{run_id}");
+    assert!(run_id.bytes().all(|b| b.is_ascii_hexdigit()), "{run_id}");
+    assert_eq!(info["role"], "master");
+    assert_eq!(info["connected_slaves"], "0");
+    assert_eq!(info["master_replid"].len(), 40);
+
+    // The run id is fixed for the process; the replication id can change.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "CHANGE-REPL-ID"]));
+    assert_eq!(resp, "+OK\r\n");
+    let after = info_fields(&resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["INFO", "server", "replication"]),
+    ));
+    assert_eq!(&after["run_id"], run_id);
+    assert_ne!(after["master_replid"], info["master_replid"]);
+    assert!(!after.contains_key("aof_enabled"));
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_failover_and_info_replication_on_standalone() {
+    let port = 16480;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["FAILOVER", "ABORT"]));
+    assert_eq!(resp, "-ERR No failover in progress.\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["FAILOVER"]));
+    assert_eq!(resp, "-ERR FAILOVER requires connected replicas.\r\n");
+
+    // One bulk string: a section header, then field:value lines.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["INFO", "replication"]));
+    let (len, body) = resp.split_once("\r\n").unwrap();
+    let body = body.strip_suffix("\r\n").unwrap();
+    assert_eq!(len, format!("${}", body.len()));
+    let mut lines = body.split_terminator("\r\n");
+    assert_eq!(lines.next(), Some("# Replication"));
+    for line in lines {
+        let (field, value) = line.split_once(':').unwrap();
+        assert!(!field.is_empty() && !value.is_empty(), "{line:?}");
+    }
+    let info = info_fields(body);
+    assert_eq!(info["role"], "master");
+    assert_eq!(info["master_failover_state"], "no-failover");
+    assert_eq!(info["second_repl_offset"], "-1");
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_master_repl_offset_counts_propagated_bytes() {
+    let port = 16473;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let offset = |stream: &mut TcpStream| -> usize {
+        let info = info_fields(&resp_roundtrip(stream, &resp_cmd(&["INFO", "replication"])));
+        info["master_repl_offset"].parse().unwrap()
+    };
+
+    let mut expected = offset(&mut stream);
+    assert_eq!(expected, 0);
+    // Each write advances the offset by the command as propagated, which
+    // for INCR is INCRBY; reads and writes that change nothing don't.
+    for (cmd, propagated) in [
+        (&["SET", "k", "v"][..], &["SET", "k", "v"][..]),
+        (&["GET", "k"], &[]),
+        (&["RPUSH", "l", "a", "b"], &["RPUSH", "l", "a", "b"]),
+        (&["INCR", "n"], &["INCRBY", "n", "1"]),
+        (&["DEL", "missing"], &[]),
+        (&["LPUSH", "k", "x"], &[]),
+        (&["SET", "k", "longer value"], &["SET", "k", "longer value"]),
+    ] {
+        resp_roundtrip(&mut stream, &resp_cmd(cmd));
+        if !propagated.is_empty() {
+            expected += resp_cmd(propagated).len();
+        }
+        assert_eq!(offset(&mut stream), expected, "{cmd:?}");
+    }
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_monitor_streams_commands() {
+    let port = 16443;
+    let mut server = spawn_server(port);
+    let mut monitor = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    monitor
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut client = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+
+    let resp = resp_roundtrip(&mut monitor, &resp_cmd(&["MONITOR"]));
+    assert_eq!(resp, "+OK\r\n");
+
+    resp_roundtrip(&mut client, &resp_cmd(&["SET", "k", "say \"hi\""]));
+    resp_roundtrip(&mut client, &resp_cmd(&["AUTH", "secret"]));
+    resp_roundtrip(&mut client, &resp_cmd(&["CONFIG", "GET", "*"]));
+    resp_roundtrip(&mut client, &resp_cmd(&["get", "k"]));
+
+    let mut buf = vec![0u8; 4096];
+    let mut feed = String::new();
+    while feed.matches("\r\n").count() < 3 {
+        let n = monitor.read(&mut buf).unwrap();
+        feed.push_str(&String::from_utf8_lossy(&buf[..n]));
+    }
+    let lines: Vec<&str> = feed.lines().collect();
+    assert_eq!(lines.len(), 3, "{feed}");
+    // `+<secs>.<micros> [0 <addr>] "args"...`
+    let (stamp, rest) = lines[0][1..].split_once(' ').unwrap();
+    let (secs, micros) = stamp.split_once('.').unwrap();
+    assert!(
+        secs.parse::<u64>().is_ok() && micros.len() == 6,
+        "{}",
+        lines[0]
+    );
+    assert!(rest.starts_with("[0 127.0.0.1:"), "{}", lines[0]);
+    assert!(
+        lines[0].ends_with(r#"] "SET" "k" "say \"hi\"""#),
+        "{}",
+        lines[0]
+    );
+    // AUTH's password is hidden and CONFIG, an admin command, isn't shown.
+    assert!(
+        lines[1].ends_with(r#"] "AUTH" "(redacted)""#),
+        "{}",
+        lines[1]
+    );
+    assert!(lines[2].ends_with(r#"] "get" "k""#), "{}", lines[2]);
+
+    // The monitor's own commands are ignored until it RESETs.
+    monitor.write_all(&resp_cmd(&["PING"])).unwrap();
+    let resp = resp_roundtrip(&mut monitor, &resp_cmd(&["RESET"]));
+    assert_eq!(resp, "+RESET\r\n");
+    resp_roundtrip(&mut client, &resp_cmd(&["GET", "k"]));
+    let resp = resp_roundtrip(&mut monitor, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_logfile_and_loglevel() {
+    let dir = std::env::temp_dir();
+    let verbose_log = dir.join(format!("rfs-test-verbose-{}.log", std::process::id()));
+    let warning_log = dir.join(format!("rfs-test-warning-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&verbose_log);
+    let _ = std::fs::remove_file(&warning_log);
+
+    let run = |port: u16, log: &std::path::Path, level: &str| {
+        let args = ["--logfile", log.to_str().unwrap(), "--loglevel", level];
+        let mut server = spawn_server_with_args(port, &args);
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+        resp_roundtrip(&mut stream, &resp_cmd(&["PING"]));
+        // The file is written from a background thread; let it catch up.
+        std::thread::sleep(Duration::from_millis(200));
+        server.kill().ok();
+        server.wait().ok();
+        std::fs::read_to_string(log).unwrap()
+    };
+
+    // "verbose" includes debug events such as accepted connections.
+    let log = run(16444, &verbose_log, "verbose");
+    assert!(log.contains("server listening"), "{log}");
+    assert!(log.contains("accepted connection"), "{log}");
+    assert!(
+        !log.contains("\x1b["),
+        "log file should have no colour codes"
+    );
+
+    // "warning" leaves out informational messages.
+    let log = run(16445, &warning_log, "warning");
+    assert!(!log.contains("server listening"), "{log}");
+
+    let _ = std::fs::remove_file(&verbose_log);
+    let _ = std::fs::remove_file(&warning_log);
+}
+
+#[test]
+fn test_json_log_format() {
+    let log = std::env::temp_dir().join(format!("rfs-test-json-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    let port = 16446;
+    let args = ["--log-format", "json", "--logfile", log.to_str().unwrap()];
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PING"]));
+    assert_eq!(resp, "+PONG\r\n");
+    std::thread::sleep(Duration::from_millis(200));
+    server.kill().ok();
+    server.wait().ok();
+
+    let contents = std::fs::read_to_string(&log).unwrap();
+    assert!(!contents.is_empty());
+    for line in contents.lines() {
+        assert!(line.starts_with('{') && line.ends_with('}'), "{line}");
+    }
+    assert!(
+        contents.contains(r#""message":"server listening""#),
+        "{contents}"
+    );
+    let _ = std::fs::remove_file(&log);
+}
+
+#[test]
+fn test_metrics_bind_and_startup_errors() {
+    let port = 16447;
+    let metrics_port = 16448;
+    let mut server = spawn_server_with_args(
+        port,
+        &["--metrics-bind", &format!("127.0.0.1:{metrics_port}")],
+    );
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+
+    let mut http = TcpStream::connect(format!("127.0.0.1:{metrics_port}")).unwrap();
+    http.set_read_timeout(Some(Duration::from_secs(2))).unwrap();
+    http.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut body = String::new();
+    let _ = http.read_to_string(&mut body);
+    assert!(body.starts_with("HTTP/1.1 200"), "{body}");
+    assert!(body.contains("rfs_commands_total"), "{body}");
+    server.kill().ok();
+    server.wait().ok();
+
+    // A log file that can't be opened stops startup with a message.
+    let output = Command::new(env!("CARGO_BIN_EXE_rfs-rs"))
+        .args(["--bind", "127.0.0.1:16449"])
+        .args(["--logfile", "/nonexistent-dir/rfs.log"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("failed to set up logging"), "{stderr}");
+}
+
+#[test]
+fn test_cluster_reports_standalone() {
+    let port = 16452;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+
+    let info = info_fields(&resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["CLUSTER", "INFO"]),
+    ));
+    assert_eq!(info["cluster_enabled"], "0");
+    assert_eq!(info["cluster_slots_assigned"], "0");
+
+    let run_id =
+        info_fields(&resp_roundtrip(&mut stream, &resp_cmd(&["INFO", "server"])))["run_id"].clone();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CLUSTER", "MYID"]));
+    assert_eq!(resp, format!("$40\r\n{run_id}\r\n"));
+
+    for sub in ["SLOTS", "shards"] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CLUSTER", sub]));
+        assert_eq!(resp, "*0\r\n", "CLUSTER {sub}");
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CLUSTER", "NODES"]));
+    assert_eq!(
+        resp,
+        "-ERR unknown subcommand or wrong number of arguments for 'NODES'. Try CLUSTER HELP.\r\n"
+    );
+
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_hash_field_expiry() {
+    let port = 16453;
+    let path = std::env::temp_dir().join(format!("rfs-hexpire-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let args = [
+        "--aof-path",
+        path.to_str().unwrap(),
+        "--aof-fsync",
+        "always",
+    ];
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut cmd = |args: &[&str]| resp_roundtrip(&mut stream, &resp_cmd(args));
+
+    cmd(&["HSET", "h", "a", "1", "b", "2", "c", "3"]);
+    let resp = cmd(&["HEXPIRE", "h", "100", "FIELDS", "2", "a", "missing"]);
+    assert_eq!(resp, "*2\r\n:1\r\n:-2\r\n");
+    let resp = cmd(&["HEXPIRE", "h", "200", "NX", "FIELDS", "1", "a"]);
+    assert_eq!(resp, "*1\r\n:0\r\n");
+    let resp = cmd(&["HTTL", "h", "FIELDS", "3", "a", "b", "missing"]);
+    assert!(resp.starts_with("*3\r\n:9"), "{resp}");
+    assert!(resp.ends_with(":-1\r\n:-2\r\n"), "{resp}");
+
+    // The field TTL survives an AOF rewrite and reload.
+    assert_eq!(cmd(&["DEBUG", "RELOAD"]), "+OK\r\n");
+    let resp = cmd(&["HPTTL", "h", "FIELDS", "1", "a"]);
+    let ms: i64 = resp
+        .trim_start_matches("*1\r\n:")
+        .trim_end()
+        .parse()
+        .unwrap();
+    assert!((90_000..=100_000).contains(&ms), "{resp}");
+
+    let resp = cmd(&["HPERSIST", "h", "FIELDS", "2", "a", "b"]);
+    assert_eq!(resp, "*2\r\n:1\r\n:-1\r\n");
+
+    // Expired fields read as absent; a past deadline deletes at once.
+    let resp = cmd(&["HPEXPIRE", "h", "100", "FIELDS", "1", "b"]);
+    assert_eq!(resp, "*1\r\n:1\r\n");
+    let resp = cmd(&["HEXPIRE", "h", "0", "FIELDS", "1", "c"]);
+    assert_eq!(resp, "*1\r\n:2\r\n");
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(cmd(&["HGET", "h", "b"]), "$-1\r\n");
+    assert_eq!(cmd(&["HGETALL", "h"]), "*2\r\n$1\r\na\r\n$1\r\n1\r\n");
+
+    // HSET clears a field's TTL.
+    cmd(&["HEXPIRE", "h", "100", "FIELDS", "1", "a"]);
+    assert_eq!(cmd(&["HSET", "h", "a", "2"]), ":0\r\n");
+    assert_eq!(cmd(&["HTTL", "h", "FIELDS", "1", "a"]), "*1\r\n:-1\r\n");
+
+    // The background sweep deletes a hash whose last field expired.
+    cmd(&["HPEXPIRE", "h", "50", "FIELDS", "1", "a"]);
+    std::thread::sleep(Duration::from_millis(1500));
+    assert_eq!(cmd(&["EXISTS", "h"]), ":0\r\n");
+
+    let resp = cmd(&["HEXPIRE", "h", "10", "FIELDS", "2", "a"]);
+    assert_eq!(
+        resp,
+        "-ERR The `numfields` parameter must match the number of arguments\r\n"
+    );
+    let resp = cmd(&["HEXPIRE", "h", "10", "a", "b", "c"]);
+    assert_eq!(resp, "-ERR Unsupported option a\r\n");
+    let resp = cmd(&["HTTL", "h", "a", "1", "b"]);
+    assert_eq!(
+        resp,
+        "-ERR Mandatory argument FIELDS is missing or not at the right position\r\n"
+    );
+    cmd(&["SET", "s", "v"]);
+    let resp = cmd(&["HTTL", "s", "FIELDS", "1", "a"]);
+    assert!(resp.starts_with("-WRONGTYPE"), "{resp}");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_emptied_collections_are_deleted() {
+    let port = 16454;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut cmd = |args: &[&str]| resp_roundtrip(&mut stream, &resp_cmd(args));
+
+    let cases: &[(&[&str], &[&str])] = &[
+        (&["RPUSH", "k", "a", "b"], &["LPOP", "k", "5"]),
+        (&["RPUSH", "k", "a"], &["RPOP", "k"]),
+        (&["SADD", "k", "a", "b"], &["SREM", "k", "a", "b"]),
+        (&["ZADD", "k", "1", "a"], &["ZREM", "k", "a"]),
+        (
+            &["HSET", "k", "f", "v"],
+            &["HEXPIRE", "k", "0", "FIELDS", "1", "f"],
+        ),
+    ];
+    for &(create, remove) in cases {
+        cmd(create);
+        assert_eq!(cmd(&["EXPIRE", "k", "100"]), ":1\r\n");
+        cmd(remove);
+        assert_eq!(cmd(&["EXISTS", "k"]), ":0\r\n", "after {remove:?}");
+        // The TTL went with the key rather than waiting for the next one.
+        cmd(create);
+        assert_eq!(cmd(&["TTL", "k"]), ":-1\r\n", "after {remove:?}");
+        cmd(&["DEL", "k"]);
+    }
+
+    cmd(&["SET", "s", "v"]);
+    for args in [
+        &["LPOP", "s", "0"][..],
+        &["RPUSH", "s", "a"],
+        &["SREM", "s", "a"],
+        &["ZREM", "s", "a"],
+    ] {
+        assert!(cmd(args).starts_with("-WRONGTYPE"), "{args:?}");
+    }
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_memory_usage_follows_listpack_thresholds() {
+    let port = 16455;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let usage = |stream: &mut TcpStream, key: &str| -> i64 {
+        let resp = resp_roundtrip(stream, &resp_cmd(&["MEMORY", "USAGE", key]));
+        resp.trim_start_matches(':').trim().parse().unwrap()
+    };
+
+    for (option, key) in [
+        ("hash-max-listpack-entries", "h"),
+        ("set-max-listpack-entries", "s"),
+        ("zset-max-listpack-entries", "z"),
+    ] {
+        resp_roundtrip(&mut stream, &resp_cmd(&["CONFIG", "SET", option, "4"]));
+        let mut sizes = Vec::new();
+        for i in 1..=5 {
+            let element = format!("element-{i}");
+            let add = match key {
+                "h" => ["HSET", key, &element, "1"].to_vec(),
+                "s" => ["SADD", key, &element].to_vec(),
+                _ => ["ZADD", key, "1", &element].to_vec(),
+            };
+            resp_roundtrip(&mut stream, &resp_cmd(&add));
+            sizes.push(usage(&mut stream, key));
+        }
+        // Each element costs the same while the collection is a listpack;
+        // the fifth converts it and costs more than all four before it.
+        let step = sizes[1] - sizes[0];
+        assert!(
+            sizes.windows(2).take(3).all(|w| w[1] - w[0] == step),
+            "{key}: {sizes:?}"
+        );
+        assert!(sizes[4] - sizes[3] > 4 * step, "{key}: {sizes:?}");
+
+        // Raising the threshold makes the same collection a listpack again.
+        resp_roundtrip(&mut stream, &resp_cmd(&["CONFIG", "SET", option, "128"]));
+        assert_eq!(usage(&mut stream, key) - sizes[3], step, "{key}");
+    }
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_debug_populate() {
+    let port = 16456;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    // An existing key is kept, not overwritten.
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "key:7", "mine"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "POPULATE", "1000"]));
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DBSIZE"]));
+    assert_eq!(resp, ":1000\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "key:7"]));
+    assert_eq!(resp, "$4\r\nmine\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "key:999"]));
+    assert_eq!(resp, "$9\r\nvalue:999\r\n");
+
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["DEBUG", "POPULATE", "10", "big", "32"]),
+    );
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "big:3"]));
+    assert_eq!(resp, format!("$32\r\nvalue:3{}\r\n", "\0".repeat(25)));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DBSIZE"]));
+    assert_eq!(resp, ":1010\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "POPULATE", "-1"]));
+    assert!(resp.starts_with("-ERR value is out of range"), "{resp}");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_proto_max_bulk_len() {
+    let port = 16457;
+    let mut server = spawn_server_with_args(port, &["--proto-max-bulk-len", "1024"]);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let value = "x".repeat(1024);
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", &value]));
+    assert_eq!(resp, "+OK\r\n");
+
+    // Only the header of the oversized argument is sent; the server must
+    // refuse it without waiting for the rest.
+    let resp = resp_roundtrip(&mut stream, b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1025\r\n");
+    assert_eq!(resp, "-ERR Protocol error: invalid bulk length\r\n");
+    let mut buf = [0u8; 16];
+    assert_eq!(
+        stream.read(&mut buf).unwrap(),
+        0,
+        "connection should be closed"
+    );
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", "k"]));
+    assert_eq!(resp, format!("$1024\r\n{value}\r\n"));
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_conditional_set_replays_its_outcome() {
+    let port = 16458;
+    let path = std::env::temp_dir().join(format!("rfs-set-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let aof = path.to_str().unwrap();
+    let args = ["--aof-path", aof, "--aof-fsync", "always"];
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    // DEBUG POPULATE keys never reach the AOF, so on replay `key:0` is
+    // missing and a literal XX would fail.
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "POPULATE", "1"]));
+    let cases: &[(&[&str], &str)] = &[
+        (&["SET", "key:0", "new", "XX"], "+OK\r\n"),
+        (&["SET", "n", "v1", "NX"], "+OK\r\n"),
+        (&["SET", "n", "v2", "NX", "GET"], "$2\r\nv1\r\n"),
+        (&["SET", "missing", "v", "XX"], "$-1\r\n"),
+        (&["SET", "t", "v", "PX", "100000"], "+OK\r\n"),
+        (&["SET", "t", "w", "KEEPTTL", "GET"], "$1\r\nv\r\n"),
+        (&["GETSET", "g", "x"], "$-1\r\n"),
+        (&["SET", "n", "v3", "NX", "XX"], "-ERR syntax error\r\n"),
+        (
+            &["SET", "n", "v3", "EX", "1", "KEEPTTL"],
+            "-ERR syntax error\r\n",
+        ),
+        (
+            &["SET", "n", "v3", "EX", "18446744073709551615"],
+            "-ERR invalid expire time in 'set'\r\n",
+        ),
+        (
+            &["SET", "n", "v3", "EX", "9223372036854775807"],
+            "-ERR invalid expire time in 'set'\r\n",
+        ),
+    ];
+    for (cmd, expected) in cases {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(cmd));
+        assert_eq!(resp, *expected, "{cmd:?}");
+    }
+    let _ = resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "l", "a"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SET", "l", "v", "GET"]));
+    assert!(resp.starts_with("-WRONGTYPE"), "{resp}");
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    for option in ["NX", "XX", "GET", "KEEPTTL", "PX"] {
+        assert!(
+            !log.contains(&format!("\r\n{option}\r\n")),
+            "{option} in {log:?}"
+        );
+    }
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    for (key, expected) in [("key:0", "new"), ("n", "v1"), ("t", "w"), ("g", "x")] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["GET", key]));
+        assert_eq!(
+            resp,
+            format!("${}\r\n{expected}\r\n", expected.len()),
+            "{key}"
+        );
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["PTTL", "t"]));
+    let pttl: i64 = resp.trim_start_matches(':').trim().parse().unwrap();
+    assert!(pttl > 0 && pttl <= 100_000, "{pttl}");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_info_expiry_stats() {
+    let port = 16459;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let stats = info_fields(&resp_roundtrip(&mut stream, &resp_cmd(&["INFO", "stats"])));
+    assert_eq!(stats["expired_keys"], "0");
+    assert_eq!(stats["evicted_keys"], "0");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["INFO", "keyspace"]));
+    assert_eq!(resp, "$12\r\n# Keyspace\r\n\r\n");
+
+    for i in 0..5 {
+        let key = format!("short:{i}");
+        resp_roundtrip(&mut stream, &resp_cmd(&["SET", &key, "v", "PX", "50"]));
+    }
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "long", "v", "EX", "100"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "plain", "v"]));
+
+    // Left untouched, the short keys can only go through the background
+    // task, which runs every second.
+    std::thread::sleep(Duration::from_millis(2200));
+    let stats = info_fields(&resp_roundtrip(&mut stream, &resp_cmd(&["INFO", "stats"])));
+    assert_eq!(stats["expired_keys"], "5");
+    let stale: f64 = stats["expired_stale_perc"].trim().parse().unwrap();
+    assert!((0.0..=100.0).contains(&stale), "{stale}");
+    let keyspace = info_fields(&resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["INFO", "keyspace"]),
+    ));
+    assert_eq!(keyspace["db0"].trim(), "keys=2,expires=1,avg_ttl=0");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_debug_listpack_entries_and_self_test() {
+    let port = 16460;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "l", "a", "b", "c", "d"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["LPOP", "l"]));
+    let llen = resp_roundtrip(&mut stream, &resp_cmd(&["LLEN", "l"]));
+    assert_eq!(llen, ":3\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "LISTPACK-ENTRIES", "l"]));
+    assert_eq!(resp, llen);
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "s", "v"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "LISTPACK-ENTRIES", "s"]));
+    assert!(resp.starts_with("-WRONGTYPE"), "{resp}");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "LISTPACK-ENTRIES", "x"]));
+    assert_eq!(resp, "-ERR no such key\r\n");
+
+    // Emptied collections and expired keys leave nothing behind.
+    resp_roundtrip(&mut stream, &resp_cmd(&["SADD", "set", "m"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SREM", "set", "m"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "t", "v", "EX", "100"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["DEL", "t"]));
+    resp_roundtrip(&mut stream, &resp_cmd(&["HSET", "h", "f", "v"]));
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["HEXPIRE", "h", "100", "FIELDS", "1", "f"]),
+    );
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["HPERSIST", "h", "FIELDS", "1", "f"]),
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "SELF-TEST"]));
+    assert_eq!(resp, "+OK\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_hgetdel_and_hgetex() {
+    let port = 16461;
+    let path = std::env::temp_dir().join(format!("rfs-hgetdel-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let aof = path.to_str().unwrap();
+    let args = ["--aof-path", aof, "--aof-fsync", "always"];
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["HSET", "h", "a", "1", "b", "2", "c", "3", "d", "4"]),
+    );
+    let cases: &[(&[&str], &str)] = &[
+        // A subset goes, the rest stays.
+        (
+            &["HGETDEL", "h", "FIELDS", "2", "a", "x"],
+            "*2\r\n$1\r\n1\r\n$-1\r\n",
+        ),
+        (&["HGET", "h", "a"], "$-1\r\n"),
+        (&["HGET", "h", "b"], "$1\r\n2\r\n"),
+        (&["HDEL", "h", "d", "d", "x"], ":1\r\n"),
+        (
+            &["HGETEX", "h", "PX", "100000", "FIELDS", "2", "b", "x"],
+            "*2\r\n$1\r\n2\r\n$-1\r\n",
+        ),
+        (&["HGETEX", "h", "FIELDS", "1", "c"], "*1\r\n$1\r\n3\r\n"),
+        (&["HTTL", "h", "FIELDS", "1", "c"], "*1\r\n:-1\r\n"),
+        (
+            &["HGETEX", "h", "PERSIST", "FIELDS", "1", "c"],
+            "*1\r\n$1\r\n3\r\n",
+        ),
+        (
+            &["HGETEX", "h", "EX", "0", "FIELDS", "1", "c"],
+            "-ERR invalid expire time in 'hgetex'\r\n",
+        ),
+        (
+            &["HGETDEL", "h", "FIELDS", "2", "c"],
+            "-ERR The `numfields` parameter must match the number of arguments\r\n",
+        ),
+        (&["HSET", "gone", "f", "v"], ":1\r\n"),
+        (
+            &["HGETDEL", "gone", "FIELDS", "1", "f"],
+            "*1\r\n$1\r\nv\r\n",
+        ),
+        (&["EXISTS", "gone"], ":0\r\n"),
+        (&["SET", "s", "v"], "+OK\r\n"),
+        (&["HGETDEL", "s", "FIELDS", "1", "f"], ""),
+    ];
+    for (cmd, expected) in cases {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(cmd));
+        if expected.is_empty() {
+            assert!(resp.starts_with("-WRONGTYPE"), "{cmd:?}: {resp}");
+        } else {
+            assert_eq!(resp, *expected, "{cmd:?}");
+        }
+    }
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    for (field, expected) in [("a", "$-1\r\n"), ("b", "$1\r\n2\r\n"), ("d", "$-1\r\n")] {
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HGET", "h", field]));
+        assert_eq!(resp, expected, "{field}");
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HPTTL", "h", "FIELDS", "1", "b"]));
+    let ttl: i64 = resp.trim_start_matches("*1\r\n:").trim().parse().unwrap();
+    assert!(ttl > 0 && ttl <= 100_000, "{ttl}");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["EXISTS", "gone"]));
+    assert_eq!(resp, ":0\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}
+
+#[test]
+fn test_max_connections_rejects_extra_clients() {
+    let port = 16462;
+    let mut server = spawn_server_with_args(port, &["--max-connections", "1"]);
+    let mut first = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    first
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    assert_eq!(
+        resp_roundtrip(&mut first, &resp_cmd(&["PING"])),
+        "+PONG\r\n"
+    );
+
+    let mut second = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    second
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut reply = String::new();
+    second.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply, "-ERR max number of clients reached\r\n");
+
+    // The first client is unaffected, and its slot frees up when it leaves.
+    assert_eq!(
+        resp_roundtrip(&mut first, &resp_cmd(&["PING"])),
+        "+PONG\r\n"
+    );
+    drop(first);
+    std::thread::sleep(Duration::from_millis(100));
+    let mut third = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    third
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    assert_eq!(
+        resp_roundtrip(&mut third, &resp_cmd(&["PING"])),
+        "+PONG\r\n"
+    );
+
+    drop(third);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_warn_reply_bytes() {
+    let port = 16463;
+    let log = std::env::temp_dir().join(format!("rfs-test-bigreply-{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    let args = [
+        "--warn-reply-bytes",
+        "1000",
+        "--logfile",
+        log.to_str().unwrap(),
+    ];
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    let element = "x".repeat(10);
+    let mut push = vec!["RPUSH", "l"];
+    push.extend(std::iter::repeat_n(element.as_str(), 100));
+    resp_roundtrip(&mut stream, &resp_cmd(&push));
+    // 100 elements of `$10\r\nxxxxxxxxxx\r\n` come to 1,706 bytes.
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["LRANGE", "l", "0", "-1"]));
+    assert_eq!(resp.len(), 1706);
+    resp_roundtrip(&mut stream, &resp_cmd(&["LRANGE", "l", "0", "9"]));
+
+    std::thread::sleep(Duration::from_millis(200));
+    let text = std::fs::read_to_string(&log).unwrap();
+    let warnings: Vec<&str> = text.lines().filter(|l| l.contains("large reply")).collect();
+    assert_eq!(warnings.len(), 1, "{text}");
+    assert!(warnings[0].contains("LRANGE"), "{}", warnings[0]);
+    assert!(warnings[0].contains("1706"), "{}", warnings[0]);
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&log);
+}
+
+#[test]
+fn test_flushall_async() {
+    let port = 16464;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["DEBUG", "POPULATE", "1000"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["FLUSHALL", "LAZY"]));
+    assert_eq!(resp, "-ERR syntax error\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DBSIZE"]));
+    assert_eq!(resp, ":1000\r\n");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["FLUSHALL", "ASYNC"]));
+    assert_eq!(resp, "+OK\r\n");
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", "v"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["FLUSHDB", "sync"]));
+    assert_eq!(resp, "+OK\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_incr_parses_integers_strictly() {
+    let port = 16465;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let not_integer = "-ERR value is not an integer or out of range\r\n";
+
+    for bad in [" 10", "10 ", "+10", "0x10", ""] {
+        resp_roundtrip(&mut stream, &resp_cmd(&["SET", "k", bad]));
+        let resp = resp_roundtrip(&mut stream, &resp_cmd(&["INCR", "k"]));
+        assert_eq!(resp, not_integer, "{bad:?}");
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["INCRBY", "k", "+1"]));
+    assert_eq!(resp, not_integer);
+
+    // APPEND and INCR work on the same bytes.
+    resp_roundtrip(&mut stream, &resp_cmd(&["SET", "n", "10"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["APPEND", "n", "1"]));
+    assert_eq!(resp, ":3\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["INCR", "n"]));
+    assert_eq!(resp, ":102\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DECRBY", "n", "2"]));
+    assert_eq!(resp, ":100\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["APPEND", "n", " "]));
+    assert_eq!(resp, ":4\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["DECR", "n"]));
+    assert_eq!(resp, not_integer);
+
+    resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["SET", "max", "9223372036854775807"]),
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["INCR", "max"]));
+    assert_eq!(resp, "-ERR increment or decrement would overflow\r\n");
+
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "l", "1"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["INCR", "l"]));
+    assert!(resp.starts_with("-WRONGTYPE"), "{resp}");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["APPEND", "l", "x"]));
+    assert!(resp.starts_with("-WRONGTYPE"), "{resp}");
+
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HINCRBY", "h", "f", "5"]));
+    assert_eq!(resp, ":5\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HINCRBY", "h", "f", "-7"]));
+    assert_eq!(resp, ":-2\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["HSET", "h", "g", " 1"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["HINCRBY", "h", "g", "1"]));
+    assert_eq!(resp, "-ERR hash value is not an integer\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_zset_encoding_flips_past_listpack_limits() {
+    let port = 16466;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    // Up to the default zset-max-listpack-entries of 128.
+    let members: Vec<String> = (0..128).map(|i| format!("m{i}")).collect();
+    let mut zadd = vec!["ZADD", "z"];
+    for m in &members {
+        zadd.extend(["1", m.as_str()]);
+    }
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&zadd));
+    assert_eq!(resp, ":128\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "z"]));
+    assert_eq!(resp, "$8\r\nlistpack\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["ZADD", "z", "2", "one-more"]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "z"]));
+    assert_eq!(resp, "$8\r\nskiplist\r\n");
+
+    // A member past the default zset-max-listpack-value of 64 bytes.
+    let fits = "x".repeat(64);
+    let too_long = "y".repeat(65);
+    resp_roundtrip(&mut stream, &resp_cmd(&["ZADD", "v", "1", &fits]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "v"]));
+    assert_eq!(resp, "$8\r\nlistpack\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["ZADD", "v", "2", &too_long]));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["OBJECT", "ENCODING", "v"]));
+    assert_eq!(resp, "$8\r\nskiplist\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_exec_is_not_interleaved() {
+    let port = 16467;
+    // Several workers, so the two connections run in parallel even on a
+    // single-CPU machine.
+    let mut server = Command::new(env!("CARGO_BIN_EXE_rfs-rs"))
+        .args(["--bind", &format!("127.0.0.1:{port}")])
+        .env("TOKIO_WORKER_THREADS", "4")
+        .spawn()
+        .expect("failed to start rfs-rs");
+    std::thread::sleep(Duration::from_millis(500));
+    let connect = move || {
+        let stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    };
+    const INCRS: i64 = 5000;
+
+    let mut stream = connect();
+    let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+    stream
+        .write_all(&resp_cmd(&["SET", "counter", "0"]))
+        .unwrap();
+    assert_eq!(read_reply(&mut reader), Reply::Simple("OK".into()));
+    stream.write_all(&resp_cmd(&["MULTI"])).unwrap();
+    assert_eq!(read_reply(&mut reader), Reply::Simple("OK".into()));
+    let incr = resp_cmd(&["INCR", "counter"]);
+    stream.write_all(&incr.repeat(INCRS as usize)).unwrap();
+    for _ in 0..INCRS {
+        assert_eq!(read_reply(&mut reader), Reply::Simple("QUEUED".into()));
+    }
+
+    // Another client polls the counter while the transaction runs. It must
+    // only ever see the value from before EXEC or the one after.
+    let poller = std::thread::spawn(move || {
+        let mut stream = connect();
+        let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+        let mut seen = Vec::new();
+        loop {
+            stream.write_all(&resp_cmd(&["GET", "counter"])).unwrap();
+            let Reply::Bulk(Some(value)) = read_reply(&mut reader) else {
+                panic!("counter missing");
+            };
+            let value: i64 = String::from_utf8(value).unwrap().parse().unwrap();
+            seen.push(value);
+            if value == INCRS {
+                return seen;
+            }
+        }
+    });
+    std::thread::sleep(Duration::from_millis(50));
+
+    stream.write_all(&resp_cmd(&["EXEC"])).unwrap();
+    let Reply::Array(replies) = read_reply(&mut reader) else {
+        panic!("EXEC should reply with an array");
+    };
+    assert_eq!(replies.len(), INCRS as usize);
+    assert_eq!(replies.last(), Some(&Reply::Int(INCRS)));
+
+    let seen = poller.join().unwrap();
+    assert!(
+        seen.iter().all(|&v| v == 0 || v == INCRS),
+        "saw a partial transaction: {:?}",
+        seen.iter().find(|&&v| v != 0 && v != INCRS)
+    );
+
+    // SYNC would need the lock EXEC holds, so it can't be queued.
+    stream.write_all(&resp_cmd(&["MULTI"])).unwrap();
+    assert_eq!(read_reply(&mut reader), Reply::Simple("OK".into()));
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["SYNC"]));
+    assert_eq!(resp, "-ERR Command not allowed inside a transaction\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_list_encoding_follows_list_max_listpack_size() {
+    let port = 16468;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let encoding = |stream: &mut TcpStream, key: &str| {
+        resp_roundtrip(stream, &resp_cmd(&["OBJECT", "ENCODING", key]))
+    };
+
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["CONFIG", "GET", "list-max-listpack-size"]),
+    );
+    assert_eq!(resp, "*2\r\n$22\r\nlist-max-listpack-size\r\n$2\r\n-2\r\n");
+
+    // The default -2 allows 8 KB: 80 elements of 100 bytes fit with their
+    // framing, an 81st doesn't.
+    let element = "x".repeat(100);
+    let mut push = vec!["RPUSH", "l"];
+    push.extend(std::iter::repeat_n(element.as_str(), 80));
+    resp_roundtrip(&mut stream, &resp_cmd(&push));
+    assert_eq!(encoding(&mut stream, "l"), "$8\r\nlistpack\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "l", &element]));
+    assert_eq!(encoding(&mut stream, "l"), "$9\r\nquicklist\r\n");
+
+    // A positive size counts entries instead.
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["CONFIG", "SET", "list-max-listpack-size", "3"]),
+    );
+    assert_eq!(resp, "+OK\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "small", "a", "b", "c"]));
+    assert_eq!(encoding(&mut stream, "small"), "$8\r\nlistpack\r\n");
+    resp_roundtrip(&mut stream, &resp_cmd(&["RPUSH", "small", "d"]));
+    assert_eq!(encoding(&mut stream, "small"), "$9\r\nquicklist\r\n");
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+}
+
+#[test]
+fn test_hz_speeds_up_expiry() {
+    // Write `count` keys that expire almost at once, in one pipeline.
+    fn write_expiring(stream: &mut TcpStream, count: usize) {
+        let mut batch = Vec::new();
+        for i in 0..count {
+            batch.extend(resp_cmd(&["SET", &format!("k{i}"), "v", "PX", "50"]));
+        }
+        stream.write_all(&batch).unwrap();
+        let mut replies = vec![0u8; count * "+OK\r\n".len()];
+        stream.read_exact(&mut replies).unwrap();
+    }
+
+    let dbsize = |stream: &mut TcpStream| -> usize {
+        let resp = resp_roundtrip(stream, &resp_cmd(&["DBSIZE"]));
+        resp.trim_start_matches(':').trim_end().parse().unwrap()
+    };
+
+    let (slow_port, fast_port) = (16482, 16483);
+    let mut slow = spawn_server_with_args(slow_port, &["--hz", "1"]);
+    let mut fast = spawn_server_with_args(fast_port, &["--hz", "100"]);
+    let mut slow_stream = TcpStream::connect(format!("127.0.0.1:{slow_port}")).unwrap();
+    let mut fast_stream = TcpStream::connect(format!("127.0.0.1:{fast_port}")).unwrap();
+    for stream in [&slow_stream, &fast_stream] {
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+    }
+
+    // Each cycle removes a bounded batch, so at 1 Hz a few thousand keys
+    // take several seconds to go while at 100 Hz they're gone at once.
+    write_expiring(&mut slow_stream, 3000);
+    write_expiring(&mut fast_stream, 3000);
+    std::thread::sleep(Duration::from_millis(1500));
+    let (slow_left, fast_left) = (dbsize(&mut slow_stream), dbsize(&mut fast_stream));
+    assert_eq!(fast_left, 0);
+    assert!(slow_left > 0, "slow server left {slow_left}");
+
+    // More effort means a bigger batch per cycle.
+    let resp = resp_roundtrip(
+        &mut slow_stream,
+        &resp_cmd(&["CONFIG", "SET", "active-expire-effort", "11"]),
+    );
+    assert_eq!(
+        resp,
+        "-ERR CONFIG SET failed (possibly related to argument 'active-expire-effort') - argument must be between 1 and 10 inclusive\r\n"
+    );
+    let resp = resp_roundtrip(
+        &mut slow_stream,
+        &resp_cmd(&["CONFIG", "SET", "hz", "100", "active-expire-effort", "10"]),
+    );
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut slow_stream, &resp_cmd(&["CONFIG", "GET", "hz"]));
+    assert_eq!(resp, "*2\r\n$2\r\nhz\r\n$3\r\n100\r\n");
+    // The 1-second sleep already under way has to finish first.
+    std::thread::sleep(Duration::from_millis(1500));
+    assert_eq!(dbsize(&mut slow_stream), 0);
+
+    slow.kill().unwrap();
+    fast.kill().unwrap();
+    let _ = slow.wait();
+    let _ = fast.wait();
+}
+
+#[test]
+fn test_config_rewrite() {
+    let port = 16484;
+    let path = std::env::temp_dir().join(format!("rfs-rewrite-{}.conf", std::process::id()));
+    std::fs::write(
+        &path,
+        "# Test config\nhz 20\nmaxmemory 1mb\nset-max-intset-entries 64\n",
+    )
+    .unwrap();
+    let file = path.to_str().unwrap().to_string();
+
+    let mut server = spawn_server_with_args(port, &["--config", &file]);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&[
+            "CONFIG",
+            "SET",
+            "hz",
+            "50",
+            "zset-max-listpack-entries",
+            "7",
+            "maxmemory",
+            "100mb",
+        ]),
+    );
+    assert_eq!(resp, "+OK\r\n");
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CONFIG", "GET", "maxmemory"]));
+    assert_eq!(resp, "*2\r\n$9\r\nmaxmemory\r\n$9\r\n104857600\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["CONFIG", "SET", "maxmemory", "lots"]),
+    );
+    assert_eq!(
+        resp,
+        "-ERR CONFIG SET failed (possibly related to argument 'maxmemory') - argument must be a memory value\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CONFIG", "REWRITE"]));
+    assert_eq!(resp, "+OK\r\n");
+    server.kill().unwrap();
+    let _ = server.wait();
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(
+        text.starts_with("# Test config\nhz 50\nmaxmemory 104857600\nset-max-intset-entries 64\n"),
+        "{text}"
+    );
+    assert!(text.contains("zset-max-listpack-entries 7\n"), "{text}");
+
+    // A server started from the rewritten file picks the changes up.
+    let mut server = spawn_server_with_args(port, &["--config", &file]);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CONFIG", "GET", "hz"]));
+    assert_eq!(resp, "*2\r\n$2\r\nhz\r\n$2\r\n50\r\n");
+    let resp = resp_roundtrip(
+        &mut stream,
+        &resp_cmd(&["CONFIG", "GET", "zset-max-listpack-entries"]),
+    );
+    assert_eq!(
+        resp,
+        "*2\r\n$25\r\nzset-max-listpack-entries\r\n$1\r\n7\r\n"
+    );
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CONFIG", "GET", "maxmemory"]));
+    assert_eq!(resp, "*2\r\n$9\r\nmaxmemory\r\n$9\r\n104857600\r\n");
+    server.kill().unwrap();
+    let _ = server.wait();
+    let _ = std::fs::remove_file(&path);
+
+    let port = 16485;
+    let mut server = spawn_server(port);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    let resp = resp_roundtrip(&mut stream, &resp_cmd(&["CONFIG", "REWRITE"]));
+    assert_eq!(resp, "-ERR The server is running without a config file\r\n");
+    server.kill().unwrap();
+    let _ = server.wait();
+}
+
+#[test]
+fn test_pipelined_subscribe_replies_in_order() {
+    let port = 16486;
+    let mut server = spawn_server(port);
+    let expected = "*3\r\n$9\r\nsubscribe\r\n$2\r\nch\r\n:1\r\n*2\r\n$4\r\npong\r\n$0\r\n\r\n";
+
+    let mut request = resp_cmd(&["SUBSCRIBE", "ch"]);
+    request.extend(resp_cmd(&["PING"]));
+    for _ in 0..50 {
+        let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        stream.write_all(&request).unwrap();
+        let mut buf = vec![0u8; expected.len()];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(String::from_utf8_lossy(&buf), expected);
+    }
+
+    server.kill().unwrap();
+    let _ = server.wait();
+}
+
+#[test]
+fn test_aof_replays_binary_collection_members() {
+    fn roundtrip(stream: &mut TcpStream, args: &[&[u8]]) -> Vec<u8> {
+        stream.write_all(&resp_cmd_bytes(args)).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        let mut buf = vec![0u8; 4096];
+        let n = stream.read(&mut buf).unwrap();
+        buf.truncate(n);
+        buf
+    }
+
+    let port = 16487;
+    let path = std::env::temp_dir().join(format!("rfs-binary-members-{}.aof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let aof = path.to_str().unwrap();
+    let args = ["--aof-path", aof, "--aof-fsync", "always"];
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    // None of these are UTF-8. Removing one of two members checks that the
+    // removal replays against the same bytes.
+    for cmd in [
+        &[&b"RPUSH"[..], b"l", b"\xff\x00"][..],
+        &[b"LPUSH", b"l", b"\xc3"],
+        &[b"SADD", b"s", b"\xfe", b"\xfd"],
+        &[b"SREM", b"s", b"\xfd"],
+        &[b"HSET", b"h", b"\x81", b"\x80"],
+        &[b"ZADD", b"z", b"1", b"\xf0", b"2", b"\xf1"],
+        &[b"ZREM", b"z", b"\xf1"],
+    ] {
+        assert!(!roundtrip(&mut stream, cmd).starts_with(b"-"));
+    }
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+
+    let mut server = spawn_server_with_args(port, &args);
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    for (cmd, expected) in [
+        (
+            &[&b"LRANGE"[..], b"l", b"0", b"-1"][..],
+            &b"*2\r\n$1\r\n\xc3\r\n$2\r\n\xff\x00\r\n"[..],
+        ),
+        (&[b"SMEMBERS", b"s"], b"*1\r\n$1\r\n\xfe\r\n"),
+        (&[b"HGET", b"h", b"\x81"], b"$1\r\n\x80\r\n"),
+        (&[b"ZRANGE", b"z", b"0", b"-1"], b"*1\r\n$1\r\n\xf0\r\n"),
+    ] {
+        assert_eq!(
+            roundtrip(&mut stream, cmd),
+            expected,
+            "{}",
+            String::from_utf8_lossy(cmd[0])
+        );
+    }
+
+    drop(stream);
+    server.kill().ok();
+    server.wait().ok();
+    let _ = std::fs::remove_file(&path);
+}